@@ -0,0 +1,153 @@
+use std::cmp::Ordering;
+
+use crate::ast::Data_Type;
+use crate::error::{IronError, IronResult};
+use crate::span::Span;
+
+/*
+    The methods arrays have, written once for the visitor's AST values and
+    the vm's Values so both raise the same errors. src/runtime.c mirrors it.
+    Methods that change the array do it in place, it's up to the caller to
+    store the array back where it came from
+*/
+pub trait Element: Clone {
+    fn type_name(&self) -> &'static str;
+    fn int(&self) -> Option<i64>;
+    fn equals(&self, other: &Self) -> bool;
+    fn order(&self, other: &Self) -> Option<Ordering>; // None when the two can't be put in order
+    fn widen(&self) -> Self; // an int as the float it stands for, anything else as it is
+    fn typed(&self, element: &Data_Type) -> Self; // an untyped [..] as an array of element, anything else as it is
+}
+
+pub enum Outcome<T> {
+    Nothing,
+    Value(T),
+    Int(i64),
+    Bool(bool),
+    Array(Vec<T>),
+}
+
+// An int going anywhere declared float, a float[], a float variable or argument, is stored as a float
+// so float math is done on it later. An array literal written outside a declaration gets its type
+// the same way, from the first place it's stored
+pub fn stored<T: Element>(element: &Data_Type, value: &T) -> T {
+    match element {
+        Data_Type::FLOAT => value.widen(),
+        Data_Type::ARRAY(inner) => value.typed(inner),
+        _ => value.clone(),
+    }
+}
+
+// fits says whether a value can go in an array of the element type
+pub fn method<T: Element>(
+    items: &mut Vec<T>,
+    element: &Data_Type,
+    fits: impl Fn(&T) -> bool,
+    name: &str,
+    args: &[T],
+    span: &Option<Span>,
+) -> IronResult<Outcome<T>> {
+    let arity = match name {
+        "len" | "pop" | "sort" | "reverse" => 0,
+        "push" | "remove" | "contains" | "index_of" => 1,
+        "insert" | "slice" => 2,
+        _ => return Err(IronError::NameError(format!("array has no method {}", name), span.clone())),
+    };
+
+    if args.len() != arity {
+        return Err(IronError::TypeError(format!("Function {} expected {} arguments, got {}", name, arity, args.len()), span.clone()));
+    }
+
+    let check = |value: &T| {
+        if fits(value) {
+            Ok(())
+        } else {
+            Err(IronError::TypeError(format!("{}[] can't hold {}", element.name(), value.type_name()), span.clone()))
+        }
+    };
+
+    Ok(match name {
+        "len" => Outcome::Int(items.len() as i64),
+        "push" => {
+            let value = stored(element, &args[0]);
+            check(&value)?;
+            items.push(value);
+            Outcome::Nothing
+        }
+        "pop" => match items.pop() {
+            Some(value) => Outcome::Value(value),
+            None => return Err(IronError::IndexError("pop from an empty array".to_string(), span.clone())),
+        },
+        "insert" => {
+            // Inserting right after the last item is fine
+            let i = position(&args[0], items.len(), true, span)?;
+            let value = stored(element, &args[1]);
+            check(&value)?;
+            items.insert(i, value);
+            Outcome::Nothing
+        }
+        "remove" => {
+            let i = position(&args[0], items.len(), false, span)?;
+            Outcome::Value(items.remove(i))
+        }
+        "contains" => Outcome::Bool(items.iter().any(|item| item.equals(&args[0]))),
+        "index_of" => Outcome::Int(items.iter().position(|item| item.equals(&args[0])).map_or(-1, |i| i as i64)),
+        "sort" => {
+            if items.iter().any(|item| items[0].order(item).is_none()) {
+                return Err(IronError::TypeError(format!("Can't sort {}[]", element.name()), span.clone()));
+            }
+
+            items.sort_by(|a, b| a.order(b).unwrap());
+            Outcome::Nothing
+        }
+        "reverse" => {
+            items.reverse();
+            Outcome::Nothing
+        }
+        "slice" => {
+            let (start, end) = (int(&args[0], span)?, int(&args[1], span)?);
+
+            if start < 0 || start > end || end as usize > items.len() {
+                return Err(IronError::IndexError(
+                    format!("slice {}:{} out of bounds for an array of length {}", start, end, items.len()),
+                    span.clone(),
+                ));
+            }
+
+            Outcome::Array(items[start as usize..end as usize].to_vec())
+        }
+        _ => unreachable!(),
+    })
+}
+
+// array(int, 10, 0), every item starts out as a copy of value
+pub fn fill<T: Element>(element: &Data_Type, fits: impl Fn(&T) -> bool, size: &T, value: T, span: &Option<Span>) -> IronResult<Vec<T>> {
+    let size = size.int().ok_or_else(|| IronError::TypeError("Array size must be an int".to_string(), span.clone()))?;
+
+    if size < 0 {
+        return Err(IronError::IndexError(format!("Array size can't be negative, got {}", size), span.clone()));
+    }
+
+    let value = stored(element, &value);
+
+    if !fits(&value) {
+        return Err(IronError::TypeError(format!("{}[] can't hold {}", element.name(), value.type_name()), span.clone()));
+    }
+
+    Ok(vec![value; size as usize])
+}
+
+fn int<T: Element>(value: &T, span: &Option<Span>) -> IronResult<i64> {
+    value.int().ok_or_else(|| IronError::TypeError("Array index must be an int".to_string(), span.clone()))
+}
+
+// An index into an array of len items, or one past the end when inserting
+fn position<T: Element>(value: &T, len: usize, inserting: bool, span: &Option<Span>) -> IronResult<usize> {
+    let i = int(value, span)?;
+
+    if i < 0 || i as usize > len || (i as usize == len && !inserting) {
+        return Err(IronError::IndexError(format!("index {} out of bounds for an array of length {}", i, len), span.clone()));
+    }
+
+    Ok(i as usize)
+}
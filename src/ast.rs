@@ -1,6 +1,8 @@
 use crate::scope::{SharedScope};
+use crate::span::Span;
 use crate::token::{Types};
 use std::collections::HashMap; 
+use std::fmt;
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum Ast_Type {
@@ -29,9 +31,22 @@ pub enum Ast_Type {
     AST_IMPORT,
     AST_DOT, 
     AST_BREAK, 
+    AST_CONTINUE,
     AST_CLASS_DEF,
     AST_CLASS_INSTANCE,
     AST_CLASS_ACCESS, // Yeah technically this is an AST_DOT but its nicer if its an access for a class
+    AST_TRY,
+    AST_THROW,
+    AST_ERROR, // The value a catch block gets handed
+    AST_ARRAY_FILL, // array(int, 10, 0), the size and fill value go in function_call_args
+    AST_MAP_DEF, // {"a": 1}, the keys and values go in map_keys and map_values
+    AST_FOR_EACH, // for (int x in xs), the loop variable is for_init and what it goes over is for_iterable
+    AST_RANGE, // range(0, 10, 2) in a for-each, the start, end and step go in function_call_args
+    AST_MATCH, // match (x) { ... }, what's matched goes in match_value and the cases in match_cases
+    AST_CASE, // case 1, 2 => { }, the patterns go in case_patterns, a default has none
+    AST_CLASS_PATTERN, // case Shop => { }, matches instances of class_name
+    AST_TERNARY, // cond ? a : b, kept in if_condition, if_body and else_body
+    AST_COMPOUND_ASSIGN, // grid[i][j] += 2 or p.x++, the target is left, the step is right and the op is operator
 }
 #[derive(Clone, PartialEq, Debug)]
 pub enum Data_Type {
@@ -43,6 +58,24 @@ pub enum Data_Type {
     BOOL, 
     CUSTOM(String) , 
     ARRAY(Box<Data_Type>), 
+    MAP(Box<Data_Type>, Box<Data_Type>), // key, value
+}
+
+impl Data_Type {
+    // How the type is written in iron source, for error messages
+    pub fn name(&self) -> String {
+        match self {
+            Data_Type::STR => "str".to_string(),
+            Data_Type::INT => "int".to_string(),
+            Data_Type::FLOAT => "float".to_string(),
+            Data_Type::VOID => "void".to_string(),
+            Data_Type::CHAR => "char".to_string(),
+            Data_Type::BOOL => "bool".to_string(),
+            Data_Type::CUSTOM(name) => name.clone(),
+            Data_Type::ARRAY(inner) => format!("{}[]", inner.name()),
+            Data_Type::MAP(key, value) => format!("map<{}, {}>", key.name(), value.name()),
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -53,6 +86,12 @@ pub struct AST {
     // Add in the scope
     pub scope: Option<SharedScope>, 
 
+    // Where the node starts in the source, None for values made at runtime
+    pub span: Option<Span>,
+
+    // The /// comments right before a fun or class, one line each
+    pub doc: Option<String>,
+
     pub variable_definition_variable_name: Option<String>,
     pub variable_definition_value: Option<Box<AST>>,
     pub variable_type: Option<Data_Type>,
@@ -70,7 +109,7 @@ pub struct AST {
     pub past_decimal: Option<i32>,
     pub float_init: Option<bool>, 
 
-    pub int_value: Option<i32>,
+    pub int_value: Option<i64>,
     pub int_init: Option<bool>,
 
     pub bool_value: Option<bool>,
@@ -81,6 +120,7 @@ pub struct AST {
     pub function_definition_body: Option<Box<AST>>,
     pub function_definition_name: Option<String>,
     pub function_definition_args: Option<Vec<AST>>,
+    pub function_return_type: Option<Data_Type>, // None when the function doesn't declare one
 
     pub left: Option<Box<AST>>,
     pub right: Option<Box<AST>>,
@@ -99,17 +139,32 @@ pub struct AST {
 
     pub reassign_name: Option<String>,
     pub reassign_value: Option<Box<AST>>,
+    pub postfix: bool, // x++ gives the value from before the step, ++x and x += 1 the one after
 
     pub for_init: Option<Box<AST>>,
     pub for_condition: Option<Box<AST>>,
     pub for_increment: Option<Box<AST>>,
     pub for_body: Option<Box<AST>>, 
+    pub for_iterable: Option<Box<AST>>,
+    pub loop_label: Option<String>, // outer: on a loop, or the loop a break or continue leaves
+
+    pub match_value: Option<Box<AST>>,
+    pub match_cases: Option<Vec<AST>>,
+    pub case_patterns: Option<Vec<AST>>,
+    pub case_body: Option<Box<AST>>,
 
     pub array_elements: Option<Vec<AST>>,
     pub array_name: Option<String>,
     pub array_len: Option<usize>,  
     pub array_index: Option<Box<AST>>,
     pub array_assign_value: Option<Box<AST>>,
+    pub array_slice_end: Option<Box<AST>>, // set for s[a:b], the start goes in array_index
+    pub array_path: Option<Vec<AST>>, // the indices after the first, grid[i][j] has [j]
+    pub array_field: Option<Box<AST>>, // set for p.items[i], the field being indexed instead of a variable
+
+    pub map_keys: Option<Vec<AST>>,
+    pub map_values: Option<Vec<AST>>,
+    pub map_evaluated: bool, // the keys and values are already worked out, so reading the map doesn't run them again
 
     pub dot_left: Option<Box<AST>>,
     pub dot_right: Option<Box<AST>>, 
@@ -124,6 +179,15 @@ pub struct AST {
     pub class_name: Option<String>,
     pub class_fields: Option<HashMap<String, AST>>, 
     pub class_args: Option<Vec<AST>>, 
+
+    pub try_body: Option<Box<AST>>,
+    pub catch_type: Option<String>,
+    pub catch_name: Option<String>,
+    pub catch_body: Option<Box<AST>>,
+    pub finally_body: Option<Box<AST>>,
+
+    pub throw_value: Option<Box<AST>>,
+    pub error_kind: Option<String>,
 }
 
 impl AST {
@@ -133,6 +197,8 @@ impl AST {
             data_type: Data_Type::VOID,
             
             scope: None, 
+            span: None,
+            doc: None,
 
             variable_definition_variable_name: None,
             variable_definition_value: None,
@@ -162,6 +228,7 @@ impl AST {
             function_definition_body:None,
             function_definition_name:None,
             function_definition_args:None,
+            function_return_type:None,
 
             left: None,
             right:None,
@@ -178,18 +245,33 @@ impl AST {
 
             reassign_name: None,
             reassign_value: None,
+            postfix: false,
 
             for_init:None,
             for_condition:None,
             for_increment:None,
             for_body:None,
+            for_iterable:None,
+            loop_label:None,
+
+            match_value:None,
+            match_cases:None,
+            case_patterns:None,
+            case_body:None,
 
             array_elements:None,
             array_len:None, 
             array_assign_value:None,
+            array_slice_end:None,
+            array_path:None,
+            array_field:None,
             array_index:None,
             array_name:None, 
 
+            map_keys:None,
+            map_values:None,
+            map_evaluated:false,
+
             dot_left:None,
             dot_right:None, 
 
@@ -204,6 +286,15 @@ impl AST {
             class_name:None, 
             class_args:None, // Only used on init 
             class_fields:None, // Our fancy dot implementation p much
+
+            try_body:None,
+            catch_type:None,
+            catch_name:None,
+            catch_body:None,
+            finally_body:None,
+
+            throw_value:None,
+            error_kind:None,
         }
     }
 
@@ -218,38 +309,145 @@ impl AST {
         node
     }
 
+    pub fn from_int(i: i64) -> AST {
+        let mut node = AST::new(Ast_Type::AST_INT);
+
+        node.int_init = Some(true);
+        node.int_value = Some(i);
+
+        node.data_type = Data_Type::INT;
+
+        node
+    }
+
+    // A float worked out at runtime, printed with the default two decimals
+    pub fn from_float(f: f64) -> AST {
+        let mut node = AST::new(Ast_Type::AST_FLOAT);
+
+        node.float_init = Some(true);
+        node.float_value = Some(f);
+
+        node.data_type = Data_Type::FLOAT;
+
+        node
+    }
+
+    pub fn from_string(s: &str) -> AST {
+        let mut node = AST::new(Ast_Type::AST_STRING);
+
+        node.string_value = Some(s.to_string());
+
+        node.data_type = Data_Type::STR;
+
+        node
+    }
+
+    // A char is a one letter string that remembers it's a char
+    pub fn from_char(c: char) -> AST {
+        let mut node = AST::from_string(&c.to_string());
+
+        node.data_type = Data_Type::CHAR;
+
+        node
+    }
+
+    pub fn from_error(kind: &str, message: &str) -> AST {
+        let mut node = AST::new(Ast_Type::AST_ERROR);
+
+        node.error_kind = Some(kind.to_string());
+        node.string_value = Some(message.to_string());
+
+        node.data_type = Data_Type::CUSTOM("Error".to_string());
+
+        node
+    }
+
+    // grid[i][j] in grid[i][j] += 1 with the indices swapped for hidden variables holding them,
+    // so the indices only run once when the element is read and then written
+    pub fn with_stored_indices(&self) -> AST {
+        let mut target = self.clone();
+        target.array_assign_value = None;
+        target.reassign_value = None;
+
+        let mut stored = self.indices().into_iter().enumerate().map(|(i, index)| {
+            let mut variable = AST::new(Ast_Type::AST_VARIABLE);
+            variable.variable_name = Some(stored_index(i));
+            variable.scope = self.scope.clone();
+            variable.span = index.span.clone();
+            variable
+        });
+
+        if self.array_index.is_some() {
+            target.array_index = stored.next().map(Box::new);
+            target.array_path = self.array_path.as_ref().map(|_| stored.collect());
+        }
+
+        target
+    }
+
+    // i then j for grid[i][j]
+    pub fn indices(&self) -> Vec<&AST> {
+        self.array_index.as_deref().into_iter().chain(self.array_path.as_deref().unwrap_or(&[])).collect()
+    }
+
     pub fn print(&self) {
+        print!("{}", self);
+    }
+}
+
+// How println shows a value, str(x) gives the same text
+impl fmt::Display for AST {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.ast_type {
-            Ast_Type::AST_STRING => print!("{}", self.string_value.as_ref().unwrap()),
-            Ast_Type::AST_INT => print!("{}", self.int_value.unwrap()),
-            Ast_Type::AST_FLOAT => print!("{:.precision$}", self.float_value.unwrap(), precision = self.past_decimal.unwrap_or(2) as usize),
-            Ast_Type::AST_BOOL => print!("{}", if self.bool_value.unwrap() { "true" } else { "false" }),
-            Ast_Type::AST_VARIABLE_DEF => {
-                while let Some(ast) = self.variable_definition_value.as_ref() {
-                    ast.print();
-                }
-            } 
+            Ast_Type::AST_STRING => write!(f, "{}", self.string_value.as_ref().unwrap()),
+            Ast_Type::AST_INT => write!(f, "{}", self.int_value.unwrap()),
+            Ast_Type::AST_FLOAT => write!(f, "{:.precision$}", self.float_value.unwrap(), precision = self.past_decimal.unwrap_or(2) as usize),
+            Ast_Type::AST_BOOL => write!(f, "{}", if self.bool_value.unwrap() { "true" } else { "false" }),
+            Ast_Type::AST_VARIABLE_DEF => match self.variable_definition_value.as_ref() {
+                Some(ast) => write!(f, "{}", ast),
+                None => Ok(()),
+            },
             Ast_Type::AST_VARIABLE => {
                 let t = self.scope.as_ref().unwrap().borrow().get_variable_definition(self.variable_name.as_ref().unwrap());
-                t.unwrap().print(); 
-            }
-            Ast_Type::AST_RETURN => {
-                self.return_value.as_ref().unwrap().print();
+                write!(f, "{}", t.unwrap())
             }
+            Ast_Type::AST_ERROR => write!(f, "{}: {}", self.error_kind.as_ref().unwrap(), self.string_value.as_ref().unwrap()),
+            Ast_Type::AST_RETURN => write!(f, "{}", self.return_value.as_ref().unwrap()),
             Ast_Type::AST_ARRAY_DEF => {
-                print!("[");
-
-                if let Some(elements) = &self.array_elements {
-                    for (i, element) in elements.iter().enumerate() {
-                        element.print();
-                        if i < elements.len() -1 {
-                            print!(", ");
-                        }
+                write!(f, "[")?;
+
+                let elements = self.array_elements.as_deref().unwrap_or(&[]);
+
+                for (i, element) in elements.iter().enumerate() {
+                    write!(f, "{}", element)?;
+                    if i < elements.len() - 1 {
+                        write!(f, ", ")?;
+                    }
+                }
+
+                write!(f, "]")
+            }
+            Ast_Type::AST_MAP_DEF => {
+                write!(f, "{{")?;
+
+                let keys = self.map_keys.as_deref().unwrap_or(&[]);
+                let values = self.map_values.as_deref().unwrap_or(&[]);
+
+                for (i, (key, value)) in keys.iter().zip(values).enumerate() {
+                    write!(f, "{}: {}", key, value)?;
+                    if i < keys.len() - 1 {
+                        write!(f, ", ")?;
                     }
                 }
-                print!("]");
+
+                write!(f, "}}")
             }
-            _ => println!("<unhandled type>, {:#?}", self.ast_type),
+            _ => writeln!(f, "<unhandled type>, {:#?}", self.ast_type),
         }
     }
 }
+
+// Name of the hidden variable with_stored_indices reads the index at position i from
+pub fn stored_index(i: usize) -> String {
+    format!("compound index {}", i)
+}
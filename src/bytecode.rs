@@ -0,0 +1,252 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{AST, Ast_Type, Data_Type};
+use crate::error::IronError;
+use crate::span::Span;
+
+// Where a variable lives once the compiler has resolved its name
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Slot {
+    Local(usize),  // offset into the current frame
+    Global(usize), // index into the globals table
+    Field(usize),  // field of the instance a method was called on
+}
+
+// Every operand is an index, so ops stay Copy and the vm never clones them
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Op {
+    Constant(usize),
+    Nil,
+    Pop,
+
+    Define(Slot), // leaves the value on the stack
+    Get(Slot),
+    Set(Slot), // leaves the value on the stack
+    Increment(Slot),
+    Decrement(Slot),
+    Widen, // an int on top becomes a float, for a value going into a variable declared float
+
+    Array(usize, usize),   // collects that many values, the constant is an empty array of the element type
+    Fill(usize),           // pops the fill value then the size, the constant is like Array's
+    Map(usize, usize),     // collects that many key and value pairs, the constant is an empty map of the type
+    GetIndex(Slot, usize), // the usize is a constant holding the array name
+    Index(usize),          // indexes into the value under the index, for grid[i][j]
+    SetIndex(Slot, usize, usize), // the last usize is how many indices are under the value
+    SetPropertyIndex(usize, usize, usize), // same for p.items[i] = v, the first usize is the field and the instance is under the indices
+    Slice(Slot, usize),    // pops the end then the start
+    Iter,                  // turns what a for-each goes over into an array, chars for a string and keys for a map
+    Range,                 // checks the start, end and step under it are ints and the step isn't 0
+
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Greater,
+    Less,
+    GreaterEqual,
+    LessEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Negate,
+    Not,
+    BitNot,
+
+    Jump(usize),
+    JumpIfFalse(usize), // pops the condition
+    SkipAnd(usize),     // when the left side of && is false it's the answer, so it's left as a bool and we jump past the right
+    SkipOr(usize),      // same for || when the left side is true
+
+    Call(usize, usize),               // function, argument count
+    CallBuiltin(usize, usize),        // constant with the name, argument count
+    CallLibrary(usize, usize, usize), // module constant, function constant, argument count
+    Invoke(usize, usize),             // method name constant, argument count, receiver sits under the args
+    InvokeSlot(Slot, usize, usize),   // same but on a variable, so array methods can change it
+    InvokeProperty(usize, usize, usize), // same but on a field, the instance sits under the args
+    New(usize, usize),                // class, argument count
+    GetProperty(usize),
+    SetProperty(usize),
+
+    PushHandler(usize), // where to jump when an error is raised
+    PopHandler,
+    MatchError(usize), // pushes whether the error on top is of the kind in the constant
+    IsInstance(usize), // pops a value and pushes whether it's an instance of the class named in the constant
+    Throw,
+    Rethrow,
+
+    Return,
+    MissingReturn, // the body of a function with a return type ran out
+}
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Nil,
+    Int(i64),
+    Float(f64, Option<i32>), // a float literal keeps the decimals it was written with for printing
+    Bool(bool),
+    Str(Rc<str>),
+    Char(char),
+    Array(Rc<Array>), // copied on write, so arrays still behave like values
+    Map(Rc<Map>),     // same as arrays
+    Instance(Rc<RefCell<Instance>>),
+    Error(Rc<IronError>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Array {
+    pub element: Data_Type, // what push and insert let in
+    pub items: Vec<Value>,
+}
+
+// Keys and values side by side, in the order the keys were added
+#[derive(Clone, Debug)]
+pub struct Map {
+    pub key: Data_Type,
+    pub value: Data_Type,
+    pub keys: Vec<Value>,
+    pub values: Vec<Value>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Instance {
+    pub class: usize,
+    pub fields: Vec<Value>,
+}
+
+impl Value {
+    pub fn array(element: Data_Type, items: Vec<Value>) -> Value {
+        Value::Array(Rc::new(Array { element, items }))
+    }
+
+    pub fn map(key: Data_Type, value: Data_Type, keys: Vec<Value>, values: Vec<Value>) -> Value {
+        Value::Map(Rc::new(Map { key, value, keys, values }))
+    }
+
+    pub fn from_ast(node: &AST) -> Option<Value> {
+        match node.ast_type {
+            Ast_Type::AST_INT => Some(Value::Int(node.int_value?)),
+            Ast_Type::AST_FLOAT => Some(Value::Float(node.float_value?, node.past_decimal)),
+            Ast_Type::AST_BOOL => Some(Value::Bool(node.bool_value?)),
+            Ast_Type::AST_STRING if node.data_type == Data_Type::CHAR => Some(Value::Char(node.string_value.as_ref()?.chars().next()?)),
+            Ast_Type::AST_STRING => Some(Value::Str(node.string_value.as_deref()?.into())),
+            _ => None,
+        }
+    }
+
+    // Going back to an AST lets the vm print values exactly like the visitor does
+    pub fn to_ast(&self) -> AST {
+        match self {
+            Value::Nil => AST::new(Ast_Type::AST_NOOP),
+            Value::Int(i) => {
+                let mut node = AST::new(Ast_Type::AST_INT);
+                node.int_value = Some(*i);
+                node.int_init = Some(true);
+                node.data_type = Data_Type::INT;
+                node
+            }
+            Value::Float(f, precision) => {
+                let mut node = AST::new(Ast_Type::AST_FLOAT);
+                node.float_value = Some(*f);
+                node.float_init = Some(true);
+                node.past_decimal = *precision;
+                node.data_type = Data_Type::FLOAT;
+                node
+            }
+            Value::Bool(b) => AST::from_bool(*b),
+            Value::Str(s) => {
+                let mut node = AST::new(Ast_Type::AST_STRING);
+                node.string_value = Some(s.to_string());
+                node.data_type = Data_Type::STR;
+                node
+            }
+            Value::Char(c) => AST::from_char(*c),
+            Value::Array(array) => {
+                let mut node = AST::new(Ast_Type::AST_ARRAY_DEF);
+                node.array_elements = Some(array.items.iter().map(|e| e.to_ast()).collect());
+                node.data_type = array.element.clone();
+                node
+            }
+            Value::Map(map) => {
+                let mut node = AST::new(Ast_Type::AST_MAP_DEF);
+                node.map_keys = Some(map.keys.iter().map(|k| k.to_ast()).collect());
+                node.map_values = Some(map.values.iter().map(|v| v.to_ast()).collect());
+                node.map_evaluated = true;
+                node.data_type = Data_Type::MAP(Box::new(map.key.clone()), Box::new(map.value.clone()));
+                node
+            }
+            Value::Instance(_) => AST::new(Ast_Type::AST_CLASS_INSTANCE),
+            Value::Error(e) => AST::from_error(e.kind(), e.message()),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "void",
+            Value::Int(_) => "int",
+            Value::Float(..) => "float",
+            Value::Bool(_) => "bool",
+            Value::Str(_) => "str",
+            Value::Char(_) => "char",
+            Value::Array(_) => "array",
+            Value::Map(_) => "map",
+            Value::Instance(_) => "instance",
+            Value::Error(_) => "Error",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub spans: Vec<Option<Span>>, // one per op, for error messages
+    pub constants: Vec<Value>,
+}
+
+impl Chunk {
+    pub fn emit(&mut self, op: Op, span: &Option<Span>) -> usize {
+        self.code.push(op);
+        self.spans.push(span.clone());
+        self.code.len() - 1
+    }
+
+    pub fn constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Data_Type>,
+    pub is_method: bool, // slot 0 holds the instance, params start at 1
+    pub returns: Option<Data_Type>,
+    pub locals: usize,
+    pub chunk: Chunk,
+}
+
+#[derive(Clone, Debug)]
+pub struct Class {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub methods: HashMap<String, usize>,
+    pub constructor: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub classes: Vec<Class>,
+    pub globals: Vec<String>,
+    pub main: usize,
+}
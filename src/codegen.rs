@@ -0,0 +1,529 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::process::Command;
+
+use crate::ast::Data_Type;
+use crate::bytecode::{Function, Op, Program, Slot, Value};
+use crate::error::{IronError, IronResult};
+use crate::span::Span;
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+/*
+    Turns a compiled program into C. The compiler has already resolved
+    every name, so each function becomes a C function working on the
+    same value stack as the vm, jumps become gotos and a try becomes a
+    setjmp. The runtime it gets pasted after lives in runtime.c
+*/
+pub struct CodeGen<'a> {
+    program: &'a Program,
+    out: String,
+    spans: Vec<String>,
+    span_ids: HashMap<String, usize>,
+    sources: Vec<(String, String)>,
+    strings: Vec<String>,
+    types: Vec<String>,
+    type_ids: HashMap<String, usize>,
+}
+
+impl<'a> CodeGen<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        CodeGen {
+            program,
+            out: String::new(),
+            spans: vec![],
+            span_ids: HashMap::new(),
+            sources: vec![],
+            strings: vec![],
+            types: vec![],
+            type_ids: HashMap::new(),
+        }
+    }
+
+    pub fn generate(mut self) -> String {
+        let program = self.program;
+        let mut bodies = String::new();
+
+        for (index, function) in program.functions.iter().enumerate() {
+            self.function(index, function);
+            bodies.push_str(&std::mem::take(&mut self.out));
+        }
+
+        let mut out = String::from(RUNTIME);
+        out.push_str("\n/* The program */\n\n");
+
+        for index in 0..self.program.functions.len() {
+            writeln!(out, "static void fn_{}(void);", index).ok();
+        }
+        out.push('\n');
+
+        let mut tables = String::new();
+        self.tables(&mut tables);
+
+        for info in &self.types {
+            out.push_str(info);
+        }
+        out.push('\n');
+
+        out.push_str(&tables);
+
+        for string in &self.strings {
+            out.push_str(string);
+        }
+        out.push('\n');
+
+        out.push_str(&bodies);
+
+        writeln!(out, "int main(void) {{").ok();
+        writeln!(out, "    fn_{}();", self.program.main).ok();
+        writeln!(out, "    fflush(stdout);").ok();
+        writeln!(out, "    return 0;").ok();
+        writeln!(out, "}}").ok();
+
+        out
+    }
+
+    fn tables(&mut self, out: &mut String) {
+        for (index, function) in self.program.functions.iter().enumerate() {
+            let params: Vec<String> = function.params.iter().map(|param| self.param_info(param)).collect();
+
+            if !params.is_empty() {
+                writeln!(out, "static const ParamInfo params_{}[] = {{ {} }};", index, params.join(", ")).ok();
+            }
+
+            if let Some(returns) = &function.returns {
+                writeln!(out, "static const ParamInfo returns_{} = {};", index, self.param_info(returns)).ok();
+            }
+        }
+
+        writeln!(out, "\nconst FnInfo functions[] = {{").ok();
+        for (index, function) in self.program.functions.iter().enumerate() {
+            let params = if function.params.is_empty() { "NULL".to_string() } else { format!("params_{}", index) };
+            let returns = match &function.returns {
+                Some(_) => format!("&returns_{}", index),
+                None => "NULL".to_string(),
+            };
+
+            writeln!(
+                out, "    {{ {}, {}, {}, {}, fn_{} }},",
+                c_string(&function.name), function.params.len(), params, returns, index
+            ).ok();
+        }
+        writeln!(out, "}};\n").ok();
+
+        for (index, class) in self.program.classes.iter().enumerate() {
+            // Sorted so the output doesn't depend on hash order
+            let mut methods: Vec<(&String, &usize)> = class.methods.iter().collect();
+            methods.sort();
+
+            let fields: Vec<String> = class.fields.iter().map(|f| c_string(f)).collect();
+            let names: Vec<String> = methods.iter().map(|(name, _)| c_string(name)).collect();
+            let fns: Vec<String> = methods.iter().map(|(_, f)| f.to_string()).collect();
+
+            writeln!(out, "static const char *const class_{}_fields[] = {{ {} }};", index, or_zero(fields, "NULL")).ok();
+            writeln!(out, "static const char *const class_{}_methods[] = {{ {} }};", index, or_zero(names, "NULL")).ok();
+            writeln!(out, "static const int class_{}_fns[] = {{ {} }};", index, or_zero(fns, "0")).ok();
+        }
+
+        writeln!(out, "\nconst ClassInfo classes[] = {{").ok();
+        for (index, class) in self.program.classes.iter().enumerate() {
+            writeln!(
+                out,
+                "    {{ {}, {}, class_{i}_fields, {}, class_{i}_methods, class_{i}_fns, {} }},",
+                c_string(&class.name),
+                class.fields.len(),
+                class.methods.len(),
+                class.constructor,
+                i = index
+            ).ok();
+        }
+        if self.program.classes.is_empty() {
+            writeln!(out, "    {{ NULL, 0, NULL, 0, NULL, NULL, 0 }},").ok();
+        }
+        writeln!(out, "}};\n").ok();
+
+        let globals = self.program.globals.len().max(1);
+        let names: Vec<String> = self.program.globals.iter().map(|g| c_string(g)).collect();
+
+        writeln!(out, "Value globals[{}];", globals).ok();
+        writeln!(out, "char globals_set[{}];", globals).ok();
+        writeln!(out, "const char *const global_names[] = {{ {} }};\n", or_zero(names, "NULL")).ok();
+
+        writeln!(out, "const SourceInfo sources[] = {{").ok();
+        for (name, text) in &self.sources {
+            writeln!(out, "    {{ {}, {} }},", c_string(name), c_string(text)).ok();
+        }
+        if self.sources.is_empty() {
+            writeln!(out, "    {{ NULL, NULL }},").ok();
+        }
+        writeln!(out, "}};\n").ok();
+
+        writeln!(out, "const SpanInfo spans[] = {{").ok();
+        for span in &self.spans {
+            writeln!(out, "    {},", span).ok();
+        }
+        if self.spans.is_empty() {
+            writeln!(out, "    {{ 0, 0, 0, 0 }},").ok();
+        }
+        writeln!(out, "}};\n").ok();
+    }
+
+    // A ParamInfo for types the ops need, made once each at file scope so an array's can point at what it holds
+    fn type_info(&mut self, t: &Data_Type) -> String {
+        let key = format!("{:?}", t);
+
+        if let Some(id) = self.type_ids.get(&key) {
+            return format!("&type_{}", id);
+        }
+
+        let info = self.param_info(t);
+        let id = self.types.len();
+
+        self.types.push(format!("static const ParamInfo type_{} = {};\n", id, info));
+        self.type_ids.insert(key, id);
+        format!("&type_{}", id)
+    }
+
+    fn param_info(&mut self, param: &Data_Type) -> String {
+        let debug = c_string(&format!("{:?}", param));
+        let name = c_string(&param.name());
+
+        match param {
+            Data_Type::INT => format!("{{ P_INT, NULL, {}, {}, NULL }}", debug, name),
+            Data_Type::FLOAT => format!("{{ P_FLOAT, NULL, {}, {}, NULL }}", debug, name),
+            Data_Type::STR => format!("{{ P_STR, NULL, {}, {}, NULL }}", debug, name),
+            Data_Type::CHAR => format!("{{ P_CHAR, NULL, {}, {}, NULL }}", debug, name),
+            Data_Type::BOOL => format!("{{ P_BOOL, NULL, {}, {}, NULL }}", debug, name),
+            Data_Type::CUSTOM(class) => format!("{{ P_CUSTOM, {}, {}, {}, NULL }}", c_string(class), debug, name),
+            Data_Type::ARRAY(element) => format!("{{ P_ARRAY, NULL, {}, {}, {} }}", debug, name, self.type_info(element)),
+            Data_Type::MAP(..) => format!("{{ P_MAP, NULL, {}, {}, NULL }}", debug, name),
+            Data_Type::VOID => format!("{{ P_VOID, NULL, {}, {}, NULL }}", debug, name),
+        }
+    }
+
+    // Index into the spans table, -1 when the op doesn't have one
+    fn span(&mut self, span: &Option<Span>) -> i64 {
+        let Some(span) = span else {
+            return -1;
+        };
+
+        let source = match self.sources.iter().position(|(name, _)| *name == span.source.name) {
+            Some(i) => i,
+            None => {
+                self.sources.push((span.source.name.clone(), span.source.text.clone()));
+                self.sources.len() - 1
+            }
+        };
+
+        let entry = format!("{{ {}, {}, {}, {} }}", source, span.line, span.column, span.len);
+
+        if let Some(id) = self.span_ids.get(&entry) {
+            return *id as i64;
+        }
+
+        self.spans.push(entry.clone());
+        self.span_ids.insert(entry, self.spans.len() - 1);
+        (self.spans.len() - 1) as i64
+    }
+
+    fn constant(&mut self, function: usize, index: usize, value: &Value) -> String {
+        match value {
+            Value::Nil => "nil_value()".to_string(),
+            Value::Int(i64::MIN) => "int_value(LLONG_MIN)".to_string(),
+            Value::Int(i) => format!("int_value({}LL)", i),
+            Value::Float(f, precision) => format!("float_bits(0x{:016x}ULL, {})", f.to_bits(), precision.unwrap_or(-1)),
+            Value::Bool(b) => format!("bool_value({})", *b as i32),
+            Value::Str(s) => {
+                let name = format!("K_{}_{}", function, index);
+                self.strings.push(format!("static Str {} = {{ {}, {} }};\n", name, s.len(), c_string(s)));
+                format!("str_value(&{})", name)
+            }
+            Value::Char(c) => {
+                let name = format!("K_{}_{}", function, index);
+                let s = c.to_string();
+                self.strings.push(format!("static Str {} = {{ {}, {} }};\n", name, s.len(), c_string(&s)));
+                format!("char_value(&{})", name)
+            }
+            Value::Error(e) => {
+                let span = self.span(e.span());
+                format!("error_value({}, {}, {})", c_string(e.kind()), c_string(e.message()), span)
+            }
+            // The compiler never makes these into constants
+            Value::Array(_) | Value::Map(_) | Value::Instance(_) => "nil_value()".to_string(),
+        }
+    }
+
+    fn function(&mut self, index: usize, function: &Function) {
+        let chunk = &function.chunk;
+
+        // Made on first use so names that are only operands don't end up as unused statics
+        let mut constants: HashMap<usize, String> = HashMap::new();
+
+        let names: Vec<String> = chunk.constants.iter()
+            .map(|value| match value {
+                Value::Str(s) => c_string(s),
+                _ => "\"\"".to_string(),
+            })
+            .collect();
+
+        // Only the ops something jumps to get a label
+        let targets: HashSet<usize> = chunk.code.iter()
+            .filter_map(|op| match op {
+                Op::Jump(t) | Op::JumpIfFalse(t) | Op::SkipAnd(t) | Op::SkipOr(t) | Op::PushHandler(t) => Some(*t),
+                _ => None,
+            })
+            .collect();
+
+        let args = function.params.len() + function.is_method as usize;
+
+        writeln!(self.out, "/* {} */", function.name.replace("*/", "* /")).ok();
+        writeln!(self.out, "static void fn_{}(void) {{", index).ok();
+        writeln!(self.out, "    Value *L = frame_enter({}, {});", function.locals, args).ok();
+
+        for (ip, op) in chunk.code.iter().enumerate() {
+            let span = self.span(&chunk.spans[ip]);
+
+            if targets.contains(&ip) {
+                writeln!(self.out, "L_{}:;", ip).ok();
+            }
+
+            let line = match *op {
+                Op::Constant(i) => {
+                    let value = match constants.get(&i) {
+                        Some(value) => value.clone(),
+                        None => self.constant(index, i, &chunk.constants[i]),
+                    };
+                    constants.insert(i, value.clone());
+                    format!("push({});", value)
+                }
+                Op::Nil => "push(nil_value());".to_string(),
+                Op::Pop => "sp--;".to_string(),
+
+                Op::Define(slot) => store(slot, "own(peek())", true, span),
+                Op::Get(slot) => format!("push({});", load(slot, span)),
+                Op::Set(slot) => format!("stack[sp - 1] = assigned({}, peek()); {}", current(slot), store(slot, "own(peek())", false, span)),
+                Op::Widen => "stack[sp - 1] = widen(peek());".to_string(),
+                Op::Increment(slot) | Op::Decrement(slot) => {
+                    let by = if matches!(op, Op::Increment(_)) { 1 } else { -1 };
+                    format!("{{ Value v = step({}, {}, {}); {} push(v); }}", load(slot, span), by, span, store(slot, "v", false, span))
+                }
+
+                Op::Array(n, element) => match &chunk.constants[element] {
+                    Value::Array(empty) => format!("push(array_from_stack({}, {}));", n, self.type_info(&empty.element)),
+                    _ => unreachable!("Array op without an element type"),
+                },
+                Op::Map(n, map) => match &chunk.constants[map] {
+                    Value::Map(empty) => format!(
+                        "push(map_from_stack({}, {}, {}));",
+                        n, self.type_info(&empty.key), self.type_info(&empty.value)
+                    ),
+                    _ => unreachable!("Map op without a map type"),
+                },
+                Op::GetIndex(slot, name) => format!(
+                    "{{ Value index = pop(); push(index_get({}, index, {}, {})); }}",
+                    load(slot, span), names[name], span
+                ),
+                Op::Slice(slot, name) => format!(
+                    "{{ Value end = pop(); Value start = pop(); push(slice({}, start, end, {}, {})); }}",
+                    load(slot, span), names[name], span
+                ),
+                Op::SetPropertyIndex(field, name, depth) => {
+                    let mut walk = format!("property_ref(stack[sp - 1], {}, {})", names[field], span);
+                    for k in 0..depth - 1 {
+                        walk = format!("index_ref({}, stack[sp + {}], {}, {})", walk, k, names[name], span);
+                    }
+                    format!(
+                        "{{ Value v = pop(); sp -= {}; index_set({}, stack[sp + {}], v, {}, {}); sp--; push(v); }}",
+                        depth, walk, depth - 1, names[name], span
+                    )
+                }
+                Op::SetIndex(slot, name, depth) => {
+                    let mut walk = reference(slot, span);
+                    for k in 0..depth - 1 {
+                        walk = format!("index_ref({}, stack[sp + {}], {}, {})", walk, k, names[name], span);
+                    }
+                    format!(
+                        "{{ Value v = pop(); sp -= {}; index_set({}, stack[sp + {}], v, {}, {}); push(v); }}",
+                        depth, walk, depth - 1, names[name], span
+                    )
+                }
+                Op::Index(name) => format!("{{ Value index = pop(); push(index_get(pop(), index, {}, {})); }}", names[name], span),
+                Op::Fill(element) => match &chunk.constants[element] {
+                    Value::Array(empty) => format!(
+                        "{{ Value v = pop(); Value size = pop(); push(array_fill(size, v, {}, {})); }}",
+                        self.type_info(&empty.element), span
+                    ),
+                    _ => unreachable!("Fill op without an element type"),
+                },
+
+                Op::IsInstance(class) => format!("push(bool_value(is_instance(pop(), {})));", names[class]),
+                Op::Iter => format!("push(iter_value(pop(), {}));", span),
+                Op::Range => format!("range_check({});", span),
+                Op::Add => binary("OP_ADD", span),
+                Op::Subtract => binary("OP_SUB", span),
+                Op::Multiply => binary("OP_MUL", span),
+                Op::Divide => binary("OP_DIV", span),
+                Op::Modulo => binary("OP_MOD", span),
+                Op::Power => binary("OP_POW", span),
+                Op::BitAnd => binary("OP_BIT_AND", span),
+                Op::BitOr => binary("OP_BIT_OR", span),
+                Op::BitXor => binary("OP_BIT_XOR", span),
+                Op::ShiftLeft => binary("OP_SHL", span),
+                Op::ShiftRight => binary("OP_SHR", span),
+                Op::Greater => binary("OP_GT", span),
+                Op::Less => binary("OP_LT", span),
+                Op::GreaterEqual => binary("OP_GE", span),
+                Op::LessEqual => binary("OP_LE", span),
+                Op::Equal => binary("OP_EQ", span),
+                Op::NotEqual => binary("OP_NE", span),
+                Op::And => binary("OP_AND", span),
+                Op::Or => binary("OP_OR", span),
+                Op::Negate => format!("push(negate(pop(), {}));", span),
+                Op::Not => format!("push(not_value(pop(), {}));", span),
+                Op::BitNot => format!("push(bit_not(pop(), {}));", span),
+
+                Op::Jump(target) => format!("goto L_{};", target),
+                Op::JumpIfFalse(target) => format!("if (!condition(pop(), {})) goto L_{};", span, target),
+                Op::SkipAnd(target) => format!("if (decides(0, {})) goto L_{};", span, target),
+                Op::SkipOr(target) => format!("if (decides(1, {})) goto L_{};", span, target),
+
+                Op::Call(f, argc) => format!("call({}, {}, {});", f, argc, span),
+                Op::CallBuiltin(name, argc) => {
+                    match names[name].as_str() {
+                        "\"print\"" | "\"println\"" => format!("print_args({}, {});", argc, (names[name] == "\"println\"") as i32),
+                        to => format!("convert({}, {}, {});", to, argc, span),
+                    }
+                }
+                Op::CallLibrary(module, f, argc) => format!(
+                    "push(call_library({}, {}, {}, {}));",
+                    names[module], names[f], argc, span
+                ),
+                Op::InvokeProperty(field, name, argc) => {
+                    format!("invoke_property({}, {}, {}, {});", names[field], names[name], argc, span)
+                }
+                Op::Invoke(name, argc) => format!("invoke({}, {}, {});", names[name], argc, span),
+                Op::InvokeSlot(slot, name, argc) => format!("invoke_at({}, {}, {}, {});", reference(slot, span), names[name], argc, span),
+                Op::New(class, argc) => format!("new_instance({}, {}, {});", class, argc, span),
+                Op::GetProperty(name) => format!("push(get_property(pop(), {}, {}));", names[name], span),
+                Op::SetProperty(name) => format!(
+                    "{{ Value v = pop(); set_property(pop(), {}, v, {}); push(v); }}",
+                    names[name], span
+                ),
+
+                Op::PushHandler(target) => format!("if (setjmp(*handler_push())) goto L_{};", target),
+                Op::PopHandler => "handler_pop();".to_string(),
+                Op::MatchError(kind) => format!("push(bool_value(match_error(peek(), {})));", names[kind]),
+                Op::Throw => format!("throw_value(pop(), {});", span),
+                Op::Rethrow => format!("rethrow(pop(), {});", span),
+
+                // The value stays on the stack for the caller
+                Op::Return if function.returns.is_some() => format!("check_return({}, {}); frame_leave(L); return;", index, span),
+                Op::Return => "frame_leave(L); return;".to_string(),
+                Op::MissingReturn => format!("missing_return({}, {});", index, span),
+            };
+
+            writeln!(self.out, "    {}", line).ok();
+        }
+
+        if targets.contains(&chunk.code.len()) {
+            writeln!(self.out, "L_{}:;", chunk.code.len()).ok();
+        }
+
+        writeln!(self.out, "}}\n").ok();
+    }
+}
+
+fn load(slot: Slot, span: i64) -> String {
+    match slot {
+        Slot::Local(i) => format!("L[{}]", i),
+        Slot::Global(i) => format!("gget({}, {})", i, span),
+        Slot::Field(i) => format!("L[0].as.o->fields[{}]", i),
+    }
+}
+
+// What the slot holds right now without checking it's defined, an undefined global is nil
+fn current(slot: Slot) -> String {
+    match slot {
+        Slot::Global(i) => format!("globals[{}]", i),
+        slot => load(slot, 0),
+    }
+}
+
+fn reference(slot: Slot, span: i64) -> String {
+    match slot {
+        Slot::Local(i) => format!("&L[{}]", i),
+        Slot::Global(i) => format!("gref({}, {})", i, span),
+        Slot::Field(i) => format!("&L[0].as.o->fields[{}]", i),
+    }
+}
+
+fn store(slot: Slot, value: &str, define: bool, span: i64) -> String {
+    match slot {
+        Slot::Local(i) => format!("L[{}] = {};", i, value),
+        Slot::Global(i) if define => format!("gdefine({}, {});", i, value),
+        Slot::Global(i) => format!("gassign({}, {}, {});", i, value, span),
+        Slot::Field(i) => format!("L[0].as.o->fields[{}] = {};", i, value),
+    }
+}
+
+fn binary(op: &str, span: i64) -> String {
+    format!("{{ Value r = pop(); Value l = pop(); push(binary({}, l, r, {})); }}", op, span)
+}
+
+// C doesn't allow empty initializers
+fn or_zero(items: Vec<String>, zero: &str) -> String {
+    if items.is_empty() {
+        zero.to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+// Anything outside printable ascii is written as an octal escape so the bytes come through untouched
+fn c_string(s: &str) -> String {
+    let mut out = String::from("\"");
+
+    for byte in s.bytes() {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'?' => out.push_str("\\?"), // no trigraphs
+            0x20..=0x7e => out.push(byte as char),
+            _ => write!(out, "\\{:03o}", byte).unwrap_or(()),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+// Writes the C to a temporary file and hands it to the system compiler, $CC overrides which one
+pub fn build(program: &Program, output: &str) -> IronResult<()> {
+    let source = CodeGen::new(program).generate();
+
+    let path = env::temp_dir().join(format!("iron-{}.c", std::process::id()));
+    fs::write(&path, source)
+        .map_err(|e| IronError::RuntimeError(format!("Could not write {}: {}", path.display(), e), None))?;
+
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    let status = Command::new(&cc)
+        .arg("-O2")
+        .arg("-o")
+        .arg(output)
+        .arg(&path)
+        .arg("-lm")
+        .status();
+
+    fs::remove_file(&path).ok();
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(_) => Err(IronError::RuntimeError(format!("{} failed to build {}", cc, output), None)),
+        Err(e) => Err(IronError::RuntimeError(format!("Could not run {}: {}", cc, e), None)),
+    }
+}
@@ -0,0 +1,1332 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{AST, Ast_Type, Data_Type, stored_index};
+use crate::bytecode::{Chunk, Class, Function, Op, Program, Slot, Value};
+use crate::error::{IronError, IronResult};
+use crate::scope::Scope;
+use crate::span::Span;
+use crate::token::Types;
+
+struct Local {
+    name: String,
+    depth: usize,
+    slot: usize,
+}
+
+struct Loop {
+    label: Option<String>,
+    breaks: Vec<usize>,    // jumps to patch once we know where the loop ends
+    continues: Vec<usize>, // same for where the next time around starts
+    tries: usize,          // how many trys were open when the loop started
+}
+
+// Everything we need while compiling one function body
+struct Context {
+    function: usize,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    slots: usize,
+    depth: usize,
+    class: Option<usize>,
+    is_script: bool,
+    loops: Vec<Loop>,
+    finally: Vec<Option<AST>>, // the finally of every try we are inside, innermost last
+}
+
+impl Context {
+    fn new(function: usize, class: Option<usize>, is_script: bool) -> Self {
+        Context {
+            function,
+            chunk: Chunk::default(),
+            locals: vec![],
+            slots: 0,
+            depth: 0,
+            class,
+            is_script,
+            loops: vec![],
+            finally: vec![],
+        }
+    }
+}
+
+/*
+    Turns the AST the parser hands back into a Program for the vm,
+    names are resolved here so the vm only ever deals with slots and indexes
+*/
+pub struct Compiler {
+    pub program: Program,
+    functions: HashMap<String, usize>,
+    classes: HashMap<String, usize>,
+    globals: HashMap<String, usize>,
+    modules: HashMap<String, Option<HashMap<String, usize>>>, // None for built in libraries like math
+    contexts: Vec<Context>,
+    span: Option<Span>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            program: Program::default(),
+            functions: HashMap::new(),
+            classes: HashMap::new(),
+            globals: HashMap::new(),
+            modules: HashMap::new(),
+            contexts: vec![],
+            span: None,
+        }
+    }
+
+    pub fn compile(mut self, ast: &AST) -> IronResult<Program> {
+        let main = self.new_function("<script>", vec![], false);
+
+        self.contexts.push(Context::new(main, None, true));
+        self.block(ast)?;
+        self.emit(Op::Nil);
+        self.emit(Op::Return);
+        self.finish();
+
+        self.program.main = main;
+        Ok(self.program)
+    }
+
+    fn ctx(&mut self) -> &mut Context {
+        self.contexts.last_mut().expect("No function being compiled")
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let span = self.span.clone();
+        self.ctx().chunk.emit(op, &span)
+    }
+
+    // For errors that belong to part of a node, like one index of grid[i][j]
+    fn emit_at(&mut self, op: Op, node: &AST) -> usize {
+        let span = node.span.clone().or_else(|| self.span.clone());
+        self.ctx().chunk.emit(op, &span)
+    }
+
+    fn constant(&mut self, value: Value) -> usize {
+        self.ctx().chunk.constant(value)
+    }
+
+    fn name(&mut self, name: &str) -> usize {
+        self.constant(Value::Str(name.into()))
+    }
+
+    fn here(&mut self) -> usize {
+        self.ctx().chunk.code.len()
+    }
+
+    // Points a jump emitted earlier at the next op
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+
+        self.ctx().chunk.code[at] = match self.ctx().chunk.code[at] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+            Op::SkipAnd(_) => Op::SkipAnd(target),
+            Op::SkipOr(_) => Op::SkipOr(target),
+            Op::PushHandler(_) => Op::PushHandler(target),
+            op => op,
+        };
+    }
+
+    fn new_function(&mut self, name: &str, params: Vec<Data_Type>, is_method: bool) -> usize {
+        self.program.functions.push(Function {
+            name: name.to_string(),
+            params,
+            is_method,
+            returns: None,
+            locals: 0,
+            chunk: Chunk::default(),
+        });
+
+        self.program.functions.len() - 1
+    }
+
+    fn finish(&mut self) {
+        let ctx = self.contexts.pop().expect("No function being compiled");
+        let function = &mut self.program.functions[ctx.function];
+
+        function.chunk = ctx.chunk;
+        function.locals = ctx.slots;
+    }
+
+    fn error(&self, message: String) -> IronError {
+        IronError::ParseError(message, self.span.clone())
+    }
+
+    fn global(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.globals.get(name) {
+            return index;
+        }
+
+        self.program.globals.push(name.to_string());
+        self.globals.insert(name.to_string(), self.program.globals.len() - 1);
+        self.program.globals.len() - 1
+    }
+
+    fn begin_scope(&mut self) {
+        self.ctx().depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let ctx = self.ctx();
+        ctx.depth -= 1;
+
+        let depth = ctx.depth;
+        ctx.locals.retain(|l| l.depth <= depth);
+    }
+
+    // A value going into a variable declared float is one, even when it was worked out as an int
+    fn widen(&mut self, def: &AST) {
+        if def.variable_type == Some(Data_Type::FLOAT) {
+            self.emit(Op::Widen);
+        }
+    }
+
+    // Top level variables of the script are globals, everything else gets a slot in its frame
+    fn declare(&mut self, name: &str) -> Slot {
+        if self.ctx().is_script && self.ctx().depth == 0 {
+            return Slot::Global(self.global(name));
+        }
+
+        let ctx = self.ctx();
+
+        // Declaring the same name twice in a scope just overwrites it like the visitor does
+        if let Some(local) = ctx.locals.iter().rev().find(|l| l.name == name && l.depth == ctx.depth) {
+            return Slot::Local(local.slot);
+        }
+
+        let slot = ctx.slots;
+        ctx.slots += 1;
+        ctx.locals.push(Local {
+            name: name.to_string(),
+            depth: ctx.depth,
+            slot,
+        });
+
+        Slot::Local(slot)
+    }
+
+    fn resolve(&mut self, name: &str) -> Slot {
+        let ctx = self.contexts.last().expect("No function being compiled");
+
+        if let Some(local) = ctx.locals.iter().rev().find(|l| l.name == name) {
+            return Slot::Local(local.slot);
+        }
+
+        if let Some(class) = ctx.class {
+            if let Some(field) = self.program.classes[class].fields.iter().position(|f| f == name) {
+                return Slot::Field(field);
+            }
+        }
+
+        Slot::Global(self.global(name))
+    }
+
+    fn is_module(&self, name: &str) -> bool {
+        let shadowed = self.contexts.last().is_some_and(|ctx| ctx.locals.iter().any(|l| l.name == name));
+        !shadowed && self.modules.contains_key(name)
+    }
+
+    // Blocks don't open a scope, same as the visitor where if and while bodies share their parent's
+    fn block(&mut self, node: &AST) -> IronResult<()> {
+        if node.ast_type != Ast_Type::AST_COMPOUND {
+            return self.statement(node);
+        }
+
+        let statements = node.compound_value.as_deref().unwrap_or(&[]);
+
+        // Functions can be called before the line they're defined on
+        for stmt in statements {
+            if stmt.ast_type == Ast_Type::AST_FUNCTION_DEF {
+                let name = stmt.function_definition_name.clone().unwrap();
+
+                if !self.functions.contains_key(&name) {
+                    let index = self.new_function(&name, vec![], false);
+                    self.functions.insert(name, index);
+                }
+            }
+        }
+
+        for stmt in statements {
+            self.statement(stmt)?;
+        }
+
+        Ok(())
+    }
+
+    fn statement(&mut self, node: &AST) -> IronResult<()> {
+        let saved = self.span.clone();
+
+        if node.span.is_some() {
+            self.span = node.span.clone();
+        }
+
+        let result = self.compile_statement(node);
+
+        self.span = saved;
+        result
+    }
+
+    fn compile_statement(&mut self, node: &AST) -> IronResult<()> {
+        match node.ast_type {
+            Ast_Type::AST_VARIABLE_DEF => {
+                match node.variable_definition_value.as_ref() {
+                    Some(value) => {
+                        self.expression(value)?;
+                        self.widen(node);
+                    }
+                    None => {
+                        self.emit(Op::Nil);
+                    }
+                }
+
+                let slot = self.declare(node.variable_definition_variable_name.as_ref().unwrap());
+                self.emit(Op::Define(slot));
+                self.emit(Op::Pop);
+            }
+            Ast_Type::AST_ARRAY_DEF => {
+                self.expression(node)?;
+
+                let slot = self.declare(node.array_name.as_ref().unwrap());
+                self.emit(Op::Define(slot));
+                self.emit(Op::Pop);
+            }
+            // P a = new P(1), P b = a and P c = make() all tag the term on the right with the name it's bound to
+            _ if node.variable_definition_variable_name.is_some() && node.class_name.is_some() => {
+                self.expression(node)?;
+
+                let slot = self.declare(node.variable_definition_variable_name.as_ref().unwrap());
+                self.emit(Op::Define(slot));
+                self.emit(Op::Pop);
+            }
+            Ast_Type::AST_CLASS_INSTANCE => {
+                self.expression(node)?;
+                self.emit(Op::Pop);
+            }
+            Ast_Type::AST_FUNCTION_DEF => {
+                let name = node.function_definition_name.clone().unwrap();
+
+                let index = match self.functions.get(&name) {
+                    Some(&index) => index,
+                    None => {
+                        let index = self.new_function(&name, vec![], false);
+                        self.functions.insert(name, index);
+                        index
+                    }
+                };
+
+                self.function(index, node, None)?;
+            }
+            Ast_Type::AST_CLASS_DEF => self.class(node)?,
+            Ast_Type::AST_COMPOUND => self.block(node)?,
+            Ast_Type::AST_IF => {
+                self.expression(node.if_condition.as_ref().unwrap())?;
+                let skip = self.emit(Op::JumpIfFalse(0));
+
+                self.block(node.if_body.as_ref().unwrap())?;
+
+                if let Some(else_body) = node.else_body.as_ref() {
+                    let end = self.emit(Op::Jump(0));
+                    self.patch(skip);
+                    self.block(else_body)?;
+                    self.patch(end);
+                }
+                else {
+                    self.patch(skip);
+                }
+            }
+            Ast_Type::AST_WHILE => {
+                let start = self.here();
+
+                self.expression(node.while_condition.as_ref().unwrap())?;
+                let exit = self.emit(Op::JumpIfFalse(0));
+
+                self.begin_loop(node);
+                self.block(node.while_body.as_ref().unwrap())?;
+                self.continue_here();
+                self.emit(Op::Jump(start));
+
+                self.patch(exit);
+                self.end_loop();
+            }
+            Ast_Type::AST_FOR => {
+                self.begin_scope();
+                self.statement(node.for_init.as_ref().unwrap())?;
+
+                let start = self.here();
+
+                self.expression(node.for_condition.as_ref().unwrap())?;
+                let exit = self.emit(Op::JumpIfFalse(0));
+
+                self.begin_loop(node);
+                self.block(node.for_body.as_ref().unwrap())?;
+                self.continue_here();
+                self.statement(node.for_increment.as_ref().unwrap())?;
+                self.emit(Op::Jump(start));
+
+                self.patch(exit);
+                self.end_loop();
+                self.end_scope();
+            }
+            Ast_Type::AST_FOR_EACH => self.for_each(node)?,
+            Ast_Type::AST_MATCH => self.match_statement(node)?,
+            Ast_Type::AST_RETURN => {
+                match node.return_value.as_ref() {
+                    Some(value) => self.expression(value)?,
+                    None => {
+                        self.emit(Op::Nil);
+                    }
+                }
+
+                self.leave_trys(0)?;
+                self.emit(Op::Return);
+            }
+            Ast_Type::AST_BREAK | Ast_Type::AST_CONTINUE => {
+                let keyword = if node.ast_type == Ast_Type::AST_BREAK { "break" } else { "continue" };
+
+                let target = match node.loop_label.as_ref() {
+                    Some(label) => self.ctx().loops.iter().rposition(|l| l.label.as_ref() == Some(label)),
+                    None => self.ctx().loops.len().checked_sub(1),
+                };
+
+                let Some(target) = target else {
+                    return Err(self.error(format!("{} used outside of a loop", keyword)));
+                };
+
+                let tries = self.ctx().loops[target].tries;
+                self.leave_trys(tries)?;
+
+                let jump = self.emit(Op::Jump(0));
+                let l = &mut self.ctx().loops[target];
+
+                if node.ast_type == Ast_Type::AST_BREAK {
+                    l.breaks.push(jump);
+                }
+                else {
+                    l.continues.push(jump);
+                }
+            }
+            Ast_Type::AST_TRY => self.try_catch(node)?,
+            Ast_Type::AST_THROW => {
+                self.expression(node.throw_value.as_ref().unwrap())?;
+                self.emit(Op::Throw);
+            }
+            Ast_Type::AST_IMPORT => self.import(node)?,
+            Ast_Type::AST_NOOP => {}
+            Ast_Type::AST_INCREMENT | Ast_Type::AST_DECREMENT => {
+                // Nothing reads the value, so x++ doesn't need to keep the old one around
+                let slot = self.resolve(node.reassign_name.as_ref().unwrap());
+                self.emit(if node.ast_type == Ast_Type::AST_INCREMENT { Op::Increment(slot) } else { Op::Decrement(slot) });
+                self.emit(Op::Pop);
+            }
+            _ => {
+                self.expression(node)?;
+                self.emit(Op::Pop);
+            }
+        }
+
+        Ok(())
+    }
+
+    // What the loop walks and how far it got live in locals with names no variable can have
+    fn for_each(&mut self, node: &AST) -> IronResult<()> {
+        let iterable = node.for_iterable.as_ref().unwrap();
+        let zero = self.constant(Value::Int(0));
+
+        self.begin_scope();
+
+        let (start, exit) = if iterable.ast_type == Ast_Type::AST_RANGE {
+            for arg in iterable.function_call_args.as_deref().unwrap() {
+                self.expression(arg)?;
+            }
+
+            self.emit_at(Op::Range, iterable);
+
+            let mut hidden = vec![];
+            for name in ["for step", "for end", "for next"] {
+                let slot = self.declare(name);
+                self.emit(Op::Define(slot));
+                self.emit(Op::Pop);
+                hidden.push(slot);
+            }
+
+            let (step, end, next) = (hidden[0], hidden[1], hidden[2]);
+            let start = self.here();
+
+            // Going up it stops at the end, going down it stops above it
+            for (direction, past) in [(Op::Greater, Op::Less), (Op::Less, Op::Greater)] {
+                self.emit(Op::Get(step));
+                self.emit(Op::Constant(zero));
+                self.emit(direction);
+                self.emit(Op::Get(next));
+                self.emit(Op::Get(end));
+                self.emit(past);
+                self.emit(Op::And);
+            }
+
+            self.emit(Op::Or);
+            let exit = self.emit(Op::JumpIfFalse(0));
+
+            self.emit(Op::Get(next));
+            self.emit(Op::Get(next));
+            self.emit(Op::Get(step));
+            self.emit(Op::Add);
+            self.emit(Op::Set(next));
+            self.emit(Op::Pop);
+
+            (start, exit)
+        }
+        else {
+            self.expression(iterable)?;
+            self.emit_at(Op::Iter, iterable);
+
+            let items = self.declare("for items");
+            self.emit(Op::Define(items));
+            self.emit(Op::Pop);
+
+            self.emit(Op::Constant(zero));
+            let index = self.declare("for index");
+            self.emit(Op::Define(index));
+            self.emit(Op::Pop);
+
+            let start = self.here();
+            let len = self.name("len");
+
+            self.emit(Op::Get(index));
+            self.emit(Op::Get(items));
+            self.emit(Op::Invoke(len, 0));
+            self.emit(Op::Less);
+            let exit = self.emit(Op::JumpIfFalse(0));
+
+            let name = self.name("for items");
+            self.emit(Op::Get(items));
+            self.emit(Op::Get(index));
+            self.emit(Op::Index(name));
+            self.emit(Op::Increment(index));
+            self.emit(Op::Pop);
+
+            (start, exit)
+        };
+
+        let variable = node.for_init.as_ref().unwrap().variable_definition_variable_name.as_ref().unwrap();
+        let slot = self.declare(variable);
+        self.emit(Op::Define(slot));
+        self.emit(Op::Pop);
+
+        self.begin_loop(node);
+        self.block(node.for_body.as_ref().unwrap())?;
+        self.continue_here();
+        self.emit(Op::Jump(start));
+
+        self.patch(exit);
+        self.end_loop();
+        self.end_scope();
+
+        Ok(())
+    }
+
+    // The value is worked out once and kept in a local, then each pattern is tested in order until one fits
+    fn match_statement(&mut self, node: &AST) -> IronResult<()> {
+        self.begin_scope();
+
+        self.expression(node.match_value.as_ref().unwrap())?;
+        let value = self.declare("match value");
+        self.emit(Op::Define(value));
+        self.emit(Op::Pop);
+
+        let mut ends = vec![];
+
+        for case in node.match_cases.as_deref().unwrap_or(&[]) {
+            let body = case.case_body.as_ref().unwrap();
+
+            // default is always last
+            let Some(patterns) = case.case_patterns.as_ref() else {
+                self.block(body)?;
+                break;
+            };
+
+            let mut to_body = vec![];
+
+            for pattern in patterns {
+                self.emit(Op::Get(value));
+
+                if pattern.ast_type == Ast_Type::AST_CLASS_PATTERN {
+                    let class = self.name(pattern.class_name.as_ref().unwrap());
+                    self.emit_at(Op::IsInstance(class), pattern);
+                }
+                else {
+                    self.expression(pattern)?;
+                    self.emit_at(Op::Equal, pattern);
+                }
+
+                let skip = self.emit(Op::JumpIfFalse(0));
+                to_body.push(self.emit(Op::Jump(0)));
+                self.patch(skip);
+            }
+
+            let next = self.emit(Op::Jump(0));
+
+            for jump in to_body {
+                self.patch(jump);
+            }
+
+            self.block(body)?;
+            ends.push(self.emit(Op::Jump(0)));
+            self.patch(next);
+        }
+
+        for end in ends {
+            self.patch(end);
+        }
+
+        self.end_scope();
+        Ok(())
+    }
+
+    fn begin_loop(&mut self, node: &AST) {
+        let tries = self.ctx().finally.len();
+        self.ctx().loops.push(Loop { label: node.loop_label.clone(), breaks: vec![], continues: vec![], tries });
+    }
+
+    // Where a continue in the innermost loop goes, right after its body
+    fn continue_here(&mut self) {
+        let continues = std::mem::take(&mut self.ctx().loops.last_mut().expect("No loop to continue").continues);
+
+        for jump in continues {
+            self.patch(jump);
+        }
+    }
+
+    fn end_loop(&mut self) {
+        let l = self.ctx().loops.pop().expect("No loop to end");
+
+        for jump in l.breaks {
+            self.patch(jump);
+        }
+    }
+
+    // Jumping out of trys (return or break) has to drop their handlers and run their finally blocks
+    fn leave_trys(&mut self, down_to: usize) -> IronResult<()> {
+        let pending = self.ctx().finally.clone();
+
+        for i in (down_to..pending.len()).rev() {
+            self.emit(Op::PopHandler);
+
+            if let Some(body) = pending[i].as_ref() {
+                self.ctx().finally.truncate(i);
+                let result = self.block(body);
+                self.ctx().finally = pending.clone();
+                result?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /*
+        try { a } catch (Kind e) { b } finally { c } becomes
+
+            PushHandler catch
+            a
+            PopHandler
+            c
+            Jump end
+        catch:                 the error is on the stack
+            MatchError Kind
+            JumpIfFalse rethrow
+            e = error
+            PushHandler rethrow
+            b
+            PopHandler
+            c
+            Jump end
+        rethrow:
+            c
+            Rethrow
+        end:
+    */
+    fn try_catch(&mut self, node: &AST) -> IronResult<()> {
+        let finally = node.finally_body.as_deref().cloned();
+
+        let handler = self.emit(Op::PushHandler(0));
+
+        self.ctx().finally.push(finally.clone());
+        self.block(node.try_body.as_ref().unwrap())?;
+        self.ctx().finally.pop();
+
+        self.emit(Op::PopHandler);
+
+        if let Some(body) = finally.as_ref() {
+            self.block(body)?;
+        }
+
+        let mut ends = vec![self.emit(Op::Jump(0))];
+
+        self.patch(handler);
+
+        let mut rethrows = vec![];
+
+        if let Some(catch_body) = node.catch_body.as_ref() {
+            let kind = self.name(node.catch_type.as_deref().unwrap_or("Error"));
+            self.emit(Op::MatchError(kind));
+            rethrows.push(self.emit(Op::JumpIfFalse(0)));
+
+            self.begin_scope();
+
+            let slot = self.declare(node.catch_name.as_ref().unwrap());
+            self.emit(Op::Define(slot));
+            self.emit(Op::Pop);
+
+            rethrows.push(self.emit(Op::PushHandler(0)));
+
+            self.ctx().finally.push(finally.clone());
+            self.block(catch_body)?;
+            self.ctx().finally.pop();
+
+            self.emit(Op::PopHandler);
+            self.end_scope();
+
+            if let Some(body) = finally.as_ref() {
+                self.block(body)?;
+            }
+
+            ends.push(self.emit(Op::Jump(0)));
+        }
+
+        for jump in rethrows {
+            self.patch(jump);
+        }
+
+        if let Some(body) = finally.as_ref() {
+            self.block(body)?;
+        }
+
+        self.emit(Op::Rethrow);
+
+        for jump in ends {
+            self.patch(jump);
+        }
+
+        Ok(())
+    }
+
+    fn function(&mut self, index: usize, node: &AST, class: Option<usize>) -> IronResult<()> {
+        let args = node.function_definition_args.as_deref().unwrap_or(&[]);
+
+        self.program.functions[index].params = args.iter().map(|a| a.variable_type.clone().unwrap()).collect();
+        self.program.functions[index].is_method = class.is_some();
+        self.program.functions[index].returns = node.function_return_type.clone();
+
+        self.contexts.push(Context::new(index, class, false));
+
+        if class.is_some() {
+            self.declare("<self>");
+        }
+
+        for arg in args {
+            self.declare(arg.variable_definition_variable_name.as_ref().unwrap());
+        }
+
+        self.block(node.function_definition_body.as_ref().unwrap())?;
+
+        match &node.function_return_type {
+            Some(t) if *t != Data_Type::VOID => {
+                let saved = self.span.clone();
+                self.span = node.span.clone();
+                self.emit(Op::MissingReturn);
+                self.span = saved;
+            }
+            _ => {
+                self.emit(Op::Nil);
+                self.emit(Op::Return);
+            }
+        }
+
+        self.finish();
+
+        Ok(())
+    }
+
+    /*
+        Fields are the constructor arguments plus every variable in the body,
+        the constructor sets them all in order and hands back the instance
+    */
+    fn class(&mut self, node: &AST) -> IronResult<()> {
+        let name = node.class_definition_name.clone().unwrap();
+        let args = node.class_definition_args.as_deref().unwrap_or(&[]);
+        let body = node.class_definition_body.as_ref().and_then(|b| b.compound_value.as_deref()).unwrap_or(&[]);
+
+        let mut fields: Vec<String> = args.iter().map(|a| a.variable_definition_variable_name.clone().unwrap()).collect();
+
+        for stmt in body {
+            let field = match stmt.ast_type {
+                Ast_Type::AST_VARIABLE_DEF => stmt.variable_definition_variable_name.clone(),
+                Ast_Type::AST_ARRAY_DEF => stmt.array_name.clone(),
+                _ => None,
+            };
+
+            if let Some(field) = field {
+                if !fields.contains(&field) {
+                    fields.push(field);
+                }
+            }
+        }
+
+        let params = args.iter().map(|a| a.variable_type.clone().unwrap()).collect();
+        let constructor = self.new_function(&name, params, true);
+
+        let class = self.program.classes.len();
+        self.program.classes.push(Class {
+            name: name.clone(),
+            fields: fields.clone(),
+            methods: HashMap::new(),
+            constructor,
+        });
+        self.classes.insert(name, class);
+
+        for stmt in body {
+            if stmt.ast_type == Ast_Type::AST_FUNCTION_DEF {
+                let method = stmt.function_definition_name.clone().unwrap();
+                let index = self.new_function(&method, vec![], true);
+                self.program.classes[class].methods.insert(method, index);
+            }
+        }
+
+        self.contexts.push(Context::new(constructor, Some(class), false));
+        self.declare("<self>");
+
+        for arg in args {
+            let slot = self.declare(arg.variable_definition_variable_name.as_ref().unwrap());
+            let field = fields.iter().position(|f| Some(f) == arg.variable_definition_variable_name.as_ref()).unwrap();
+
+            self.emit(Op::Get(slot));
+            self.emit(Op::Define(Slot::Field(field)));
+            self.emit(Op::Pop);
+        }
+
+        for stmt in body {
+            let (field, has_value) = match stmt.ast_type {
+                Ast_Type::AST_VARIABLE_DEF => (stmt.variable_definition_variable_name.clone(), stmt.variable_definition_value.is_some()),
+                Ast_Type::AST_ARRAY_DEF => (stmt.array_name.clone(), true),
+                _ => continue,
+            };
+
+            if !has_value {
+                continue;
+            }
+
+            let field = fields.iter().position(|f| Some(f) == field.as_ref()).unwrap();
+
+            match stmt.ast_type {
+                Ast_Type::AST_ARRAY_DEF => self.expression(stmt)?,
+                _ => {
+                    self.expression(stmt.variable_definition_value.as_ref().unwrap())?;
+                    self.widen(stmt);
+                }
+            }
+
+            self.emit(Op::Define(Slot::Field(field)));
+            self.emit(Op::Pop);
+        }
+
+        self.emit(Op::Get(Slot::Local(0)));
+        self.emit(Op::Return);
+        self.finish();
+
+        for stmt in body {
+            if stmt.ast_type == Ast_Type::AST_FUNCTION_DEF {
+                let index = self.program.classes[class].methods[stmt.function_definition_name.as_ref().unwrap()];
+                self.function(index, stmt, Some(class))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Libraries get compiled in right away, only their functions are kept like the visitor does
+    fn import(&mut self, node: &AST) -> IronResult<()> {
+        let lib = node.variable_name.clone().unwrap();
+
+        if node.is_builtin.unwrap_or(false) {
+            self.modules.insert(lib, None);
+            return Ok(());
+        }
+
+        let lib_ast = crate::load_library(&lib, &node.span, Rc::new(RefCell::new(Scope::new())))?;
+
+        let statements = lib_ast.compound_value.as_deref().unwrap_or(&[]);
+        let mut exports = HashMap::new();
+
+        for stmt in statements {
+            if stmt.ast_type == Ast_Type::AST_FUNCTION_DEF {
+                let name = stmt.function_definition_name.clone().unwrap();
+                let index = self.new_function(&name, vec![], false);
+
+                exports.insert(name.clone(), index);
+                self.functions.entry(name).or_insert(index);
+            }
+        }
+
+        for stmt in statements {
+            if stmt.ast_type == Ast_Type::AST_FUNCTION_DEF {
+                let index = exports[stmt.function_definition_name.as_ref().unwrap()];
+                self.function(index, stmt, None)?;
+            }
+        }
+
+        self.modules.insert(lib, Some(exports));
+        Ok(())
+    }
+
+    fn expression(&mut self, node: &AST) -> IronResult<()> {
+        let saved = self.span.clone();
+
+        if node.span.is_some() {
+            self.span = node.span.clone();
+        }
+
+        let result = self.compile_expression(node);
+
+        self.span = saved;
+        result
+    }
+
+    fn compile_expression(&mut self, node: &AST) -> IronResult<()> {
+        match node.ast_type {
+            Ast_Type::AST_INT | Ast_Type::AST_FLOAT | Ast_Type::AST_BOOL | Ast_Type::AST_STRING => {
+                let value = Value::from_ast(node).ok_or_else(|| self.error("Literal is missing its value".to_string()))?;
+                let index = self.constant(value);
+                self.emit(Op::Constant(index));
+            }
+            Ast_Type::AST_VARIABLE => {
+                let name = node.variable_name.as_ref().unwrap();
+
+                if self.is_module(name) {
+                    return Err(IronError::TypeError(format!("Library `{}` can't be used as a value", name), self.span.clone()));
+                }
+
+                let slot = self.resolve(name);
+                self.emit(Op::Get(slot));
+            }
+            Ast_Type::AST_BINARY => {
+                self.expression(node.left.as_ref().unwrap())?;
+
+                // && and || skip the right side when the left one already decides them
+                let skip = match node.operator.as_ref().unwrap() {
+                    Types::TOKEN_AND => Some(self.emit_at(Op::SkipAnd(0), node.left.as_ref().unwrap())),
+                    Types::TOKEN_OR => Some(self.emit_at(Op::SkipOr(0), node.left.as_ref().unwrap())),
+                    _ => None,
+                };
+
+                self.expression(node.right.as_ref().unwrap())?;
+
+                let op = match node.operator.as_ref().unwrap() {
+                    Types::TOKEN_ADD => Op::Add,
+                    Types::TOKEN_SUBTRACT => Op::Subtract,
+                    Types::TOKEN_ASTERISK => Op::Multiply,
+                    Types::TOKEN_FSLASH => Op::Divide,
+                    Types::TOKEN_PERCENT => Op::Modulo,
+                    Types::TOKEN_CARROT => Op::Power,
+                    Types::TOKEN_AMPERSAND => Op::BitAnd,
+                    Types::TOKEN_PIPE => Op::BitOr,
+                    Types::TOKEN_XOR => Op::BitXor,
+                    Types::TOKEN_SHIFT_LEFT => Op::ShiftLeft,
+                    Types::TOKEN_SHIFT_RIGHT => Op::ShiftRight,
+                    Types::TOKEN_GREATER_THAN => Op::Greater,
+                    Types::TOKEN_LESS_THAN => Op::Less,
+                    Types::TOKEN_GEQ => Op::GreaterEqual,
+                    Types::TOKEN_LEQ => Op::LessEqual,
+                    Types::TOKEN_EE => Op::Equal,
+                    Types::TOKEN_NEQ => Op::NotEqual,
+                    Types::TOKEN_AND => Op::And,
+                    Types::TOKEN_OR => Op::Or,
+                    op => return Err(self.error(format!("Unknown operator {:?}", op))),
+                };
+
+                self.emit(op);
+
+                if let Some(skip) = skip {
+                    self.patch(skip);
+                }
+            }
+            Ast_Type::AST_TERNARY => {
+                self.expression(node.if_condition.as_ref().unwrap())?;
+                let otherwise = self.emit_at(Op::JumpIfFalse(0), node.if_condition.as_ref().unwrap());
+
+                self.expression(node.if_body.as_ref().unwrap())?;
+                let end = self.emit(Op::Jump(0));
+
+                self.patch(otherwise);
+                self.expression(node.else_body.as_ref().unwrap())?;
+                self.patch(end);
+            }
+            Ast_Type::AST_UNARY => {
+                self.expression(node.right.as_ref().unwrap())?;
+
+                match node.operator.as_ref().unwrap() {
+                    Types::TOKEN_SUBTRACT => self.emit(Op::Negate),
+                    Types::TOKEN_NOT => self.emit(Op::Not),
+                    Types::TOKEN_TILDE => self.emit(Op::BitNot),
+                    op => return Err(self.error(format!("Unknown unary operator {:?}", op))),
+                };
+            }
+            Ast_Type::AST_FUNCTION_CALL => self.call(node)?,
+            Ast_Type::AST_DOT => self.dot(node)?,
+            Ast_Type::AST_CLASS_ACCESS => {
+                self.expression(node.dot_left.as_ref().unwrap())?;
+                self.property(node.dot_right.as_ref().unwrap(), node.reassign_value.as_deref())?;
+            }
+            Ast_Type::AST_ARRAY_ACCESS if node.array_field.is_some() => self.field_access(node)?,
+            Ast_Type::AST_ARRAY_ACCESS => {
+                let name = node.array_name.clone().unwrap();
+                let slot = self.resolve(&name);
+                let name = self.name(&name);
+
+                let index = node.array_index.as_ref().unwrap();
+                self.expression(index)?;
+
+                let path = node.array_path.as_deref().unwrap_or(&[]);
+
+                let op = match (node.array_assign_value.as_ref(), node.array_slice_end.as_ref()) {
+                    (Some(value), _) => {
+                        for index in path {
+                            self.expression(index)?;
+                        }
+
+                        self.expression(value)?;
+                        Op::SetIndex(slot, name, path.len() + 1)
+                    }
+                    (None, Some(end)) => {
+                        self.expression(end)?;
+                        Op::Slice(slot, name)
+                    }
+                    (None, None) => Op::GetIndex(slot, name),
+                };
+
+                // Index errors point at the index, same as the visitor
+                self.emit_at(op, index);
+
+                if node.array_assign_value.is_none() {
+                    for index in path {
+                        self.expression(index)?;
+                        self.emit_at(Op::Index(name), index);
+                    }
+                }
+            }
+            Ast_Type::AST_ARRAY_FILL => {
+                let args = node.function_call_args.as_deref().unwrap();
+                self.expression(&args[0])?;
+                self.expression(&args[1])?;
+
+                let element = self.constant(Value::array(node.data_type.clone(), vec![]));
+                self.emit(Op::Fill(element));
+            }
+            Ast_Type::AST_ARRAY_DEF => {
+                let elements = node.array_elements.as_deref().unwrap_or(&[]);
+
+                for element in elements {
+                    self.expression(element)?;
+                }
+
+                // An empty array stands in for the element type
+                let element = self.constant(Value::array(node.data_type.clone(), vec![]));
+                self.emit(Op::Array(elements.len(), element));
+            }
+            Ast_Type::AST_MAP_DEF => {
+                let keys = node.map_keys.as_deref().unwrap_or(&[]);
+                let values = node.map_values.as_deref().unwrap_or(&[]);
+
+                for (key, value) in keys.iter().zip(values) {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                }
+
+                let Data_Type::MAP(key, value) = &node.data_type else {
+                    return Err(self.error(format!("Map literal of type {}", node.data_type.name())));
+                };
+
+                // Like arrays, an empty map stands in for the type
+                let map = self.constant(Value::map((**key).clone(), (**value).clone(), vec![], vec![]));
+                self.emit(Op::Map(keys.len(), map));
+            }
+            Ast_Type::AST_REASSIGN => {
+                self.expression(node.reassign_value.as_ref().unwrap())?;
+
+                let slot = self.resolve(node.reassign_name.as_ref().unwrap());
+                self.emit(Op::Set(slot));
+            }
+            Ast_Type::AST_INCREMENT | Ast_Type::AST_DECREMENT => {
+                let slot = self.resolve(node.reassign_name.as_ref().unwrap());
+
+                // x++ gives the value from before, Increment leaves the new one
+                if node.postfix {
+                    self.emit(Op::Get(slot));
+                }
+
+                self.emit(if node.ast_type == Ast_Type::AST_INCREMENT { Op::Increment(slot) } else { Op::Decrement(slot) });
+
+                if node.postfix {
+                    self.emit(Op::Pop);
+                }
+            }
+            Ast_Type::AST_COMPOUND_ASSIGN => self.compound_assign(node)?,
+            Ast_Type::AST_CLASS_INSTANCE => {
+                let name = node.class_name.as_ref().unwrap();
+
+                let class = *self.classes.get(name)
+                    .ok_or_else(|| IronError::NameError(format!("Class {} not defined", name), self.span.clone()))?;
+
+                let args = node.class_args.as_deref().unwrap_or(&[]);
+
+                for arg in args {
+                    self.expression(arg)?;
+                }
+
+                self.emit(Op::New(class, args.len()));
+            }
+            Ast_Type::AST_VARIABLE_DEF
+            | Ast_Type::AST_FUNCTION_DEF
+            | Ast_Type::AST_CLASS_DEF
+            | Ast_Type::AST_COMPOUND
+            | Ast_Type::AST_IF
+            | Ast_Type::AST_WHILE
+            | Ast_Type::AST_FOR
+            | Ast_Type::AST_FOR_EACH
+            | Ast_Type::AST_MATCH
+            | Ast_Type::AST_RETURN
+            | Ast_Type::AST_BREAK
+            | Ast_Type::AST_CONTINUE
+            | Ast_Type::AST_TRY
+            | Ast_Type::AST_THROW
+            | Ast_Type::AST_IMPORT
+            | Ast_Type::AST_NOOP => {
+                self.statement(node)?;
+                self.emit(Op::Nil);
+            }
+            _ => return Err(self.error(format!("Cannot compile {:?}", node.ast_type))),
+        }
+
+        Ok(())
+    }
+
+    fn arguments(&mut self, node: &AST) -> IronResult<usize> {
+        let args = node.function_call_args.as_deref().unwrap_or(&[]);
+
+        for arg in args {
+            self.expression(arg)?;
+        }
+
+        Ok(args.len())
+    }
+
+    fn call(&mut self, node: &AST) -> IronResult<()> {
+        let name = node.function_call_name.clone().unwrap();
+
+        if matches!(name.as_str(), "print" | "println" | "int" | "float" | "str") {
+            let argc = self.arguments(node)?;
+            let name = self.name(&name);
+            self.emit(Op::CallBuiltin(name, argc));
+            return Ok(());
+        }
+
+        // Methods can call each other without going through the instance
+        let class = self.contexts.last().and_then(|ctx| ctx.class);
+
+        if let Some(class) = class {
+            if self.program.classes[class].methods.contains_key(&name) {
+                self.emit(Op::Get(Slot::Local(0)));
+                let argc = self.arguments(node)?;
+                let name = self.name(&name);
+                self.emit(Op::Invoke(name, argc));
+                return Ok(());
+            }
+        }
+
+        let argc = self.arguments(node)?;
+
+        match self.functions.get(&name) {
+            Some(&function) => {
+                self.emit(Op::Call(function, argc));
+            }
+            None => self.raise(IronError::NameError(format!("Function '{}' not defined", name), self.span.clone())),
+        }
+
+        Ok(())
+    }
+
+    // Some errors can only be reported once the line actually runs
+    fn raise(&mut self, error: IronError) {
+        let index = self.constant(Value::Error(Rc::new(error)));
+        self.emit(Op::Constant(index));
+        self.emit(Op::Rethrow);
+    }
+
+    fn dot(&mut self, node: &AST) -> IronResult<()> {
+        let left = node.dot_left.as_ref().unwrap();
+        let right = node.dot_right.as_ref().unwrap();
+
+        let module = match left.ast_type {
+            Ast_Type::AST_VARIABLE | Ast_Type::AST_IMPORT => left.variable_name.clone().filter(|n| self.is_module(n)),
+            _ => None,
+        };
+
+        let Some(module) = module else {
+            // Same for a field, p.items.push(5) changes p
+            if let Some(field) = left.dot_right.as_deref().filter(|f| f.ast_type == Ast_Type::AST_VARIABLE) {
+                if right.ast_type == Ast_Type::AST_FUNCTION_CALL {
+                    self.expression(left.dot_left.as_ref().unwrap())?;
+                    let argc = self.arguments(right)?;
+                    let field = self.name(field.variable_name.as_ref().unwrap());
+                    let name = self.name(right.function_call_name.as_ref().unwrap());
+                    self.emit(Op::InvokeProperty(field, name, argc));
+                    return Ok(());
+                }
+            }
+
+            // Calling on a variable lets array methods change it in place
+            if left.ast_type == Ast_Type::AST_VARIABLE && right.ast_type == Ast_Type::AST_FUNCTION_CALL {
+                let slot = self.resolve(left.variable_name.as_ref().unwrap());
+                let argc = self.arguments(right)?;
+                let name = self.name(right.function_call_name.as_ref().unwrap());
+                self.emit(Op::InvokeSlot(slot, name, argc));
+                return Ok(());
+            }
+
+            self.expression(left)?;
+            return self.property(right, None);
+        };
+
+        if right.ast_type != Ast_Type::AST_FUNCTION_CALL {
+            self.raise(IronError::RuntimeError("Cannot call dot access on non-function node".to_string(), self.span.clone()));
+            return Ok(());
+        }
+
+        let function = right.function_call_name.clone().unwrap();
+        let argc = self.arguments(right)?;
+
+        match self.modules[&module].as_ref() {
+            None => {
+                let module = self.name(&module);
+                let function = self.name(&function);
+                self.emit(Op::CallLibrary(module, function, argc));
+            }
+            Some(exports) => match exports.get(&function) {
+                Some(&index) => {
+                    self.emit(Op::Call(index, argc));
+                }
+                None => self.raise(IronError::NameError(format!("Function '{}' not found in imported AST", function), self.span.clone())),
+            },
+        }
+
+        Ok(())
+    }
+
+    // p.items[i], read off the field or stored into it without copying the instance
+    fn field_access(&mut self, node: &AST) -> IronResult<()> {
+        let field = node.array_field.as_deref().unwrap();
+        let name = self.name(node.array_name.as_ref().unwrap());
+
+        let index = node.array_index.as_deref().unwrap();
+        let path = node.array_path.as_deref().unwrap_or(&[]);
+
+        let Some(value) = node.array_assign_value.as_ref() else {
+            self.expression(field)?;
+
+            for index in std::iter::once(index).chain(path) {
+                self.expression(index)?;
+                self.emit_at(Op::Index(name), index);
+            }
+
+            return Ok(());
+        };
+
+        self.expression(field.dot_left.as_ref().unwrap())?;
+
+        for index in std::iter::once(index).chain(path) {
+            self.expression(index)?;
+        }
+
+        self.expression(value)?;
+
+        let property = self.name(field.dot_right.as_ref().unwrap().variable_name.as_ref().unwrap());
+        self.emit_at(Op::SetPropertyIndex(property, name, path.len() + 1), index);
+        Ok(())
+    }
+
+    // The instance is already on the stack
+    // The indices go into hidden locals first, then the element is read, stepped and stored through them
+    fn compound_assign(&mut self, node: &AST) -> IronResult<()> {
+        let target = node.left.as_ref().unwrap();
+        self.begin_scope();
+
+        for (i, index) in target.indices().into_iter().enumerate() {
+            self.expression(index)?;
+            let slot = self.declare(&stored_index(i));
+            self.emit(Op::Define(slot));
+            self.emit(Op::Pop);
+        }
+
+        let mut store = target.with_stored_indices();
+        self.expression(&store)?;
+
+        let before = self.declare("compound target");
+        self.emit(Op::Define(before));
+        self.emit(Op::Pop);
+
+        let mut read = AST::new(Ast_Type::AST_VARIABLE);
+        read.variable_name = Some("compound target".to_string());
+
+        let mut step = AST::new(Ast_Type::AST_BINARY);
+        step.left = Some(Box::new(read));
+        step.right = node.right.clone();
+        step.operator = node.operator.clone();
+        step.span = node.span.clone();
+
+        match store.ast_type {
+            Ast_Type::AST_VARIABLE => {
+                store.ast_type = Ast_Type::AST_REASSIGN;
+                store.reassign_name = store.variable_name.clone();
+                store.reassign_value = Some(Box::new(step));
+            }
+            Ast_Type::AST_ARRAY_ACCESS => store.array_assign_value = Some(Box::new(step)),
+            _ => store.reassign_value = Some(Box::new(step)),
+        }
+
+        self.expression(&store)?;
+
+        if node.postfix {
+            self.emit(Op::Pop);
+            self.emit(Op::Get(before));
+        }
+
+        self.end_scope();
+        Ok(())
+    }
+
+    fn property(&mut self, right: &AST, value: Option<&AST>) -> IronResult<()> {
+        if right.ast_type == Ast_Type::AST_FUNCTION_CALL {
+            let argc = self.arguments(right)?;
+            let name = self.name(right.function_call_name.as_ref().unwrap());
+            self.emit(Op::Invoke(name, argc));
+            return Ok(());
+        }
+
+        let name = self.name(right.variable_name.as_ref().unwrap());
+
+        match value {
+            Some(value) => {
+                self.expression(value)?;
+                self.emit(Op::SetProperty(name));
+            }
+            None => {
+                self.emit(Op::GetProperty(name));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,375 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{AST, Ast_Type, Data_Type};
+use crate::error::{IronError, IronResult};
+
+/*
+    `iron doc path` reads every .iron and .steel file under path and writes
+    a page for each one, listing its functions and classes with their ///
+    comments, plus an index of the pages. A class used in a signature links
+    to the page it's defined on, and so does an imported library
+*/
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Html,
+    Markdown,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Html => "html",
+            Format::Markdown => "md",
+        }
+    }
+}
+
+struct Function {
+    name: String,
+    args: Vec<(String, Data_Type)>,
+    returns: Option<Data_Type>,
+    doc: Option<String>,
+}
+
+struct Class {
+    name: String,
+    args: Vec<(String, Data_Type)>,
+    fields: Vec<(String, Option<Data_Type>)>,
+    methods: Vec<Function>,
+    doc: Option<String>,
+}
+
+struct Module {
+    page: String, // the path under the root without its extension, lib/trig is lib.trig
+    path: String,
+    library: bool, // a .steel file, which `import "name"` pulls in
+    imports: Vec<(String, bool)>, // the name and whether it's a builtin like <math>
+    functions: Vec<Function>,
+    classes: Vec<Class>,
+}
+
+impl Module {
+    fn stem(&self) -> &str {
+        self.page.rsplit('.').next().unwrap_or(&self.page)
+    }
+}
+
+// Parses everything under root, a file that doesn't parse is reported and left out
+pub fn document(root: &str, output: &str, format: Format) -> Vec<IronError> {
+    let mut files = vec![];
+    let mut errors = vec![];
+
+    if let Err(e) = sources(Path::new(root), &mut files) {
+        return vec![e];
+    }
+
+    let base = if Path::new(root).is_dir() { Path::new(root) } else { Path::new(root).parent().unwrap_or(Path::new("")) };
+    let mut modules = vec![];
+
+    for file in files {
+        let path = file.to_string_lossy().to_string();
+
+        match crate::parse_file(&path) {
+            Ok(ast) => modules.push(module(&file, base, &ast)),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if let Err(e) = write_site(&modules, Path::new(output), format) {
+        errors.push(e);
+    }
+
+    errors
+}
+
+pub fn sources(path: &Path, files: &mut Vec<PathBuf>) -> IronResult<()> {
+    let unreadable = || IronError::ImportError(format!("Could not read {}", path.display()), None);
+
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)
+        .map_err(|_| unreadable())?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+
+    for entry in entries {
+        let source = entry.extension().is_some_and(|e| e == "iron" || e == "steel");
+
+        if entry.is_dir() {
+            sources(&entry, files)?;
+        }
+        else if source {
+            files.push(entry);
+        }
+    }
+
+    Ok(())
+}
+
+fn module(file: &Path, base: &Path, ast: &AST) -> Module {
+    let relative = file.strip_prefix(base).unwrap_or(file).with_extension("");
+    let page = relative.iter().map(|part| part.to_string_lossy()).collect::<Vec<_>>().join(".");
+
+    let mut module = Module {
+        page,
+        path: file.to_string_lossy().to_string(),
+        library: file.extension().is_some_and(|e| e == "steel"),
+        imports: vec![],
+        functions: vec![],
+        classes: vec![],
+    };
+
+    for node in statements(ast) {
+        match node.ast_type {
+            Ast_Type::AST_FUNCTION_DEF => module.functions.push(function(node)),
+            Ast_Type::AST_CLASS_DEF => module.classes.push(class(node)),
+            Ast_Type::AST_IMPORT => module.imports.push((node.variable_name.clone().unwrap_or_default(), node.is_builtin.unwrap_or(false))),
+            _ => {}
+        }
+    }
+
+    module
+}
+
+fn statements(node: &AST) -> &[AST] {
+    node.compound_value.as_deref().unwrap_or(&[])
+}
+
+fn arguments(args: &Option<Vec<AST>>) -> Vec<(String, Data_Type)> {
+    args.iter()
+        .flatten()
+        .map(|arg| (arg.variable_definition_variable_name.clone().unwrap_or_default(), arg.variable_type.clone().unwrap_or(Data_Type::VOID)))
+        .collect()
+}
+
+fn function(node: &AST) -> Function {
+    Function {
+        name: node.function_definition_name.clone().unwrap_or_default(),
+        args: arguments(&node.function_definition_args),
+        returns: node.function_return_type.clone(),
+        doc: node.doc.clone(),
+    }
+}
+
+fn class(node: &AST) -> Class {
+    let body = node.class_definition_body.as_deref().map(statements).unwrap_or(&[]);
+
+    Class {
+        name: node.class_definition_name.clone().unwrap_or_default(),
+        args: arguments(&node.class_definition_args),
+        fields: body.iter().filter_map(field).collect(),
+        methods: body.iter().filter(|s| s.ast_type == Ast_Type::AST_FUNCTION_DEF).map(function).collect(),
+        doc: node.doc.clone(),
+    }
+}
+
+// An array literal keeps its element type, and an instance only the name of its class
+fn field(node: &AST) -> Option<(String, Option<Data_Type>)> {
+    match node.ast_type {
+        Ast_Type::AST_VARIABLE_DEF => Some((node.variable_definition_variable_name.clone()?, node.variable_type.clone())),
+        Ast_Type::AST_ARRAY_DEF => Some((node.array_name.clone()?, Some(Data_Type::ARRAY(Box::new(node.data_type.clone()))))),
+        Ast_Type::AST_CLASS_INSTANCE => Some((node.variable_definition_variable_name.clone()?, node.class_name.clone().map(Data_Type::CUSTOM))),
+        _ => None,
+    }
+}
+
+// Writes one page per module and the index that lists them
+fn write_site(modules: &[Module], output: &Path, format: Format) -> IronResult<()> {
+    let write = |name: &str, contents: String| {
+        let path = output.join(format!("{}.{}", name, format.extension()));
+        fs::write(&path, contents).map_err(|_| IronError::RuntimeError(format!("Could not write {}", path.display()), None))
+    };
+
+    fs::create_dir_all(output).map_err(|_| IronError::RuntimeError(format!("Could not create {}", output.display()), None))?;
+
+    let site = Site { modules, format };
+
+    for module in modules {
+        write(&module.page, site.page(module))?;
+    }
+
+    write("index", site.index())
+}
+
+struct Site<'a> {
+    modules: &'a [Module],
+    format: Format,
+}
+
+impl Site<'_> {
+    fn index(&self) -> String {
+        let items = self.modules.iter().map(|module| {
+            let names: Vec<&str> = module.classes.iter().map(|c| c.name.as_str())
+                .chain(module.functions.iter().map(|f| f.name.as_str()))
+                .collect();
+
+            let link = self.link(&module.page, None, &module.path);
+            let summary = if names.is_empty() { String::new() } else { format!(" - {}", self.text(&names.join(", "))) };
+
+            link + &summary
+        });
+
+        let body = self.heading(1, "Index", None) + &self.list(items);
+        self.document("Index", &body)
+    }
+
+    fn page(&self, module: &Module) -> String {
+        let mut body = self.heading(1, &module.path, None);
+        body += &self.paragraph(&format!("{} - {}", self.link("index", None, "Index"), self.text(if module.library { "library" } else { "program" })));
+
+        if !module.imports.is_empty() {
+            body += &self.heading(2, "Imports", None);
+
+            body += &self.list(module.imports.iter().map(|(name, builtin)| {
+                let target = self.modules.iter().find(|m| m.library && m.stem() == name && !builtin);
+
+                match target {
+                    Some(m) => self.link(&m.page, None, name),
+                    None if *builtin => self.code(&format!("<{}>", name)),
+                    None => self.code(name),
+                }
+            }));
+        }
+
+        if !module.classes.is_empty() {
+            body += &self.heading(2, "Classes", None);
+        }
+
+        for class in &module.classes {
+            let args = self.arguments(&class.args);
+            body += &self.heading(3, &class.name, Some(&format!("class.{}", class.name)));
+            body += &self.signature(&format!("class {}({})", self.text(&class.name), args));
+            body += &self.doc(&class.doc);
+
+            if !class.fields.is_empty() {
+                body += &self.paragraph("Fields");
+                body += &self.list(class.fields.iter().map(|(name, t)| {
+                    let t = t.as_ref().map_or(String::new(), |t| format!("{} ", self.data_type(t)));
+                    t + &self.text(name)
+                }));
+            }
+
+            for method in &class.methods {
+                body += &self.function(method, 4, &format!("{}.{}", class.name, method.name));
+            }
+        }
+
+        if !module.functions.is_empty() {
+            body += &self.heading(2, "Functions", None);
+        }
+
+        for function in &module.functions {
+            body += &self.function(function, 3, &format!("fun.{}", function.name));
+        }
+
+        self.document(&module.path, &body)
+    }
+
+    fn function(&self, function: &Function, level: usize, anchor: &str) -> String {
+        let returns = function.returns.as_ref().map_or(String::new(), |t| format!(" {} {}", self.text("->"), self.data_type(t)));
+        let signature = format!("fun {}({}){}", self.text(&function.name), self.arguments(&function.args), returns);
+
+        self.heading(level, &function.name, Some(anchor)) + &self.signature(&signature) + &self.doc(&function.doc)
+    }
+
+    fn arguments(&self, args: &[(String, Data_Type)]) -> String {
+        args.iter().map(|(name, t)| format!("{} {}", self.data_type(t), self.text(name))).collect::<Vec<_>>().join(", ")
+    }
+
+    // A type as it's written in iron, with any class in it linking to where it's defined
+    fn data_type(&self, t: &Data_Type) -> String {
+        match t {
+            Data_Type::ARRAY(inner) => format!("{}[]", self.data_type(inner)),
+            Data_Type::MAP(key, value) => format!("{}{}, {}{}", self.text("map<"), self.data_type(key), self.data_type(value), self.text(">")),
+            Data_Type::CUSTOM(name) => match self.modules.iter().find(|m| m.classes.iter().any(|c| &c.name == name)) {
+                Some(m) => self.link(&m.page, Some(&format!("class.{}", name)), name),
+                None => self.text(name),
+            },
+            t => self.text(&t.name()),
+        }
+    }
+
+    fn doc(&self, doc: &Option<String>) -> String {
+        match doc {
+            Some(doc) => doc.split("\n\n").map(|p| self.paragraph(&self.text(p))).collect(),
+            None => String::new(),
+        }
+    }
+
+    // What follows is the only part that knows html from markdown
+
+    fn document(&self, title: &str, body: &str) -> String {
+        match self.format {
+            Format::Html => format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+                self.text(title), STYLE, body
+            ),
+            Format::Markdown => body.to_string(),
+        }
+    }
+
+    fn heading(&self, level: usize, title: &str, anchor: Option<&str>) -> String {
+        match (self.format, anchor) {
+            (Format::Html, Some(anchor)) => format!("<h{0} id=\"{1}\">{2}</h{0}>\n", level, anchor, self.text(title)),
+            (Format::Html, None) => format!("<h{0}>{1}</h{0}>\n", level, self.text(title)),
+            (Format::Markdown, Some(anchor)) => format!("<a id=\"{}\"></a>\n\n{} {}\n\n", anchor, "#".repeat(level), title),
+            (Format::Markdown, None) => format!("{} {}\n\n", "#".repeat(level), title),
+        }
+    }
+
+    fn paragraph(&self, text: &str) -> String {
+        match self.format {
+            Format::Html => format!("<p>{}</p>\n", text),
+            Format::Markdown => format!("{}\n\n", text),
+        }
+    }
+
+    fn list(&self, items: impl Iterator<Item = String>) -> String {
+        match self.format {
+            Format::Html => format!("<ul>\n{}</ul>\n", items.map(|item| format!("<li>{}</li>\n", item)).collect::<String>()),
+            Format::Markdown => items.map(|item| format!("- {}\n", item)).collect::<String>() + "\n",
+        }
+    }
+
+    // Markdown can't link inside a code span, so signatures there are plain text with the links kept
+    fn signature(&self, code: &str) -> String {
+        match self.format {
+            Format::Html => format!("<pre>{}</pre>\n", code),
+            Format::Markdown => format!("> {}\n\n", code),
+        }
+    }
+
+    fn code(&self, text: &str) -> String {
+        match self.format {
+            Format::Html => format!("<code>{}</code>", self.text(text)),
+            Format::Markdown => format!("`{}`", text),
+        }
+    }
+
+    fn link(&self, page: &str, anchor: Option<&str>, text: &str) -> String {
+        let target = format!("{}.{}{}", page, self.format.extension(), anchor.map_or(String::new(), |a| format!("#{}", a)));
+
+        match self.format {
+            Format::Html => format!("<a href=\"{}\">{}</a>", target, self.text(text)),
+            Format::Markdown => format!("[{}]({})", self.text(text), target),
+        }
+    }
+
+    fn text(&self, text: &str) -> String {
+        match self.format {
+            Format::Html => text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;"),
+            Format::Markdown => text.replace('<', "\\<").replace('>', "\\>"),
+        }
+    }
+}
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; }
+pre { background: #f4f4f4; padding: 0.5em; }
+h3, h4 { margin-bottom: 0.2em; }
+";
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use crate::span::{self, Span};
+
+// Every variant holds the message and where in the source it went wrong
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, PartialEq, Debug)]
+pub enum IronError {
+    LexError(String, Option<Span>),
+    ParseError(String, Option<Span>),
+    TypeError(String, Option<Span>),
+    NameError(String, Option<Span>),
+    IndexError(String, Option<Span>),
+    KeyError(String, Option<Span>), // a map lookup with a key that isn't there
+    ArithmeticError(String, Option<Span>), // an int overflowed or was divided by zero
+    ImportError(String, Option<Span>),
+    RuntimeError(String, Option<Span>),
+    UserError(String, Option<Span>), // raised by throw in a script
+}
+
+pub type IronResult<T> = Result<T, IronError>;
+
+impl IronError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IronError::LexError(..) => "LexError",
+            IronError::ParseError(..) => "ParseError",
+            IronError::TypeError(..) => "TypeError",
+            IronError::NameError(..) => "NameError",
+            IronError::IndexError(..) => "IndexError",
+            IronError::KeyError(..) => "KeyError",
+            IronError::ArithmeticError(..) => "ArithmeticError",
+            IronError::ImportError(..) => "ImportError",
+            IronError::RuntimeError(..) => "RuntimeError",
+            IronError::UserError(..) => "Error",
+        }
+    }
+
+    // Turns a kind back into an error, used when a caught error gets thrown again
+    pub fn from_kind(kind: &str, message: String, span: Option<Span>) -> Self {
+        match kind {
+            "LexError" => IronError::LexError(message, span),
+            "ParseError" => IronError::ParseError(message, span),
+            "TypeError" => IronError::TypeError(message, span),
+            "NameError" => IronError::NameError(message, span),
+            "IndexError" => IronError::IndexError(message, span),
+            "KeyError" => IronError::KeyError(message, span),
+            "ArithmeticError" => IronError::ArithmeticError(message, span),
+            "ImportError" => IronError::ImportError(message, span),
+            "RuntimeError" => IronError::RuntimeError(message, span),
+            _ => IronError::UserError(message, span),
+        }
+    }
+
+    pub fn is_kind(kind: &str) -> bool {
+        matches!(kind, "Error" | "LexError" | "ParseError" | "TypeError" | "NameError" | "IndexError" | "KeyError" | "ArithmeticError" | "ImportError" | "RuntimeError")
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            IronError::LexError(message, _)
+            | IronError::ParseError(message, _)
+            | IronError::TypeError(message, _)
+            | IronError::NameError(message, _)
+            | IronError::IndexError(message, _)
+            | IronError::KeyError(message, _)
+            | IronError::ArithmeticError(message, _)
+            | IronError::ImportError(message, _)
+            | IronError::RuntimeError(message, _)
+            | IronError::UserError(message, _) => message,
+        }
+    }
+
+    pub fn span(&self) -> &Option<Span> {
+        match self {
+            IronError::LexError(_, span)
+            | IronError::ParseError(_, span)
+            | IronError::TypeError(_, span)
+            | IronError::NameError(_, span)
+            | IronError::IndexError(_, span)
+            | IronError::KeyError(_, span)
+            | IronError::ArithmeticError(_, span)
+            | IronError::ImportError(_, span)
+            | IronError::RuntimeError(_, span)
+            | IronError::UserError(_, span) => span,
+        }
+    }
+
+    // Errors raised deep inside the scope have no idea where they came from,
+    // so the caller fills the span in on the way out
+    pub fn with_span(mut self, span: &Option<Span>) -> Self {
+        match &mut self {
+            IronError::LexError(_, s)
+            | IronError::ParseError(_, s)
+            | IronError::TypeError(_, s)
+            | IronError::NameError(_, s)
+            | IronError::IndexError(_, s)
+            | IronError::KeyError(_, s)
+            | IronError::ArithmeticError(_, s)
+            | IronError::ImportError(_, s)
+            | IronError::RuntimeError(_, s)
+            | IronError::UserError(_, s) => {
+                if s.is_none() {
+                    *s = span.clone();
+                }
+            }
+        }
+
+        self
+    }
+
+    pub fn render(&self) -> String {
+        span::render(self.span(), &format!("error[{}]: {}", self.kind(), self.message()))
+    }
+
+    // Follows the sysexits.h codes so scripts calling iron can tell failures apart
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            IronError::LexError(..) | IronError::ParseError(..) => 65,
+            IronError::ImportError(..) => 66,
+            _ => 70,
+        }
+    }
+}
+
+impl fmt::Display for IronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span() {
+            Some(span) => write!(f, "{}: {}: {}", span, self.kind(), self.message()),
+            None => write!(f, "{}: {}", self.kind(), self.message()),
+        }
+    }
+}
+
+impl std::error::Error for IronError {}
@@ -0,0 +1,381 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::{IronError, IronResult};
+use crate::lexer::Lexer;
+use crate::token::{Token, Types};
+
+/*
+    `iron fmt` prints a file back out the one way Iron is written, four space
+    indents, a space around binary operators, a { on the line it opens and
+    never more than one blank line in a row. It works on the tokens instead
+    of the AST so comments and any line breaks inside a statement are kept,
+    and it only ever changes the space between tokens
+*/
+
+const KEYWORDS: [&str; 19] = [
+    "return", "if", "else", "while", "for", "match", "in", "new", "import", "break",
+    "continue", "try", "catch", "finally", "throw", "case", "default", "fun", "class",
+];
+
+// Formats every .iron and .steel file under paths, and hands back the ones that
+// weren't formatted already. With check they're only reported, not written
+pub fn format_files(paths: &[String], check: bool) -> (Vec<String>, Vec<IronError>) {
+    let mut files = vec![];
+    let mut changed = vec![];
+    let mut errors = vec![];
+
+    for path in paths {
+        if let Err(e) = crate::doc::sources(Path::new(path), &mut files) {
+            errors.push(e);
+        }
+    }
+
+    for file in files {
+        let path = file.to_string_lossy().to_string();
+
+        match format_file(&path, check) {
+            Ok(true) => changed.push(path),
+            Ok(false) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+
+    (changed, errors)
+}
+
+// Whether the file changed, one that doesn't parse is left alone
+fn format_file(path: &str, check: bool) -> IronResult<bool> {
+    crate::parse_file(path)?;
+
+    let source = fs::read_to_string(path)
+        .map_err(|_| IronError::ImportError(format!("Could not read the file {}", path), None))?;
+    let formatted = format(&source, path)?;
+
+    if formatted == source {
+        return Ok(false);
+    }
+
+    if !check {
+        fs::write(path, &formatted)
+            .map_err(|_| IronError::RuntimeError(format!("Could not write {}", path), None))?;
+    }
+
+    Ok(true)
+}
+
+pub fn format(source: &str, file: &str) -> IronResult<String> {
+    let tokens = Lexer::new_with_file(source, file).source_tokens()?;
+    let mut printer = Printer::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        printer.print(token, tokens.get(i + 1));
+    }
+
+    let mut out = printer.out;
+
+    if !out.is_empty() {
+        out.push('\n');
+    }
+
+    // Anything but space changing would be a bug in here, so it's caught before the file is written
+    let after = Lexer::new_with_file(&out, file).source_tokens()?;
+    let code = |tokens: &[Token]| -> Vec<(Types, String)> {
+        tokens.iter()
+            .map(|t| (t.kind.clone(), if t.kind == Types::TOKEN_COMMENT { String::new() } else { t.value.clone() }))
+            .collect()
+    };
+
+    if code(&tokens) != code(&after) {
+        return Err(IronError::RuntimeError(format!("Formatting {} would change its code, so it was left alone", file), None));
+    }
+
+    Ok(out)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Open {
+    Paren,
+    Box,
+    Literal, // the { of a map
+    Block,
+}
+
+// What a token does, which is most of what decides the space around it
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    Operand, // a value or the end of one, like x, 1, ) or the > of map<K, V>
+    Keyword,
+    Unary,
+    Postfix,
+    Binary,
+    Open,
+    Comma,
+    Colon, // after a map key or a label, not the : of ?:
+    TypeOpen, // the < of map<K, V> or import <name>
+    Block, // the { and } of a block
+    Other, // ; and .
+}
+
+struct Printer {
+    out: String,
+    stack: Vec<Open>,
+    angles: usize, // < of a type still open
+    questions: usize, // ? still waiting on their :
+    prev: Option<(Role, Token)>, // the last token that wasn't a comment
+    last_line: usize, // the line the last token, comment or not, ended on
+    comment_last: bool, // whether that token was a comment
+    must_break: bool, // the next token goes on a new line
+    statement_start: bool, // nothing of the current statement has been printed
+}
+
+impl Printer {
+    fn new() -> Self {
+        Printer {
+            out: String::new(),
+            stack: vec![],
+            angles: 0,
+            questions: 0,
+            prev: None,
+            last_line: 0,
+            comment_last: false,
+            must_break: false,
+            statement_start: true,
+        }
+    }
+
+    fn print(&mut self, token: &Token, next: Option<&Token>) {
+        let line = token.span.as_ref().map_or(0, |s| s.line);
+        let end = line + token.value.matches('\n').count();
+
+        if token.kind == Types::TOKEN_COMMENT {
+            // Something after it on the same line, like the } in { /* nothing */ }
+            let followed = next.is_some_and(|t| t.span.as_ref().is_some_and(|s| s.line == end));
+            self.comment(token, line, followed);
+        }
+        else {
+            self.code(token, line);
+        }
+
+        self.last_line = end;
+        self.comment_last = token.kind == Types::TOKEN_COMMENT;
+    }
+
+    // A comment stays at the end of the line it's on, unless the line goes on to what should be the next one
+    fn comment(&mut self, token: &Token, line: usize, followed: bool) {
+        if self.out.is_empty() {
+            // The first thing in the file
+        }
+        else if line == self.last_line && !(self.must_break && followed) {
+            self.out.push(' ');
+        }
+        else {
+            self.new_line(line, self.indent(false), true);
+        }
+
+        let column = token.span.as_ref().map_or(1, |s| s.column);
+        let shift = self.current_column() as isize - (column as isize - 1);
+
+        // A block comment's later lines move along with its first one
+        for (i, text) in token.value.lines().enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+                self.out.push_str(&reindent(text, shift));
+            }
+            else {
+                self.out.push_str(text.trim_end());
+            }
+        }
+
+        if token.value.starts_with("//") {
+            self.must_break = true;
+        }
+    }
+
+    fn code(&mut self, token: &Token, line: usize) {
+        let role = self.role(token);
+        let joins = self.joins(token, role);
+
+        if matches!(token.kind, Types::TOKEN_RPARENT | Types::TOKEN_RBOX | Types::TOKEN_RBRACK) {
+            self.stack.pop();
+        }
+
+        if self.out.is_empty() {
+            // The first token of the file
+        }
+        else if (self.must_break || role == Role::Block && token.kind == Types::TOKEN_RBRACK || line > self.last_line) && !joins {
+            let closes = matches!(token.kind, Types::TOKEN_RPARENT | Types::TOKEN_RBOX | Types::TOKEN_RBRACK);
+            let blank = !(role == Role::Block && token.kind == Types::TOKEN_RBRACK);
+            self.new_line(line, self.indent(closes || role == Role::Block), blank);
+        }
+        else if self.spaced(token, role) {
+            self.out.push(' ');
+        }
+
+        self.out.push_str(&token.value);
+        self.must_break = false;
+        self.statement_start = false;
+
+        match (&token.kind, role) {
+            (Types::TOKEN_LPARENT, _) => self.stack.push(Open::Paren),
+            (Types::TOKEN_LBOX, _) => self.stack.push(Open::Box),
+            (Types::TOKEN_LBRACK, Role::Block) => self.stack.push(Open::Block),
+            (Types::TOKEN_LBRACK, _) => self.stack.push(Open::Literal),
+            (Types::TOKEN_QUESTION, _) => self.questions += 1,
+            (Types::TOKEN_COLON, Role::Binary) => self.questions -= 1,
+            (_, Role::TypeOpen) => self.angles += 1,
+            (Types::TOKEN_GREATER_THAN, Role::Operand) => self.angles -= 1,
+            (Types::TOKEN_SHIFT_RIGHT, Role::Operand) => self.angles = self.angles.saturating_sub(2),
+            _ => {}
+        }
+
+        let ends_statement = token.kind == Types::TOKEN_SEMI && self.brackets() == 0;
+
+        if ends_statement || role == Role::Block {
+            self.must_break = true;
+            self.statement_start = true;
+            self.questions = 0;
+        }
+
+        self.prev = Some((role, token.clone()));
+    }
+
+    fn role(&self, token: &Token) -> Role {
+        let prev = self.prev.as_ref().map(|(role, _)| *role);
+        let prev_value = self.prev.as_ref().map_or("", |(_, t)| t.value.as_str());
+        let after_operand = matches!(prev, Some(Role::Operand | Role::Postfix));
+
+        match token.kind {
+            Types::TOKEN_ID if KEYWORDS.contains(&token.value.as_str()) => Role::Keyword,
+            Types::TOKEN_ID | Types::TOKEN_INT | Types::TOKEN_FLOAT | Types::TOKEN_STRING | Types::TOKEN_CHAR
+                | Types::TOKEN_FSTRING | Types::TOKEN_BOOL | Types::TOKEN_RPARENT | Types::TOKEN_RBOX => Role::Operand,
+            Types::TOKEN_LPARENT | Types::TOKEN_LBOX => Role::Open,
+            Types::TOKEN_LBRACK if self.literal_brace() => Role::Open,
+            Types::TOKEN_RBRACK if self.stack.last() == Some(&Open::Literal) => Role::Operand,
+            Types::TOKEN_LBRACK | Types::TOKEN_RBRACK => Role::Block,
+            Types::TOKEN_INCREMENT | Types::TOKEN_DECREMENT if after_operand => Role::Postfix,
+            Types::TOKEN_ADD | Types::TOKEN_SUBTRACT if after_operand => Role::Binary,
+            Types::TOKEN_INCREMENT | Types::TOKEN_DECREMENT | Types::TOKEN_ADD | Types::TOKEN_SUBTRACT
+                | Types::TOKEN_NOT | Types::TOKEN_TILDE => Role::Unary,
+            Types::TOKEN_LESS_THAN if prev_value == "map" || prev_value == "import" => Role::TypeOpen,
+            Types::TOKEN_GREATER_THAN | Types::TOKEN_SHIFT_RIGHT if self.angles > 0 => Role::Operand,
+            Types::TOKEN_COMMA => Role::Comma,
+            Types::TOKEN_COLON if self.questions > 0 => Role::Binary,
+            // The : of a slice sits between its ends like a .
+            Types::TOKEN_COLON if self.stack.last() == Some(&Open::Box) => Role::Other,
+            Types::TOKEN_COLON => Role::Colon,
+            Types::TOKEN_SEMI | Types::TOKEN_DOT => Role::Other,
+            _ => Role::Binary,
+        }
+    }
+
+    // A { where a value goes is a map, anywhere else it opens a block
+    fn literal_brace(&self) -> bool {
+        match &self.prev {
+            Some((Role::Open | Role::Comma | Role::Colon, _)) => true,
+            Some((Role::Binary, t)) => !matches!(t.kind, Types::TOKEN_FAT_ARROW | Types::TOKEN_ARROW),
+            Some((Role::Keyword, t)) => t.value == "return",
+            _ => false,
+        }
+    }
+
+    // Tokens that stay on the line before them whatever the source did, like the { of
+    // a block, unless a line comment is in the way. An else, catch or finally after a }
+    // goes on its own line
+    fn joins(&self, token: &Token, role: Role) -> bool {
+        if self.comment_last && self.must_break {
+            return false;
+        }
+
+        let after_open = matches!(&self.prev, Some((Role::Block, t)) if t.kind == Types::TOKEN_LBRACK);
+
+        match token.kind {
+            Types::TOKEN_LBRACK => role == Role::Block,
+            Types::TOKEN_RBRACK => role == Role::Block && after_open && !self.comment_last,
+            _ => false,
+        }
+    }
+
+    fn spaced(&self, token: &Token, role: Role) -> bool {
+        let Some((prev, prev_token)) = &self.prev else {
+            return false;
+        };
+
+        if self.comment_last {
+            return true;
+        }
+
+        // - -x and + ++x can't be written together or they'd lex as something else
+        let glued = *prev == Role::Unary && matches!((prev_token.value.as_str(), token.value.as_str()), ("-", "-" | "--") | ("+", "+" | "++"));
+
+        match (*prev, role) {
+            _ if glued => true,
+            (_, Role::Comma | Role::Other | Role::Postfix | Role::Colon) => false,
+            (Role::Other, _) if matches!(prev_token.kind, Types::TOKEN_DOT | Types::TOKEN_COLON) => false,
+            (Role::Open | Role::Unary | Role::TypeOpen, _) => false,
+            _ if matches!(token.kind, Types::TOKEN_RPARENT | Types::TOKEN_RBOX) => false,
+            _ if token.kind == Types::TOKEN_RBRACK && role == Role::Operand => false,
+            (Role::Operand | Role::Postfix, Role::Open) => false,
+            (Role::Block, Role::Block) => token.kind != Types::TOKEN_RBRACK,
+            (Role::Keyword, Role::TypeOpen) => true,
+            (_, Role::TypeOpen) => false,
+            (_, Role::Operand) if self.angles > 0 && matches!(token.kind, Types::TOKEN_GREATER_THAN | Types::TOKEN_SHIFT_RIGHT) => false,
+            _ => true,
+        }
+    }
+
+    // blank is whether a blank line in the source can be kept here
+    fn new_line(&mut self, line: usize, indent: usize, blank: bool) {
+        let blank = blank && line > self.last_line + 1 && !self.after_open();
+
+        self.out.push('\n');
+
+        if blank {
+            self.out.push('\n');
+        }
+
+        self.out.push_str(&" ".repeat(indent));
+        self.must_break = false;
+    }
+
+    // No blank line goes right after a { or right before a }
+    fn after_open(&self) -> bool {
+        matches!(&self.prev, Some((Role::Block, t)) if t.kind == Types::TOKEN_LBRACK) && !self.comment_last
+    }
+
+    fn indent(&self, statement: bool) -> usize {
+        let blocks = self.stack.iter().filter(|open| **open == Open::Block).count();
+
+        if statement || self.statement_start {
+            return 4 * (blocks + self.brackets());
+        }
+
+        // A statement carried onto another line sits one level further in
+        4 * (blocks + self.brackets().max(1))
+    }
+
+    // The ( [ and map { open since the innermost block
+    fn brackets(&self) -> usize {
+        self.stack.iter().rev().take_while(|open| **open != Open::Block).count()
+    }
+
+    fn current_column(&self) -> usize {
+        self.out.rsplit('\n').next().map_or(0, |line| line.chars().count())
+    }
+}
+
+// Moves a line by shift columns, never past its first character
+fn reindent(text: &str, shift: isize) -> String {
+    let text = text.trim_end();
+
+    if text.is_empty() {
+        return String::new();
+    }
+
+    if shift >= 0 {
+        return format!("{}{}", " ".repeat(shift as usize), text);
+    }
+
+    let space = text.len() - text.trim_start().len();
+    text[space.min(shift.unsigned_abs())..].to_string()
+}
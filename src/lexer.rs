@@ -1,25 +1,53 @@
-use crate::token::{Token, Types};
+use std::rc::Rc;
+
+use crate::error::{IronError, IronResult};
+use crate::span::{Source, Span};
+use crate::token::{Piece, Token, Types};
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Lexer {
     current_char: Option<char>, 
     index: u64, 
-    input: Vec<char>,
+    input: Rc<[char]>, // shared, so a copy of the lexer to look ahead or back with is cheap
+    line: usize,
+    column: usize,
+    pub source: Rc<Source>,
+    peeked: Option<Token>, // the token after the parser's current one, once it's looked at it
 }
 
 impl Lexer {
     pub fn new(input: &str) -> Self {
+        Lexer::new_with_file(input, "<input>")
+    }
+
+    pub fn new_with_file(input: &str, file: &str) -> Self {
         let chars: Vec<char> = input.chars().collect();
-        let first = chars.get(0).copied();
+        let first = chars.first().copied();
 
         Lexer {
             current_char: first,
             index: 0, 
-            input: chars,
+            input: chars.into(),
+            line: 1,
+            column: 1,
+            source: Rc::new(Source::new(file, input)),
+            peeked: None,
         }
     }
 
+    pub fn peek(&self) -> Option<char> {
+        self.input.get(self.index as usize + 1).copied()
+    }
+
     pub fn advance(&mut self) {
+        if self.current_char == Some('\n') {
+            self.line += 1;
+            self.column = 1;
+        }
+        else {
+            self.column += 1;
+        }
+
         self.index += 1;
         if self.index < self.input.len().try_into().unwrap() {
             self.current_char = Some(self.input[self.index as usize]);
@@ -54,16 +82,22 @@ impl Lexer {
 
         let kind = match result.as_str() {
             "true" | "false" => Types::TOKEN_BOOL,
+            "xor" => Types::TOKEN_XOR,
             _ => Types::TOKEN_ID,
         };
 
         Token::new(kind, result)
     }
 
+    // The whole literal is collected, 0xFF, 0b1010, 1_000 or 1.5e-3, and the parser checks it
     pub fn collect_num(&mut self) -> Token {
         let mut result = String::new();
+        let radix = matches!(self.peek(), Some('x' | 'X' | 'b' | 'B')) && self.current_char == Some('0');
+
         while let Some(c) = self.current_char {
-            if(c.is_ascii_digit() || c == '.') {
+            let exponent_sign = !radix && matches!(c, '+' | '-') && result.ends_with(['e', 'E']);
+
+            if c.is_ascii_alphanumeric() || c == '_' || c == '.' || exponent_sign {
                 result.push(c);
                 self.advance();
             }
@@ -72,59 +106,384 @@ impl Lexer {
             }
         }
 
-        if result.contains('.') {
+        if !radix && (result.contains('.') || result.contains(['e', 'E'])) {
             return Token::new(Types::TOKEN_FLOAT, result);
         }
         
         Token::new(Types::TOKEN_INT, result)
     }
 
-    pub fn next_token(&mut self) -> Token {
+    // Block comments nest, so commenting out code that has comments in it works
+    pub fn skip_comment(&mut self) -> IronResult<()> {
+        let (line, column) = (self.line, self.column);
+        let mut depth = 0;
+
+        loop {
+            match (self.current_char, self.peek()) {
+                (Some('/'), Some('*')) => depth += 1,
+                (Some('*'), Some('/')) => depth -= 1,
+                (Some(_), _) => {
+                    self.advance();
+                    continue;
+                }
+                (None, _) => return Err(self.error_at("Unterminated block comment", line, column, 2)),
+            }
+
+            self.advance();
+            self.advance();
+
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    // Runs to the end of the line, and hands back the text of a /// doc comment
+    pub fn skip_line_comment(&mut self) -> Option<String> {
+        let start = self.index as usize;
+
+        while self.current_char.is_some_and(|c| c != '\n') {
+            self.advance();
+        }
+
+        let text: String = self.input[start..self.index as usize].iter().collect();
+        let doc = text.strip_prefix("///").filter(|rest| !rest.starts_with('/'))?;
+
+        Some(doc.strip_prefix(' ').unwrap_or(doc).trim_end().to_string())
+    }
+
+    pub fn next_token(&mut self) -> IronResult<Token> {
+        if let Some(token) = self.peeked.take() {
+            return Ok(token);
+        }
+
         self.skip_space();
+        let mut doc = vec![];
 
-        match self.current_char {
-            Some('"') => {
-                self.advance() ;
-                let mut string = String::new();
+        loop {
+            match (self.current_char, self.peek()) {
+                (Some('/'), Some('*')) => self.skip_comment()?,
+                (Some('/'), Some('/')) => doc.extend(self.skip_line_comment()),
+                _ => break,
+            }
 
-                while let Some(c) = self.current_char {
-                    if c == '"' {
-                        break;
-                    }
+            self.skip_space();
+        }
+
+        let (line, column, start) = (self.line, self.column, self.index);
+
+        let token = self.collect_token();
+        let span = Some(Span::new(self.source.clone(), line, column, (self.index - start).max(1) as usize));
+
+        // Errors that don't point anywhere more exact point at the whole token
+        let mut token = token.map_err(|e| e.with_span(&span))?;
+
+        // The lexer hands back EOF with the offending text for anything it does not know
+        if token.kind == Types::TOKEN_EOF && !token.value.is_empty() {
+            return Err(IronError::LexError(format!("Unexpected character '{}'", token.value), span));
+        }
+
+        token.span = span;
+        token.doc = (!doc.is_empty()).then(|| doc.join("\n"));
+
+        Ok(token)
+    }
+
+    // The token next_token will hand back, without moving on
+    pub fn peek_token(&mut self) -> IronResult<Token> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.next_token()?);
+        }
+
+        Ok(self.peeked.clone().unwrap())
+    }
+
+    // Every token with the text it's written as, comments included, so iron fmt can print the file back out
+    pub fn source_tokens(&mut self) -> IronResult<Vec<Token>> {
+        let mut tokens = vec![];
+
+        loop {
+            self.skip_space();
+            let (line, column, start) = (self.line, self.column, self.index);
+
+            let kind = match (self.current_char, self.peek()) {
+                (Some('/'), Some('*')) => {
+                    self.skip_comment()?;
+                    Types::TOKEN_COMMENT
+                }
+                (Some('/'), Some('/')) => {
+                    self.skip_line_comment();
+                    Types::TOKEN_COMMENT
+                }
+                _ => match self.next_token()?.kind {
+                    Types::TOKEN_EOF => return Ok(tokens),
+                    kind => kind,
+                },
+            };
+
+            let text: String = self.input[start as usize..self.index as usize].iter().collect();
+            let mut token = Token::new(kind, text);
+            token.span = Some(Span::new(self.source.clone(), line, column, (self.index - start) as usize));
+            tokens.push(token);
+        }
+    }
+
+    // A lexer over the same file that starts at an earlier spot, for the expressions in an f-string
+    pub fn at(&self, index: u64, line: usize, column: usize) -> Lexer {
+        let mut lexer = self.clone();
+
+        lexer.index = index;
+        lexer.current_char = self.input.get(index as usize).copied();
+        lexer.line = line;
+        lexer.column = column;
+        lexer.peeked = None;
+        lexer
+    }
+
+    // An error pointing at len characters from where the lexer is
+    fn error_at(&self, message: &str, line: usize, column: usize, len: usize) -> IronError {
+        IronError::LexError(message.to_string(), Some(Span::new(self.source.clone(), line, column, len)))
+    }
+
+    pub fn collect_string(&mut self) -> IronResult<Token> {
+        self.advance();
+        let mut string = String::new();
+
+        loop {
+            match self.current_char {
+                Some('"') => break,
+                Some('\\') => string.push(self.escape()?),
+                Some(c) => {
                     string.push(c);
-                    self.advance()
+                    self.advance();
+                }
+                None => return Err(IronError::LexError("Unterminated string literal".to_string(), None)),
+            }
+        }
+
+        self.advance();
+        Ok(Token::new(Types::TOKEN_STRING, string))
+    }
+
+    // r"C:\dir" keeps its backslashes, and r#"say "hi""# can hold quotes up to a " with as many #
+    pub fn collect_raw_string(&mut self) -> IronResult<Token> {
+        let (line, column) = (self.line, self.column);
+        self.advance();
+
+        let mut hashes = 0;
+        while self.current_char == Some('#') {
+            hashes += 1;
+            self.advance();
+        }
+
+        if self.current_char != Some('"') {
+            return Err(self.error_at("Expected \" to start the raw string", line, column, hashes + 1));
+        }
+
+        self.advance();
+        let mut string = String::new();
+
+        loop {
+            match self.current_char {
+                Some('"') if self.closes_raw(hashes) => break,
+                Some(c) => {
+                    string.push(c);
+                    self.advance();
+                }
+                None => return Err(IronError::LexError("Unterminated string literal".to_string(), None)),
+            }
+        }
+
+        for _ in 0..=hashes {
+            self.advance();
+        }
+
+        Ok(Token::new(Types::TOKEN_STRING, string))
+    }
+
+    fn closes_raw(&self, hashes: usize) -> bool {
+        (1..=hashes).all(|i| self.input.get(self.index as usize + i) == Some(&'#'))
+    }
+
+    pub fn collect_char(&mut self) -> IronResult<Token> {
+        let (line, column) = (self.line, self.column);
+        self.advance();
+
+        let c = match self.current_char {
+            Some('\'') => return Err(self.error_at("Empty char literal", line, column, 2)),
+            Some('\\') => self.escape()?,
+            Some(c) if c != '\n' => {
+                self.advance();
+                c
+            }
+            _ => return Err(IronError::LexError("Unterminated char literal".to_string(), None)),
+        };
+
+        if self.current_char != Some('\'') {
+            let closed = self.input[self.index as usize..].iter().take_while(|c| **c != '\n').any(|c| *c == '\'');
+            let message = if closed { "A char literal holds exactly one character" } else { "Unterminated char literal" };
+            return Err(IronError::LexError(message.to_string(), None));
+        }
+
+        self.advance();
+        Ok(Token::new(Types::TOKEN_CHAR, c.to_string()))
+    }
+
+    // f"Money: {money}", the text is collected here and each {} is left for the parser to read where it sits
+    pub fn collect_fstring(&mut self) -> IronResult<Token> {
+        let start = self.index as usize + 2;
+        self.advance();
+        self.advance();
+
+        let mut pieces = vec![];
+        let mut text = String::new();
+
+        loop {
+            match self.current_char {
+                Some('"') => break,
+                Some(c @ ('{' | '}')) if self.peek() == Some(c) => {
+                    text.push(c);
+                    self.advance();
+                    self.advance();
+                }
+                Some('}') => return Err(self.error_at("A } in an f-string is written }}", self.line, self.column, 1)),
+                Some('{') => {
+                    let (line, column) = (self.line, self.column);
+                    self.advance();
+
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    pieces.push(Piece::Code { index: self.index, line: self.line, column: self.column });
+
+                    if !self.skip_code()? {
+                        return Err(self.error_at("Empty {} in an f-string", line, column, 2));
+                    }
+                }
+                Some('\\') => text.push(self.escape()?),
+                Some(c) => {
+                    text.push(c);
+                    self.advance();
+                }
+                None => return Err(IronError::LexError("Unterminated string literal".to_string(), None)),
+            }
+        }
+
+        let raw: String = self.input[start..self.index as usize].iter().collect();
+        self.advance();
+
+        pieces.push(Piece::Text(text));
+        pieces.retain(|piece| *piece != Piece::Text(String::new()));
+
+        let mut token = Token::new(Types::TOKEN_FSTRING, raw);
+        token.pieces = pieces;
+        Ok(token)
+    }
+
+    // Goes past an embedded expression and its }, strings in it can hold braces. False when it was empty
+    fn skip_code(&mut self) -> IronResult<bool> {
+        let (line, column) = (self.line, self.column);
+        let mut depth = 0;
+        let mut empty = true;
+
+        loop {
+            match self.current_char {
+                Some('}') if depth == 0 => break,
+                Some(quote @ ('"' | '\'')) => {
+                    empty = false;
+                    self.advance();
+
+                    while self.current_char.is_some_and(|c| c != quote) {
+                        if self.current_char == Some('\\') {
+                            self.advance();
+                        }
+                        self.advance();
+                    }
+                }
+                Some(c) => {
+                    depth += (c == '{') as i32 - (c == '}') as i32;
+                    empty &= c.is_whitespace();
                 }
+                None => return Err(self.error_at("Unterminated {} in an f-string", line, column - 1, 1)),
+            }
+
+            self.advance();
+        }
+
+        self.advance();
+        Ok(!empty)
+    }
+
+    // The character a \ stands for, \u{1F600} is any unicode character by its hex code
+    fn escape(&mut self) -> IronResult<char> {
+        let (line, column) = (self.line, self.column);
+        self.advance();
+
+        let c = match self.current_char {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c @ ('\\' | '"' | '\'')) => c,
+            Some('u') => return self.unicode_escape(line, column),
+            Some(c) => return Err(self.error_at(&format!("Unknown escape \\{}", c), line, column, 2)),
+            None => return Err(IronError::LexError("Unterminated string literal".to_string(), None)),
+        };
+
+        self.advance();
+        Ok(c)
+    }
+
+    fn unicode_escape(&mut self, line: usize, column: usize) -> IronResult<char> {
+        self.advance();
+        let mut digits = String::new();
+
+        if self.current_char == Some('{') {
+            self.advance();
 
+            while let Some(c) = self.current_char.filter(|c| c.is_ascii_hexdigit()) {
+                digits.push(c);
                 self.advance();
-                Token::new(Types::TOKEN_STRING, string)
             }
+        }
+
+        let c = u32::from_str_radix(&digits, 16).ok().filter(|_| digits.len() <= 6).and_then(char::from_u32);
+
+        match c {
+            Some(c) if self.current_char == Some('}') => {
+                self.advance();
+                Ok(c)
+            }
+            _ => Err(self.error_at("Invalid unicode escape, it's written \\u{1F600}", line, column, digits.len() + 2)),
+        }
+    }
+
+    fn collect_token(&mut self) -> IronResult<Token> {
+        match self.current_char {
+            Some('"') => self.collect_string(),
+            Some('\'') => self.collect_char(),
+            Some('r') if matches!(self.peek(), Some('"' | '#')) => self.collect_raw_string(),
+            Some('f') if self.peek() == Some('"') => self.collect_fstring(),
+            _ => Ok(self.collect_symbol()),
+        }
+    }
+
+    fn collect_symbol(&mut self) -> Token {
+        match self.current_char {
             Some('%') => {
                 self.advance();
+
+                if self.current_char == Some('=') {
+                    self.advance();
+                    return Token::new(Types::TOKEN_PERCENT_EQUALS, "%=".to_string());
+                }
+
                 Token::new(Types::TOKEN_PERCENT, "%".to_string())
             }
             Some('/') => {
                 self.advance();
 
-                 if self.current_char == Some('*') {
-                    self.advance(); 
-
-                    loop {
-                        match self.current_char {
-                            Some('*') => {
-                                self.advance(); 
-                                if self.current_char == Some('/') {
-                                    self.advance(); 
-                                    break; 
-                                }
-                            }
-                            Some(_) => {
-                                self.advance();
-                            }
-                            None => break, 
-                        }
-                    }
-
-                    return self.next_token(); 
+                if self.current_char == Some('=') {
+                    self.advance();
+                    return Token::new(Types::TOKEN_FSLASH_EQUALS, "/=".to_string());
                 }
 
                 Token::new(Types::TOKEN_FSLASH, "/".to_string())
@@ -152,7 +511,7 @@ impl Lexer {
                     return Token::new(Types::TOKEN_AND, "&&".to_string());
                 }
                 
-                Token::new(Types::TOKEN_EOF, "".to_string())
+                Token::new(Types::TOKEN_AMPERSAND, "&".to_string())
             }
             Some('|') => {
                 self.advance();
@@ -161,7 +520,11 @@ impl Lexer {
                     return Token::new(Types::TOKEN_OR, "||".to_string());
                 }
                 
-                Token::new(Types::TOKEN_EOF, "".to_string())
+                Token::new(Types::TOKEN_PIPE, "|".to_string())
+            }
+            Some('~') => {
+                self.advance();
+                Token::new(Types::TOKEN_TILDE, "~".to_string())
             }
             Some('=') => {
                 self.advance();
@@ -169,6 +532,10 @@ impl Lexer {
                     self.advance(); 
                     return Token::new(Types::TOKEN_EE, "==".to_string());
                 }
+                if self.current_char == Some('>') {
+                    self.advance();
+                    return Token::new(Types::TOKEN_FAT_ARROW, "=>".to_string());
+                }
                 Token::new(Types::TOKEN_EQUALS, "=".to_string())
             }
             Some(';') => {
@@ -183,12 +550,25 @@ impl Lexer {
                     return Token::new(Types::TOKEN_INCREMENT, "++".to_string());
                 }
 
+                if self.current_char == Some('=') {
+                    self.advance();
+                    return Token::new(Types::TOKEN_ADD_EQUALS, "+=".to_string());
+                }
+
                 Token::new(Types::TOKEN_ADD, "+".to_string())
             }
             Some(',') => {
                 self.advance();
                 Token::new(Types::TOKEN_COMMA, ",".to_string())
             }
+            Some(':') => {
+                self.advance();
+                Token::new(Types::TOKEN_COLON, ":".to_string())
+            }
+            Some('?') => {
+                self.advance();
+                Token::new(Types::TOKEN_QUESTION, "?".to_string())
+            }
             Some('-') => {
                 self.advance();
 
@@ -197,11 +577,26 @@ impl Lexer {
                     return Token::new(Types::TOKEN_DECREMENT, "--".to_string());
                 }
 
+                if self.current_char == Some('>') {
+                    self.advance();
+                    return Token::new(Types::TOKEN_ARROW, "->".to_string());
+                }
+
+                if self.current_char == Some('=') {
+                    self.advance();
+                    return Token::new(Types::TOKEN_SUBTRACT_EQUALS, "-=".to_string());
+                }
+
                 Token::new(Types::TOKEN_SUBTRACT, "-".to_string())
             }
             Some('*') => {
                 self.advance();
 
+                if self.current_char == Some('=') {
+                    self.advance();
+                    return Token::new(Types::TOKEN_ASTERISK_EQUALS, "*=".to_string());
+                }
+
                 Token::new(Types::TOKEN_ASTERISK, "*".to_string())
             }
             Some('(') => {
@@ -231,6 +626,11 @@ impl Lexer {
                     return Token::new(Types::TOKEN_LEQ, "<=".to_string());
                 }
 
+                if self.current_char == Some('<') {
+                    self.advance();
+                    return Token::new(Types::TOKEN_SHIFT_LEFT, "<<".to_string());
+                }
+
                 Token::new(Types::TOKEN_LESS_THAN, "<".to_string())
             }
             Some('>') => {
@@ -241,6 +641,12 @@ impl Lexer {
                     return Token::new(Types::TOKEN_GEQ, ">=".to_string());
                 }
 
+                // The parser splits this back into two > when it closes a map<K, map<K, V>>
+                if self.current_char == Some('>') {
+                    self.advance();
+                    return Token::new(Types::TOKEN_SHIFT_RIGHT, ">>".to_string());
+                }
+
                 Token::new(Types::TOKEN_GREATER_THAN, ">".to_string())
             }
             Some('[') => {
@@ -253,6 +659,12 @@ impl Lexer {
             }
             Some('^') => {
                 self.advance(); 
+
+                if self.current_char == Some('=') {
+                    self.advance();
+                    return Token::new(Types::TOKEN_CARROT_EQUALS, "^=".to_string());
+                }
+
                 Token::new(Types::TOKEN_CARROT, "^".to_string()) 
             }
             Some(c) if c.is_ascii_digit() => self.collect_num(),
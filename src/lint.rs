@@ -0,0 +1,690 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::ast::{AST, Ast_Type, Data_Type};
+use crate::error::{IronError, IronResult};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::scope::{Scope, SharedScope};
+use crate::span::{self, Source, Span};
+
+/*
+    `iron lint` walks the parsed AST for code that runs but is probably a
+    mistake, like a variable nobody reads or a line after a return. Each
+    rule can be turned off in a .ironlint file where iron is run from,
+    one `rule = off` per line
+*/
+
+pub const CONFIG: &str = ".ironlint";
+
+pub const RULES: [&str; 7] = [
+    "unused_variable",
+    "unused_import",
+    "shadowed_name",
+    "unreachable_code",
+    "constant_condition",
+    "missing_return",
+    "const_assignment",
+];
+
+pub struct Warning {
+    pub rule: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Warning {
+    pub fn render(&self) -> String {
+        span::render(&self.span, &format!("warning[{}]: {}", self.rule, self.message))
+    }
+}
+
+// The rules that are on, every one unless the config turns it off
+pub fn config(path: &Path) -> IronResult<HashSet<&'static str>> {
+    let mut rules: HashSet<&'static str> = RULES.into_iter().collect();
+
+    let Ok(text) = fs::read_to_string(path) else {
+        return Ok(rules);
+    };
+
+    let source = Rc::new(Source::new(&path.to_string_lossy(), &text));
+
+    for (i, line) in text.lines().enumerate() {
+        let setting = line.split('#').next().unwrap_or("").trim();
+        let span = Some(Span::new(source.clone(), i + 1, line.len() - line.trim_start().len() + 1, setting.chars().count().max(1)));
+
+        if setting.is_empty() {
+            continue;
+        }
+
+        let Some((name, value)) = setting.split_once('=') else {
+            return Err(IronError::ParseError("Expected a rule = on or a rule = off".to_string(), span));
+        };
+
+        let Some(rule) = RULES.into_iter().find(|rule| *rule == name.trim()) else {
+            return Err(IronError::NameError(format!("Unknown lint rule {}", name.trim()), span));
+        };
+
+        match value.trim() {
+            "on" => rules.insert(rule),
+            "off" => rules.remove(rule),
+            value => return Err(IronError::ParseError(format!("A rule is either on or off, not {}", value), span)),
+        };
+    }
+
+    Ok(rules)
+}
+
+// Lints every .iron and .steel file under paths, a file that doesn't parse is an error instead
+pub fn lint_files(paths: &[String], rules: &HashSet<&'static str>) -> (Vec<Warning>, Vec<IronError>) {
+    let mut files = vec![];
+    let mut warnings = vec![];
+    let mut errors = vec![];
+
+    for path in paths {
+        if let Err(e) = crate::doc::sources(Path::new(path), &mut files) {
+            errors.push(e);
+        }
+    }
+
+    for file in files {
+        match lint_file(&file.to_string_lossy()) {
+            Ok(found) => warnings.extend(found.into_iter().filter(|w| rules.contains(w.rule))),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    (warnings, errors)
+}
+
+// The parser's global scope is kept so the imports it saw can be checked against what was used
+fn lint_file(path: &str) -> IronResult<Vec<Warning>> {
+    let source = fs::read_to_string(path)
+        .map_err(|_| IronError::ImportError(format!("Could not read the file {}", path), None))?;
+
+    let mut lexer = Lexer::new_with_file(&source, path);
+    let scope = Rc::new(RefCell::new(Scope::new()));
+    let ast = Parser::new(&mut lexer, scope.clone())?.parse()?;
+
+    Ok(lint(&ast, &scope))
+}
+
+pub fn lint(ast: &AST, scope: &SharedScope) -> Vec<Warning> {
+    let mut linter = Linter::new();
+
+    linter.block(ast);
+    linter.end_globals();
+    linter.imports(&scope.borrow().imports);
+
+    let mut warnings = linter.warnings;
+    warnings.sort_by_key(|w| w.span.as_ref().map(|s| (s.line, s.column)));
+    warnings
+}
+
+struct Variable {
+    name: String,
+    span: Option<Span>,
+    used: bool,
+    is_const: bool,
+    argument: bool, // a function's, reported as an argument instead of a variable
+    checked: bool, // false for names like a catch's error that don't have to be used
+}
+
+struct Linter {
+    warnings: Vec<Warning>,
+    scopes: Vec<Vec<Variable>>,
+    function_base: usize, // scopes below this belong to the function we're nested in, only the globals at 0 stay visible
+    fields: Vec<(String, bool)>, // the fields of the class whose methods we're in, and whether they're const
+    class: Option<String>,
+    unresolved: HashSet<String>, // names a function used before the global they refer to was declared
+    modules: HashSet<String>, // names used on the left of a dot that aren't variables, like trig in trig.sin(x)
+    calls: HashSet<String>, // every function called by name
+}
+
+impl Linter {
+    fn new() -> Self {
+        Linter {
+            warnings: vec![],
+            scopes: vec![vec![]],
+            function_base: 0,
+            fields: vec![],
+            class: None,
+            unresolved: HashSet::new(),
+            modules: HashSet::new(),
+            calls: HashSet::new(),
+        }
+    }
+
+    fn warn(&mut self, rule: &'static str, message: String, span: &Option<Span>) {
+        self.warnings.push(Warning { rule, message, span: span.clone() });
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(vec![]);
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("No scope open");
+        self.unused(scope);
+    }
+
+    // Globals can be read by a function defined before them, so they're only reported once everything's been seen
+    fn end_globals(&mut self) {
+        let mut globals = std::mem::take(&mut self.scopes[0]);
+
+        for variable in &mut globals {
+            variable.used |= self.unresolved.contains(&variable.name);
+        }
+
+        self.unused(globals);
+    }
+
+    fn unused(&mut self, scope: Vec<Variable>) {
+        for variable in scope {
+            if variable.used || !variable.checked || variable.name.starts_with('_') {
+                continue;
+            }
+
+            let what = if variable.argument { "Argument" } else { "Variable" };
+            self.warn("unused_variable", format!("{} {} is never used", what, variable.name), &variable.span);
+        }
+    }
+
+    // Same order the compiler resolves names in: locals, then fields, then globals
+    fn find(&self, name: &str) -> Option<(usize, usize)> {
+        for i in (self.function_base..self.scopes.len()).rev() {
+            if let Some(j) = self.scopes[i].iter().position(|v| v.name == name) {
+                return Some((i, j));
+            }
+        }
+
+        if self.field(name).is_some() {
+            return None;
+        }
+
+        self.scopes[0].iter().position(|v| v.name == name).map(|j| (0, j))
+    }
+
+    fn field(&self, name: &str) -> Option<bool> {
+        self.fields.iter().find(|(field, _)| field == name).map(|(_, is_const)| *is_const)
+    }
+
+    fn declare(&mut self, name: &str, node: &AST, argument: bool, checked: bool) {
+        let outer = self.find(name).map(|(i, j)| (i, self.scopes[i][j].span.clone()));
+        let current = self.scopes.len() - 1;
+
+        // Declaring it again in the same scope replaces it, so the two count as one variable
+        if let Some((i, _)) = outer.as_ref().filter(|(i, _)| *i == current) {
+            let j = self.scopes[*i].iter().position(|v| v.name == name).unwrap();
+            self.scopes[*i][j].is_const = node.is_const == Some(true);
+            return;
+        }
+
+        if let Some((_, span)) = outer {
+            let message = match span {
+                Some(span) => format!("{} shadows the {} declared on line {}", name, name, span.line),
+                None => format!("{} shadows an earlier {}", name, name),
+            };
+
+            self.warn("shadowed_name", message, &node.span);
+        }
+        else if self.field(name).is_some() {
+            let class = self.class.clone().unwrap_or_default();
+            self.warn("shadowed_name", format!("{} shadows the field {} of {}", name, name, class), &node.span);
+        }
+
+        self.scopes[current].push(Variable {
+            name: name.to_string(),
+            span: node.span.clone(),
+            used: false,
+            is_const: node.is_const == Some(true),
+            argument,
+            checked,
+        });
+    }
+
+    fn read(&mut self, name: &str) {
+        match self.find(name) {
+            Some((i, j)) => self.scopes[i][j].used = true,
+            None if self.field(name).is_none() => {
+                self.unresolved.insert(name.to_string());
+            }
+            None => {}
+        }
+    }
+
+    // x = ..., x += ... and x++ all store into x, only the last two read it as well
+    fn write(&mut self, name: &str, reads: bool, span: &Option<Span>) {
+        let is_const = match self.find(name) {
+            Some((i, j)) => {
+                self.scopes[i][j].used |= reads;
+                self.scopes[i][j].is_const
+            }
+            None => self.field(name).unwrap_or(false),
+        };
+
+        if is_const {
+            self.warn("const_assignment", format!("{} is const and can't be assigned", name), span);
+        }
+    }
+
+    fn block(&mut self, node: &AST) {
+        if node.ast_type != Ast_Type::AST_COMPOUND {
+            self.statement(node);
+            return;
+        }
+
+        let statements = node.compound_value.as_deref().unwrap_or(&[]);
+
+        for (i, stmt) in statements.iter().enumerate() {
+            self.statement(stmt);
+
+            let Some(what) = leaves(stmt) else {
+                continue;
+            };
+
+            // Functions and classes can still be used from before the return
+            let rest = statements[i + 1..].iter()
+                .find(|s| !matches!(s.ast_type, Ast_Type::AST_FUNCTION_DEF | Ast_Type::AST_CLASS_DEF));
+
+            if let Some(unreachable) = rest {
+                self.warn("unreachable_code", format!("Unreachable code after {}", what), &unreachable.span);
+            }
+
+            for stmt in &statements[i + 1..] {
+                self.statement(stmt);
+            }
+
+            return;
+        }
+    }
+
+    fn statement(&mut self, node: &AST) {
+        match node.ast_type {
+            Ast_Type::AST_VARIABLE_DEF => {
+                if let Some(value) = node.variable_definition_value.as_ref() {
+                    self.expression(value);
+                }
+
+                self.declare(node.variable_definition_variable_name.as_ref().unwrap(), node, false, true);
+            }
+            Ast_Type::AST_ARRAY_DEF => {
+                self.children(node);
+                self.declare(node.array_name.as_ref().unwrap(), node, false, true);
+            }
+            Ast_Type::AST_FUNCTION_DEF => self.function(node),
+            Ast_Type::AST_CLASS_DEF => self.class_definition(node),
+            Ast_Type::AST_COMPOUND => self.block(node),
+            Ast_Type::AST_IF => {
+                self.condition(node.if_condition.as_ref().unwrap());
+                self.block(node.if_body.as_ref().unwrap());
+
+                if let Some(else_body) = node.else_body.as_ref() {
+                    self.block(else_body);
+                }
+            }
+            Ast_Type::AST_WHILE => {
+                let condition = node.while_condition.as_ref().unwrap();
+                let body = node.while_body.as_ref().unwrap();
+
+                if condition.ast_type == Ast_Type::AST_BOOL && condition.bool_value == Some(true) {
+                    if !exits(body, node.loop_label.as_deref(), &mut vec![], true) {
+                        self.warn("constant_condition", "while (true) never ends, nothing in it breaks out".to_string(), &condition.span);
+                    }
+                }
+                else {
+                    self.condition(condition);
+                }
+
+                self.block(body);
+            }
+            Ast_Type::AST_FOR => {
+                self.begin_scope();
+                self.statement(node.for_init.as_ref().unwrap());
+                self.condition(node.for_condition.as_ref().unwrap());
+                self.block(node.for_body.as_ref().unwrap());
+                self.statement(node.for_increment.as_ref().unwrap());
+                self.end_scope();
+            }
+            Ast_Type::AST_FOR_EACH => {
+                self.expression(node.for_iterable.as_ref().unwrap());
+                self.begin_scope();
+
+                let variable = node.for_init.as_ref().unwrap();
+                self.declare(variable.variable_definition_variable_name.as_ref().unwrap(), variable, false, true);
+
+                self.block(node.for_body.as_ref().unwrap());
+                self.end_scope();
+            }
+            Ast_Type::AST_MATCH => {
+                self.expression(node.match_value.as_ref().unwrap());
+
+                for case in node.match_cases.as_deref().unwrap_or(&[]) {
+                    for pattern in case.case_patterns.as_deref().unwrap_or(&[]) {
+                        self.expression(pattern);
+                    }
+
+                    self.block(case.case_body.as_ref().unwrap());
+                }
+            }
+            Ast_Type::AST_TRY => {
+                self.block(node.try_body.as_ref().unwrap());
+
+                if let Some(catch_body) = node.catch_body.as_ref() {
+                    self.begin_scope();
+                    self.declare(node.catch_name.as_ref().unwrap(), node, false, false);
+                    self.block(catch_body);
+                    self.end_scope();
+                }
+
+                if let Some(finally_body) = node.finally_body.as_ref() {
+                    self.block(finally_body);
+                }
+            }
+            Ast_Type::AST_IMPORT | Ast_Type::AST_BREAK | Ast_Type::AST_CONTINUE | Ast_Type::AST_NOOP => {}
+            _ => {
+                self.expression(node);
+
+                // Shop s = new Shop(1) comes back as the value with the name stuck on it
+                if let (Some(name), Some(_)) = (node.variable_definition_variable_name.as_ref(), node.class_name.as_ref()) {
+                    self.declare(name, node, false, true);
+                }
+            }
+        }
+    }
+
+    // if (true) and while (1 > 2) always go the same way, while (true) is left to the loop to check
+    fn condition(&mut self, node: &AST) {
+        if is_constant(node) {
+            let message = match node.bool_value {
+                Some(value) if node.ast_type == Ast_Type::AST_BOOL => format!("Condition is always {}", value),
+                _ => "Condition only uses constants, so it never changes".to_string(),
+            };
+
+            self.warn("constant_condition", message, &node.span);
+        }
+
+        self.expression(node);
+    }
+
+    fn function(&mut self, node: &AST) {
+        let name = node.function_definition_name.clone().unwrap();
+        let body = node.function_definition_body.as_ref().unwrap();
+        let saved_base = self.function_base;
+
+        self.begin_scope();
+        self.function_base = self.scopes.len() - 1;
+
+        for arg in node.function_definition_args.as_deref().unwrap_or(&[]) {
+            self.declare(arg.variable_definition_variable_name.as_ref().unwrap(), arg, true, true);
+        }
+
+        self.block(body);
+
+        self.end_scope();
+        self.function_base = saved_base;
+
+        let declared = node.function_return_type.as_ref().filter(|t| **t != Data_Type::VOID);
+
+        if (declared.is_some() || returns_value(body)) && leaves(body).is_none() {
+            let message = match declared {
+                Some(t) => format!("Function {} should return {} but can reach its end without returning", name, t.name()),
+                None => format!("Function {} returns a value on some paths but can reach its end without one", name),
+            };
+
+            self.warn("missing_return", message, &node.span);
+        }
+    }
+
+    // Fields are set up with only the constructor arguments in view, methods see every field
+    fn class_definition(&mut self, node: &AST) {
+        let args = node.class_definition_args.as_deref().unwrap_or(&[]);
+        let body = node.class_definition_body.as_ref().and_then(|b| b.compound_value.as_deref()).unwrap_or(&[]);
+        let saved_base = self.function_base;
+        let saved_fields = std::mem::take(&mut self.fields);
+        let saved_class = self.class.replace(node.class_definition_name.clone().unwrap());
+
+        self.begin_scope();
+        self.function_base = self.scopes.len() - 1;
+
+        for arg in args {
+            self.declare(arg.variable_definition_variable_name.as_ref().unwrap(), arg, false, false);
+        }
+
+        for stmt in body {
+            if stmt.ast_type == Ast_Type::AST_FUNCTION_DEF {
+                continue;
+            }
+
+            let name = stmt.variable_definition_variable_name.as_ref().or(stmt.array_name.as_ref());
+
+            // A field is read from outside the class as much as inside, so it's never unused
+            self.children(stmt);
+
+            if let Some(name) = name {
+                self.fields.push((name.clone(), stmt.is_const == Some(true)));
+            }
+        }
+
+        self.end_scope();
+        self.function_base = saved_base;
+
+        for arg in args {
+            self.fields.push((arg.variable_definition_variable_name.clone().unwrap(), false));
+        }
+
+        for stmt in body {
+            if stmt.ast_type == Ast_Type::AST_FUNCTION_DEF {
+                self.function(stmt);
+            }
+        }
+
+        self.fields = saved_fields;
+        self.class = saved_class;
+    }
+
+    fn expression(&mut self, node: &AST) {
+        match node.ast_type {
+            Ast_Type::AST_VARIABLE => self.read(node.variable_name.as_ref().unwrap()),
+            Ast_Type::AST_REASSIGN => {
+                self.expression(node.reassign_value.as_ref().unwrap());
+                self.write(node.reassign_name.as_ref().unwrap(), false, &node.span);
+            }
+            Ast_Type::AST_INCREMENT | Ast_Type::AST_DECREMENT => self.write(node.reassign_name.as_ref().unwrap(), true, &node.span),
+            Ast_Type::AST_COMPOUND_ASSIGN => {
+                let target = node.left.as_ref().unwrap();
+
+                if target.ast_type == Ast_Type::AST_VARIABLE {
+                    self.write(target.variable_name.as_ref().unwrap(), true, &node.span);
+                }
+                else {
+                    self.expression(target);
+                }
+
+                self.expression(node.right.as_ref().unwrap());
+            }
+            Ast_Type::AST_ARRAY_ACCESS => {
+                // p.items[i] reads p through the field instead
+                if node.array_field.is_none() {
+                    self.read(node.array_name.as_ref().unwrap());
+                }
+                self.children(node);
+            }
+            Ast_Type::AST_FUNCTION_CALL => {
+                self.calls.insert(node.function_call_name.clone().unwrap());
+                self.children(node);
+            }
+            Ast_Type::AST_DOT | Ast_Type::AST_CLASS_ACCESS => {
+                let left = node.dot_left.as_ref().unwrap();
+                let right = node.dot_right.as_ref().unwrap();
+
+                match left.ast_type {
+                    Ast_Type::AST_VARIABLE if self.find(left.variable_name.as_ref().unwrap()).is_none() => {
+                        self.modules.insert(left.variable_name.clone().unwrap());
+                        self.read(left.variable_name.as_ref().unwrap());
+                    }
+                    _ => self.expression(left),
+                }
+
+                // The right side is a field or a method of the left, not a name in scope
+                if right.ast_type == Ast_Type::AST_FUNCTION_CALL {
+                    self.children(right);
+                }
+
+                if let Some(value) = node.reassign_value.as_ref() {
+                    self.expression(value);
+                }
+            }
+            Ast_Type::AST_TERNARY => {
+                self.condition(node.if_condition.as_ref().unwrap());
+                self.expression(node.if_body.as_ref().unwrap());
+                self.expression(node.else_body.as_ref().unwrap());
+            }
+            Ast_Type::AST_VARIABLE_DEF
+            | Ast_Type::AST_FUNCTION_DEF
+            | Ast_Type::AST_CLASS_DEF
+            | Ast_Type::AST_COMPOUND
+            | Ast_Type::AST_IF
+            | Ast_Type::AST_WHILE
+            | Ast_Type::AST_FOR
+            | Ast_Type::AST_FOR_EACH
+            | Ast_Type::AST_MATCH
+            | Ast_Type::AST_TRY
+            | Ast_Type::AST_IMPORT => self.statement(node),
+            _ => self.children(node),
+        }
+    }
+
+    // Everything under a node that's only read, like the operands of a binary or the args of a call
+    fn children(&mut self, node: &AST) {
+        let boxed = [
+            &node.variable_definition_value, &node.left, &node.right, &node.return_value, &node.throw_value,
+            &node.array_index, &node.array_slice_end, &node.array_assign_value, &node.reassign_value, &node.array_field,
+        ];
+        let lists = [
+            &node.function_call_args, &node.array_elements, &node.array_path, &node.map_keys, &node.map_values, &node.class_args,
+        ];
+
+        for child in boxed.into_iter().flatten() {
+            self.expression(child);
+        }
+
+        for child in lists.into_iter().flatten().flatten() {
+            self.expression(child);
+        }
+    }
+
+    // A library is used through its name, lib.f(), or by calling one of its functions directly
+    fn imports(&mut self, imports: &[AST]) {
+        for import in imports {
+            let name = import.variable_name.clone().unwrap();
+            let exports = if import.is_builtin == Some(true) { vec![] } else { library_functions(&name) };
+
+            if !self.modules.contains(&name) && !exports.iter().any(|f| self.calls.contains(f)) {
+                self.warn("unused_import", format!("Import {} is never used", name), &import.span);
+            }
+        }
+    }
+}
+
+// The functions a library from examples/lib defines, none if it can't be read
+fn library_functions(lib: &str) -> Vec<String> {
+    let Ok(ast) = crate::load_library(lib, &None, Rc::new(RefCell::new(Scope::new()))) else {
+        return vec![];
+    };
+
+    ast.compound_value.unwrap_or_default().into_iter()
+        .filter_map(|stmt| stmt.function_definition_name)
+        .collect()
+}
+
+// What makes the statements after node unreachable, if anything does
+fn leaves(node: &AST) -> Option<&'static str> {
+    let always = |body: &Option<Box<AST>>| body.as_deref().and_then(leaves).is_some();
+
+    match node.ast_type {
+        Ast_Type::AST_RETURN => Some("return"),
+        Ast_Type::AST_THROW => Some("throw"),
+        Ast_Type::AST_BREAK => Some("break"),
+        Ast_Type::AST_CONTINUE => Some("continue"),
+        Ast_Type::AST_COMPOUND => node.compound_value.as_deref().unwrap_or(&[]).iter().find_map(leaves),
+        Ast_Type::AST_IF if always(&node.if_body) && always(&node.else_body) => Some("an if where every branch leaves"),
+        Ast_Type::AST_MATCH => {
+            let cases = node.match_cases.as_deref().unwrap_or(&[]);
+            let default = cases.iter().any(|c| c.case_patterns.as_ref().is_none_or(|p| p.is_empty()));
+
+            (default && cases.iter().all(|c| always(&c.case_body))).then_some("a match where every case leaves")
+        }
+        Ast_Type::AST_TRY => {
+            let finally = always(&node.finally_body);
+            let caught = node.catch_body.is_none() || always(&node.catch_body);
+
+            (finally || always(&node.try_body) && caught).then_some("a try that always leaves")
+        }
+        Ast_Type::AST_WHILE => {
+            let condition = node.while_condition.as_ref().unwrap();
+            let forever = condition.ast_type == Ast_Type::AST_BOOL && condition.bool_value == Some(true);
+
+            (forever && !exits(node.while_body.as_ref().unwrap(), node.loop_label.as_deref(), &mut vec![], false)).then_some("a loop that never ends")
+        }
+        _ => None,
+    }
+}
+
+// Whether a loop body has a break out of the loop, or with returns a return or throw too. inner
+// holds the labels of the loops in the body we've gone into, a break naming one only leaves that one
+fn exits(node: &AST, label: Option<&str>, inner: &mut Vec<Option<String>>, returns: bool) -> bool {
+    let named = |inner: &[Option<String>], target: &str| inner.iter().any(|l| l.as_deref() == Some(target));
+
+    match node.ast_type {
+        Ast_Type::AST_BREAK => match node.loop_label.as_deref() {
+            Some(target) => !named(inner, target),
+            None => inner.is_empty(),
+        },
+        // continue outer from in here leaves this loop for the one it names
+        Ast_Type::AST_CONTINUE => node.loop_label.as_deref().is_some_and(|target| Some(target) != label && !named(inner, target)),
+        Ast_Type::AST_RETURN | Ast_Type::AST_THROW => returns,
+        Ast_Type::AST_FUNCTION_DEF | Ast_Type::AST_CLASS_DEF => false,
+        Ast_Type::AST_WHILE | Ast_Type::AST_FOR | Ast_Type::AST_FOR_EACH => {
+            inner.push(node.loop_label.clone());
+            let found = statements(node).into_iter().any(|child| exits(child, label, inner, returns));
+            inner.pop();
+            found
+        }
+        _ => statements(node).into_iter().any(|child| exits(child, label, inner, returns)),
+    }
+}
+
+// Whether any return in the function, not counting ones nested in other functions, hands back a value
+fn returns_value(node: &AST) -> bool {
+    match node.ast_type {
+        Ast_Type::AST_RETURN => node.return_value.is_some(),
+        Ast_Type::AST_FUNCTION_DEF | Ast_Type::AST_CLASS_DEF => false,
+        _ => statements(node).into_iter().any(returns_value),
+    }
+}
+
+// The statements directly under a node, the bodies of an if, a loop, a match or a try
+fn statements(node: &AST) -> Vec<&AST> {
+    let bodies = [
+        &node.if_body, &node.else_body, &node.while_body, &node.for_body, &node.try_body, &node.catch_body, &node.finally_body,
+    ];
+
+    let mut children: Vec<&AST> = bodies.into_iter().flatten().map(|b| b.as_ref()).collect();
+    children.extend(node.compound_value.as_deref().unwrap_or(&[]));
+    children.extend(node.match_cases.as_deref().unwrap_or(&[]).iter().filter_map(|c| c.case_body.as_deref()));
+    children
+}
+
+// Literals, and operators on nothing but literals
+fn is_constant(node: &AST) -> bool {
+    match node.ast_type {
+        Ast_Type::AST_BOOL | Ast_Type::AST_INT | Ast_Type::AST_FLOAT | Ast_Type::AST_STRING => true,
+        Ast_Type::AST_BINARY => node.left.as_deref().is_some_and(is_constant) && node.right.as_deref().is_some_and(is_constant),
+        Ast_Type::AST_UNARY => node.right.as_deref().is_some_and(is_constant),
+        _ => false,
+    }
+}
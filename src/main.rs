@@ -1,40 +1,281 @@
-#![allow(non_snake_case, non_camel_case_types, non_upper_case_globals, unused_parens, unused_variables, unused_mut, dead_code)]
+#![allow(non_snake_case, non_camel_case_types, non_upper_case_globals, unused_parens, unused_variables, unused_mut, dead_code, clippy::upper_case_acronyms)]
 
 mod scope;
+mod span;
+mod error;
 mod lexer;
 mod ast; 
 mod token; 
 mod visitor; 
+mod repl;
+mod bytecode;
+mod compiler;
+mod vm;
+mod codegen;
+mod typeck;
+mod strings;
+mod arrays;
+mod maps;
+mod numbers;
+mod doc;
+mod fmt;
+mod lint;
 
 use std::env;
 use std::fs;
+use std::path::Path;
+use std::process;
+use std::thread;
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use ast::AST;
+use error::{IronError, IronResult};
 use lexer::Lexer;
 use visitor::Visitor; 
-use scope::Scope; 
+use compiler::Compiler;
+use vm::VM;
+use scope::{Scope, SharedScope};
+use span::Span;
 
 
 mod parser;
 use parser::Parser;
 
+// Each call the interpreter makes is a few nested visits, so it runs on a stack with room for MAX_DEPTH of them
+const STACK_SIZE: usize = 512 * 1024 * 1024;
+
 pub fn main() {
+    let iron = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("Could not start the interpreter");
+
+    // A panic has already been printed, it just needs the exit code one would have had
+    if iron.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn run() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() < 2 {
-        panic!("Unexpected input");
+    match args.get(1).map(|a| a.as_str()) {
+        Some("build") => return build(&args[2..]),
+        Some("check") => return check(&args[2..]),
+        Some("doc") => return doc(&args[2..]),
+        Some("fmt") => return fmt(&args[2..]),
+        Some("lint") => return lint(&args[2..]),
+        _ => {}
+    }
+
+    let mut use_vm = false;
+    let mut file = None;
+
+    for arg in &args[1..] {
+        match arg.as_str() {
+            "--vm" => use_vm = true,
+            _ => file = Some(arg.clone()),
+        }
+    }
+
+    let Some(file) = file else {
+        repl::Repl::new().run();
+        return;
+    };
+
+    if let Err(e) = run_file(&file, use_vm) {
+        eprintln!("{}", e.render());
+        process::exit(e.exit_code());
+    }
+}
+
+// iron build file.iron -o out
+fn build(args: &[String]) {
+    let mut file = None;
+    let mut output = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next().cloned(),
+            _ => file = Some(arg.clone()),
+        }
+    }
+
+    let Some(file) = file else {
+        eprintln!("usage: iron build <file.iron> [-o <output>]");
+        process::exit(64);
+    };
+
+    // Defaults to the file name without the .iron, but never the source itself
+    let output = output.unwrap_or_else(|| match file.strip_suffix(".iron") {
+        Some(stem) => stem.to_string(),
+        None => format!("{}.out", file),
+    });
+
+    if let Err(e) = build_file(&file, &output) {
+        eprintln!("{}", e.render());
+        process::exit(e.exit_code());
     }
+}
+
+// iron check file.iron, reports every type error without running anything
+fn check(args: &[String]) {
+    let Some(file) = args.first() else {
+        eprintln!("usage: iron check <file.iron>");
+        process::exit(64);
+    };
+
+    let errors = match parse_file(file) {
+        Ok(ast) => typeck::check(&ast),
+        Err(e) => vec![e],
+    };
+
+    for e in &errors {
+        eprintln!("{}", e.render());
+    }
+
+    if let Some(e) = errors.first() {
+        process::exit(e.exit_code());
+    }
+}
+
+// iron doc path [-o dir] [--markdown], a page for every .iron and .steel file under path
+fn doc(args: &[String]) {
+    let mut path = None;
+    let mut output = "doc".to_string();
+    let mut format = doc::Format::Html;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next().cloned().unwrap_or(output),
+            "--markdown" => format = doc::Format::Markdown,
+            _ => path = Some(arg.clone()),
+        }
+    }
+
+    let Some(path) = path else {
+        eprintln!("usage: iron doc <path> [-o <dir>] [--markdown]");
+        process::exit(64);
+    };
+
+    let errors = doc::document(&path, &output, format);
+
+    for e in &errors {
+        eprintln!("{}", e.render());
+    }
+
+    if let Some(e) = errors.first() {
+        process::exit(e.exit_code());
+    }
+}
+
+fn fmt(args: &[String]) {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths: Vec<String> = args.iter().filter(|arg| *arg != "--check").cloned().collect();
 
-    let n = &args[1];
+    if paths.is_empty() {
+        eprintln!("usage: iron fmt [--check] <path>...");
+        process::exit(64);
+    }
+
+    let (changed, errors) = fmt::format_files(&paths, check);
+
+    for e in &errors {
+        eprintln!("{}", e.render());
+    }
+
+    if let Some(e) = errors.first() {
+        process::exit(e.exit_code());
+    }
+
+    if check && !changed.is_empty() {
+        for path in &changed {
+            eprintln!("{} is not formatted", path);
+        }
+
+        process::exit(1);
+    }
+}
+
+fn lint(args: &[String]) {
+    if args.is_empty() {
+        eprintln!("usage: iron lint <path>...");
+        process::exit(64);
+    }
+
+    let rules = lint::config(Path::new(lint::CONFIG)).unwrap_or_else(|e| {
+        eprintln!("{}", e.render());
+        process::exit(e.exit_code());
+    });
+
+    let (warnings, errors) = lint::lint_files(args, &rules);
+
+    for warning in &warnings {
+        eprintln!("{}", warning.render());
+    }
+
+    for e in &errors {
+        eprintln!("{}", e.render());
+    }
+
+    if let Some(e) = errors.first() {
+        process::exit(e.exit_code());
+    }
+
+    if !warnings.is_empty() {
+        process::exit(1);
+    }
+}
+
+pub fn build_file(n: &str, output: &str) -> IronResult<()> {
+    let ast = parse_checked(n)?;
+    let program = Compiler::new().compile(&ast)?;
+    codegen::build(&program, output)
+}
+
+pub fn parse_file(n: &str) -> IronResult<AST> {
     let source = fs::read_to_string(n)
-        .unwrap_or_else(|_| panic!("Could not read the file {}", n));
+        .map_err(|_| IronError::ImportError(format!("Could not read the file {}", n), None))?;
 
-    let mut lexer = Lexer::new(&source);
+    let mut lexer = Lexer::new_with_file(&source, n);
     let mut global_scope = Rc::new(RefCell::new(Scope::new())); 
-    let mut parser: Parser = Parser::new(&mut lexer, global_scope);
-    let mut ast = parser.parse();
+    let mut parser: Parser = Parser::new(&mut lexer, global_scope)?;
+    parser.parse()
+}
+
+// import "name" reads examples/lib/name.steel, its functions go in scope as it's parsed
+pub fn load_library(lib: &str, span: &Option<Span>, scope: SharedScope) -> IronResult<AST> {
+    let path = format!("examples/lib/{}.steel", lib);
+    let contents = fs::read_to_string(&path)
+        .map_err(|_| IronError::ImportError(format!("Library `{}` not found at path {}", lib, path), span.clone()))?;
+
+    let mut lexer = Lexer::new_with_file(&contents, &path);
+    Parser::new(&mut lexer, scope)?.parse()
+}
+
+// Type errors are caught before anything runs
+pub fn parse_checked(n: &str) -> IronResult<AST> {
+    let ast = parse_file(n)?;
+
+    match typeck::check(&ast).into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(ast),
+    }
+}
+
+pub fn run_file(n: &str, use_vm: bool) -> IronResult<AST> {
+    let mut ast = parse_checked(n)?;
+
+    if use_vm {
+        let program = Compiler::new().compile(&ast)?;
+        VM::new(program).run()?;
+        return Ok(AST::new(ast::Ast_Type::AST_NOOP));
+    }
+
     let mut visitor = Visitor::new(); 
-    visitor.visit( &mut ast);
+    visitor.visit( &mut ast)
 }
\ No newline at end of file
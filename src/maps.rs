@@ -0,0 +1,74 @@
+use crate::arrays::Element;
+use crate::error::{IronError, IronResult};
+use crate::span::Span;
+
+/*
+    Lookups and methods for maps, shared by the visitor and the vm the
+    same way arrays.rs is. A map is its keys and values side by side,
+    kept in the order the keys were first added so printing and keys()
+    come out the same everywhere. src/runtime.c mirrors it
+*/
+pub trait Key: Element {
+    fn describe(&self) -> String; // how the key is shown when it's missing
+}
+
+pub enum Outcome<T> {
+    Value(T),
+    Int(i64),
+    Bool(bool),
+    Keys(Vec<T>),
+    Values(Vec<T>),
+}
+
+// Goes through the keys in order, so a lookup takes longer the more keys there are
+pub fn find<T: Key>(keys: &[T], key: &T) -> Option<usize> {
+    keys.iter().position(|k| k.equals(key))
+}
+
+// name is the map the key was looked up in
+pub fn missing<T: Key>(key: &T, name: &str, span: &Option<Span>) -> IronError {
+    IronError::KeyError(format!("key {} not found in {}", key.describe(), name), span.clone())
+}
+
+pub fn get<T: Key>(keys: &[T], values: &[T], key: &T, name: &str, span: &Option<Span>) -> IronResult<T> {
+    match find(keys, key) {
+        Some(i) => Ok(values[i].clone()),
+        None => Err(missing(key, name, span)),
+    }
+}
+
+// A new key goes on the end, an existing one keeps its place
+pub fn set<T: Key>(keys: &mut Vec<T>, values: &mut Vec<T>, key: T, value: T) {
+    match find(keys, &key) {
+        Some(i) => values[i] = value,
+        None => {
+            keys.push(key);
+            values.push(value);
+        }
+    }
+}
+
+pub fn method<T: Key>(keys: &mut Vec<T>, values: &mut Vec<T>, name: &str, args: &[T], span: &Option<Span>) -> IronResult<Outcome<T>> {
+    let arity = match name {
+        "len" | "keys" | "values" => 0,
+        "has" | "remove" => 1,
+        _ => return Err(IronError::NameError(format!("map has no method {}", name), span.clone())),
+    };
+
+    if args.len() != arity {
+        return Err(IronError::TypeError(format!("Function {} expected {} arguments, got {}", name, arity, args.len()), span.clone()));
+    }
+
+    Ok(match name {
+        "len" => Outcome::Int(keys.len() as i64),
+        "keys" => Outcome::Keys(keys.clone()),
+        "values" => Outcome::Values(values.clone()),
+        "has" => Outcome::Bool(find(keys, &args[0]).is_some()),
+        "remove" => {
+            let i = find(keys, &args[0]).ok_or_else(|| missing(&args[0], "map", span))?;
+            keys.remove(i);
+            Outcome::Value(values.remove(i))
+        }
+        _ => unreachable!(),
+    })
+}
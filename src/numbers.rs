@@ -0,0 +1,161 @@
+use crate::error::{IronError, IronResult};
+use crate::span::Span;
+
+/*
+    Math on two ints, number literals and the int() and float() conversions.
+    Ints are 64 bit and a result that doesn't fit is an ArithmeticError
+    instead of wrapping around. The visitor and the vm both call into here
+    so they behave the same, and src/runtime.c mirrors it
+*/
+
+// l op r where op is how the operator is written, / and % cut toward zero like C
+pub fn arithmetic(op: &str, l: i64, r: i64, span: &Option<Span>) -> IronResult<i64> {
+    let result = match op {
+        "+" => l.checked_add(r),
+        "-" => l.checked_sub(r),
+        "*" => l.checked_mul(r),
+        "/" if r == 0 => return Err(IronError::ArithmeticError("Division by zero".to_string(), span.clone())),
+        "%" if r == 0 => return Err(IronError::ArithmeticError("Modulo by zero".to_string(), span.clone())),
+        "/" => l.checked_div(r),
+        "%" => l.checked_rem(r),
+        "^" => return power(l, r, span),
+        "&" => Some(l & r),
+        "|" => Some(l | r),
+        "xor" => Some(l ^ r),
+        "<<" | ">>" => return shift(op, l, r, span),
+        _ => unreachable!("{} isn't an int operator", op),
+    };
+
+    result.ok_or_else(|| overflow(&format!("{} {} {}", l, op, r), span))
+}
+
+// A negative power is a fraction, which is cut toward zero the same way
+fn power(base: i64, exponent: i64, span: &Option<Span>) -> IronResult<i64> {
+    match (base, exponent) {
+        (0, e) if e < 0 => Err(IronError::ArithmeticError("Division by zero".to_string(), span.clone())),
+        (1, _) => Ok(1),
+        (-1, e) => Ok(if e % 2 == 0 { 1 } else { -1 }),
+        (_, e) if e < 0 => Ok(0),
+        (0, _) if exponent > 0 => Ok(0),
+        (b, e) => u32::try_from(e).ok()
+            .and_then(|e| b.checked_pow(e))
+            .ok_or_else(|| overflow(&format!("{} ^ {}", b, e), span)),
+    }
+}
+
+// Bits shifted off the end are dropped, and >> keeps the sign
+fn shift(op: &str, l: i64, r: i64, span: &Option<Span>) -> IronResult<i64> {
+    if !(0..64).contains(&r) {
+        return Err(IronError::ArithmeticError(format!("Can't shift by {}, shifts go from 0 to 63", r), span.clone()));
+    }
+
+    Ok(if op == "<<" { l << r } else { l >> r })
+}
+
+// The operators that only work on two ints
+pub fn is_bitwise(op: &str) -> bool {
+    matches!(op, "&" | "|" | "xor" | "<<" | ">>")
+}
+
+pub fn negate(i: i64, span: &Option<Span>) -> IronResult<i64> {
+    i.checked_neg().ok_or_else(|| overflow(&format!("-({})", i), span))
+}
+
+fn overflow(what: &str, span: &Option<Span>) -> IronError {
+    IronError::ArithmeticError(format!("{} overflows an int", what), span.clone())
+}
+
+// int(x) of a float cuts it toward zero
+pub fn float_to_int(f: f64, span: &Option<Span>) -> IronResult<i64> {
+    if !(-9223372036854775808.0..9223372036854775808.0).contains(&f) {
+        return Err(IronError::ArithmeticError("float is out of range for an int".to_string(), span.clone()));
+    }
+
+    Ok(f as i64)
+}
+
+// int("42"), an optional sign and then digits, nothing else around them
+pub fn parse_int(s: &str, span: &Option<Span>) -> IronResult<i64> {
+    let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(cant_convert(s, "int", span));
+    }
+
+    s.parse().map_err(|_| overflow(&format!("\"{}\"", s), span))
+}
+
+// float("2.5"), written like a float literal with an optional sign and exponent
+pub fn parse_float(s: &str, span: &Option<Span>) -> IronResult<f64> {
+    let unsigned = s.strip_prefix(['+', '-']).unwrap_or(s);
+    let (mantissa, exponent) = unsigned.split_once(['e', 'E']).unwrap_or((unsigned, "0"));
+    let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+
+    if whole.len() + fraction.len() == 0 || exponent.is_empty() || !digits(whole) || !digits(fraction) || !digits(exponent) {
+        return Err(cant_convert(s, "float", span));
+    }
+
+    s.parse().map_err(|_| cant_convert(s, "float", span))
+}
+
+fn cant_convert(s: &str, to: &str, span: &Option<Span>) -> IronError {
+    IronError::TypeError(format!("Can't convert \"{}\" to {}", s, to), span.clone())
+}
+
+// An int literal as written, 0xFF and 0b1010 can set all 64 bits so 0xFFFFFFFFFFFFFFFF is -1
+pub fn int_literal(text: &str, span: &Option<Span>) -> IronResult<i64> {
+    let (digits, radix) = match text.get(..2) {
+        Some("0x" | "0X") => (&text[2..], 16),
+        Some("0b" | "0B") => (&text[2..], 2),
+        _ => (text, 10),
+    };
+
+    if !grouped_digits(digits, radix) || digits.is_empty() {
+        return Err(IronError::ParseError(format!("Invalid int literal {}", text), span.clone()));
+    }
+
+    let digits = digits.replace('_', "");
+    let value = match radix {
+        10 => digits.parse::<i64>().ok(),
+        _ => u64::from_str_radix(&digits, radix).ok().map(|u| u as i64),
+    };
+
+    value.ok_or_else(|| IronError::ParseError(format!("{} is too big for an int", text), span.clone()))
+}
+
+// A float literal like 2.5, 1_000.0 or 1.5e-3, along with how many decimals it's printed with
+pub fn float_literal(text: &str, span: &Option<Span>) -> IronResult<(f64, i32)> {
+    let invalid = || IronError::ParseError(format!("Invalid float literal {}", text), span.clone());
+
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent.strip_prefix(['+', '-']).unwrap_or(exponent))),
+        None => (text, None),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    if !grouped_digits(whole, 10) || !grouped_digits(fraction, 10) || exponent.is_some_and(|e| e.is_empty() || !grouped_digits(e, 10)) {
+        return Err(invalid());
+    }
+
+    let value: f64 = text.replace('_', "").parse().map_err(|_| invalid())?;
+
+    if !value.is_finite() {
+        return Err(IronError::ParseError(format!("{} is too big for a float", text), span.clone()));
+    }
+
+    // 1.5e-3 shows as 0.0015 and 2.5e2 as 250.0, it stays a float with at least one decimal
+    // No f64 needs more than 324 decimals to show its first digit
+    let shift = text.split_once(['e', 'E']).map_or(0, |(_, e)| e.replace('_', "").parse::<i64>().unwrap_or(0));
+    let decimals = fraction.replace('_', "").len() as i64 - shift;
+
+    Ok((value, decimals.clamp(1, 324) as i32))
+}
+
+// Digits of the radix, where an _ can only sit between two of them
+fn grouped_digits(digits: &str, radix: u32) -> bool {
+    !digits.starts_with('_') && !digits.ends_with('_') && !digits.contains("__")
+        && digits.chars().all(|c| c == '_' || c.is_digit(radix))
+}
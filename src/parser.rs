@@ -3,8 +3,11 @@ use std::cell::RefCell;
 
 use crate::ast::{AST, Ast_Type, Data_Type};
 use crate::lexer::Lexer;
-use crate::token::{Token, Types};
+use crate::token::{Piece, Token, Types};
 use crate::scope::{Scope, SharedScope};
+use crate::error::{IronError, IronResult};
+use crate::span::Span;
+use crate::numbers;
 
 #[derive(Debug)]
 pub struct Parser<'a> {
@@ -12,124 +15,207 @@ pub struct Parser<'a> {
     pub current_token: Token,
     pub prev_token: Option<Token>,
     pub scope: SharedScope,
+    pub loops: Vec<Option<String>>, // the labels of the loops around what's being parsed, innermost last
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(lexer: &'a mut Lexer, scope: SharedScope) -> Self {
-        let f_t = lexer.next_token();
-        Parser {
+    pub fn new(lexer: &'a mut Lexer, scope: SharedScope) -> IronResult<Self> {
+        let f_t = lexer.next_token()?;
+        Ok(Parser {
             lexer, 
             current_token: f_t,
             prev_token: None,
             scope,
-        }
+            loops: vec![],
+        })
     }
 
-    pub fn eat(&mut self, t: Types) {
+    pub fn eat(&mut self, t: Types) -> IronResult<()> {
         // println!("Ate {:#?} with value {}", t, self.current_token.value); 
         if self.current_token.kind == t {
             self.prev_token = Some(self.current_token.clone());
-            self.current_token = self.lexer.next_token();
+            self.current_token = self.lexer.next_token()?;
+            Ok(())
         }
         else {
-            panic!("Unexpected token attempted to eat: {:?} got {:?} instead", t, self.current_token.kind);
+            Err(self.error(format!("Unexpected token attempted to eat: {:?} got {:?} instead", t, self.current_token.kind)))
         }
     }
 
-    pub fn parse(&mut self) -> AST {
+    // A parse error pointing at the token we are currently sitting on
+    pub fn error(&self, message: String) -> IronError {
+        IronError::ParseError(message, self.current_token.span.clone())
+    }
+
+    pub fn parse(&mut self) -> IronResult<AST> {
         self.parse_statements()
     }
 
-    pub fn parse_statements(&mut self) -> AST {
+    pub fn parse_statements(&mut self) -> IronResult<AST> {
         let mut comp = AST::new(Ast_Type::AST_COMPOUND);
         comp.scope = Some(self.scope.clone());
         comp.compound_value = Some(Vec::new());
         
         while self.current_token.kind != Types::TOKEN_EOF {
-            let ast_state = self.parse_statement();
+            let ast_state = self.parse_statement()?;
 
             if let Some(ref mut v) = comp.compound_value {
                 v.push(ast_state);
             }
 
             if self.current_token.kind == Types::TOKEN_SEMI {
-                self.eat(Types::TOKEN_SEMI);
+                self.eat(Types::TOKEN_SEMI)?;
             }
         }
 
-        comp
+        Ok(comp)
     }
 
-    pub fn parse_statement(&mut self) -> AST {
-        match self.current_token.kind {
-            Types::TOKEN_ID => self.parse_id(),
-            _ => self.parse_expr(),
+    pub fn parse_statement(&mut self) -> IronResult<AST> {
+        let span = self.current_token.span.clone();
+
+        // Only a statement can be labeled, in an expression x: is the middle of a ?:, a slice or a map
+        let labeled = self.current_token.kind == Types::TOKEN_ID && self.peek()?.kind == Types::TOKEN_COLON;
+
+        let mut node = match self.current_token.kind {
+            Types::TOKEN_ID if labeled => self.parse_label()?,
+            Types::TOKEN_ID => self.parse_id()?,
+            _ => self.parse_expr()?,
+        };
+
+        if node.span.is_none() {
+            node.span = span;
         }
+
+        Ok(node)
     }
 
-    pub fn parse_expr(&mut self) -> AST {
-        self.parse_or()
+    pub fn parse_expr(&mut self) -> IronResult<AST> {
+        self.parse_ternary()
     }
 
-    pub fn parse_id(&mut self) -> AST {
+    // cond ? a : b binds looser than everything else, so a ? b : c ? d : e is a ? b : (c ? d : e)
+    pub fn parse_ternary(&mut self) -> IronResult<AST> {
+        let condition = self.parse_or()?;
+
+        if self.current_token.kind != Types::TOKEN_QUESTION {
+            return Ok(condition);
+        }
+
+        self.eat(Types::TOKEN_QUESTION)?;
+        let then = self.parse_expr()?;
+        self.eat(Types::TOKEN_COLON)?;
+        let otherwise = self.parse_expr()?;
+
+        let mut node = AST::new(Ast_Type::AST_TERNARY);
+        node.span = condition.span.clone();
+        node.if_condition = Some(Box::new(condition));
+        node.if_body = Some(Box::new(then));
+        node.else_body = Some(Box::new(otherwise));
+        node.scope = Some(self.scope.clone());
+        Ok(node)
+    }
+
+    pub fn parse_id(&mut self) -> IronResult<AST> {
         if self.scope.clone().borrow().get_class_definition(self.current_token.value.clone().as_str()).is_some() {
             return self.parse_class(); 
         }
-        match self.current_token.value.as_str() {
-            "int" | "str" | "bool" | "float" => self.parse_variable_definition(),
+        match self.current_token.value.clone().as_str() {
+            // int(x), float(x) and str(x) convert, with no ( they start a declaration
+            "int" | "str" | "float" if self.peek()?.kind == Types::TOKEN_LPARENT => self.parse_variable(),
+            "int" | "str" | "bool" | "float" | "char" => self.parse_variable_definition(),
+            "map" => self.parse_map_definition(),
             "fun" => self.parse_function_definition(),
             "class" => self.parse_class_definition(), 
             "return" => self.parse_return(),
             "if" => self.parse_if(),
-            "while" => self.parse_while(), 
+            "match" => self.parse_match(),
+            "while" => self.parse_while(None), 
             "new" => self.parse_class_return(), 
-            "for" => self.parse_for(), 
+            "for" => self.parse_for(None), 
             "import" => self.parse_import(), 
-            "break" => self.parse_break(), 
-            _ => {
-                return self.parse_variable();
-            },
+            "break" | "continue" => self.parse_break(), 
+            "try" => self.parse_try(),
+            "throw" => self.parse_throw(),
+            "const" => self.parse_const(),
+            _ => self.parse_variable(),
+        }
+    }
+
+    // The token after the current one, without moving on
+    pub fn peek(&mut self) -> IronResult<Token> {
+        self.lexer.peek_token()
+    }
+
+    // outer: while (...) names the loop so a break or continue further in can leave it
+    pub fn parse_label(&mut self) -> IronResult<AST> {
+        let label = self.current_token.value.clone();
+        self.eat(Types::TOKEN_ID)?;
+        self.eat(Types::TOKEN_COLON)?;
+
+        match self.current_token.value.as_str() {
+            "while" => self.parse_while(Some(label)),
+            "for" => self.parse_for(Some(label)),
+            _ => Err(self.error(format!("Only loops can be labeled, {} isn't on one", label))),
+        }
+    }
+
+    // const int x = 1; declares x like any other variable, iron lint reports anything assigned to it later
+    pub fn parse_const(&mut self) -> IronResult<AST> {
+        let span = self.current_token.span.clone();
+        self.eat(Types::TOKEN_ID)?; // eat "const"
+
+        let mut node = self.parse_id()?;
+        let declares = matches!(node.ast_type, Ast_Type::AST_VARIABLE_DEF | Ast_Type::AST_ARRAY_DEF)
+            || node.variable_definition_variable_name.is_some() && node.class_name.is_some();
+
+        if !declares {
+            return Err(IronError::ParseError("Only a variable declaration can be const".to_string(), span));
         }
+
+        node.is_const = Some(true);
+        node.span = span;
+        Ok(node)
     }
 
-    pub fn parse_class_return(&mut self, ) -> AST {      
-        self.eat(Types::TOKEN_ID); // eat "new"   
+    pub fn parse_class_return(&mut self, ) -> IronResult<AST> {      
+        self.eat(Types::TOKEN_ID)?; // eat "new"   
         let class_name = self.current_token.value.clone(); 
 
-        self.eat(Types::TOKEN_ID); // eat the class name 
-        self.eat(Types::TOKEN_LPARENT);
+        self.eat(Types::TOKEN_ID)?; // eat the class name 
+        self.eat(Types::TOKEN_LPARENT)?;
 
         let mut args = Vec::new();
 
         if self.current_token.kind != Types::TOKEN_RPARENT {
-            args.push(self.parse_term());
+            args.push(self.parse_term()?);
 
             while self.current_token.kind == Types::TOKEN_COMMA {
-                self.eat(Types::TOKEN_COMMA);
-                args.push(self.parse_term());
+                self.eat(Types::TOKEN_COMMA)?;
+                args.push(self.parse_term()?);
             }
         }
 
-        self.eat(Types::TOKEN_RPARENT);
+        self.eat(Types::TOKEN_RPARENT)?;
 
         let mut ast = AST::new(Ast_Type::AST_CLASS_INSTANCE);
         ast.class_name = Some(class_name.clone());
         ast.class_args = Some(args.clone());
         ast.scope = Some(self.scope.clone());
 
-        ast
+        Ok(ast)
     }
 
-    pub fn parse_class(&mut self) -> AST {
+    pub fn parse_class(&mut self) -> IronResult<AST> {
         let type_name:String = self.current_token.value.clone();
-        self.eat(Types::TOKEN_ID); 
+        self.eat(Types::TOKEN_ID)?; 
 
         let n:String = self.current_token.value.clone();
-        self.eat(Types::TOKEN_ID); 
+        self.eat(Types::TOKEN_ID)?; 
 
-        self.eat(Types::TOKEN_EQUALS); 
+        self.eat(Types::TOKEN_EQUALS)?; 
 
-        let mut term = self.parse_term(); 
+        let mut term = self.parse_term()?; 
 
         term.variable_definition_variable_name = Some(n.clone()); 
         term.class_name = Some(type_name); 
@@ -137,27 +223,32 @@ impl<'a> Parser<'a> {
 
         self.scope.borrow_mut().add_variable_definition(term.clone());
 
-        term 
+        Ok(term) 
     }
 
-    pub fn parse_variable(&mut self) -> AST {
+    pub fn parse_variable(&mut self) -> IronResult<AST> {
         let n = self.current_token.value.clone();
+        let span = self.current_token.span.clone();
 
-        self.eat(Types::TOKEN_ID);
+        self.eat(Types::TOKEN_ID)?;
 
         let mut ast = AST::new(Ast_Type::AST_VARIABLE);
         ast.variable_name = Some(n.clone());
         ast.scope = Some(self.scope.clone());
+        ast.span = span.clone();
 
         while self.current_token.kind == Types::TOKEN_DOT {
-            self.eat(Types::TOKEN_DOT);
+            self.eat(Types::TOKEN_DOT)?;
 
             let field = self.current_token.value.clone();
-            self.eat(Types::TOKEN_ID);
+            let field_span = self.current_token.span.clone();
+            self.eat(Types::TOKEN_ID)?;
 
             let mut node = AST::new(Ast_Type::AST_DOT); 
+            node.span = span.clone();
 
-            if let Some(var) = self.scope.clone().borrow().get_variable_definition(&n.clone()) {
+            // Only the first dot is on the instance itself, p.items.len() calls len on the field
+            if let Some(var) = self.scope.clone().borrow().get_variable_definition(&n.clone()).filter(|_| ast.dot_left.is_none()) {
                 if var.class_name.is_some() {
                     node.ast_type = Ast_Type::AST_CLASS_ACCESS;
                     ast.variable_name = Some(n.clone());
@@ -169,13 +260,14 @@ impl<'a> Parser<'a> {
             node.scope = Some(self.scope.clone()); 
 
             if self.current_token.kind == Types::TOKEN_LPARENT {
-                node.dot_right = Some(Box::new(self.parse_function_call()));
+                node.dot_right = Some(Box::new(self.parse_function_call()?));
                 node.scope = Some(self.scope.clone()); 
             }
             else if self.current_token.kind == Types::TOKEN_EQUALS {
                 let mut right = AST::new(Ast_Type::AST_VARIABLE); 
 
                 right.variable_name = Some(field); 
+                right.span = field_span;
 
                 right.scope = Some(self.scope.clone());
                 node.dot_right = Some(Box::new(right));
@@ -186,6 +278,7 @@ impl<'a> Parser<'a> {
                 let mut right = AST::new(Ast_Type::AST_VARIABLE);
 
                 right.variable_name = Some(field); 
+                right.span = field_span;
                 right.scope = Some(self.scope.clone());
                 node.dot_right = Some(Box::new(right));
             }
@@ -194,85 +287,301 @@ impl<'a> Parser<'a> {
             ast = node; 
         }
 
+        // p.x += 1 or p.x++, the field is read and written back through the same object
+        if ast.ast_type != Ast_Type::AST_VARIABLE && self.is_compound_assignment() {
+            if ast.dot_right.as_ref().unwrap().ast_type != Ast_Type::AST_VARIABLE {
+                return Err(self.error(format!("Can't use {} on a method call", self.current_token.value)));
+            }
+
+            ast.ast_type = Ast_Type::AST_CLASS_ACCESS;
+            return self.parse_compound_assignment(ast);
+        }
+
         if self.current_token.kind == Types::TOKEN_LPARENT {
             return self.parse_function_call();
         }
         else if self.current_token.kind == Types::TOKEN_EQUALS {
             return self.parse_reassignment(n); 
         }
+        else if self.compound_operator().is_some() {
+            return self.parse_compound_assignment(ast);
+        }
         else if self.current_token.kind == Types::TOKEN_INCREMENT {
-            self.eat(Types::TOKEN_INCREMENT);
+            self.eat(Types::TOKEN_INCREMENT)?;
             let mut increment = AST::new(Ast_Type::AST_INCREMENT);
             increment.reassign_name = Some(n.clone());
+            increment.postfix = true;
             increment.scope = Some(self.scope.clone());
+            increment.span = span;
             
-            return increment
+            return Ok(increment);
         }
         else if self.current_token.kind == Types::TOKEN_DECREMENT {
-            self.eat(Types::TOKEN_DECREMENT);
+            self.eat(Types::TOKEN_DECREMENT)?;
             let mut decrement = AST::new(Ast_Type::AST_DECREMENT);
             decrement.reassign_name = Some(n.clone());
+            decrement.postfix = true;
             decrement.scope = Some(self.scope.clone());
+            decrement.span = span;
             
-            return decrement
+            return Ok(decrement);
         }
         else if self.current_token.kind == Types::TOKEN_LBOX {
-            self.eat(Types::TOKEN_LBOX);
-            let index = self.parse_expr();
-            self.eat(Types::TOKEN_RBOX);
+            // p.items[i] reads and writes through the field, a method's result can't be indexed
+            let field = match ast.dot_right.as_deref() {
+                None => None,
+                Some(right) if right.ast_type == Ast_Type::AST_VARIABLE => Some(Box::new(ast.clone())),
+                Some(_) => return Err(self.error("Only a variable or a field can be indexed".to_string())),
+            };
+            let n = field.as_deref().map(dotted).unwrap_or(n);
+
+            self.eat(Types::TOKEN_LBOX)?;
+            let index = self.parse_expr()?;
+
+            let mut slice_end = None;
+
+            if self.current_token.kind == Types::TOKEN_COLON {
+                if field.is_some() {
+                    return Err(self.error("Only a variable can be sliced".to_string()));
+                }
+
+                self.eat(Types::TOKEN_COLON)?;
+                slice_end = Some(Box::new(self.parse_expr()?));
+            }
+
+            self.eat(Types::TOKEN_RBOX)?;
+
+            // grid[i][j], each extra index goes one array deeper
+            let mut path = vec![];
+
+            while self.current_token.kind == Types::TOKEN_LBOX {
+                if slice_end.is_some() {
+                    return Err(self.error("Can't index into a slice".to_string()));
+                }
+
+                self.eat(Types::TOKEN_LBOX)?;
+                path.push(self.parse_expr()?);
+
+                if self.current_token.kind == Types::TOKEN_COLON {
+                    return Err(self.error("Only a variable can be sliced".to_string()));
+                }
+
+                self.eat(Types::TOKEN_RBOX)?;
+            }
+
+            let path = if path.is_empty() { None } else { Some(path) };
 
             if self.current_token.kind == Types::TOKEN_EQUALS {
-                return self.parse_array_assignment(n.clone(), index.clone());
+                if slice_end.is_some() {
+                    return Err(self.error("Can't assign to a slice".to_string()));
+                }
+
+                let mut node = self.parse_array_assignment(n.clone(), index.clone())?;
+                node.array_path = path;
+                node.array_field = field;
+                node.span = span;
+                return Ok(node);
+            }
+
+            // Strings aren't in the parser's scope, the visitor reports a missing name when it runs
+            let def = field.is_none().then(|| self.scope.clone().borrow().get_variable_definition(&n.clone())).flatten();
+            let mut element = def.map(|d| d.data_type).unwrap_or(Data_Type::VOID);
+
+            for _ in path.iter().flatten() {
+                element = match element {
+                    Data_Type::ARRAY(inner) => *inner,
+                    _ => Data_Type::VOID,
+                };
             }
 
             let mut node = AST::new(Ast_Type::AST_ARRAY_ACCESS);
             node.array_name = Some(n.clone());
             node.array_index = Some(Box::new(index));
-            node.data_type = self.scope.clone().borrow().get_variable_definition(&n.clone()).unwrap().data_type; 
+            node.array_slice_end = slice_end;
+            node.array_path = path;
+            node.array_field = field;
+            node.data_type = element;
             node.scope = Some(self.scope.clone()); 
-            return node; 
+            node.span = span;
+
+            if self.is_compound_assignment() {
+                if node.array_slice_end.is_some() {
+                    return Err(self.error("Can't assign to a slice".to_string()));
+                }
+
+                return self.parse_compound_assignment(node);
+            }
+
+            return Ok(node); 
         }
         
-        ast
+        Ok(ast)
     }
 
-    pub fn parse_array_definition(&mut self, declared_type: Data_Type) -> AST {
-        self.eat(Types::TOKEN_LBOX);
-        self.eat(Types::TOKEN_RBOX);
+    // The operator += and the rest apply before storing the result
+    fn compound_operator(&self) -> Option<Types> {
+        match self.current_token.kind {
+            Types::TOKEN_ADD_EQUALS => Some(Types::TOKEN_ADD),
+            Types::TOKEN_SUBTRACT_EQUALS => Some(Types::TOKEN_SUBTRACT),
+            Types::TOKEN_ASTERISK_EQUALS => Some(Types::TOKEN_ASTERISK),
+            Types::TOKEN_FSLASH_EQUALS => Some(Types::TOKEN_FSLASH),
+            Types::TOKEN_PERCENT_EQUALS => Some(Types::TOKEN_PERCENT),
+            Types::TOKEN_CARROT_EQUALS => Some(Types::TOKEN_CARROT),
+            _ => None,
+        }
+    }
+
+    fn is_compound_assignment(&self) -> bool {
+        self.compound_operator().is_some()
+            || self.current_token.kind == Types::TOKEN_INCREMENT
+            || self.current_token.kind == Types::TOKEN_DECREMENT
+    }
+
+    // target is a variable, an element or a field and an op=, ++ or -- comes after it
+    pub fn parse_compound_assignment(&mut self, target: AST) -> IronResult<AST> {
+        let kind = self.current_token.kind.clone();
+        let span = target.span.clone();
+
+        let (operator, step) = match self.compound_operator() {
+            Some(operator) => {
+                self.eat(kind.clone())?;
+                (operator, self.parse_expr()?)
+            }
+            None => {
+                self.eat(kind.clone())?;
+                let operator = if kind == Types::TOKEN_INCREMENT { Types::TOKEN_ADD } else { Types::TOKEN_SUBTRACT };
+                (operator, self.one(span.clone()))
+            }
+        };
+
+        let mut node = AST::new(Ast_Type::AST_COMPOUND_ASSIGN);
+        node.left = Some(Box::new(target));
+        node.operator = Some(operator);
+        node.right = Some(Box::new(step));
+        node.postfix = kind == Types::TOKEN_INCREMENT || kind == Types::TOKEN_DECREMENT;
+        node.scope = Some(self.scope.clone());
+        node.span = span;
+        Ok(node)
+    }
+
+    // The step ++ and -- take
+    fn one(&self, span: Option<Span>) -> AST {
+        let mut one = AST::from_int(1);
+        one.scope = Some(self.scope.clone());
+        one.span = span;
+        one
+    }
+
+    // ++x and --x step first and give the new value, x is a variable, an element or a field
+    pub fn parse_prefix(&mut self) -> IronResult<AST> {
+        let kind = self.current_token.kind.clone();
+        let symbol = self.current_token.value.clone();
+        let span = self.current_token.span.clone();
+        self.eat(kind.clone())?;
+
+        if self.current_token.kind != Types::TOKEN_ID {
+            return Err(self.error(format!("{} needs a variable, an element or a field after it", symbol)));
+        }
+
+        let mut target = self.parse_variable()?;
+
+        let assignable = match target.ast_type {
+            Ast_Type::AST_VARIABLE => true,
+            Ast_Type::AST_ARRAY_ACCESS => target.array_slice_end.is_none() && target.array_assign_value.is_none(),
+            Ast_Type::AST_DOT | Ast_Type::AST_CLASS_ACCESS => {
+                target.reassign_value.is_none() && target.dot_right.as_ref().unwrap().ast_type == Ast_Type::AST_VARIABLE
+            }
+            _ => false,
+        };
+
+        if !assignable {
+            return Err(IronError::ParseError(format!("{} needs a variable, an element or a field after it", symbol), span));
+        }
+
+        if target.ast_type == Ast_Type::AST_VARIABLE {
+            let mut node = AST::new(if kind == Types::TOKEN_INCREMENT { Ast_Type::AST_INCREMENT } else { Ast_Type::AST_DECREMENT });
+            node.reassign_name = target.variable_name.clone();
+            node.scope = Some(self.scope.clone());
+            node.span = span;
+            return Ok(node);
+        }
+
+        if target.ast_type == Ast_Type::AST_DOT {
+            target.ast_type = Ast_Type::AST_CLASS_ACCESS;
+        }
+
+        let mut node = AST::new(Ast_Type::AST_COMPOUND_ASSIGN);
+        node.operator = Some(if kind == Types::TOKEN_INCREMENT { Types::TOKEN_ADD } else { Types::TOKEN_SUBTRACT });
+        node.right = Some(Box::new(self.one(span.clone())));
+        node.left = Some(Box::new(target));
+        node.scope = Some(self.scope.clone());
+        node.span = span;
+        Ok(node)
+    }
+
+    // declared_type is the type before the first `[]`, int[][] grid holds int[]s
+    pub fn parse_array_definition(&mut self, declared_type: Data_Type) -> IronResult<AST> {
+        self.eat(Types::TOKEN_LBOX)?;
+        self.eat(Types::TOKEN_RBOX)?;
+
+        let mut declared_type = declared_type;
+
+        while self.current_token.kind == Types::TOKEN_LBOX {
+            self.eat(Types::TOKEN_LBOX)?;
+            self.eat(Types::TOKEN_RBOX)?;
+            declared_type = Data_Type::ARRAY(Box::new(declared_type));
+        }
 
         let name = self.current_token.value.clone(); // Name
 
-        self.eat(Types::TOKEN_ID);
+        self.eat(Types::TOKEN_ID)?;
+
+        self.eat(Types::TOKEN_EQUALS)?;
 
-        self.eat(Types::TOKEN_EQUALS);
+        // Anything other than a literal, like a call or another array, is a plain definition holding an array
+        if self.current_token.kind != Types::TOKEN_LBOX {
+            let mut def = AST::new(Ast_Type::AST_VARIABLE_DEF);
+            def.variable_definition_variable_name = Some(name);
+            def.variable_definition_value = Some(Box::new(self.parse_expr()?));
+            def.variable_type = Some(Data_Type::ARRAY(Box::new(declared_type)));
+            def.scope = Some(self.scope.clone());
+
+            return Ok(def);
+        }
 
+        let mut node = self.parse_array_literal(declared_type)?;
+        node.array_name = Some(name.clone()); 
+
+        self.scope.borrow_mut().add_variable_definition(node.clone()); 
 
+        Ok(node) 
+    }   
+
+    // [a, b, ...] holding declared_type, a literal inside is another array when declared_type is one
+    pub fn parse_array_literal(&mut self, declared_type: Data_Type) -> IronResult<AST> {
+        let span = self.current_token.span.clone();
         let mut elements = vec![];
 
-        self.eat(Types::TOKEN_LBOX);
+        self.eat(Types::TOKEN_LBOX)?;
 
         if self.current_token.kind != Types::TOKEN_RBOX {
             loop {
-                let mut element = self.parse_expr();
-
-                if element.data_type == Data_Type::INT && declared_type == Data_Type::FLOAT {
-                    element.data_type = Data_Type::FLOAT;
-                    element.float_value = Some(element.int_value.unwrap() as f64);
-                    element.int_value = None;
-                    element.int_init = None;
-                    element.float_init = Some(true);
-                    element.past_decimal = Some(0); 
-                    element.ast_type = Ast_Type::AST_FLOAT;
-                }
+                let element = self.parse_element(&declared_type)?;
+                let element_span = element.span.clone();
 
-                if element.data_type != declared_type  {
-                    panic!("Element's data type is not the declared type")
+                // Only literals are checked here, the type checker gets the rest and works out the type of an untyped one
+                let literal = matches!(element.ast_type, Ast_Type::AST_INT | Ast_Type::AST_FLOAT | Ast_Type::AST_STRING | Ast_Type::AST_BOOL);
+                let nested = element.ast_type == Ast_Type::AST_ARRAY_DEF;
+                let typed = declared_type != Data_Type::VOID;
+
+                if typed && ((literal && element.data_type != declared_type) || (nested && !matches!(declared_type, Data_Type::ARRAY(_)))) {
+                    return Err(IronError::TypeError("Element's data type is not the declared type".to_string(), element_span));
                 }
 
                 elements.push(element);
 
                 if self.current_token.kind == Types::TOKEN_COMMA {
-                    self.eat(Types::TOKEN_COMMA);
+                    self.eat(Types::TOKEN_COMMA)?;
                 }
                 else {
                     break; 
@@ -280,33 +589,111 @@ impl<'a> Parser<'a> {
             }
         }
 
-        self.eat(Types::TOKEN_RBOX);
+        self.eat(Types::TOKEN_RBOX)?;
 
         let mut node = AST::new(Ast_Type::AST_ARRAY_DEF);
         node.array_elements = Some(elements);
-        node.array_name = Some(name.clone()); 
         node.data_type = declared_type; 
         node.scope = Some(self.scope.clone()); 
+        node.span = span;
 
-        self.scope.borrow_mut().add_variable_definition(node.clone()); 
+        Ok(node)
+    }
 
-        node 
-    }   
+    // One item of a literal that holds declared_type, nested literals get their type from it
+    // and an int literal becomes a float where a float is wanted
+    pub fn parse_element(&mut self, declared_type: &Data_Type) -> IronResult<AST> {
+        let mut element = match declared_type {
+            Data_Type::ARRAY(inner) if self.current_token.kind == Types::TOKEN_LBOX => self.parse_array_literal((**inner).clone())?,
+            Data_Type::MAP(..) if self.current_token.kind == Types::TOKEN_LBRACK => self.parse_map_literal(declared_type.clone())?,
+            _ => self.parse_expr()?,
+        };
+
+        if element.data_type == Data_Type::INT && *declared_type == Data_Type::FLOAT {
+            widen_literal(&mut element);
+        }
+
+        Ok(element)
+    }
+
+    // map<K, V> name = {...}, or anything else that gives a map
+    pub fn parse_map_definition(&mut self) -> IronResult<AST> {
+        let t = self.parse_map_type()?;
+
+        if self.current_token.kind == Types::TOKEN_LBOX {
+            return self.parse_array_definition(t);
+        }
+
+        let name = self.current_token.value.clone();
+        self.eat(Types::TOKEN_ID)?;
+        self.eat(Types::TOKEN_EQUALS)?;
 
-    pub fn parse_array_assignment(&mut self, name: String, index: AST) -> AST {
-        self.eat(Types::TOKEN_EQUALS);
-        let value = self.parse_expr();
+        let value = if self.current_token.kind == Types::TOKEN_LBRACK {
+            self.parse_map_literal(t.clone())?
+        } else {
+            self.parse_expr()?
+        };
+
+        let mut def = AST::new(Ast_Type::AST_VARIABLE_DEF);
+        def.variable_definition_variable_name = Some(name);
+        def.variable_definition_value = Some(Box::new(value));
+        def.variable_type = Some(t);
+        def.scope = Some(self.scope.clone());
+
+        Ok(def)
+    }
+
+    // {key: value, ...} for a map of type t, the type checker makes sure the keys and values fit
+    pub fn parse_map_literal(&mut self, t: Data_Type) -> IronResult<AST> {
+        let Data_Type::MAP(_, value_type) = &t else {
+            unreachable!("Map literal for {}", t.name());
+        };
+
+        let span = self.current_token.span.clone();
+        let mut keys = vec![];
+        let mut values = vec![];
+
+        self.eat(Types::TOKEN_LBRACK)?;
+
+        while self.current_token.kind != Types::TOKEN_RBRACK {
+            keys.push(self.parse_expr()?);
+            self.eat(Types::TOKEN_COLON)?;
+            values.push(self.parse_element(value_type)?);
+
+            if self.current_token.kind == Types::TOKEN_COMMA {
+                self.eat(Types::TOKEN_COMMA)?;
+            }
+            else {
+                break;
+            }
+        }
+
+        self.eat(Types::TOKEN_RBRACK)?;
+
+        let mut node = AST::new(Ast_Type::AST_MAP_DEF);
+        node.map_keys = Some(keys);
+        node.map_values = Some(values);
+        node.data_type = t;
+        node.scope = Some(self.scope.clone());
+        node.span = span;
+
+        Ok(node)
+    }
+
+    pub fn parse_array_assignment(&mut self, name: String, index: AST) -> IronResult<AST> {
+        self.eat(Types::TOKEN_EQUALS)?;
+        let value = self.parse_expr()?;
 
         let mut node = AST::new(Ast_Type::AST_ARRAY_ACCESS);
         node.array_index = Some(Box::new(index.clone()));
         node.array_name = Some(name.clone());
         node.array_assign_value = Some(Box::new(value.clone()));
         node.scope = Some(self.scope.clone()); 
-        node 
+        Ok(node) 
     }
 
-    pub fn parse_variable_definition(&mut self) -> AST {
-        self.eat(Types::TOKEN_ID); 
+    pub fn parse_variable_definition(&mut self) -> IronResult<AST> {
+        self.eat(Types::TOKEN_ID)?; 
 
         let type_name = self.prev_token.as_ref().unwrap().clone().value; 
 
@@ -315,6 +702,7 @@ impl<'a> Parser<'a> {
             "int"  => Data_Type::INT,
             "bool"  => Data_Type::BOOL,
             "float" => Data_Type::FLOAT,
+            "char" => Data_Type::CHAR,
             _ => Data_Type::CUSTOM(self.current_token.value.clone()), // Custom class def 
         };
 
@@ -324,7 +712,7 @@ impl<'a> Parser<'a> {
         }
 
         let n = self.current_token.value.clone();
-        self.eat(Types::TOKEN_ID);
+        self.eat(Types::TOKEN_ID)?;
 
         if self.current_token.kind != Types::TOKEN_EQUALS {
             let mut def = AST::new(Ast_Type::AST_VARIABLE_DEF);
@@ -332,16 +720,19 @@ impl<'a> Parser<'a> {
             def.variable_definition_variable_name = Some(n.clone());
             def.scope = Some(self.scope.clone());
             
-            return def; 
+            return Ok(def); 
         }
 
-        self.eat(Types::TOKEN_EQUALS);
+        self.eat(Types::TOKEN_EQUALS)?;
 
-        let val = self.parse_term();
+        let val = self.parse_term()?;
+        let val_span = val.span.clone();
         let evaluated = self.eval_ast(val.clone());
 
         let inferred_type = if evaluated.float_init.unwrap_or(false) {
             Data_Type::FLOAT
+        } else if evaluated.string_value.is_some() && evaluated.data_type == Data_Type::CHAR {
+            Data_Type::CHAR
         } else if evaluated.string_value.is_some() {
             Data_Type::STR
         } else if evaluated.int_init.unwrap_or(false) {
@@ -353,6 +744,17 @@ impl<'a> Parser<'a> {
         };
 
         let mut val = val;
+
+        // float x = 3; holds 3.0, anything else int is widened when it's stored
+        let inferred_type = if inferred_type == Data_Type::INT && t == Data_Type::FLOAT {
+            if val.ast_type == Ast_Type::AST_INT {
+                widen_literal(&mut val);
+            }
+            Data_Type::FLOAT
+        } else {
+            inferred_type
+        };
+
         val.data_type = inferred_type.clone();
 
         let mut def = AST::new(Ast_Type::AST_VARIABLE_DEF);
@@ -362,22 +764,22 @@ impl<'a> Parser<'a> {
         def.variable_type = Some(inferred_type.clone());
 
         if def.variable_type != Some(t.clone()) {
-            panic!(
+            return Err(IronError::TypeError(format!(
                 "Variable {:?} is not the type {:?} that you assigned it, instead it's {:?}",
                 def.variable_definition_variable_name.clone().unwrap(),
                 t,
                 def.variable_type.unwrap()
-            );
+            ), val_span));
         }
 
         //self.scope.borrow_mut().add_variable_definition(def.clone());
-        def
+        Ok(def)
     }
 
-    pub fn parse_class_access(&mut self, node: AST) -> AST {
+    pub fn parse_class_access(&mut self, node: AST) -> IronResult<AST> {
         if self.current_token.kind == Types::TOKEN_EQUALS {
-            self.eat(Types::TOKEN_EQUALS);
-            let val = self.parse_expr();
+            self.eat(Types::TOKEN_EQUALS)?;
+            let val = self.parse_expr()?;
 
             let mut ast = AST::new(Ast_Type::AST_CLASS_ACCESS);
             ast.dot_left = node.dot_left.clone();
@@ -386,7 +788,7 @@ impl<'a> Parser<'a> {
             ast.reassign_value = Some(Box::new(val.clone()));
             ast.scope = Some(self.scope.clone());
 
-            return ast;
+            return Ok(ast);
         }
 
         let mut ast = AST::new(Ast_Type::AST_CLASS_ACCESS);
@@ -394,39 +796,49 @@ impl<'a> Parser<'a> {
         ast.dot_right = node.dot_right.clone();
         ast.scope = Some(self.scope.clone());
 
-        ast
+        Ok(ast)
     }
 
-    pub fn parse_class_definition(&mut self) -> AST {
+    pub fn parse_class_definition(&mut self) -> IronResult<AST> {
         let mut ast = AST::new(Ast_Type::AST_CLASS_DEF); 
+        ast.doc = self.current_token.doc.clone();
 
-        self.eat(Types::TOKEN_ID); 
+        self.eat(Types::TOKEN_ID)?; 
         let n = self.current_token.value.clone();
-        self.eat(Types::TOKEN_ID);
+        self.eat(Types::TOKEN_ID)?;
 
-        self.eat(Types::TOKEN_LPARENT);
+        self.eat(Types::TOKEN_LPARENT)?;
 
         let mut args:Vec<AST> = vec![]; 
 
         let class_scope = Rc::new(RefCell::new(crate::scope::Scope::new_with_parent(self.scope.clone())));
 
         while self.current_token.kind != Types::TOKEN_RPARENT {
-            let t = match self.current_token.value.as_str() {
-                "str" => Data_Type::STR,
-                "int" => Data_Type::INT,
-                "float" => Data_Type::FLOAT,
-                "bool" => Data_Type::BOOL,
-                _ => panic!("Incorrect type for class {}", n),  
-            };
+            let t = if self.current_token.value == "map" {
+                self.parse_map_type()?
+            } else {
+                let t = match self.current_token.value.as_str() {
+                    "str" => Data_Type::STR,
+                    "int" => Data_Type::INT,
+                    "float" => Data_Type::FLOAT,
+                    "bool" => Data_Type::BOOL,
+                    "char" => Data_Type::CHAR,
+                    _ => return Err(self.error(format!("Incorrect type for class {}", n))),  
+                };
 
-            self.eat(Types::TOKEN_ID);
+                self.eat(Types::TOKEN_ID)?;
+                t
+            };
+            let t = self.parse_array_suffix(t)?;
 
             let n2 = self.current_token.value.clone();
+            let arg_span = self.current_token.span.clone();
             
-            self.eat(Types::TOKEN_ID);
+            self.eat(Types::TOKEN_ID)?;
 
 
             let mut arg = AST::new(Ast_Type::AST_VARIABLE_DEF);
+            arg.span = arg_span;
             arg.variable_definition_variable_name = Some(n2);
             arg.variable_type = Some(t);
             arg.scope = Some(class_scope.clone()); 
@@ -436,23 +848,30 @@ impl<'a> Parser<'a> {
             args.push(arg);
 
             if self.current_token.kind == Types::TOKEN_COMMA {
-                self.eat(Types::TOKEN_COMMA);
+                self.eat(Types::TOKEN_COMMA)?;
             } else {
                 break;
             }
         }
 
-        self.eat(Types::TOKEN_RPARENT);
-        self.eat(Types::TOKEN_LBRACK);
+        self.eat(Types::TOKEN_RPARENT)?;
+
+        if self.current_token.kind == Types::TOKEN_ARROW {
+            self.eat(Types::TOKEN_ARROW)?;
+            ast.function_return_type = Some(self.parse_return_type()?);
+        }
+
+        self.eat(Types::TOKEN_LBRACK)?;
 
         let mut temp_parser = Parser {
             lexer: self.lexer,
             current_token: self.current_token.clone(),
             prev_token: self.prev_token.clone(),
             scope: class_scope.clone(), 
+            loops: vec![],
         }; 
 
-        let mut body = temp_parser.parse_function_body(); 
+        let mut body = temp_parser.parse_function_body()?; 
 
         set_scope_recursively(&mut body, class_scope.clone());
 
@@ -462,7 +881,7 @@ impl<'a> Parser<'a> {
         self.current_token = temp_parser.current_token;
         self.prev_token = temp_parser.prev_token;
 
-        self.eat(Types::TOKEN_RBRACK);
+        self.eat(Types::TOKEN_RBRACK)?;
 
         ast.class_definition_args = Some(args);
         ast.class_definition_name = Some(n.clone()); 
@@ -483,35 +902,134 @@ impl<'a> Parser<'a> {
         ast.scope = Some(class_scope.clone());
         self.scope.borrow_mut().add_class_definition(ast.clone());
 
-        ast
+        Ok(ast)
     }
 
-    pub fn parse_break(&mut self) -> AST {        
-        self.eat(Types::TOKEN_ID);
+    // break and continue, with a label when it's for a loop further out than the innermost
+    pub fn parse_break(&mut self) -> IronResult<AST> {        
+        let keyword = self.current_token.value.clone();
+        let span = self.current_token.span.clone();
+        self.eat(Types::TOKEN_ID)?;
 
-        let mut node = AST::new(Ast_Type::AST_BREAK);
+        let mut node = AST::new(if keyword == "break" { Ast_Type::AST_BREAK } else { Ast_Type::AST_CONTINUE });
         node.scope = Some(self.scope.clone()); 
+        node.span = span.clone();
+
+        if self.loops.is_empty() {
+            return Err(IronError::ParseError(format!("{} used outside of a loop", keyword), span));
+        }
+
+        if self.current_token.kind == Types::TOKEN_ID {
+            let label = self.current_token.value.clone();
+
+            if !self.loops.contains(&Some(label.clone())) {
+                return Err(self.error(format!("No loop labeled {} around this {}", label, keyword)));
+            }
+
+            self.eat(Types::TOKEN_ID)?;
+            node.loop_label = Some(label);
+        }
+
+        Ok(node) 
+    }
+
+    // The body of a loop, where break and continue can be used
+    fn parse_loop_body(&mut self, label: &Option<String>) -> IronResult<AST> {
+        self.eat(Types::TOKEN_LBRACK)?;
+
+        self.loops.push(label.clone());
+        let body = self.parse_function_body()?;
+        self.loops.pop();
+
+        self.eat(Types::TOKEN_RBRACK)?;
+        Ok(body)
+    }
+
+    pub fn parse_try(&mut self) -> IronResult<AST> {
+        self.eat(Types::TOKEN_ID)?; // eat "try"
+        self.eat(Types::TOKEN_LBRACK)?;
+
+        let body = self.parse_function_body()?;
+
+        self.eat(Types::TOKEN_RBRACK)?;
+
+        let mut ast = AST::new(Ast_Type::AST_TRY);
+        ast.try_body = Some(Box::new(body));
+
+        if self.current_token.value == "catch" {
+            self.eat(Types::TOKEN_ID)?;
+            self.eat(Types::TOKEN_LPARENT)?;
+
+            let kind = self.current_token.value.clone();
+
+            if !IronError::is_kind(&kind) {
+                return Err(self.error(format!("Unknown error type {} in catch", kind)));
+            }
+
+            self.eat(Types::TOKEN_ID)?;
+
+            let name = self.current_token.value.clone();
+
+            self.eat(Types::TOKEN_ID)?;
+            self.eat(Types::TOKEN_RPARENT)?;
+            self.eat(Types::TOKEN_LBRACK)?;
+
+            let catch_body = self.parse_function_body()?;
+
+            self.eat(Types::TOKEN_RBRACK)?;
+
+            ast.catch_type = Some(kind);
+            ast.catch_name = Some(name);
+            ast.catch_body = Some(Box::new(catch_body));
+        }
+
+        if self.current_token.value == "finally" {
+            self.eat(Types::TOKEN_ID)?;
+            self.eat(Types::TOKEN_LBRACK)?;
+
+            let finally_body = self.parse_function_body()?;
+
+            self.eat(Types::TOKEN_RBRACK)?;
+
+            ast.finally_body = Some(Box::new(finally_body));
+        }
+
+        if ast.catch_body.is_none() && ast.finally_body.is_none() {
+            return Err(self.error("Expected catch or finally after try block".to_string()));
+        }
 
-        node 
+        ast.scope = Some(self.scope.clone());
+        Ok(ast)
     }
 
-    pub fn parse_import(&mut self) -> AST {
-        self.eat(Types::TOKEN_ID);
+    pub fn parse_throw(&mut self) -> IronResult<AST> {
+        self.eat(Types::TOKEN_ID)?; // eat "throw"
+
+        let mut node = AST::new(Ast_Type::AST_THROW);
+        node.throw_value = Some(Box::new(self.parse_expr()?));
+        node.scope = Some(self.scope.clone());
 
+        Ok(node)
+    }
+
+    pub fn parse_import(&mut self) -> IronResult<AST> {
         let mut node = AST::new(Ast_Type::AST_IMPORT);
+        node.span = self.current_token.span.clone();
+
+        self.eat(Types::TOKEN_ID)?;
 
 
         if self.current_token.kind == Types::TOKEN_LESS_THAN {
-            self.eat(Types::TOKEN_LESS_THAN);
+            self.eat(Types::TOKEN_LESS_THAN)?;
 
             node.string_value = Some(self.current_token.value.clone());
             node.variable_name = Some(self.current_token.value.clone());
 
             node.is_builtin = Some(true);
 
-            self.eat(Types::TOKEN_ID);
+            self.eat(Types::TOKEN_ID)?;
 
-            self.eat(Types::TOKEN_GREATER_THAN);
+            self.eat(Types::TOKEN_GREATER_THAN)?;
 
         }
         else {
@@ -522,122 +1040,247 @@ impl<'a> Parser<'a> {
 
             node.is_builtin = Some(false); 
 
-            self.eat(Types::TOKEN_STRING); 
+            self.eat(Types::TOKEN_STRING)?; 
         }
 
         node.scope = Some(self.scope.clone());
 
         self.scope.borrow_mut().add_import(&node.clone());
 
-        node
+        Ok(node)
     }
 
-    pub fn parse_return(&mut self) -> AST {
-        self.eat(Types::TOKEN_ID); 
+    pub fn parse_return(&mut self) -> IronResult<AST> {
+        self.eat(Types::TOKEN_ID)?; 
 
 
         let mut node = AST::new(Ast_Type::AST_RETURN);
 
         if self.current_token.kind != Types::TOKEN_SEMI {
-            let expr = self.parse_term();
+            let expr = self.parse_term()?;
             node.return_value = Some(Box::new(expr));
         }
 
-        node
+        Ok(node)
     }
 
-    pub fn parse_integer(&mut self) -> AST {
+    pub fn parse_integer(&mut self) -> IronResult<AST> {
         let t = self.current_token.clone();
         let mut ast = AST::new(Ast_Type::AST_INT);
 
-        ast.int_value = Some(t.value.parse::<i32>().unwrap_or_else(|_| panic!("invalid integer {:#?}", t.value)));
+        ast.int_value = Some(numbers::int_literal(&t.value, &t.span)?);
         ast.int_init = Some(true);
         ast.data_type = Data_Type::INT;
         ast.scope = Some(self.scope.clone());
-        self.eat(Types::TOKEN_INT);
-        ast
+        self.eat(Types::TOKEN_INT)?;
+        Ok(ast)
     }
 
-    pub fn parse_float(&mut self) -> AST {
+    pub fn parse_float(&mut self) -> IronResult<AST> {
         let t = self.current_token.clone();
         let mut ast = AST::new(Ast_Type::AST_FLOAT);
 
-        ast.float_value = Some(t.value.parse::<f64>().unwrap_or_else(|_| panic!("invalid float {:#?}", t.value)));
+        let (value, past_decimal) = numbers::float_literal(&t.value, &t.span)?;
+
+        ast.float_value = Some(value);
         ast.float_init = Some(true);
         ast.data_type = Data_Type::FLOAT;
-        ast.past_decimal = Some(t.value.split('.').nth(1).map_or(0, |s| s.len() as i32));
+        ast.past_decimal = Some(past_decimal);
         ast.scope = Some(self.scope.clone());
-        self.eat(Types::TOKEN_FLOAT);
-        ast
+        self.eat(Types::TOKEN_FLOAT)?;
+        Ok(ast)
     }
 
-    pub fn parse_bool(&mut self) -> AST {
+    pub fn parse_bool(&mut self) -> IronResult<AST> {
         let mut ast = AST::new(Ast_Type::AST_BOOL);
         ast.bool_value = Some(self.current_token.value == "true");
         ast.bool_init = Some(true);
+        ast.data_type = Data_Type::BOOL;
         ast.scope = Some(self.scope.clone());
-        self.eat(Types::TOKEN_BOOL);
-        ast
+        self.eat(Types::TOKEN_BOOL)?;
+        Ok(ast)
     }
 
-    pub fn parse_string(&mut self) -> AST {
+    pub fn parse_string(&mut self) -> IronResult<AST> {
         let mut ast = AST::new(Ast_Type::AST_STRING);
         ast.string_value = Some(self.current_token.value.clone());
+        ast.data_type = Data_Type::STR;
+        ast.scope = Some(self.scope.clone());
+        self.eat(Types::TOKEN_STRING)?;
+        Ok(ast)
+    }
+
+    // Bitwise operators bind tighter than comparisons, so x & 1 == 0 is (x & 1) == 0
+    pub fn parse_char(&mut self) -> IronResult<AST> {
+        let mut ast = AST::from_char(self.current_token.value.chars().next().unwrap_or_default());
         ast.scope = Some(self.scope.clone());
-        self.eat(Types::TOKEN_STRING);
-        ast
+        self.eat(Types::TOKEN_CHAR)?;
+        Ok(ast)
+    }
+
+    // f"Money: {money}" is "Money: " + str(money), the expressions are read where they sit in the file
+    pub fn parse_fstring(&mut self) -> IronResult<AST> {
+        let token = self.current_token.clone();
+        let mut parts = vec![];
+
+        for piece in &token.pieces {
+            let part = match piece {
+                Piece::Text(text) => {
+                    let mut ast = AST::from_string(text);
+                    ast.span = token.span.clone();
+                    ast.scope = Some(self.scope.clone());
+                    ast
+                }
+                Piece::Code { index, line, column } => {
+                    let mut lexer = self.lexer.at(*index, *line, *column);
+                    std::mem::swap(self.lexer, &mut lexer);
+
+                    let expr = self.parse_embedded();
+
+                    std::mem::swap(self.lexer, &mut lexer);
+                    self.current_token = token.clone();
+
+                    let expr = expr?;
+                    let mut call = AST::new(Ast_Type::AST_FUNCTION_CALL);
+                    call.function_call_name = Some("str".to_string());
+                    call.span = expr.span.clone();
+                    call.function_call_args = Some(vec![expr]);
+                    call.scope = Some(self.scope.clone());
+                    call
+                }
+            };
+
+            parts.push(part);
+        }
+
+        self.eat(Types::TOKEN_FSTRING)?;
+
+        let mut parts = parts.into_iter();
+        let first = parts.next().unwrap_or_else(|| {
+            let mut ast = AST::from_string("");
+            ast.span = token.span.clone();
+            ast.scope = Some(self.scope.clone());
+            ast
+        });
+
+        Ok(parts.fold(first, |left, right| self.combine_ast(left, Types::TOKEN_ADD, right)))
+    }
+
+    // One {expression} of an f-string, with the lexer sitting just inside the {
+    fn parse_embedded(&mut self) -> IronResult<AST> {
+        self.current_token = self.lexer.next_token()?;
+        let expr = self.parse_expr()?;
+
+        if self.current_token.kind != Types::TOKEN_RBRACK {
+            return Err(self.error("Expected } after the expression in the f-string".to_string()));
+        }
+
+        Ok(expr)
+    }
+
+    pub fn parse_term(&mut self) -> IronResult<AST> {
+        self.parse_bit_or()
+    }
+
+    pub fn parse_bit_or(&mut self) -> IronResult<AST> {
+        let mut left = self.parse_bit_xor()?;
+
+        while self.current_token.kind == Types::TOKEN_PIPE {
+            let op = self.current_token.kind.clone();
+            self.eat(op.clone())?;
+            let right = self.parse_bit_xor()?;
+            left = self.combine_ast(left, op, right);
+        }
+
+        Ok(left)
     }
 
-    pub fn parse_term(&mut self) -> AST {
-        self.parse_addition()
+    pub fn parse_bit_xor(&mut self) -> IronResult<AST> {
+        let mut left = self.parse_bit_and()?;
+
+        while self.current_token.kind == Types::TOKEN_XOR {
+            let op = self.current_token.kind.clone();
+            self.eat(op.clone())?;
+            let right = self.parse_bit_and()?;
+            left = self.combine_ast(left, op, right);
+        }
+
+        Ok(left)
+    }
+
+    pub fn parse_bit_and(&mut self) -> IronResult<AST> {
+        let mut left = self.parse_shift()?;
+
+        while self.current_token.kind == Types::TOKEN_AMPERSAND {
+            let op = self.current_token.kind.clone();
+            self.eat(op.clone())?;
+            let right = self.parse_shift()?;
+            left = self.combine_ast(left, op, right);
+        }
+
+        Ok(left)
     }
 
-    pub fn parse_addition(&mut self) -> AST {        
-        let mut left = self.parse_multiplication();
+    pub fn parse_shift(&mut self) -> IronResult<AST> {
+        let mut left = self.parse_addition()?;
+
+        while matches!(self.current_token.kind, Types::TOKEN_SHIFT_LEFT | Types::TOKEN_SHIFT_RIGHT) {
+            let op = self.current_token.kind.clone();
+            self.eat(op.clone())?;
+            let right = self.parse_addition()?;
+            left = self.combine_ast(left, op, right);
+        }
+
+        Ok(left)
+    }
+
+    pub fn parse_addition(&mut self) -> IronResult<AST> {        
+        let mut left = self.parse_multiplication()?;
 
         while matches!(self.current_token.kind, Types::TOKEN_ADD | Types::TOKEN_SUBTRACT) {
             let op = self.current_token.kind.clone();
-            self.eat(op.clone());
+            self.eat(op.clone())?;
 
-            let right = self.parse_multiplication();
+            let right = self.parse_multiplication()?;
 
             left = self.combine_ast(left, op, right);
         }
 
-        left
+        Ok(left)
     }
 
-    pub fn parse_exponentiation(&mut self) -> AST {
-        let mut left = self.parse_factor();
+    pub fn parse_exponentiation(&mut self) -> IronResult<AST> {
+        let mut left = self.parse_factor()?;
 
         while matches!(self.current_token.kind, Types::TOKEN_CARROT) {
             let op = self.current_token.kind.clone();
-            self.eat(op.clone());
-            let right = self.parse_exponentiation();
+            self.eat(op.clone())?;
+            let right = self.parse_exponentiation()?;
 
             left = self.combine_ast(left, op, right);
         }
 
-        left
+        Ok(left)
     }
 
-    pub fn parse_multiplication(&mut self) -> AST {
-        let mut left = self.parse_exponentiation();
+    pub fn parse_multiplication(&mut self) -> IronResult<AST> {
+        let mut left = self.parse_exponentiation()?;
 
         while matches!(self.current_token.kind, Types::TOKEN_ASTERISK | Types::TOKEN_FSLASH | Types::TOKEN_PERCENT) {
             let op = self.current_token.kind.clone();
-            self.eat(op.clone());
+            self.eat(op.clone())?;
 
-            let right = self.parse_exponentiation();
+            let right = self.parse_exponentiation()?;
 
             left = self.combine_ast(left, op, right);
         }
 
-        left
+        Ok(left)
     }
 
     pub fn combine_ast(&mut self, left:AST, op:Types, right:AST) -> AST {
         let mut node = AST::new(Ast_Type::AST_BINARY);
+        node.span = left.span.clone();
         node.left=Some(Box::new(left));
         node.right = Some(Box::new(right));
         node.operator = Some(op);
@@ -645,39 +1288,42 @@ impl<'a> Parser<'a> {
         node
     }
 
-    pub fn parse_factor(&mut self) -> AST {
-        match self.current_token.kind {
-            Types::TOKEN_FLOAT => self.parse_float(),
-            Types::TOKEN_INT => self.parse_integer(),
-            Types::TOKEN_ID => self.parse_id(),
+    pub fn parse_factor(&mut self) -> IronResult<AST> {
+        let span = self.current_token.span.clone();
+
+        let mut node = match self.current_token.kind {
+            Types::TOKEN_FLOAT => self.parse_float()?,
+            Types::TOKEN_INT => self.parse_integer()?,
+            Types::TOKEN_ID => self.parse_id()?,
             Types::TOKEN_LPARENT => {
-                self.eat(Types::TOKEN_LPARENT);
-                let expr = self.parse_expr();
-                self.eat(Types::TOKEN_RPARENT);
+                self.eat(Types::TOKEN_LPARENT)?;
+                let expr = self.parse_expr()?;
+                self.eat(Types::TOKEN_RPARENT)?;
                 expr
             }
             Types::TOKEN_SUBTRACT => {
-                self.eat(Types::TOKEN_SUBTRACT);
+                self.eat(Types::TOKEN_SUBTRACT)?;
 
                 match self.current_token.kind {
                     Types::TOKEN_INT => {
-                        let mut ast = self.parse_integer();
+                        let mut ast = self.parse_integer()?;
                         ast.int_value = Some(-ast.int_value.unwrap());
                         ast
                     }
                     Types::TOKEN_FLOAT => {
-                        let mut ast = self.parse_float();
+                        let mut ast = self.parse_float()?;
                         ast.float_value = Some(-ast.float_value.unwrap());
                         ast
                     }
                     Types::TOKEN_ID => {
-                        let mut ast = self.parse_id();
+                        let operand_span = self.current_token.span.clone();
+                        let mut ast = self.parse_id()?;
                         let evaluated = self.eval_ast(ast.clone());
 
                         match evaluated.ast_type {
                             Ast_Type::AST_INT => {
                                 let mut node = AST::new(Ast_Type::AST_INT);
-                                node.int_value = Some(-evaluated.int_value.unwrap());
+                                node.int_value = Some(numbers::negate(evaluated.int_value.unwrap(), &operand_span)?);
                                 node.int_init = Some(true);
                                 node.data_type = Data_Type::INT;
                                 node.scope = Some(self.scope.clone());
@@ -691,26 +1337,38 @@ impl<'a> Parser<'a> {
                                 node.scope = Some(self.scope.clone());
                                 node
                             }
-                            _ => panic!("Cannot negate non-numeric type"),
+                            _ => return Err(IronError::TypeError("Cannot negate non-numeric type".to_string(), operand_span)),
                         }
                     }
-                    _ => panic!("Unexpected token after unary minus: {:?}", self.current_token),
+                    _ => return Err(self.error(format!("Unexpected token after unary minus: {:?}", self.current_token.kind))),
                 }
             }
-            Types::TOKEN_STRING => self.parse_string(),
-            Types::TOKEN_BOOL => self.parse_bool(), 
-            Types::TOKEN_NOT => {
-                self.eat(Types::TOKEN_NOT);
-                let expr = self.parse_factor();
+            Types::TOKEN_STRING => self.parse_string()?,
+            Types::TOKEN_CHAR => self.parse_char()?,
+            Types::TOKEN_FSTRING => self.parse_fstring()?,
+            Types::TOKEN_BOOL => self.parse_bool()?, 
+            // Outside a declaration nothing says what it holds, it takes the type of wherever it's stored
+            Types::TOKEN_LBOX => self.parse_array_literal(Data_Type::VOID)?,
+            Types::TOKEN_INCREMENT | Types::TOKEN_DECREMENT => self.parse_prefix()?,
+            Types::TOKEN_NOT | Types::TOKEN_TILDE => {
+                let op = self.current_token.kind.clone();
+                self.eat(op.clone())?;
+                let expr = self.parse_factor()?;
 
                 let mut node = AST::new(Ast_Type::AST_UNARY);
-                node.operator = Some(Types::TOKEN_NOT);
+                node.operator = Some(op);
                 node.right = Some(Box::new(expr));
                 node.scope = Some(self.scope.clone());
                 node
             }
-            _ => panic!("Unexpected token in factor {:?}", self.current_token.clone()),
+            _ => return Err(self.error(format!("Unexpected token in factor {:?}", self.current_token.kind))),
+        };
+
+        if node.span.is_none() {
+            node.span = span;
         }
+
+        Ok(node)
     }
 
     pub fn eval_ast(&mut self, ast: AST) -> AST {
@@ -744,66 +1402,105 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn parse_function_call(&mut self) -> AST {
+    pub fn parse_function_call(&mut self) -> IronResult<AST> {
         let mut ast = AST::new(Ast_Type::AST_FUNCTION_CALL);
         ast.function_call_name = Some(self.prev_token.as_ref().unwrap().value.clone());
-        self.eat(Types::TOKEN_LPARENT);
+        ast.span = self.prev_token.as_ref().unwrap().span.clone();
+        self.eat(Types::TOKEN_LPARENT)?;
+
+        // array(int, 10, 0) takes a type first, which nothing else does
+        let is_type = matches!(self.current_token.value.as_str(), "int" | "str" | "float" | "bool" | "char" | "map")
+            || self.scope.borrow().get_class_definition(&self.current_token.value).is_some();
+
+        if ast.function_call_name.as_deref() == Some("array") && self.current_token.kind == Types::TOKEN_ID && is_type {
+            return self.parse_array_fill(ast);
+        }
         let mut args = Vec::new();
 
         if self.current_token.kind != Types::TOKEN_RPARENT {
-            args.push(self.parse_term());
+            args.push(self.parse_term()?);
 
             while self.current_token.kind == Types::TOKEN_COMMA {
-                self.eat(Types::TOKEN_COMMA);
-                args.push(self.parse_term());
+                self.eat(Types::TOKEN_COMMA)?;
+                args.push(self.parse_term()?);
             }
         }
 
-        self.eat(Types::TOKEN_RPARENT);
+        self.eat(Types::TOKEN_RPARENT)?;
         ast.function_call_args = Some(args);
         ast.scope = Some(self.scope.clone());
-        ast
+        Ok(ast)
+    }
+
+    // The rest of array(type, size, fill) after the `(`
+    pub fn parse_array_fill(&mut self, call: AST) -> IronResult<AST> {
+        let element = self.parse_return_type()?;
+
+        self.eat(Types::TOKEN_COMMA)?;
+        let size = self.parse_term()?;
+        self.eat(Types::TOKEN_COMMA)?;
+        let fill = self.parse_term()?;
+        self.eat(Types::TOKEN_RPARENT)?;
+
+        let mut ast = AST::new(Ast_Type::AST_ARRAY_FILL);
+        ast.data_type = element;
+        ast.function_call_args = Some(vec![size, fill]);
+        ast.scope = Some(self.scope.clone());
+        ast.span = call.span;
+
+        Ok(ast)
     }
 
-    pub fn parse_function_definition(&mut self) -> AST {
+    pub fn parse_function_definition(&mut self) -> IronResult<AST> {
         let mut ast = AST::new(Ast_Type::AST_FUNCTION_DEF);
+        ast.span = self.current_token.span.clone();
+        ast.doc = self.current_token.doc.clone();
 
-        self.eat(Types::TOKEN_ID);
+        self.eat(Types::TOKEN_ID)?;
 
         let n = self.current_token.value.clone();
         ast.function_definition_name = Some(n.clone());
 
-        self.eat(Types::TOKEN_ID);
-        self.eat(Types::TOKEN_LPARENT);
+        self.eat(Types::TOKEN_ID)?;
+        self.eat(Types::TOKEN_LPARENT)?;
 
         let mut args = vec![];
 
         let func_scope = Rc::new(RefCell::new(crate::scope::Scope::new_with_parent(self.scope.clone())));
 
         while self.current_token.kind != Types::TOKEN_RPARENT {
-            let t = match self.current_token.value.as_str() {
-                "str" => Data_Type::STR,
-                "int" => Data_Type::INT,
-                "float" => Data_Type::FLOAT,
-                "bool" => Data_Type::BOOL,
-                _ =>{
-                    if func_scope.clone().borrow().get_class_definition(&self.current_token.value).is_some() {
-                        Data_Type::CUSTOM(self.current_token.value.to_string()) 
-                    }
-                    else {
-                        panic!("Incorrect type for function {}", n)
-                    }
-                } ,
-            };
+            let t = if self.current_token.value == "map" {
+                self.parse_map_type()?
+            } else {
+                let t = match self.current_token.value.as_str() {
+                    "str" => Data_Type::STR,
+                    "int" => Data_Type::INT,
+                    "float" => Data_Type::FLOAT,
+                    "bool" => Data_Type::BOOL,
+                    "char" => Data_Type::CHAR,
+                    _ =>{
+                        if func_scope.clone().borrow().get_class_definition(&self.current_token.value).is_some() {
+                            Data_Type::CUSTOM(self.current_token.value.to_string()) 
+                        }
+                        else {
+                            return Err(IronError::NameError(format!("unknown type {}", self.current_token.value), self.current_token.span.clone()));
+                        }
+                    } ,
+                };
 
-            self.eat(Types::TOKEN_ID);
+                self.eat(Types::TOKEN_ID)?;
+                t
+            };
+            let t = self.parse_array_suffix(t)?;
 
             let n2 = self.current_token.value.clone();
+            let arg_span = self.current_token.span.clone();
             
-            self.eat(Types::TOKEN_ID);
+            self.eat(Types::TOKEN_ID)?;
 
 
             let mut arg = AST::new(Ast_Type::AST_VARIABLE_DEF);
+            arg.span = arg_span;
             arg.variable_definition_variable_name = Some(n2);
             arg.variable_type = Some(t);
             arg.scope = Some(func_scope.clone()); 
@@ -813,14 +1510,20 @@ impl<'a> Parser<'a> {
             args.push(arg);
 
             if self.current_token.kind == Types::TOKEN_COMMA {
-                self.eat(Types::TOKEN_COMMA);
+                self.eat(Types::TOKEN_COMMA)?;
             } else {
                 break;
             }
         }
 
-        self.eat(Types::TOKEN_RPARENT);
-        self.eat(Types::TOKEN_LBRACK);
+        self.eat(Types::TOKEN_RPARENT)?;
+
+        if self.current_token.kind == Types::TOKEN_ARROW {
+            self.eat(Types::TOKEN_ARROW)?;
+            ast.function_return_type = Some(self.parse_return_type()?);
+        }
+
+        self.eat(Types::TOKEN_LBRACK)?;
 
         let mut temp_parser = Parser {
             lexer: self.lexer,
@@ -828,77 +1531,244 @@ impl<'a> Parser<'a> {
             prev_token: self.prev_token.clone(),
            
             scope: func_scope.clone(),
+            loops: vec![],
         };
 
-        ast.function_definition_body = Some(Box::new(temp_parser.parse_function_body()));
+        ast.function_definition_body = Some(Box::new(temp_parser.parse_function_body()?));
 
         self.current_token = temp_parser.current_token;
         self.prev_token = temp_parser.prev_token;
 
-        self.eat(Types::TOKEN_RBRACK);
+        self.eat(Types::TOKEN_RBRACK)?;
 
         ast.scope = Some(func_scope.clone());
         ast.function_definition_args = Some(args);
 
         self.scope.borrow_mut().add_function_definition(ast.clone());
 
-        ast
+        Ok(ast)
+    }
+
+    // The type after `->`: a builtin, void or a class name, with `[]` for an array of it.
+    // Class names aren't looked up here so a method can return its own class
+    pub fn parse_return_type(&mut self) -> IronResult<Data_Type> {
+        if self.current_token.value == "map" {
+            let t = self.parse_map_type()?;
+            return self.parse_array_suffix(t);
+        }
+
+        let t = match self.current_token.value.as_str() {
+            "str" => Data_Type::STR,
+            "int" => Data_Type::INT,
+            "float" => Data_Type::FLOAT,
+            "bool" => Data_Type::BOOL,
+            "char" => Data_Type::CHAR,
+            "void" => Data_Type::VOID,
+            name => Data_Type::CUSTOM(name.to_string()),
+        };
+
+        self.eat(Types::TOKEN_ID)?;
+
+        if t == Data_Type::VOID && self.current_token.kind == Types::TOKEN_LBOX {
+            return Err(self.error("There are no void arrays".to_string()));
+        }
+
+        self.parse_array_suffix(t)
+    }
+
+    // map<K, V>, keys have to be something that can be compared for equality
+    pub fn parse_map_type(&mut self) -> IronResult<Data_Type> {
+        self.eat(Types::TOKEN_ID)?;
+        self.eat(Types::TOKEN_LESS_THAN)?;
+
+        let key_span = self.current_token.span.clone();
+        let key = self.parse_return_type()?;
+
+        if !matches!(key, Data_Type::INT | Data_Type::STR | Data_Type::CHAR | Data_Type::BOOL) {
+            return Err(IronError::ParseError(format!("Map keys must be int, str, char or bool, not {}", key.name()), key_span));
+        }
+
+        self.eat(Types::TOKEN_COMMA)?;
+        let value = self.parse_return_type()?;
+
+        if value == Data_Type::VOID {
+            return Err(self.error("Maps can't hold void".to_string()));
+        }
+
+        self.close_type()?;
+
+        Ok(Data_Type::MAP(Box::new(key), Box::new(value)))
     }
 
-    pub fn parse_if(&mut self) -> AST {
-        self.eat(Types::TOKEN_ID);
-        self.eat(Types::TOKEN_LPARENT);
+    // The >> at the end of map<str, map<str, int>> closes two maps, so it's eaten one > at a time
+    fn close_type(&mut self) -> IronResult<()> {
+        if self.current_token.kind != Types::TOKEN_SHIFT_RIGHT {
+            return self.eat(Types::TOKEN_GREATER_THAN);
+        }
+
+        let mut rest = Token::new(Types::TOKEN_GREATER_THAN, ">".to_string());
+        rest.span = self.current_token.span.clone().map(|span| Span::new(span.source, span.line, span.column + 1, 1));
+
+        self.prev_token = Some(self.current_token.clone());
+        self.current_token = rest;
+        Ok(())
+    }
+
+    // Each `[]` after a type makes it an array of what came before
+    pub fn parse_array_suffix(&mut self, t: Data_Type) -> IronResult<Data_Type> {
+        let mut t = t;
+
+        while self.current_token.kind == Types::TOKEN_LBOX {
+            self.eat(Types::TOKEN_LBOX)?;
+            self.eat(Types::TOKEN_RBOX)?;
+            t = Data_Type::ARRAY(Box::new(t));
+        }
+
+        Ok(t)
+    }
 
-        let condition = self.parse_expr();
+    pub fn parse_if(&mut self) -> IronResult<AST> {
+        self.eat(Types::TOKEN_ID)?;
+        self.eat(Types::TOKEN_LPARENT)?;
 
-        self.eat(Types::TOKEN_RPARENT);
-        self.eat(Types::TOKEN_LBRACK);
+        let condition = self.parse_expr()?;
 
-        let body = self.parse_function_body();
+        self.eat(Types::TOKEN_RPARENT)?;
+        self.eat(Types::TOKEN_LBRACK)?;
 
-        self.eat(Types::TOKEN_RBRACK);
+        let body = self.parse_function_body()?;
+
+        self.eat(Types::TOKEN_RBRACK)?;
 
         let mut ast = AST::new(Ast_Type::AST_IF);
         ast.if_condition = Some(Box::new(condition.clone()));
         ast.if_body = Some(Box::new(body.clone()));
 
         if self.current_token.value == "else" {
-            self.eat(Types::TOKEN_ID);
-            self.eat(Types::TOKEN_LBRACK);
+            self.eat(Types::TOKEN_ID)?;
+
+            // else if (...) is an if in the else, so a chain nests one if per link
+            if self.current_token.value == "if" {
+                ast.else_body = Some(Box::new(self.parse_if()?));
+                ast.scope = Some(self.scope.clone());
+                return Ok(ast);
+            }
 
-            let e = self.parse_function_body();
+            self.eat(Types::TOKEN_LBRACK)?;
 
-            self.eat(Types::TOKEN_RBRACK);
+            let e = self.parse_function_body()?;
+
+            self.eat(Types::TOKEN_RBRACK)?;
 
             ast.else_body = Some(Box::new(e.clone()));
         }
 
         ast.scope = Some(self.scope.clone());
-        ast 
+        Ok(ast) 
+    }
+
+    // match (x) { case 1, 2 => { } default => { } }, the first case with a pattern equal to x runs
+    pub fn parse_match(&mut self) -> IronResult<AST> {
+        let span = self.current_token.span.clone();
+        self.eat(Types::TOKEN_ID)?;
+        self.eat(Types::TOKEN_LPARENT)?;
+
+        let value = self.parse_expr()?;
+
+        self.eat(Types::TOKEN_RPARENT)?;
+        self.eat(Types::TOKEN_LBRACK)?;
+
+        let mut cases = vec![];
+        let mut has_default = false;
+
+        while self.current_token.kind != Types::TOKEN_RBRACK {
+            let case_span = self.current_token.span.clone();
+
+            if has_default {
+                return Err(self.error("default has to be the last case".to_string()));
+            }
+
+            let mut case = AST::new(Ast_Type::AST_CASE);
+            case.span = case_span;
+
+            match self.current_token.value.as_str() {
+                "case" => {
+                    self.eat(Types::TOKEN_ID)?;
+                    let mut patterns = vec![self.parse_pattern()?];
+
+                    while self.current_token.kind == Types::TOKEN_COMMA {
+                        self.eat(Types::TOKEN_COMMA)?;
+                        patterns.push(self.parse_pattern()?);
+                    }
+
+                    case.case_patterns = Some(patterns);
+                }
+                "default" => {
+                    self.eat(Types::TOKEN_ID)?;
+                    has_default = true;
+                }
+                _ => return Err(self.error(format!("Expected case or default in match, got {}", self.current_token.value))),
+            }
+
+            self.eat(Types::TOKEN_FAT_ARROW)?;
+            self.eat(Types::TOKEN_LBRACK)?;
+            case.case_body = Some(Box::new(self.parse_function_body()?));
+            self.eat(Types::TOKEN_RBRACK)?;
+
+            cases.push(case);
+        }
+
+        self.eat(Types::TOKEN_RBRACK)?;
+
+        let mut ast = AST::new(Ast_Type::AST_MATCH);
+        ast.match_value = Some(Box::new(value));
+        ast.match_cases = Some(cases);
+        ast.scope = Some(self.scope.clone());
+        ast.span = span;
+        Ok(ast)
+    }
+
+    // A class name matches instances of it, anything else is a value compared with ==
+    fn parse_pattern(&mut self) -> IronResult<AST> {
+        let name = self.current_token.value.clone();
+        let is_class = self.current_token.kind == Types::TOKEN_ID
+            && self.scope.borrow().get_class_definition(&name).is_some()
+            && matches!(self.peek()?.kind, Types::TOKEN_COMMA | Types::TOKEN_FAT_ARROW);
+
+        if !is_class {
+            return self.parse_expr();
+        }
+
+        let mut pattern = AST::new(Ast_Type::AST_CLASS_PATTERN);
+        pattern.class_name = Some(name);
+        pattern.span = self.current_token.span.clone();
+        pattern.scope = Some(self.scope.clone());
+        self.eat(Types::TOKEN_ID)?;
+        Ok(pattern)
     }
 
-    pub fn parse_function_body(&mut self) -> AST {
+    pub fn parse_function_body(&mut self) -> IronResult<AST> {
         let mut comp = AST::new(Ast_Type::AST_COMPOUND);
         comp.scope = Some(self.scope.clone());
         comp.compound_value = Some(Vec::new());
         
         while self.current_token.kind != Types::TOKEN_RBRACK {
-            let ast_state = self.parse_statement();
+            let ast_state = self.parse_statement()?;
 
             if let Some(ref mut v) = comp.compound_value {
                 v.push(ast_state);
             }
 
             if self.current_token.kind == Types::TOKEN_SEMI {
-                self.eat(Types::TOKEN_SEMI);
+                self.eat(Types::TOKEN_SEMI)?;
             }
         }
 
-        comp
+        Ok(comp)
     }
 
-    pub fn parse_comparison(&mut self) -> AST {
-        let mut left = self.parse_term();
+    pub fn parse_comparison(&mut self) -> IronResult<AST> {
+        let mut left = self.parse_term()?;
 
         while matches!(self.current_token.kind, 
             Types::TOKEN_GREATER_THAN |
@@ -907,66 +1777,67 @@ impl<'a> Parser<'a> {
             Types::TOKEN_GEQ
         ) {
             let op = self.current_token.kind.clone();
-            self.eat(op.clone());
+            self.eat(op.clone())?;
 
-            let right = self.parse_term();
+            let right = self.parse_term()?;
 
             left = self.combine_ast(left, op, right)
         }
 
-        left
+        Ok(left)
     }
 
-    pub fn parse_while(&mut self) -> AST {
-        self.eat(Types::TOKEN_ID);
-        self.eat(Types::TOKEN_LPARENT);
-
-        let condition = self.parse_expr();
+    pub fn parse_while(&mut self, label: Option<String>) -> IronResult<AST> {
+        self.eat(Types::TOKEN_ID)?;
+        self.eat(Types::TOKEN_LPARENT)?;
 
-        self.eat(Types::TOKEN_RPARENT);
-        self.eat(Types::TOKEN_LBRACK);
+        let condition = self.parse_expr()?;
 
-        let body = self.parse_function_body();
+        self.eat(Types::TOKEN_RPARENT)?;
 
-        self.eat(Types::TOKEN_RBRACK); 
+        let body = self.parse_loop_body(&label)?;
 
         let mut ast = AST::new(Ast_Type::AST_WHILE);
+        ast.loop_label = label;
 
         ast.while_condition = Some(Box::new(condition.clone()));
         ast.while_body = Some(Box::new(body.clone()));
 
-        ast
+        Ok(ast)
     }
 
-    pub fn parse_reassignment(&mut self, name: String) -> AST {
-        self.eat(Types::TOKEN_EQUALS);
-        let value = self.parse_term();
+    pub fn parse_reassignment(&mut self, name: String) -> IronResult<AST> {
+        self.eat(Types::TOKEN_EQUALS)?;
+        let value = self.parse_term()?;
 
         let mut node = AST::new(Ast_Type::AST_REASSIGN);
         node.reassign_name = Some(name);
         node.reassign_value = Some(Box::new(value));
         node.scope = Some(self.scope.clone());
-        node
+        Ok(node)
     }
 
-    pub fn parse_for(&mut self) -> AST {
-        self.eat(Types::TOKEN_ID);
-        self.eat(Types::TOKEN_LPARENT);
+    pub fn parse_for(&mut self, label: Option<String>) -> IronResult<AST> {
+        self.eat(Types::TOKEN_ID)?;
+        self.eat(Types::TOKEN_LPARENT)?;
 
-        let init = self.parse_statement();
-        self.eat(Types::TOKEN_SEMI);
+        if let Some(variable) = self.parse_for_each_variable()? {
+            return self.parse_for_each(variable, label);
+        }
+
+        let init = self.parse_statement()?;
+        self.eat(Types::TOKEN_SEMI)?;
 
-        let condition = self.parse_expr();
-        self.eat(Types::TOKEN_SEMI);
+        let condition = self.parse_expr()?;
+        self.eat(Types::TOKEN_SEMI)?;
 
-        let increment = self.parse_statement();
-        self.eat(Types::TOKEN_RPARENT);
+        let increment = self.parse_statement()?;
+        self.eat(Types::TOKEN_RPARENT)?;
 
-        self.eat(Types::TOKEN_LBRACK);
-        let body = self.parse_function_body();
-        self.eat(Types::TOKEN_RBRACK);
+        let body = self.parse_loop_body(&label)?;
 
         let mut ast = AST::new(Ast_Type::AST_FOR);
+        ast.loop_label = label;
         let loop_scope = Rc::new(RefCell::new(Scope::new_with_parent(self.scope.clone())));
 
         ast.for_init = Some(Box::new(init));
@@ -980,49 +1851,150 @@ impl<'a> Parser<'a> {
         set_scope_recursively(ast.for_increment.as_mut().unwrap(), loop_scope.clone());
         set_scope_recursively(ast.for_body.as_mut().unwrap(), loop_scope.clone());
 
-        ast
+        Ok(ast)
     }
 
-    pub fn parse_or(&mut self) -> AST {
-        let mut left = self.parse_and();
+    // for (int x in xs) and for (int i = 0; ...) both start with a type and a name, so look for the `in` and go back if it isn't there
+    fn parse_for_each_variable(&mut self) -> IronResult<Option<AST>> {
+        let lexer = self.lexer.clone();
+        let current = self.current_token.clone();
+        let prev = self.prev_token.clone();
+        let span = self.current_token.span.clone();
+
+        let head = (|| {
+            if self.current_token.kind != Types::TOKEN_ID {
+                return None;
+            }
+
+            let t = self.parse_return_type().ok()?;
+            let name = self.current_token.value.clone();
+            self.eat(Types::TOKEN_ID).ok()?;
+
+            (self.current_token.kind == Types::TOKEN_ID && self.current_token.value == "in").then_some((t, name))
+        })();
+
+        let Some((t, name)) = head else {
+            *self.lexer = lexer;
+            self.current_token = current;
+            self.prev_token = prev;
+            return Ok(None);
+        };
+
+        self.eat(Types::TOKEN_ID)?;
+
+        if t == Data_Type::VOID {
+            return Err(IronError::ParseError("Variables can't be void".to_string(), span));
+        }
+
+        let mut variable = AST::new(Ast_Type::AST_VARIABLE_DEF);
+        variable.variable_definition_variable_name = Some(name);
+        variable.variable_type = Some(t);
+        variable.span = span;
+        Ok(Some(variable))
+    }
+
+    pub fn parse_for_each(&mut self, variable: AST, label: Option<String>) -> IronResult<AST> {
+        let span = self.current_token.span.clone();
+        let mut iterable = self.parse_expr()?;
+
+        // range(end), range(start, end) and range(start, end, step), filled out to all three
+        if iterable.ast_type == Ast_Type::AST_FUNCTION_CALL && iterable.function_call_name.as_deref() == Some("range") {
+            let mut args = iterable.function_call_args.take().unwrap_or_default();
+
+            if args.is_empty() || args.len() > 3 {
+                return Err(IronError::ParseError(format!("range takes 1 to 3 arguments, got {}", args.len()), span));
+            }
+
+            if args.len() == 1 {
+                args.insert(0, AST::from_int(0));
+            }
+
+            if args.len() == 2 {
+                args.push(AST::from_int(1));
+            }
+
+            iterable.ast_type = Ast_Type::AST_RANGE;
+            iterable.function_call_args = Some(args);
+        }
+
+        self.eat(Types::TOKEN_RPARENT)?;
+
+        let body = self.parse_loop_body(&label)?;
+
+        let mut ast = AST::new(Ast_Type::AST_FOR_EACH);
+        ast.loop_label = label;
+        let loop_scope = Rc::new(RefCell::new(Scope::new_with_parent(self.scope.clone())));
+
+        ast.for_init = Some(Box::new(variable));
+        ast.for_iterable = Some(Box::new(iterable));
+        ast.for_body = Some(Box::new(body));
+        ast.scope = Some(self.scope.clone());
+
+        set_scope_recursively(ast.for_init.as_mut().unwrap(), loop_scope.clone());
+        set_scope_recursively(ast.for_body.as_mut().unwrap(), loop_scope.clone());
+
+        Ok(ast)
+    }
+
+    pub fn parse_or(&mut self) -> IronResult<AST> {
+        let mut left = self.parse_and()?;
 
         while self.current_token.kind == Types::TOKEN_OR {
             let op = self.current_token.kind.clone();
-            self.eat(op.clone());
-            let right = self.parse_and();
+            self.eat(op.clone())?;
+            let right = self.parse_and()?;
             left = self.combine_ast(left, op, right);
         }
 
-        left
+        Ok(left)
     }
 
-    pub fn parse_and(&mut self) -> AST {
-        let  mut left = self.parse_equality(); 
+    pub fn parse_and(&mut self) -> IronResult<AST> {
+        let  mut left = self.parse_equality()?; 
 
         while self.current_token.kind == Types::TOKEN_AND {
             let op = self.current_token.kind.clone();
-            self.eat(op.clone());
-            let right = self.parse_equality();
+            self.eat(op.clone())?;
+            let right = self.parse_equality()?;
             left = self.combine_ast(left, op, right);
         }
 
-        left
+        Ok(left)
     }
 
-    pub fn parse_equality(&mut self) -> AST {
-        let mut left: AST = self.parse_comparison();
+    pub fn parse_equality(&mut self) -> IronResult<AST> {
+        let mut left: AST = self.parse_comparison()?;
         
         while matches!(self.current_token.kind, Types::TOKEN_EE | Types::TOKEN_NEQ) {
             let op = self.current_token.kind.clone();   
-            self.eat(op.clone());
-            let right = self.parse_comparison();
+            self.eat(op.clone())?;
+            let right = self.parse_comparison()?;
             left = self.combine_ast(left, op, right);
         }
 
-        left
+        Ok(left)
+    }
+}
+
+// p.items for the name errors use, a.b.c when fields are chained
+fn dotted(node: &AST) -> String {
+    match node.dot_left.as_deref() {
+        Some(left) => format!("{}.{}", dotted(left), node.dot_right.as_ref().unwrap().variable_name.clone().unwrap_or_default()),
+        None => node.variable_name.clone().unwrap_or_default(),
     }
 }
 
+// An int literal written where a float goes, printed like 3.0
+fn widen_literal(element: &mut AST) {
+    element.data_type = Data_Type::FLOAT;
+    element.float_value = Some(element.int_value.unwrap() as f64);
+    element.int_value = None;
+    element.int_init = None;
+    element.float_init = Some(true);
+    element.past_decimal = Some(1);
+    element.ast_type = Ast_Type::AST_FLOAT;
+}
+
 fn set_scope_recursively(node: &mut AST, scope: Rc<RefCell<Scope>>) {
     node.scope = Some(scope.clone());
 
@@ -1073,4 +2045,24 @@ fn set_scope_recursively(node: &mut AST, scope: Rc<RefCell<Scope>>) {
     if let Some(cond) = node.for_condition.as_mut() {
         set_scope_recursively(cond, scope.clone());
     }
+
+    if let Some(iterable) = node.for_iterable.as_mut() {
+        set_scope_recursively(iterable, scope.clone());
+    }
+
+    if let Some(value) = node.match_value.as_mut() {
+        set_scope_recursively(value, scope.clone());
+    }
+
+    for case in node.match_cases.iter_mut().flatten() {
+        set_scope_recursively(case, scope.clone());
+    }
+
+    for pattern in node.case_patterns.iter_mut().flatten() {
+        set_scope_recursively(pattern, scope.clone());
+    }
+
+    if let Some(body) = node.case_body.as_mut() {
+        set_scope_recursively(body, scope.clone());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,195 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::ast::{AST, Ast_Type};
+use crate::error::{IronError, IronResult};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::scope::{Scope, SharedScope};
+use crate::token::Types;
+use crate::visitor::Visitor;
+
+pub struct Repl {
+    pub scope: SharedScope,
+    pub visitor: Visitor,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl {
+            scope: Rc::new(RefCell::new(Scope::new())),
+            visitor: Visitor::new(),
+        }
+    }
+
+    pub fn run(&mut self) {
+        println!("iron repl, :quit to leave");
+
+        let stdin = io::stdin();
+        let mut buffer = String::new();
+
+        loop {
+            print!("{}", if buffer.is_empty() { "iron> " } else { "...   " });
+            io::stdout().flush().ok();
+
+            let mut line = String::new();
+
+            match stdin.read_line(&mut line) {
+                Ok(0) | Err(_) => break, // Ctrl-D
+                Ok(_) => {}
+            }
+
+            if buffer.is_empty() && line.trim().starts_with(':') {
+                if !self.command(line.trim()) {
+                    break;
+                }
+                continue;
+            }
+
+            buffer.push_str(&line);
+
+            // Keep reading until every { has been closed
+            if brace_depth(&buffer) > 0 {
+                continue;
+            }
+
+            if !buffer.trim().is_empty() {
+                if let Err(e) = self.eval(&buffer, "<repl>") {
+                    eprintln!("{}", e.render());
+                }
+            }
+
+            buffer.clear();
+        }
+    }
+
+    // Returns false when the repl should stop
+    pub fn command(&mut self, line: &str) -> bool {
+        let mut parts = line.splitn(2, ' ');
+        let name = parts.next().unwrap_or("");
+        let arg = parts.next().unwrap_or("").trim();
+
+        match name {
+            ":quit" | ":q" => return false,
+            ":scope" => self.scope.borrow().print_all(),
+            ":reset" => {
+                self.scope = Rc::new(RefCell::new(Scope::new()));
+                self.visitor = Visitor::new();
+            }
+            ":load" => {
+                if arg.is_empty() {
+                    eprintln!("usage: :load <file.iron>");
+                    return true;
+                }
+
+                let result = fs::read_to_string(arg)
+                    .map_err(|_| IronError::ImportError(format!("Could not read the file {}", arg), None))
+                    .and_then(|source| self.eval(&source, arg));
+
+                if let Err(e) = result {
+                    eprintln!("{}", e.render());
+                }
+            }
+            _ => eprintln!("Unknown command {}, try :scope, :load, :reset or :quit", name),
+        }
+
+        true
+    }
+
+    /*
+        Runs the source against the persistent scope, statements are
+        parsed as expressions so that things like x + 1 work on their own
+        and anything that produces a value gets printed
+    */
+    pub fn eval(&mut self, source: &str, name: &str) -> IronResult<()> {
+        let mut lexer = Lexer::new_with_file(source, name);
+        let mut parser = Parser::new(&mut lexer, self.scope.clone())?;
+
+        let mut statements = vec![];
+
+        while parser.current_token.kind != Types::TOKEN_EOF {
+            statements.push(parser.parse_expr()?);
+
+            if parser.current_token.kind == Types::TOKEN_SEMI {
+                parser.eat(Types::TOKEN_SEMI)?;
+            }
+        }
+
+        for mut stmt in statements {
+            if is_expression(&stmt) {
+                let result = self.visitor.visit(&mut stmt)?;
+
+                if is_value(&result) {
+                    result.print();
+                    println!();
+                }
+            }
+            else {
+                // Definitions only land in the scope through a compound
+                let mut comp = AST::new(Ast_Type::AST_COMPOUND);
+                comp.scope = Some(self.scope.clone());
+                comp.compound_value = Some(vec![stmt]);
+
+                self.visitor.visit(&mut comp)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// An f-string parses to str() calls joined with +, so the calls and binaries cover it
+fn is_expression(node: &AST) -> bool {
+    // p.v = 1 and a[0] = 1 are assignments, they print nothing
+    if node.reassign_value.is_some() || node.array_assign_value.is_some() {
+        return false;
+    }
+
+    matches!(node.ast_type,
+        Ast_Type::AST_VARIABLE |
+        Ast_Type::AST_BINARY |
+        Ast_Type::AST_UNARY |
+        Ast_Type::AST_TERNARY |
+        Ast_Type::AST_INT |
+        Ast_Type::AST_FLOAT |
+        Ast_Type::AST_STRING |
+        Ast_Type::AST_BOOL |
+        Ast_Type::AST_FUNCTION_CALL |
+        Ast_Type::AST_DOT |
+        Ast_Type::AST_CLASS_ACCESS |
+        Ast_Type::AST_ARRAY_ACCESS
+    )
+}
+
+fn is_value(node: &AST) -> bool {
+    matches!(node.ast_type,
+        Ast_Type::AST_INT |
+        Ast_Type::AST_FLOAT |
+        Ast_Type::AST_STRING |
+        Ast_Type::AST_BOOL |
+        Ast_Type::AST_ARRAY_DEF |
+        Ast_Type::AST_MAP_DEF |
+        Ast_Type::AST_ERROR
+    )
+}
+
+// Counted over the lexer's tokens, so braces in strings, chars and comments don't count
+fn brace_depth(source: &str) -> i32 {
+    let mut lexer = Lexer::new(source);
+    let mut depth = 0;
+
+    loop {
+        match lexer.next_token() {
+            Ok(token) => match token.kind {
+                Types::TOKEN_LBRACK => depth += 1,
+                Types::TOKEN_RBRACK => depth -= 1,
+                Types::TOKEN_EOF => return depth,
+                _ => {}
+            },
+            // Inside a block the rest may still come, otherwise running it shows the error
+            Err(_) => return depth,
+        }
+    }
+}
@@ -0,0 +1,1615 @@
+/*
+    Runtime for programs built with `iron build`, it gets pasted in front
+    of the generated code. Values, printing and error messages follow the
+    vm (src/vm.rs) so a built program prints exactly what `iron` would
+*/
+
+#include <ctype.h>
+#include <errno.h>
+#include <limits.h>
+#include <math.h>
+#include <setjmp.h>
+#include <stdarg.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct Str {
+    int len;
+    const char *data;
+} Str;
+
+typedef struct Array Array;
+typedef struct Map Map;
+typedef struct Instance Instance;
+typedef struct Err Err;
+typedef struct ParamInfo ParamInfo;
+
+typedef enum { T_NIL, T_INT, T_FLOAT, T_BOOL, T_STR, T_CHAR, T_ARRAY, T_MAP, T_INSTANCE, T_ERROR } Tag;
+
+typedef struct Value {
+    Tag tag;
+    int precision; /* decimals a float literal was written with, -1 for the default of 2 */
+    union {
+        long long i;
+        double f;
+        int b;
+        Str *s; /* chars keep their one letter here too */
+        Array *a;
+        Map *m;
+        Instance *o;
+        Err *e;
+    } as;
+} Value;
+
+struct Array {
+    int len;
+    Value *items;
+    const ParamInfo *element; /* what the array was declared to hold */
+};
+
+/* Keys and values side by side, in the order the keys were added, like src/maps.rs */
+struct Map {
+    int len;
+    Value *keys;
+    Value *values;
+    const ParamInfo *key;
+    const ParamInfo *value;
+};
+
+struct Instance {
+    int class_id;
+    Value *fields;
+};
+
+struct Err {
+    const char *kind;
+    Str *message;
+    int span;
+};
+
+typedef struct { int source; int line; int column; int len; } SpanInfo;
+typedef struct { const char *name; const char *text; } SourceInfo;
+struct ParamInfo { int type; const char *class_name; const char *debug; const char *name; const ParamInfo *element; /* what an array holds */ };
+typedef struct {
+    const char *name;
+    int arity;
+    const ParamInfo *params;
+    const ParamInfo *returns; /* NULL when the function doesn't declare a return type */
+    void (*fn)(void);
+} FnInfo;
+
+typedef struct {
+    const char *name;
+    int nfields;
+    const char *const *fields;
+    int nmethods;
+    const char *const *method_names;
+    const int *method_fns;
+    int constructor;
+} ClassInfo;
+
+enum { P_INT, P_FLOAT, P_STR, P_CHAR, P_BOOL, P_CUSTOM, P_ARRAY, P_MAP, P_VOID };
+enum { OP_ADD, OP_SUB, OP_MUL, OP_DIV, OP_MOD, OP_POW, OP_BIT_AND, OP_BIT_OR, OP_BIT_XOR, OP_SHL, OP_SHR, OP_GT, OP_LT, OP_GE, OP_LE, OP_EQ, OP_NE, OP_AND, OP_OR };
+
+/* Filled in by the generated code below */
+extern const SpanInfo spans[];
+extern const SourceInfo sources[];
+extern const FnInfo functions[];
+extern const ClassInfo classes[];
+extern Value globals[];
+extern char globals_set[];
+extern const char *const global_names[];
+
+#define STACK_MAX (1 << 16)
+#define LOCALS_MAX (1 << 20)
+#define HANDLERS_MAX 1024
+#define DEPTH_MAX 1000 /* the same MAX_DEPTH the interpreter and the VM stop at */
+
+static Value stack[STACK_MAX];
+static int sp = 0;
+
+static Value locals[LOCALS_MAX];
+static Value *locals_top = locals;
+static int depth = 0; /* calls in progress, the top of the program included */
+
+typedef struct {
+    jmp_buf buf;
+    int sp;
+    Value *locals;
+    int depth;
+} Handler;
+
+static Handler handlers[HANDLERS_MAX];
+static int handler_count = 0;
+
+static Value nil_value(void) {
+    Value v;
+    v.tag = T_NIL;
+    v.precision = -1;
+    v.as.i = 0;
+    return v;
+}
+
+static Value int_value(long long i) {
+    Value v = nil_value();
+    v.tag = T_INT;
+    v.as.i = i;
+    return v;
+}
+
+static Value float_value(double f, int precision) {
+    Value v = nil_value();
+    v.tag = T_FLOAT;
+    v.precision = precision;
+    v.as.f = f;
+    return v;
+}
+
+/* Constants are written as their bits so nothing is lost going through decimal */
+static Value float_bits(unsigned long long bits, int precision) {
+    double f;
+    memcpy(&f, &bits, sizeof f);
+    return float_value(f, precision);
+}
+
+/* An int becomes the float it stands for, shown like the literal 3.0 */
+static Value widen(Value v) {
+    return v.tag == T_INT ? float_value((double)v.as.i, 1) : v;
+}
+
+static Value typed(Value v, const ParamInfo *element);
+
+/* An int going anywhere declared float, a float[], variable or argument, becomes a float like arrays::stored,
+   and an untyped [..] gets the type of the array it's stored as */
+static Value stored(const ParamInfo *declared, Value v) {
+    if (declared->type == P_FLOAT) return widen(v);
+    if (declared->type == P_ARRAY) return typed(v, declared->element);
+    return v;
+}
+
+/* An array literal written outside a declaration holds void until it's stored somewhere, it's only ever a temporary so it's changed in place */
+static Value typed(Value v, const ParamInfo *element) {
+    if (v.tag != T_ARRAY || v.as.a->element->type != P_VOID) return v;
+
+    v.as.a->element = element;
+    for (int i = 0; i < v.as.a->len; i++) v.as.a->items[i] = stored(element, v.as.a->items[i]);
+    return v;
+}
+
+/* A variable defined as a float only ever holds floats, so an int assigned to it later is widened too.
+   An untyped [..] assigned to an array variable becomes the same kind of array */
+static Value assigned(Value old, Value v) {
+    if (old.tag == T_FLOAT) return widen(v);
+    if (old.tag == T_ARRAY) return typed(v, old.as.a->element);
+    return v;
+}
+
+static Value bool_value(int b) {
+    Value v = nil_value();
+    v.tag = T_BOOL;
+    v.as.b = b != 0;
+    return v;
+}
+
+static Value str_value(Str *s) {
+    Value v = nil_value();
+    v.tag = T_STR;
+    v.as.s = s;
+    return v;
+}
+
+static Value char_value(Str *s) {
+    Value v = str_value(s);
+    v.tag = T_CHAR;
+    return v;
+}
+
+static int is_text(Value v) {
+    return v.tag == T_STR || v.tag == T_CHAR;
+}
+
+static Str *new_str(const char *data, int len) {
+    Str *s = malloc(sizeof(Str));
+    char *copy = malloc(len + 1);
+
+    memcpy(copy, data, len);
+    copy[len] = '\0';
+
+    s->len = len;
+    s->data = copy;
+    return s;
+}
+
+static Err *new_err(const char *kind, const char *message, int span) {
+    Err *e = malloc(sizeof(Err));
+    e->kind = kind;
+    e->message = new_str(message, strlen(message));
+    e->span = span;
+    return e;
+}
+
+static Value err_value(Err *e) {
+    Value v = nil_value();
+    v.tag = T_ERROR;
+    v.as.e = e;
+    return v;
+}
+
+static Value error_value(const char *kind, const char *message, int span) {
+    return err_value(new_err(kind, message, span));
+}
+
+static const char *type_name(Value v) {
+    switch (v.tag) {
+        case T_NIL: return "void";
+        case T_INT: return "int";
+        case T_FLOAT: return "float";
+        case T_BOOL: return "bool";
+        case T_STR: return "str";
+        case T_CHAR: return "char";
+        case T_ARRAY: return "array";
+        case T_MAP: return "map";
+        case T_INSTANCE: return "instance";
+        case T_ERROR: return "Error";
+    }
+    return "void";
+}
+
+static int exit_code(const char *kind) {
+    if (strcmp(kind, "LexError") == 0 || strcmp(kind, "ParseError") == 0) return 65;
+    if (strcmp(kind, "ImportError") == 0) return 66;
+    return 70;
+}
+
+/* Same layout as Span::render */
+static void render_error(Err *e) {
+    fprintf(stderr, "error[%s]: %.*s", e->kind, e->message->len, e->message->data);
+
+    if (e->span < 0) {
+        fprintf(stderr, "\n");
+        return;
+    }
+
+    const SpanInfo *s = &spans[e->span];
+    const SourceInfo *src = &sources[s->source];
+
+    char number[16];
+    snprintf(number, sizeof number, "%d", s->line);
+    int gutter = (int)strlen(number);
+
+    fprintf(stderr, "\n%*s--> %s:%d:%d\n", gutter, "", src->name, s->line, s->column);
+
+    const char *p = src->text;
+    for (int n = 1; n < s->line && p; n++) {
+        p = strchr(p, '\n');
+        if (p) p++;
+    }
+
+    if (!p || *p == '\0') {
+        fprintf(stderr, "\n");
+        return;
+    }
+
+    const char *end = strchr(p, '\n');
+    int len = end ? (int)(end - p) : (int)strlen(p);
+
+    while (len > 0 && (p[len - 1] == ' ' || p[len - 1] == '\t' || p[len - 1] == '\r')) {
+        len--;
+    }
+
+    fprintf(stderr, "%*s |\n", gutter, "");
+    fprintf(stderr, "%s | %.*s\n", number, len, p);
+    fprintf(stderr, "%*s | ", gutter, "");
+
+    int chars = 0;
+    for (int i = 0; i < len && chars < s->column - 1; i++) {
+        if ((p[i] & 0xC0) == 0x80) continue;
+        fputc(p[i] == '\t' ? '\t' : ' ', stderr);
+        chars++;
+    }
+
+    for (int i = 0; i < (s->len > 1 ? s->len : 1); i++) {
+        fputc('^', stderr);
+    }
+
+    fprintf(stderr, "\n");
+}
+
+/* Jumps to the closest try, or ends the program like an uncaught error in the interpreter */
+static void raise_err(Err *e) {
+    if (handler_count > 0) {
+        Handler *h = &handlers[--handler_count];
+
+        sp = h->sp;
+        locals_top = h->locals;
+        depth = h->depth;
+        stack[sp++] = err_value(e);
+
+        longjmp(h->buf, 1);
+    }
+
+    fflush(stdout);
+    render_error(e);
+    exit(exit_code(e->kind));
+}
+
+static void fail(const char *kind, int span, const char *format, ...) {
+    char message[512];
+    va_list args;
+
+    va_start(args, format);
+    vsnprintf(message, sizeof message, format, args);
+    va_end(args);
+
+    raise_err(new_err(kind, message, span));
+}
+
+static void push(Value v) {
+    if (sp == STACK_MAX) fail("RuntimeError", -1, "Stack overflow");
+    stack[sp++] = v;
+}
+
+static Value pop(void) {
+    return stack[--sp];
+}
+
+static Value peek(void) {
+    return stack[sp - 1];
+}
+
+/* Arrays and maps are values, so storing one anywhere takes a copy */
+static Value own(Value v) {
+    if (v.tag == T_MAP) {
+        Map *m = malloc(sizeof(Map));
+        *m = *v.as.m;
+        m->keys = malloc(sizeof(Value) * (m->len > 0 ? m->len : 1));
+        m->values = malloc(sizeof(Value) * (m->len > 0 ? m->len : 1));
+
+        for (int i = 0; i < m->len; i++) {
+            m->keys[i] = v.as.m->keys[i];
+            m->values[i] = own(v.as.m->values[i]);
+        }
+
+        v.as.m = m;
+        return v;
+    }
+
+    if (v.tag != T_ARRAY) return v;
+
+    Array *a = malloc(sizeof(Array));
+    a->len = v.as.a->len;
+    a->items = malloc(sizeof(Value) * (a->len > 0 ? a->len : 1));
+    a->element = v.as.a->element;
+
+    for (int i = 0; i < a->len; i++) {
+        a->items[i] = own(v.as.a->items[i]);
+    }
+
+    v.as.a = a;
+    return v;
+}
+
+static jmp_buf *handler_push(void) {
+    if (handler_count == HANDLERS_MAX) fail("RuntimeError", -1, "Too many nested try blocks");
+
+    Handler *h = &handlers[handler_count++];
+    h->sp = sp;
+    h->locals = locals_top;
+    h->depth = depth;
+    return &h->buf;
+}
+
+static void handler_pop(void) {
+    handler_count--;
+}
+
+/* Moves the arguments off the stack into a fresh set of locals */
+static Value *frame_enter(int nlocals, int nargs) {
+    int size = nlocals > nargs ? nlocals : nargs;
+
+    if (locals_top + size > locals + LOCALS_MAX) fail("RuntimeError", -1, "Stack overflow");
+
+    Value *L = locals_top;
+
+    for (int i = 0; i < nargs; i++) {
+        L[i] = own(stack[sp - nargs + i]);
+    }
+
+    for (int i = nargs; i < size; i++) {
+        L[i] = nil_value();
+    }
+
+    sp -= nargs;
+    locals_top += size;
+    depth++;
+    return L;
+}
+
+static void frame_leave(Value *L) {
+    locals_top = L;
+    depth--;
+}
+
+static Value gget(int i, int span) {
+    if (!globals_set[i]) fail("NameError", span, "Undefined variable: %s", global_names[i]);
+    return globals[i];
+}
+
+static Value *gref(int i, int span) {
+    gget(i, span);
+    return &globals[i];
+}
+
+static void gdefine(int i, Value v) {
+    globals[i] = v;
+    globals_set[i] = 1;
+}
+
+static void gassign(int i, Value v, int span) {
+    if (!globals_set[i]) fail("NameError", span, "Variable %s not defined", global_names[i]);
+    globals[i] = v;
+}
+
+static int truthy(Value v, int *ok) {
+    *ok = 1;
+
+    switch (v.tag) {
+        case T_BOOL: return v.as.b;
+        case T_INT: return v.as.i != 0;
+        case T_FLOAT: return v.as.f != 0.0;
+        default: *ok = 0; return 0;
+    }
+}
+
+static int condition(Value v, int span) {
+    int ok;
+    int b = truthy(v, &ok);
+
+    if (!ok) fail("TypeError", span, "Condition must be a bool, int or float, got %s", type_name(v));
+    return b;
+}
+
+/* Whether the left side of && (or || when is_or) on top of the stack is the answer, if so it's turned into a bool */
+static int decides(int is_or, int span) {
+    int ok;
+    int b = truthy(peek(), &ok);
+    if (!ok) fail("TypeError", span, "Invalid left operand type for boolean operation");
+    if (b != is_or) return 0;
+
+    sp--;
+    push(bool_value(b));
+    return 1;
+}
+
+static int number(Value v, double *out) {
+    if (v.tag == T_INT) { *out = v.as.i; return 1; }
+    if (v.tag == T_FLOAT) { *out = v.as.f; return 1; }
+    return 0;
+}
+
+static const char *operator_symbol(int op) {
+    switch (op) {
+        case OP_ADD: return "+";
+        case OP_SUB: return "-";
+        case OP_MUL: return "*";
+        case OP_DIV: return "/";
+        case OP_MOD: return "%";
+        case OP_BIT_AND: return "&";
+        case OP_BIT_OR: return "|";
+        case OP_BIT_XOR: return "xor";
+        case OP_SHL: return "<<";
+        case OP_SHR: return ">>";
+        default: return "^";
+    }
+}
+
+/* A negative power is a fraction, cut toward zero like numbers::power */
+static long long int_power(long long base, long long exponent, int span) {
+    if (base == 0 && exponent < 0) fail("ArithmeticError", span, "Division by zero");
+    if (base == 1) return 1;
+    if (base == -1) return exponent % 2 == 0 ? 1 : -1;
+    if (exponent < 0) return 0;
+
+    long long result = 1;
+
+    for (long long i = 0; i < exponent && result != 0; i++) {
+        if (__builtin_mul_overflow(result, base, &result)) fail("ArithmeticError", span, "%lld ^ %lld overflows an int", base, exponent);
+    }
+
+    return result;
+}
+
+/* Same as numbers::arithmetic, a result that doesn't fit is an error instead of wrapping around */
+static Value int_arithmetic(int op, long long a, long long b, int span) {
+    long long result = 0;
+    int overflow = 0;
+
+    switch (op) {
+        case OP_ADD: overflow = __builtin_add_overflow(a, b, &result); break;
+        case OP_SUB: overflow = __builtin_sub_overflow(a, b, &result); break;
+        case OP_MUL: overflow = __builtin_mul_overflow(a, b, &result); break;
+        case OP_DIV:
+        case OP_MOD:
+            if (b == 0) fail("ArithmeticError", span, "%s", op == OP_DIV ? "Division by zero" : "Modulo by zero");
+            overflow = a == LLONG_MIN && b == -1;
+            if (!overflow) result = op == OP_DIV ? a / b : a % b;
+            break;
+        case OP_POW: return int_value(int_power(a, b, span));
+        case OP_BIT_AND: return int_value(a & b);
+        case OP_BIT_OR: return int_value(a | b);
+        case OP_BIT_XOR: return int_value(a ^ b);
+        case OP_SHL:
+        case OP_SHR:
+            /* Bits shifted off the end are dropped, and >> keeps the sign */
+            if (b < 0 || b > 63) fail("ArithmeticError", span, "Can't shift by %lld, shifts go from 0 to 63", b);
+            return int_value(op == OP_SHL ? (long long)((unsigned long long)a << b) : a >> b);
+    }
+
+    if (overflow) fail("ArithmeticError", span, "%lld %s %lld overflows an int", a, operator_symbol(op), b);
+    return int_value(result);
+}
+
+static Value binary(int op, Value l, Value r, int span) {
+    if (op == OP_AND || op == OP_OR) {
+        int ok;
+        int lb = truthy(l, &ok);
+        if (!ok) fail("TypeError", span, "Invalid left operand type for boolean operation");
+        int rb = truthy(r, &ok);
+        if (!ok) fail("TypeError", span, "Invalid right operand type for boolean operation");
+
+        return bool_value(op == OP_AND ? (lb && rb) : (lb || rb));
+    }
+
+    if (op == OP_ADD && is_text(l) && is_text(r)) {
+        int len = l.as.s->len + r.as.s->len;
+        char *data = malloc(len + 1);
+
+        memcpy(data, l.as.s->data, l.as.s->len);
+        memcpy(data + l.as.s->len, r.as.s->data, r.as.s->len);
+        data[len] = '\0';
+
+        Str *s = malloc(sizeof(Str));
+        s->len = len;
+        s->data = data;
+        return str_value(s);
+    }
+
+    if ((op == OP_EQ || op == OP_NE) && is_text(l) && is_text(r)) {
+        int same = l.as.s->len == r.as.s->len && memcmp(l.as.s->data, r.as.s->data, l.as.s->len) == 0;
+        return bool_value(op == OP_EQ ? same : !same);
+    }
+
+    if ((op == OP_EQ || op == OP_NE) && l.tag == T_BOOL && r.tag == T_BOOL) {
+        int same = l.as.b == r.as.b;
+        return bool_value(op == OP_EQ ? same : !same);
+    }
+
+    if (l.tag == T_INT && r.tag == T_INT) {
+        long long x = l.as.i, y = r.as.i;
+
+        switch (op) {
+            case OP_GT: return bool_value(x > y);
+            case OP_LT: return bool_value(x < y);
+            case OP_GE: return bool_value(x >= y);
+            case OP_LE: return bool_value(x <= y);
+            case OP_EQ: return bool_value(x == y);
+            case OP_NE: return bool_value(x != y);
+        }
+
+        return int_arithmetic(op, x, y, span);
+    }
+
+    if (op >= OP_BIT_AND && op <= OP_SHR) {
+        fail("TypeError", span, "Cannot apply %s to %s", operator_symbol(op), type_name(l.tag == T_INT ? r : l));
+    }
+
+    double a, b;
+    if (!number(l, &a)) fail("TypeError", span, "Invalid left operand type");
+    if (!number(r, &b)) fail("TypeError", span, "Invalid right operand type");
+
+    switch (op) {
+        case OP_GT: return bool_value(a > b);
+        case OP_LT: return bool_value(a < b);
+        case OP_GE: return bool_value(a >= b);
+        case OP_LE: return bool_value(a <= b);
+        case OP_EQ: return bool_value(fabs(a - b) < 1e-8);
+        case OP_NE: return bool_value(fabs(a - b) >= 1e-8);
+    }
+
+    double result = 0.0;
+
+    switch (op) {
+        case OP_ADD: result = a + b; break;
+        case OP_SUB: result = a - b; break;
+        case OP_MUL: result = a * b; break;
+        case OP_DIV: result = a / b; break;
+        case OP_MOD: result = fmod(a, b); break;
+        case OP_POW: result = pow(a, b); break;
+    }
+
+    return float_value(result, -1);
+}
+
+static Value negate(Value v, int span) {
+    if (v.tag == T_INT && v.as.i == LLONG_MIN) fail("ArithmeticError", span, "-(%lld) overflows an int", v.as.i);
+    if (v.tag == T_INT) return int_value(-v.as.i);
+    if (v.tag == T_FLOAT) return float_value(-v.as.f, v.precision);
+    fail("TypeError", span, "Unary minus only supports int and float");
+    return v;
+}
+
+static Value not_value(Value v, int span) {
+    if (v.tag == T_BOOL) return bool_value(!v.as.b);
+    if (v.tag == T_INT) return int_value(v.as.i == 0 ? 1 : 0);
+    if (v.tag == T_FLOAT) return float_value(v.as.f == 0.0 ? 1.0 : 0.0, v.precision);
+    fail("TypeError", span, "Unary not only supports bool, int, float");
+    return v;
+}
+
+static Value bit_not(Value v, int span) {
+    if (v.tag != T_INT) fail("TypeError", span, "Cannot apply ~ to %s", type_name(v));
+    return int_value(~v.as.i);
+}
+
+static Value step(Value v, int by, int span) {
+    long long result;
+
+    if (v.tag == T_INT && __builtin_add_overflow(v.as.i, by, &result)) {
+        fail("ArithmeticError", span, "%lld %s 1 overflows an int", v.as.i, by > 0 ? "+" : "-");
+    }
+
+    if (v.tag == T_INT) return int_value(result);
+    if (v.tag == T_FLOAT) return float_value(v.as.f + by, v.precision);
+    fail("TypeError", span, "Cannot apply %s to non-numeric type (only int/float supported)", by > 0 ? "++" : "--");
+    return v;
+}
+
+static Value array_from_stack(int n, const ParamInfo *element) {
+    Array *a = malloc(sizeof(Array));
+    a->len = n;
+    a->items = malloc(sizeof(Value) * (n > 0 ? n : 1));
+    a->element = element;
+
+    for (int i = 0; i < n; i++) {
+        a->items[i] = stored(element, stack[sp - n + i]);
+    }
+
+    sp -= n;
+
+    Value v = nil_value();
+    v.tag = T_ARRAY;
+    v.as.a = a;
+    return v;
+}
+
+static long long array_index(Value index, int span) {
+    if (index.tag != T_INT) fail("TypeError", span, "Array index must be an int");
+    return index.as.i;
+}
+
+/* Strings are UTF-8 but positions count characters, like src/strings.rs */
+static int utf8_count(const Str *s) {
+    int n = 0;
+
+    for (int i = 0; i < s->len; i++) {
+        if (((unsigned char)s->data[i] & 0xC0) != 0x80) n++;
+    }
+
+    return n;
+}
+
+/* Where the nth character starts, or the end of the string */
+static int utf8_offset(const Str *s, int n) {
+    int chars = 0;
+
+    for (int i = 0; i < s->len; i++) {
+        if (((unsigned char)s->data[i] & 0xC0) == 0x80) continue;
+        if (chars == n) return i;
+        chars++;
+    }
+
+    return s->len;
+}
+
+static Value string_index(Str *s, long long i, const char *name, int span) {
+    if (i < 0 || i >= utf8_count(s)) fail("IndexError", span, "index %lld out of bounds for string %s", i, name);
+
+    int start = utf8_offset(s, (int)i);
+    return char_value(new_str(s->data + start, utf8_offset(s, (int)i + 1) - start));
+}
+
+static Value slice(Value target, Value start_value, Value end_value, const char *name, int span) {
+    long long start = array_index(start_value, span);
+    long long end = array_index(end_value, span);
+
+    if (target.tag != T_STR) fail("TypeError", span, "%s is not a string", name);
+
+    Str *s = target.as.s;
+    if (start < 0 || start > end || end > utf8_count(s)) fail("IndexError", span, "slice %lld:%lld out of bounds for string %s", start, end, name);
+
+    int from = utf8_offset(s, (int)start);
+    return str_value(new_str(s->data + from, utf8_offset(s, (int)end) - from));
+}
+
+/* Numbers compare by value and text by text, like == does */
+static int equals(Value a, Value b) {
+    double x, y;
+
+    if (a.tag == T_BOOL && b.tag == T_BOOL) return a.as.b == b.as.b;
+    if (a.tag == T_INT && b.tag == T_INT) return a.as.i == b.as.i;
+    if (number(a, &x) && number(b, &y)) return fabs(x - y) < 1e-8;
+    if (is_text(a) && is_text(b)) return a.as.s->len == b.as.s->len && memcmp(a.as.s->data, b.as.s->data, a.as.s->len) == 0;
+    return 0;
+}
+
+/* How a key is shown when it's missing, like Key::describe */
+static const char *describe(Value v, char *out, size_t size) {
+    switch (v.tag) {
+        case T_STR: snprintf(out, size, "\"%.*s\"", v.as.s->len, v.as.s->data); break;
+        case T_CHAR: snprintf(out, size, "'%.*s'", v.as.s->len, v.as.s->data); break;
+        case T_INT: snprintf(out, size, "%lld", v.as.i); break;
+        case T_BOOL: snprintf(out, size, "%s", v.as.b ? "true" : "false"); break;
+        default: snprintf(out, size, "%s", type_name(v)); break;
+    }
+
+    return out;
+}
+
+static int map_find(const Map *m, Value key) {
+    for (int i = 0; i < m->len; i++) {
+        if (equals(m->keys[i], key)) return i;
+    }
+
+    return -1;
+}
+
+static void map_missing(Value key, const char *name, int span) {
+    char shown[256];
+    fail("KeyError", span, "key %s not found in %s", describe(key, shown, sizeof shown), name);
+}
+
+/* A new key goes on the end, an existing one keeps its place */
+static void map_set(Map *m, Value key, Value v) {
+    int i = map_find(m, key);
+
+    if (i < 0) {
+        m->keys = realloc(m->keys, sizeof(Value) * (m->len + 1));
+        m->values = realloc(m->values, sizeof(Value) * (m->len + 1));
+        m->keys[m->len] = key;
+        i = m->len++;
+    }
+
+    m->values[i] = v;
+}
+
+static Value map_from_stack(int n, const ParamInfo *key, const ParamInfo *value) {
+    Map *m = malloc(sizeof(Map));
+    m->len = 0;
+    m->keys = malloc(sizeof(Value));
+    m->values = malloc(sizeof(Value));
+    m->key = key;
+    m->value = value;
+
+    for (int i = sp - n * 2; i < sp; i += 2) {
+        map_set(m, stack[i], own(stored(value, stack[i + 1])));
+    }
+
+    sp -= n * 2;
+
+    Value v = nil_value();
+    v.tag = T_MAP;
+    v.as.m = m;
+    return v;
+}
+
+static Value index_get(Value target, Value index, const char *name, int span) {
+    if (target.tag == T_MAP) {
+        int at = map_find(target.as.m, index);
+        if (at < 0) map_missing(index, name, span);
+        return target.as.m->values[at];
+    }
+
+    long long i = array_index(index, span);
+
+    if (target.tag == T_STR) return string_index(target.as.s, i, name, span);
+    if (target.tag != T_ARRAY) fail("TypeError", span, "%s is not an array", name);
+    if (i < 0 || i >= target.as.a->len) fail("IndexError", span, "index %lld out of bounds for array %s", i, name);
+
+    return target.as.a->items[i];
+}
+
+/* The element a nested write goes through, grid[i][j] = v walks grid[i] first */
+static Value *index_ref(Value *target, Value index, const char *name, int span) {
+    if (target->tag == T_MAP) {
+        int at = map_find(target->as.m, index);
+        if (at < 0) map_missing(index, name, span);
+        return &target->as.m->values[at];
+    }
+
+    if (target->tag != T_ARRAY) fail("TypeError", span, "%s is not an array", name);
+
+    long long i = array_index(index, span);
+    if (i < 0 || i >= target->as.a->len) fail("IndexError", span, "index %lld out of bounds for array %s", i, name);
+
+    return &target->as.a->items[i];
+}
+
+/* Storing under a new key adds it, going through one needs it to be there */
+static void index_set(Value *target, Value index, Value v, const char *name, int span) {
+    if (target->tag == T_MAP) {
+        map_set(target->as.m, index, own(stored(target->as.m->value, v)));
+        return;
+    }
+
+    if (target->tag == T_ARRAY) v = stored(target->as.a->element, v);
+
+    *index_ref(target, index, name, span) = own(v);
+}
+
+static void write_value(FILE *out, Value v) {
+    switch (v.tag) {
+        case T_STR:
+        case T_CHAR: fwrite(v.as.s->data, 1, v.as.s->len, out); break;
+        case T_INT: fprintf(out, "%lld", v.as.i); break;
+        case T_FLOAT:
+            /* Written the way Rust writes them, printf gives nan a sign */
+            if (isnan(v.as.f)) fprintf(out, "NaN");
+            else if (isinf(v.as.f)) fprintf(out, "%sinf", v.as.f < 0 ? "-" : "");
+            else fprintf(out, "%.*f", v.precision < 0 ? 2 : v.precision, v.as.f);
+            break;
+        case T_BOOL: fprintf(out, "%s", v.as.b ? "true" : "false"); break;
+        case T_ERROR: fprintf(out, "%s: %.*s", v.as.e->kind, v.as.e->message->len, v.as.e->message->data); break;
+        case T_ARRAY:
+            fprintf(out, "[");
+            for (int i = 0; i < v.as.a->len; i++) {
+                write_value(out, v.as.a->items[i]);
+                if (i < v.as.a->len - 1) fprintf(out, ", ");
+            }
+            fprintf(out, "]");
+            break;
+        case T_MAP:
+            fprintf(out, "{");
+            for (int i = 0; i < v.as.m->len; i++) {
+                write_value(out, v.as.m->keys[i]);
+                fprintf(out, ": ");
+                write_value(out, v.as.m->values[i]);
+                if (i < v.as.m->len - 1) fprintf(out, ", ");
+            }
+            fprintf(out, "}");
+            break;
+        case T_NIL: fprintf(out, "<unhandled type>, AST_NOOP\n"); break;
+        case T_INSTANCE: fprintf(out, "<unhandled type>, AST_CLASS_INSTANCE\n"); break;
+    }
+}
+
+static void print_value(Value v) {
+    write_value(stdout, v);
+}
+
+/* int("42") and float("2.5"), checked the way numbers::parse_int and parse_float check them */
+static int all_digits(const char *from, const char *to) {
+    for (const char *c = from; c < to; c++) {
+        if (!isdigit((unsigned char)*c)) return 0;
+    }
+
+    return 1;
+}
+
+static Value parse_number(const char *to, Str *s, int span) {
+    char text[512];
+    snprintf(text, sizeof text, "%.*s", s->len, s->data);
+
+    const char *unsigned_part = text + (text[0] == '+' || text[0] == '-');
+    const char *end = text + strlen(text);
+
+    if (strcmp(to, "int") == 0) {
+        if (unsigned_part == end || !all_digits(unsigned_part, end)) fail("TypeError", span, "Can't convert \"%s\" to int", text);
+
+        errno = 0;
+        long long i = strtoll(text, NULL, 10);
+        if (errno == ERANGE) fail("ArithmeticError", span, "\"%s\" overflows an int", text);
+        return int_value(i);
+    }
+
+    const char *e = strpbrk(unsigned_part, "eE");
+    const char *mantissa_end = e ? e : end;
+    const char *exponent = e ? e + 1 + (e[1] == '+' || e[1] == '-') : end;
+    const char *dot = memchr(unsigned_part, '.', mantissa_end - unsigned_part);
+    const char *whole_end = dot ? dot : mantissa_end;
+    const char *fraction = dot ? dot + 1 : mantissa_end;
+
+    int valid = mantissa_end - unsigned_part - (dot != NULL) > 0
+        && (!e || exponent < end)
+        && all_digits(unsigned_part, whole_end)
+        && all_digits(fraction, mantissa_end)
+        && all_digits(exponent, end);
+
+    if (!valid) fail("TypeError", span, "Can't convert \"%s\" to float", text);
+    return float_value(strtod(text, NULL), -1);
+}
+
+/* The int(), float() and str() builtins, like convert in the visitor */
+static void convert(const char *to, int argc, int span) {
+    if (argc != 1) fail("TypeError", span, "Function %s expected 1 arguments, got %d", to, argc);
+
+    Value v = pop();
+
+    if (strcmp(to, "str") == 0) {
+        char *data;
+        size_t len;
+        FILE *out = open_memstream(&data, &len);
+
+        write_value(out, v);
+        fclose(out);
+        push(str_value(new_str(data, (int)len)));
+        free(data);
+        return;
+    }
+
+    if (v.tag == T_STR) {
+        push(parse_number(to, v.as.s, span));
+        return;
+    }
+
+    double f;
+    if (v.tag == T_BOOL) f = v.as.b;
+    else if (!number(v, &f)) fail("TypeError", span, "Can't convert %s to %s", type_name(v), to);
+
+    if (strcmp(to, "float") == 0) {
+        push(float_value(f, -1));
+        return;
+    }
+
+    if (v.tag != T_FLOAT) {
+        push(int_value(v.tag == T_INT ? v.as.i : (long long)f));
+        return;
+    }
+
+    if (isnan(f) || f < -9223372036854775808.0 || f >= 9223372036854775808.0) {
+        fail("ArithmeticError", span, "float is out of range for an int");
+    }
+
+    push(int_value((long long)f));
+}
+
+static void print_args(int argc, int newline) {
+    for (int i = sp - argc; i < sp; i++) {
+        print_value(stack[i]);
+    }
+
+    if (newline) printf("\n");
+
+    sp -= argc;
+    push(nil_value());
+}
+
+/* Whether p is an array of element, going by names like "int[]" */
+static int array_of(const ParamInfo *p, const ParamInfo *element) {
+    size_t len = strlen(element->name);
+    return strncmp(p->name, element->name, len) == 0 && strcmp(p->name + len, "[]") == 0;
+}
+
+/* Whether p is the type of m, going by names like "map<str, int>" */
+static int map_of(const ParamInfo *p, const Map *m) {
+    char name[256];
+    snprintf(name, sizeof name, "map<%s, %s>", m->key->name, m->value->name);
+    return strcmp(p->name, name) == 0;
+}
+
+static int accepts(const ParamInfo *p, Value v) {
+    switch (p->type) {
+        case P_INT: return v.tag == T_INT;
+        case P_FLOAT: return v.tag == T_FLOAT || v.tag == T_INT;
+        case P_STR: return v.tag == T_STR;
+        case P_CHAR: return v.tag == T_CHAR;
+        case P_BOOL: return v.tag == T_BOOL;
+        case P_CUSTOM:
+            if (v.tag == T_INSTANCE) return strcmp(classes[v.as.o->class_id].name, p->class_name) == 0;
+            if (v.tag == T_ERROR) return strcmp(p->class_name, "Error") == 0;
+            return 0;
+        case P_ARRAY: return v.tag == T_ARRAY && array_of(p, v.as.a->element);
+        case P_MAP: return v.tag == T_MAP && map_of(p, v.as.m);
+        case P_VOID: return v.tag == T_NIL;
+    }
+    return 0;
+}
+
+/* The value being returned is on top of the stack */
+static void check_return(int fn, int span) {
+    const FnInfo *f = &functions[fn];
+
+    stack[sp - 1] = stored(f->returns, stack[sp - 1]);
+
+    if (!accepts(f->returns, stack[sp - 1])) {
+        fail("TypeError", span, "Function %s should return %s, got %s", f->name, f->returns->name, type_name(stack[sp - 1]));
+    }
+}
+
+static void missing_return(int fn, int span) {
+    fail("TypeError", span, "Function %s should return %s but ended without a return", functions[fn].name, functions[fn].returns->name);
+}
+
+static void check_args(int fn, int argc, int span) {
+    const FnInfo *f = &functions[fn];
+
+    if (f->arity != argc) fail("TypeError", span, "Function %s expected %d arguments, got %d", f->name, f->arity, argc);
+    if (depth > DEPTH_MAX) fail("RuntimeError", span, "Stack overflow, calls nested more than %d deep", DEPTH_MAX);
+
+    for (int i = 0; i < argc; i++) {
+        Value arg = stored(&f->params[i], stack[sp - argc + i]);
+
+        if (!accepts(&f->params[i], arg)) {
+            fail("TypeError", span, "Function %s argument type mismatch: expected %s, got %s", f->name, f->params[i].debug, type_name(arg));
+        }
+
+        stack[sp - argc + i] = arg;
+    }
+}
+
+static void call(int fn, int argc, int span) {
+    check_args(fn, argc, span);
+    functions[fn].fn();
+}
+
+/* Where needle first shows up in s at or after from, -1 if it doesn't */
+static int find(const Str *s, const Str *needle, int from) {
+    for (int i = from; i + needle->len <= s->len; i++) {
+        if (memcmp(s->data + i, needle->data, needle->len) == 0) return i;
+    }
+
+    return -1;
+}
+
+static int is_space(char c) {
+    return c == ' ' || c == '\t' || c == '\n' || c == '\f' || c == '\r';
+}
+
+static void push_piece(Array *a, const char *data, int len) {
+    a->items = realloc(a->items, sizeof(Value) * (a->len + 1));
+    a->items[a->len++] = str_value(new_str(data, len));
+}
+
+static const ParamInfo str_element = { P_STR, NULL, "STR", "str" };
+
+static Value split(Str *s, Str *sep) {
+    Array *a = malloc(sizeof(Array));
+    a->len = 0;
+    a->items = malloc(sizeof(Value));
+    a->element = &str_element;
+
+    if (sep->len == 0) {
+        int count = utf8_count(s);
+
+        for (int i = 0; i < count; i++) {
+            int start = utf8_offset(s, i);
+            push_piece(a, s->data + start, utf8_offset(s, i + 1) - start);
+        }
+    } else {
+        int start = 0, at;
+
+        while ((at = find(s, sep, start)) >= 0) {
+            push_piece(a, s->data + start, at - start);
+            start = at + sep->len;
+        }
+
+        push_piece(a, s->data + start, s->len - start);
+    }
+
+    Value v = nil_value();
+    v.tag = T_ARRAY;
+    v.as.a = a;
+    return v;
+}
+
+static Value replace(Str *s, Str *from, Str *to) {
+    if (from->len == 0) return str_value(s);
+
+    int cap = s->len + 1, len = 0;
+    char *data = malloc(cap);
+    int start = 0, at;
+
+    for (;;) {
+        at = find(s, from, start);
+        int keep = (at < 0 ? s->len : at) - start;
+        int add = at < 0 ? 0 : to->len;
+
+        while (len + keep + add + 1 > cap) {
+            cap *= 2;
+            data = realloc(data, cap);
+        }
+
+        memcpy(data + len, s->data + start, keep);
+        len += keep;
+
+        if (at < 0) break;
+
+        memcpy(data + len, to->data, to->len);
+        len += to->len;
+        start = at + from->len;
+    }
+
+    Str *result = malloc(sizeof(Str));
+    data[len] = '\0';
+    result->len = len;
+    result->data = data;
+    return str_value(result);
+}
+
+/* Same checks and messages as strings::method, the receiver sits under the arguments */
+static void string_method(const char *name, int argc, int span) {
+    int arity;
+
+    if (strcmp(name, "len") == 0 || strcmp(name, "upper") == 0 || strcmp(name, "lower") == 0 || strcmp(name, "trim") == 0) arity = 0;
+    else if (strcmp(name, "split") == 0 || strcmp(name, "contains") == 0 || strcmp(name, "starts_with") == 0) arity = 1;
+    else if (strcmp(name, "replace") == 0) arity = 2;
+    else fail("NameError", span, "str has no method %s", name);
+
+    if (argc != arity) fail("TypeError", span, "Function %s expected %d arguments, got %d", name, arity, argc);
+
+    for (int i = sp - argc; i < sp; i++) {
+        if (!is_text(stack[i])) fail("TypeError", span, "Function %s argument type mismatch: expected STR, got %s", name, type_name(stack[i]));
+    }
+
+    Str *s = stack[sp - argc - 1].as.s;
+    Str *a = argc > 0 ? stack[sp - argc].as.s : NULL;
+    Value result;
+
+    if (strcmp(name, "len") == 0) {
+        result = int_value(utf8_count(s));
+    } else if (strcmp(name, "upper") == 0 || strcmp(name, "lower") == 0) {
+        Str *changed = new_str(s->data, s->len);
+        char *data = (char *)changed->data;
+        int upper = name[0] == 'u';
+
+        for (int i = 0; i < s->len; i++) {
+            if (upper && data[i] >= 'a' && data[i] <= 'z') data[i] -= 32;
+            if (!upper && data[i] >= 'A' && data[i] <= 'Z') data[i] += 32;
+        }
+
+        result = str_value(changed);
+    } else if (strcmp(name, "trim") == 0) {
+        int start = 0, end = s->len;
+
+        while (start < end && is_space(s->data[start])) start++;
+        while (end > start && is_space(s->data[end - 1])) end--;
+
+        result = str_value(new_str(s->data + start, end - start));
+    } else if (strcmp(name, "split") == 0) {
+        result = split(s, a);
+    } else if (strcmp(name, "contains") == 0) {
+        result = bool_value(find(s, a, 0) >= 0);
+    } else if (strcmp(name, "starts_with") == 0) {
+        result = bool_value(a->len <= s->len && memcmp(s->data, a->data, a->len) == 0);
+    } else {
+        result = replace(s, a, stack[sp - 1].as.s);
+    }
+
+    sp -= argc + 1;
+    push(result);
+}
+
+/* Sets *out to <0, 0 or >0, returns 0 when the two can't be put in order */
+static int order(Value a, Value b, int *out) {
+    double x, y;
+
+    if (a.tag == T_INT && b.tag == T_INT) {
+        *out = a.as.i < b.as.i ? -1 : a.as.i > b.as.i ? 1 : 0;
+        return 1;
+    }
+
+    if (number(a, &x) && number(b, &y)) {
+        *out = x < y ? -1 : x > y ? 1 : 0;
+        return 1;
+    }
+
+    if (is_text(a) && is_text(b)) {
+        int n = a.as.s->len < b.as.s->len ? a.as.s->len : b.as.s->len;
+        int c = memcmp(a.as.s->data, b.as.s->data, n);
+        *out = c != 0 ? c : a.as.s->len - b.as.s->len;
+        return 1;
+    }
+
+    return 0;
+}
+
+/* Stable, so equal items keep their order like Rust's sort */
+static void merge_sort(Value *items, Value *scratch, int len) {
+    if (len < 2) return;
+
+    int half = len / 2;
+    merge_sort(items, scratch, half);
+    merge_sort(items + half, scratch, len - half);
+
+    int i = 0, j = half, k = 0, c;
+
+    while (i < half && j < len) {
+        order(items[j], items[i], &c);
+        scratch[k++] = c < 0 ? items[j++] : items[i++];
+    }
+
+    while (i < half) scratch[k++] = items[i++];
+    while (j < len) scratch[k++] = items[j++];
+
+    memcpy(items, scratch, sizeof(Value) * len);
+}
+
+static void array_fits(Array *a, Value v, int span) {
+    if (!accepts(a->element, v)) fail("TypeError", span, "%s[] can't hold %s", a->element->name, type_name(v));
+}
+
+/* array(int, 10, 0), same checks and messages as arrays::fill */
+static Value array_fill(Value size, Value v, const ParamInfo *element, int span) {
+    if (size.tag != T_INT) fail("TypeError", span, "Array size must be an int");
+    if (size.as.i < 0) fail("IndexError", span, "Array size can't be negative, got %lld", size.as.i);
+    v = stored(element, v);
+    if (!accepts(element, v)) fail("TypeError", span, "%s[] can't hold %s", element->name, type_name(v));
+
+    Value result = array_from_stack(0, element);
+    Array *a = result.as.a;
+    a->len = (int)size.as.i;
+    a->items = realloc(a->items, sizeof(Value) * (a->len > 0 ? a->len : 1));
+
+    for (int i = 0; i < a->len; i++) a->items[i] = own(v);
+    return result;
+}
+
+/* An index into the array, or one past the end when inserting */
+static int array_position(Array *a, Value index, int inserting, int span) {
+    long long i = array_index(index, span);
+
+    if (i < 0 || i > a->len || (i == a->len && !inserting)) {
+        fail("IndexError", span, "index %lld out of bounds for an array of length %d", i, a->len);
+    }
+
+    return (int)i;
+}
+
+/* Same checks and messages as arrays::method, the arguments are on top of the stack and the array is changed in place */
+static void array_method(Value *target, const char *name, int argc, int span) {
+    int arity;
+
+    if (strcmp(name, "len") == 0 || strcmp(name, "pop") == 0 || strcmp(name, "sort") == 0 || strcmp(name, "reverse") == 0) arity = 0;
+    else if (strcmp(name, "push") == 0 || strcmp(name, "remove") == 0 || strcmp(name, "contains") == 0 || strcmp(name, "index_of") == 0) arity = 1;
+    else if (strcmp(name, "insert") == 0 || strcmp(name, "slice") == 0) arity = 2;
+    else fail("NameError", span, "array has no method %s", name);
+
+    if (argc != arity) fail("TypeError", span, "Function %s expected %d arguments, got %d", name, arity, argc);
+
+    Array *a = target->as.a;
+    Value *args = &stack[sp - argc];
+    Value result = nil_value();
+
+    if (strcmp(name, "len") == 0) {
+        result = int_value(a->len);
+    } else if (strcmp(name, "push") == 0) {
+        args[0] = stored(a->element, args[0]);
+        array_fits(a, args[0], span);
+        a->items = realloc(a->items, sizeof(Value) * (a->len + 1));
+        a->items[a->len++] = own(args[0]);
+    } else if (strcmp(name, "pop") == 0) {
+        if (a->len == 0) fail("IndexError", span, "pop from an empty array");
+        result = a->items[--a->len];
+    } else if (strcmp(name, "insert") == 0) {
+        int i = array_position(a, args[0], 1, span);
+        args[1] = stored(a->element, args[1]);
+        array_fits(a, args[1], span);
+
+        a->items = realloc(a->items, sizeof(Value) * (a->len + 1));
+        memmove(a->items + i + 1, a->items + i, sizeof(Value) * (a->len - i));
+        a->items[i] = own(args[1]);
+        a->len++;
+    } else if (strcmp(name, "remove") == 0) {
+        int i = array_position(a, args[0], 0, span);
+
+        result = a->items[i];
+        memmove(a->items + i, a->items + i + 1, sizeof(Value) * (a->len - i - 1));
+        a->len--;
+    } else if (strcmp(name, "contains") == 0 || strcmp(name, "index_of") == 0) {
+        int found = -1;
+
+        for (int i = 0; i < a->len && found < 0; i++) {
+            if (equals(a->items[i], args[0])) found = i;
+        }
+
+        result = name[0] == 'c' ? bool_value(found >= 0) : int_value(found);
+    } else if (strcmp(name, "sort") == 0) {
+        int c;
+
+        for (int i = 0; i < a->len; i++) {
+            if (!order(a->items[0], a->items[i], &c)) fail("TypeError", span, "Can't sort %s[]", a->element->name);
+        }
+
+        Value *scratch = malloc(sizeof(Value) * (a->len > 0 ? a->len : 1));
+        merge_sort(a->items, scratch, a->len);
+        free(scratch);
+    } else if (strcmp(name, "reverse") == 0) {
+        for (int i = 0, j = a->len - 1; i < j; i++, j--) {
+            Value v = a->items[i];
+            a->items[i] = a->items[j];
+            a->items[j] = v;
+        }
+    } else {
+        long long start = array_index(args[0], span);
+        long long end = array_index(args[1], span);
+
+        if (start < 0 || start > end || end > a->len) {
+            fail("IndexError", span, "slice %lld:%lld out of bounds for an array of length %d", start, end, a->len);
+        }
+
+        for (long long i = start; i < end; i++) {
+            push(a->items[i]);
+        }
+
+        result = own(array_from_stack(end - start, a->element));
+    }
+
+    sp -= argc;
+    push(result);
+}
+
+static Value array_copy(const Value *items, int n, const ParamInfo *element) {
+    Value result = array_from_stack(0, element);
+    Array *a = result.as.a;
+    a->len = n;
+    a->items = realloc(a->items, sizeof(Value) * (n > 0 ? n : 1));
+
+    for (int i = 0; i < n; i++) a->items[i] = own(items[i]);
+    return result;
+}
+
+static int is_instance(Value v, const char *class_name) {
+    return v.tag == T_INSTANCE && strcmp(classes[v.as.o->class_id].name, class_name) == 0;
+}
+
+static const ParamInfo char_element = { P_CHAR, NULL, "CHAR", "char" };
+
+/* What a for-each walks, like Op::Iter in src/vm.rs: chars for a string and keys for a map */
+static Value iter_value(Value v, int span) {
+    if (v.tag == T_ARRAY) return v;
+    if (v.tag == T_MAP) return array_copy(v.as.m->keys, v.as.m->len, v.as.m->key);
+    if (v.tag != T_STR) fail("TypeError", span, "Can't loop over %s", type_name(v));
+
+    const Str *s = v.as.s;
+    Value result = array_from_stack(0, &char_element);
+    Array *a = result.as.a;
+    a->items = realloc(a->items, sizeof(Value) * (s->len > 0 ? s->len : 1));
+
+    for (int i = 0; i < s->len; ) {
+        int end = i + 1;
+        while (end < s->len && ((unsigned char)s->data[end] & 0xC0) == 0x80) end++;
+
+        a->items[a->len++] = char_value(new_str(s->data + i, end - i));
+        i = end;
+    }
+
+    return result;
+}
+
+/* The start, end and step of a range are on top of the stack */
+static void range_check(int span) {
+    for (int i = sp - 3; i < sp; i++) {
+        if (stack[i].tag != T_INT) fail("TypeError", span, "range takes ints, got %s", type_name(stack[i]));
+    }
+
+    if (stack[sp - 1].as.i == 0) fail("RuntimeError", span, "range step can't be 0");
+}
+
+/* Same checks and messages as maps::method, the arguments are on top of the stack and the map is changed in place */
+static void map_method(Value *target, const char *name, int argc, int span) {
+    int arity;
+
+    if (strcmp(name, "len") == 0 || strcmp(name, "keys") == 0 || strcmp(name, "values") == 0) arity = 0;
+    else if (strcmp(name, "has") == 0 || strcmp(name, "remove") == 0) arity = 1;
+    else fail("NameError", span, "map has no method %s", name);
+
+    if (argc != arity) fail("TypeError", span, "Function %s expected %d arguments, got %d", name, arity, argc);
+
+    Map *m = target->as.m;
+    Value *args = &stack[sp - argc];
+    Value result = nil_value();
+
+    if (strcmp(name, "len") == 0) {
+        result = int_value(m->len);
+    } else if (strcmp(name, "keys") == 0) {
+        result = array_copy(m->keys, m->len, m->key);
+    } else if (strcmp(name, "values") == 0) {
+        result = array_copy(m->values, m->len, m->value);
+    } else if (strcmp(name, "has") == 0) {
+        result = bool_value(map_find(m, args[0]) >= 0);
+    } else {
+        int i = map_find(m, args[0]);
+        if (i < 0) map_missing(args[0], "map", span);
+
+        result = m->values[i];
+        memmove(m->keys + i, m->keys + i + 1, sizeof(Value) * (m->len - i - 1));
+        memmove(m->values + i, m->values + i + 1, sizeof(Value) * (m->len - i - 1));
+        m->len--;
+    }
+
+    sp -= argc;
+    push(result);
+}
+
+static void invoke(const char *name, int argc, int span) {
+    Value receiver = stack[sp - argc - 1];
+
+    if (is_text(receiver)) {
+        string_method(name, argc, span);
+        return;
+    }
+
+    /* Not a variable, so whatever the method changes is thrown away */
+    if (receiver.tag == T_ARRAY || receiver.tag == T_MAP) {
+        Value copy = own(receiver);
+        if (receiver.tag == T_ARRAY) array_method(&copy, name, argc, span);
+        else map_method(&copy, name, argc, span);
+
+        stack[sp - 2] = stack[sp - 1];
+        sp--;
+        return;
+    }
+
+    if (receiver.tag != T_INSTANCE) fail("TypeError", span, "Cannot call %s on %s", name, type_name(receiver));
+
+    const ClassInfo *c = &classes[receiver.as.o->class_id];
+
+    for (int i = 0; i < c->nmethods; i++) {
+        if (strcmp(c->method_names[i], name) == 0) {
+            call(c->method_fns[i], argc, span);
+            return;
+        }
+    }
+
+    fail("NameError", span, "Function '%s' not defined", name);
+}
+
+/* Methods on an array or map variable change the variable itself */
+static void invoke_at(Value *target, const char *name, int argc, int span) {
+    if (target->tag == T_ARRAY) {
+        array_method(target, name, argc, span);
+        return;
+    }
+
+    if (target->tag == T_MAP) {
+        map_method(target, name, argc, span);
+        return;
+    }
+
+    /* Anything else is called like a normal method, with the receiver under the arguments */
+    push(nil_value());
+    memmove(&stack[sp - argc], &stack[sp - argc - 1], sizeof(Value) * argc);
+    stack[sp - argc - 1] = *target;
+
+    invoke(name, argc, span);
+}
+
+static void new_instance(int class_id, int argc, int span) {
+    const ClassInfo *c = &classes[class_id];
+
+    Instance *o = malloc(sizeof(Instance));
+    o->class_id = class_id;
+    o->fields = malloc(sizeof(Value) * (c->nfields > 0 ? c->nfields : 1));
+
+    for (int i = 0; i < c->nfields; i++) {
+        o->fields[i] = nil_value();
+    }
+
+    check_args(c->constructor, argc, span);
+
+    /* The instance goes under the arguments, where a method expects it */
+    push(nil_value());
+    memmove(&stack[sp - argc], &stack[sp - argc - 1], sizeof(Value) * argc);
+
+    Value v = nil_value();
+    v.tag = T_INSTANCE;
+    v.as.o = o;
+    stack[sp - argc - 1] = v;
+
+    functions[c->constructor].fn();
+}
+
+static int field_index(Instance *o, const char *name) {
+    const ClassInfo *c = &classes[o->class_id];
+
+    for (int i = 0; i < c->nfields; i++) {
+        if (strcmp(c->fields[i], name) == 0) return i;
+    }
+
+    return -1;
+}
+
+static Value get_property(Value target, const char *name, int span) {
+    if (target.tag == T_INSTANCE) {
+        int i = field_index(target.as.o, name);
+        if (i < 0) fail("NameError", span, "%s has no field %s", classes[target.as.o->class_id].name, name);
+        return target.as.o->fields[i];
+    }
+
+    if (target.tag == T_ERROR) {
+        if (strcmp(name, "message") == 0) return str_value(target.as.e->message);
+        if (strcmp(name, "kind") == 0) return str_value(new_str(target.as.e->kind, strlen(target.as.e->kind)));
+        fail("NameError", span, "Error has no field %s", name);
+    }
+
+    fail("TypeError", span, "Dot access not supported on %s", type_name(target));
+    return target;
+}
+
+static void set_property(Value target, const char *name, Value v, int span) {
+    if (target.tag != T_INSTANCE) fail("TypeError", span, "Dot access not supported on %s", type_name(target));
+
+    int i = field_index(target.as.o, name);
+    if (i < 0) fail("NameError", span, "%s has no field %s", classes[target.as.o->class_id].name, name);
+
+    target.as.o->fields[i] = own(assigned(target.as.o->fields[i], v));
+}
+
+/* The field itself, so p.items[i] = v and p.items.push(v) change what p holds */
+static Value *property_ref(Value target, const char *name, int span) {
+    if (target.tag != T_INSTANCE) fail("TypeError", span, "Dot access not supported on %s", type_name(target));
+
+    int i = field_index(target.as.o, name);
+    if (i < 0) fail("NameError", span, "%s has no field %s", classes[target.as.o->class_id].name, name);
+
+    return &target.as.o->fields[i];
+}
+
+/* The instance sits under the arguments, the field takes its place as the receiver */
+static void invoke_property(const char *field, const char *name, int argc, int span) {
+    Value target = stack[sp - argc - 1];
+
+    if (target.tag != T_INSTANCE) {
+        stack[sp - argc - 1] = get_property(target, field, span);
+        invoke(name, argc, span);
+        return;
+    }
+
+    Value *ref = property_ref(target, field, span);
+
+    memmove(&stack[sp - argc - 1], &stack[sp - argc], sizeof(Value) * argc);
+    sp--;
+
+    invoke_at(ref, name, argc, span);
+}
+
+static Value call_library(const char *module, const char *function, int argc, int span) {
+    if (strcmp(module, "math") != 0) fail("ImportError", span, "Built-in library `%s` not implemented", module);
+
+    double (*f)(double) = NULL;
+
+    if (strcmp(function, "sqrt") == 0) f = sqrt;
+    else if (strcmp(function, "abs") == 0) f = fabs;
+    else if (strcmp(function, "floor") == 0) f = floor;
+    else fail("NameError", span, "Function `%s` not found in <math>", function);
+
+    double x;
+    if (argc != 1 || !number(stack[sp - 1], &x)) fail("TypeError", span, "%s() requires int or float", function);
+
+    sp -= argc;
+    return float_value(f(x), -1);
+}
+
+static int match_error(Value v, const char *kind) {
+    if (v.tag != T_ERROR) return 0;
+    return strcmp(kind, "Error") == 0 || strcmp(kind, v.as.e->kind) == 0;
+}
+
+static void throw_value(Value v, int span) {
+    if (v.tag == T_ERROR) raise_err(new_err(v.as.e->kind, v.as.e->message->data, span));
+
+    if (v.tag == T_STR) {
+        Err *e = malloc(sizeof(Err));
+        e->kind = "Error";
+        e->message = v.as.s;
+        e->span = span;
+        raise_err(e);
+    }
+
+    fail("TypeError", span, "Can only throw a string or a caught error");
+}
+
+static void rethrow(Value v, int span) {
+    if (v.tag == T_ERROR) raise_err(v.as.e);
+    fail("RuntimeError", span, "Can only rethrow an error");
+}
@@ -1,4 +1,5 @@
-use crate::ast::{Ast_Type, AST};
+use crate::ast::AST;
+use crate::error::{IronError, IronResult};
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::fmt;
@@ -69,32 +70,24 @@ impl Scope {
         None
     }
 
-    pub fn add_variable_definition(&mut self, def: AST) {
-        if def.ast_type == Ast_Type::AST_VARIABLE_DEF {
-            let name = def
-                .variable_definition_variable_name
-                .as_ref()
-                .expect("Var def has no name");
-            for existing_def in self.variable_definitions.iter_mut() {
-                if let Some(existing_name) = &existing_def.variable_definition_variable_name {
-                    if existing_name == name {
-                        *existing_def = def; 
-                        return;
-                    }
-                }
+    // Runs f on the definition where it's kept, so a big map can be read or changed without copying it
+    pub fn with_variable_definition<R>(&mut self, name: &str, f: impl FnOnce(&mut AST) -> R) -> Option<R> {
+        for def in &mut self.variable_definitions {
+            if def.variable_definition_variable_name.as_ref().or(def.array_name.as_ref()).is_some_and(|n| n == name) {
+                return Some(f(def));
             }
         }
-        else if def.ast_type == Ast_Type::AST_ARRAY_DEF {
-            let name = def
-                .array_name 
-                .as_ref()
-                .expect("Array def has no name");
+
+        self.parent.as_ref()?.borrow_mut().with_variable_definition(name, f)
+    }
+
+    pub fn add_variable_definition(&mut self, def: AST) {
+        // Defining a name again in the same scope replaces it, whether it held an array or not
+        if let Some(name) = def.variable_definition_variable_name.clone().or_else(|| def.array_name.clone()) {
             for existing_def in self.variable_definitions.iter_mut() {
-                if let Some(existing_name) = &existing_def.variable_definition_variable_name {
-                    if existing_name == name {
-                        *existing_def = def; 
-                        return;
-                    }
+                if existing_def.variable_definition_variable_name.as_ref() == Some(&name) || existing_def.array_name.as_ref() == Some(&name) {
+                    *existing_def = def;
+                    return;
                 }
             }
         }
@@ -117,26 +110,34 @@ impl Scope {
     }
 
     pub fn add_function_definition(&mut self, def: AST) {
+        // Redefining a function (like in the repl) replaces the old one
+        for existing_def in self.function_definitions.iter_mut() {
+            if existing_def.function_definition_name == def.function_definition_name {
+                *existing_def = def;
+                return;
+            }
+        }
+
         self.function_definitions.push(def);
     }
 
-    pub fn update_variable_definition(&mut self, name: String, new_def: AST) {
+    pub fn update_variable_definition(&mut self, name: String, new_def: AST) -> IronResult<()> {
         for v in &mut self.variable_definitions {
             if v.variable_definition_variable_name == Some(name.clone()) {
                 *v = new_def;
-                return;
+                return Ok(());
             }
             if v.array_name == Some(name.clone()) {
                 *v = new_def; 
-                return; 
+                return Ok(()); 
             }
         }
 
         if let Some(ref parent) = self.parent {
-            parent.borrow_mut().update_variable_definition(name, new_def);
+            parent.borrow_mut().update_variable_definition(name, new_def)
         }
         else {
-            panic!("Variable {} not found in any scope", name);
+            Err(IronError::NameError(format!("Variable {} not found in any scope", name), None))
         }
     }
 
@@ -185,7 +186,8 @@ impl Scope {
             println!("Func name {}", f_name);
         }
         for var in &self.variable_definitions {
-            let name = var.variable_definition_variable_name.as_ref().unwrap();
+            // Arrays are kept under their array_name
+            let name = var.variable_definition_variable_name.as_ref().or(var.array_name.as_ref()).unwrap();
             println!("Var {}", name); 
         }
         for class in &self.class_definitions {
@@ -0,0 +1,95 @@
+use std::fmt;
+use std::rc::Rc;
+
+// The file a span points into, kept around so errors can quote the line
+#[derive(Clone, PartialEq, Debug)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(name: &str, text: &str) -> Self {
+        Source {
+            name: name.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.text.lines().nth(line.saturating_sub(1))
+    }
+}
+
+// Line and column are both 1-based, len is counted in chars
+#[derive(Clone, PartialEq)]
+pub struct Span {
+    pub source: Rc<Source>,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(source: Rc<Source>, line: usize, column: usize, len: usize) -> Self {
+        Span {
+            source,
+            line,
+            column,
+            len,
+        }
+    }
+
+    /*
+        Renders a header the same way rustc does, so
+        error[NameError]: Undefined variable: x
+         --> examples/test.iron:12:8
+           |
+        12 |     println(x);
+           |             ^
+    */
+    pub fn render(&self, header: &str) -> String {
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+
+        let mut out = format!("{}\n{}--> {}\n", header, gutter, self);
+
+        if let Some(text) = self.source.line(self.line) {
+            let text = text.trim_end();
+
+            // Tabs would throw the caret off so we copy them into the padding
+            let padding: String = text
+                .chars()
+                .take(self.column.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+
+            out.push_str(&format!("{} |\n", gutter));
+            out.push_str(&format!("{} | {}\n", number, text));
+            out.push_str(&format!("{} | {}{}", gutter, padding, "^".repeat(self.len.max(1))));
+        }
+
+        out
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.source.name, self.line, self.column)
+    }
+}
+
+// The whole source text is behind the Rc, so only print where we are
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Span({})", self)
+    }
+}
+
+// Errors on nodes built at runtime have no span, those just get the header
+pub fn render(span: &Option<Span>, header: &str) -> String {
+    match span {
+        Some(s) => s.render(header),
+        None => header.to_string(),
+    }
+}
@@ -0,0 +1,68 @@
+use crate::error::{IronError, IronResult};
+use crate::span::Span;
+
+/*
+    The methods, indexing and slicing strings have. The visitor and the vm
+    both call into here so they behave the same, and src/runtime.c mirrors
+    it. Positions count characters, not bytes. Only ASCII letters change
+    case and only ASCII whitespace is trimmed, which keeps C in step
+*/
+pub enum Text {
+    Int(i64),
+    Bool(bool),
+    Str(String),
+    List(Vec<String>),
+}
+
+// Each argument is its text, or the name of its type when it isn't a string or a char
+pub fn method(s: &str, name: &str, args: &[Result<String, &'static str>], span: &Option<Span>) -> IronResult<Text> {
+    let arity = match name {
+        "len" | "upper" | "lower" | "trim" => 0,
+        "split" | "contains" | "starts_with" => 1,
+        "replace" => 2,
+        _ => return Err(IronError::NameError(format!("str has no method {}", name), span.clone())),
+    };
+
+    if args.len() != arity {
+        return Err(IronError::TypeError(format!("Function {} expected {} arguments, got {}", name, arity, args.len()), span.clone()));
+    }
+
+    let mut text = vec![];
+
+    for arg in args {
+        match arg {
+            Ok(t) => text.push(t.as_str()),
+            Err(t) => return Err(IronError::TypeError(format!("Function {} argument type mismatch: expected STR, got {}", name, t), span.clone())),
+        }
+    }
+
+    Ok(match name {
+        "len" => Text::Int(s.chars().count() as i64),
+        "upper" => Text::Str(s.to_ascii_uppercase()),
+        "lower" => Text::Str(s.to_ascii_lowercase()),
+        "trim" => Text::Str(s.trim_matches(|c: char| c.is_ascii_whitespace()).to_string()),
+        "split" if text[0].is_empty() => Text::List(s.chars().map(String::from).collect()),
+        "split" => Text::List(s.split(text[0]).map(String::from).collect()),
+        "contains" => Text::Bool(s.contains(text[0])),
+        "starts_with" => Text::Bool(s.starts_with(text[0])),
+        "replace" if text[0].is_empty() => Text::Str(s.to_string()),
+        "replace" => Text::Str(s.replace(text[0], text[1])),
+        _ => unreachable!(),
+    })
+}
+
+pub fn index(s: &str, i: i64, name: &str, span: &Option<Span>) -> IronResult<char> {
+    let c = if i >= 0 { s.chars().nth(i as usize) } else { None };
+    c.ok_or_else(|| IronError::IndexError(format!("index {} out of bounds for string {}", i, name), span.clone()))
+}
+
+// s[start:end], the end isn't included
+pub fn slice(s: &str, start: i64, end: i64, name: &str, span: &Option<Span>) -> IronResult<String> {
+    let len = s.chars().count() as i64;
+
+    if start < 0 || start > end || end > len {
+        return Err(IronError::IndexError(format!("slice {}:{} out of bounds for string {}", start, end, name), span.clone()));
+    }
+
+    Ok(s.chars().skip(start as usize).take((end - start) as usize).collect())
+}
@@ -1,3 +1,5 @@
+use crate::span::Span;
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum Types {
         TOKEN_ID, TOKEN_EQUALS, TOKEN_STRING, TOKEN_SEMI,
@@ -8,13 +10,33 @@ pub enum Types {
         TOKEN_LESS_THAN, TOKEN_GEQ, TOKEN_LEQ, TOKEN_EE, TOKEN_INCREMENT,
         TOKEN_DECREMENT, TOKEN_NEQ, TOKEN_OR, TOKEN_AND, TOKEN_NOT,
         TOKEN_RBOX, TOKEN_LBOX, // [ & ] symbols
-        TOKEN_DOT, TOKEN_PERCENT, TOKEN_CARROT
+        TOKEN_DOT, TOKEN_PERCENT, TOKEN_CARROT,
+        TOKEN_ARROW, // -> before a return type
+        TOKEN_FAT_ARROW, // => after a match case
+        TOKEN_QUESTION, // the ? in cond ? a : b
+        TOKEN_ADD_EQUALS, TOKEN_SUBTRACT_EQUALS, TOKEN_ASTERISK_EQUALS,
+        TOKEN_FSLASH_EQUALS, TOKEN_PERCENT_EQUALS, TOKEN_CARROT_EQUALS, // += and friends
+        TOKEN_AMPERSAND, TOKEN_PIPE, TOKEN_TILDE, TOKEN_XOR, // bitwise & | ~ and the xor keyword
+        TOKEN_SHIFT_LEFT, TOKEN_SHIFT_RIGHT, // << and >>
+        TOKEN_CHAR, // 'c'
+        TOKEN_FSTRING, // f"...", its pieces are on the token
+        TOKEN_COMMENT, // only from source_tokens, the parser never sees comments
+}
+
+// A piece of an f-string, text with its escapes already done or where an embedded expression starts
+#[derive(Clone, PartialEq, Debug)]
+pub enum Piece {
+    Text(String),
+    Code { index: u64, line: usize, column: usize },
 }
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Token {
     pub kind: Types,
     pub value: String, 
+    pub span: Option<Span>, // filled in by the lexer once the token is collected
+    pub pieces: Vec<Piece>, // only an f-string has any
+    pub doc: Option<String>, // the /// comments right before the token, one line each
 }
 
 impl Token {
@@ -22,6 +44,9 @@ impl Token {
         Token{
             kind, 
             value,
+            span: None,
+            pieces: vec![],
+            doc: None,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,1290 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{AST, Ast_Type, Data_Type, stored_index};
+use crate::error::IronError;
+use crate::scope::Scope;
+use crate::span::Span;
+use crate::token::Types;
+
+#[derive(Clone, Debug)]
+struct Signature {
+    params: Vec<Data_Type>,
+    returns: Option<Data_Type>, // None when the function doesn't say, so its result is unknown
+}
+
+#[derive(Clone, Debug)]
+struct Class {
+    args: Vec<Data_Type>,
+    fields: HashMap<String, Data_Type>,
+    methods: HashMap<String, Signature>,
+}
+
+/*
+    Walks the AST before anything runs and reports the mistakes we can
+    be sure about. A type of None means we can't tell (a function's
+    result, a variable that is only defined at runtime) and is never
+    reported, so the checker only complains about code that would fail
+*/
+pub struct TypeChecker {
+    pub errors: Vec<IronError>,
+    scopes: Vec<HashMap<String, Data_Type>>,
+    function_base: usize, // scopes below this belong to the function we are nested in, only the globals at 0 stay visible
+    functions: HashMap<String, Signature>,
+    classes: HashMap<String, Class>,
+    class: Option<String>,
+    returns: Option<(String, Data_Type)>, // the function we're in and the type it declared
+    modules: HashMap<String, Option<HashMap<String, Signature>>>, // None for built in libraries like math
+    span: Option<Span>,
+}
+
+pub fn check(ast: &AST) -> Vec<IronError> {
+    let mut checker = TypeChecker::new();
+    checker.block(ast);
+    checker.errors
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        TypeChecker {
+            errors: vec![],
+            scopes: vec![HashMap::new()],
+            function_base: 0,
+            functions: HashMap::new(),
+            classes: HashMap::new(),
+            class: None,
+            returns: None,
+            modules: HashMap::new(),
+            span: None,
+        }
+    }
+
+    fn type_error(&mut self, message: String) {
+        self.errors.push(IronError::TypeError(message, self.span.clone()));
+    }
+
+    fn name_error(&mut self, message: String) {
+        self.errors.push(IronError::NameError(message, self.span.clone()));
+    }
+
+    // The first class named in a type that was never declared
+    fn unknown_class(&self, t: &Data_Type) -> Option<String> {
+        match t {
+            Data_Type::CUSTOM(class) if class != "Error" && !self.classes.contains_key(class) => Some(class.clone()),
+            Data_Type::ARRAY(element) => self.unknown_class(element),
+            Data_Type::MAP(key, value) => self.unknown_class(key).or_else(|| self.unknown_class(value)),
+            _ => None,
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &str, t: Data_Type) {
+        if let Some(class) = mapped_class(&t, false) {
+            self.type_error(format!("A map can't hold {} instances, {} is declared {}", class, name, t.name()));
+        }
+
+        self.scopes.last_mut().expect("No scope open").insert(name.to_string(), t);
+    }
+
+    // Same order the compiler resolves names in: locals, then fields, then globals
+    fn lookup(&self, name: &str) -> Option<Data_Type> {
+        for scope in self.scopes[self.function_base..].iter().rev() {
+            if let Some(t) = scope.get(name) {
+                return Some(t.clone());
+            }
+        }
+
+        if let Some(t) = self.class.as_ref().and_then(|c| self.classes.get(c)).and_then(|c| c.fields.get(name)) {
+            return Some(t.clone());
+        }
+
+        self.scopes[0].get(name).cloned()
+    }
+
+    fn is_module(&self, name: &str) -> bool {
+        self.modules.contains_key(name) && self.lookup(name).is_none()
+    }
+
+    // Blocks don't open a scope, same as the visitor where if and while bodies share their parent's
+    fn block(&mut self, node: &AST) {
+        if node.ast_type != Ast_Type::AST_COMPOUND {
+            self.statement(node);
+            return;
+        }
+
+        let statements = node.compound_value.as_deref().unwrap_or(&[]);
+
+        // Functions can be called before the line they're defined on
+        for stmt in statements {
+            if stmt.ast_type == Ast_Type::AST_FUNCTION_DEF {
+                let name = stmt.function_definition_name.clone().unwrap();
+                self.functions.insert(name, signature(stmt));
+            }
+        }
+
+        for stmt in statements {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, node: &AST) {
+        let saved = self.span.clone();
+
+        if node.span.is_some() {
+            self.span = node.span.clone();
+        }
+
+        self.check_statement(node);
+
+        self.span = saved;
+    }
+
+    fn check_statement(&mut self, node: &AST) {
+        match node.ast_type {
+            Ast_Type::AST_VARIABLE_DEF => {
+                let name = node.variable_definition_variable_name.clone().unwrap();
+                let declared = node.variable_type.clone().unwrap_or(Data_Type::VOID);
+
+                if let Some(value) = node.variable_definition_value.as_ref() {
+                    if let Some(t) = self.expression(value) {
+                        if !assignable(&declared, &t) {
+                            self.type_error(format!("Variable {} is declared {} but assigned {}", name, declared.name(), t.name()));
+                        }
+                    }
+                }
+
+                self.declare(&name, declared);
+            }
+            Ast_Type::AST_ARRAY_DEF => {
+                self.expression(node);
+                self.declare(node.array_name.as_ref().unwrap(), Data_Type::ARRAY(Box::new(node.data_type.clone())));
+            }
+            Ast_Type::AST_FUNCTION_DEF => self.function(node),
+            Ast_Type::AST_CLASS_DEF => self.class_definition(node),
+            Ast_Type::AST_COMPOUND => self.block(node),
+            Ast_Type::AST_IF => {
+                self.condition(node.if_condition.as_ref().unwrap());
+                self.block(node.if_body.as_ref().unwrap());
+
+                if let Some(else_body) = node.else_body.as_ref() {
+                    self.block(else_body);
+                }
+            }
+            Ast_Type::AST_WHILE => {
+                self.condition(node.while_condition.as_ref().unwrap());
+                self.block(node.while_body.as_ref().unwrap());
+            }
+            Ast_Type::AST_FOR => {
+                self.begin_scope();
+                self.statement(node.for_init.as_ref().unwrap());
+                self.condition(node.for_condition.as_ref().unwrap());
+                self.block(node.for_body.as_ref().unwrap());
+                self.statement(node.for_increment.as_ref().unwrap());
+                self.end_scope();
+            }
+            Ast_Type::AST_FOR_EACH => self.for_each(node),
+            Ast_Type::AST_MATCH => self.match_statement(node),
+            Ast_Type::AST_RETURN => {
+                let t = match node.return_value.as_ref() {
+                    Some(value) => self.expression(value),
+                    None => Some(Data_Type::VOID),
+                };
+
+                if let (Some((name, expected)), Some(t)) = (self.returns.clone(), t) {
+                    if !assignable(&expected, &t) {
+                        self.type_error(format!("Function {} should return {}, got {}", name, expected.name(), t.name()));
+                    }
+                }
+            }
+            Ast_Type::AST_TRY => {
+                self.block(node.try_body.as_ref().unwrap());
+
+                if let Some(catch_body) = node.catch_body.as_ref() {
+                    self.begin_scope();
+                    self.declare(node.catch_name.as_ref().unwrap(), Data_Type::CUSTOM("Error".to_string()));
+                    self.block(catch_body);
+                    self.end_scope();
+                }
+
+                if let Some(finally_body) = node.finally_body.as_ref() {
+                    self.block(finally_body);
+                }
+            }
+            Ast_Type::AST_THROW => {
+                let t = self.expression(node.throw_value.as_ref().unwrap());
+
+                if let Some(t) = t {
+                    if t != Data_Type::STR && t != Data_Type::CUSTOM("Error".to_string()) {
+                        self.type_error(format!("Can only throw a string or a caught error, got {}", t.name()));
+                    }
+                }
+            }
+            Ast_Type::AST_IMPORT => self.import(node),
+            Ast_Type::AST_BREAK | Ast_Type::AST_CONTINUE | Ast_Type::AST_NOOP => {}
+            _ => {
+                let t = self.expression(node);
+
+                // Shop s = new Shop(1) comes back as the value with the name stuck on it
+                if let (Some(name), Some(class)) = (node.variable_definition_variable_name.as_ref(), node.class_name.as_ref()) {
+                    let declared = Data_Type::CUSTOM(class.clone());
+
+                    if let Some(t) = t {
+                        if !assignable(&declared, &t) {
+                            self.type_error(format!("Variable {} is declared {} but assigned {}", name, declared.name(), t.name()));
+                        }
+                    }
+
+                    self.declare(name, declared);
+                }
+            }
+        }
+    }
+
+    fn condition(&mut self, node: &AST) {
+        let saved = self.span.clone();
+
+        if node.span.is_some() {
+            self.span = node.span.clone();
+        }
+
+        if let Some(t) = self.expression(node) {
+            if t != Data_Type::BOOL {
+                self.type_error(format!("Condition must be a bool, got {}", t.name()));
+            }
+        }
+
+        self.span = saved;
+    }
+
+    // Arrays give their elements, strings their chars, maps their keys and ranges ints
+    fn for_each(&mut self, node: &AST) {
+        let variable = node.for_init.as_ref().unwrap();
+        let iterable = node.for_iterable.as_ref().unwrap();
+        let name = variable.variable_definition_variable_name.clone().unwrap();
+        let declared = variable.variable_type.clone().unwrap();
+
+        let element = if iterable.ast_type == Ast_Type::AST_RANGE {
+            for arg in iterable.function_call_args.as_deref().unwrap() {
+                if let Some(t) = self.expression(arg).filter(|t| *t != Data_Type::INT) {
+                    self.type_error(format!("range takes ints, got {}", t.name()));
+                }
+            }
+
+            Some(Data_Type::INT)
+        }
+        else {
+            match self.expression(iterable) {
+                Some(Data_Type::ARRAY(element)) => Some(*element),
+                Some(Data_Type::STR) => Some(Data_Type::CHAR),
+                Some(Data_Type::MAP(key, _)) => Some(*key),
+                Some(t) => {
+                    self.type_error(format!("Can't loop over {}", t.name()));
+                    None
+                }
+                None => None,
+            }
+        };
+
+        if let Some(element) = element.filter(|t| *t != declared) {
+            self.type_error(format!("Loop variable {} is declared {} but the loop gives {}", name, declared.name(), element.name()));
+        }
+
+        self.begin_scope();
+        self.declare(&name, declared);
+        self.block(node.for_body.as_ref().unwrap());
+        self.end_scope();
+    }
+
+    // Ints, floats, strings, chars and bools are matched by value and instances by their class
+    fn match_statement(&mut self, node: &AST) {
+        let t = self.expression(node.match_value.as_ref().unwrap());
+
+        let t = match t {
+            Some(t @ (Data_Type::INT | Data_Type::FLOAT | Data_Type::STR | Data_Type::CHAR | Data_Type::BOOL)) => Some(t),
+            Some(Data_Type::CUSTOM(name)) if self.classes.contains_key(&name) => Some(Data_Type::CUSTOM(name)),
+            Some(t) => {
+                self.type_error(format!("Can't match on {}", t.name()));
+                None
+            }
+            None => None,
+        };
+
+        for case in node.match_cases.as_deref().unwrap_or(&[]) {
+            for pattern in case.case_patterns.as_deref().unwrap_or(&[]) {
+                let p = match pattern.ast_type {
+                    Ast_Type::AST_CLASS_PATTERN => Some(Data_Type::CUSTOM(pattern.class_name.clone().unwrap())),
+                    _ => self.expression(pattern),
+                };
+
+                let (Some(t), Some(p)) = (t.as_ref(), p) else {
+                    continue;
+                };
+
+                let fits = match t {
+                    Data_Type::CUSTOM(_) => *t == p && pattern.ast_type == Ast_Type::AST_CLASS_PATTERN,
+                    _ => (orderable(t, &p) || *t == p) && pattern.ast_type != Ast_Type::AST_CLASS_PATTERN,
+                };
+
+                if !fits {
+                    self.type_error(format!("Cannot match {} against {}", t.name(), p.name()));
+                }
+            }
+
+            self.block(case.case_body.as_ref().unwrap());
+        }
+    }
+
+    fn function(&mut self, node: &AST) {
+        let saved_base = self.function_base;
+
+        let args = node.function_definition_args.as_deref().unwrap_or(&[]);
+        for t in args.iter().filter_map(|a| a.variable_type.as_ref()).chain(node.function_return_type.as_ref()) {
+            if let Some(class) = self.unknown_class(t) {
+                self.name_error(format!("unknown type {}", class));
+            }
+        }
+
+        // Returns aren't compared against a type that doesn't exist, the NameError already says what's wrong
+        let returns = node.function_return_type.clone().filter(|t| self.unknown_class(t).is_none());
+        let saved_returns = std::mem::replace(
+            &mut self.returns,
+            returns.map(|t| (node.function_definition_name.clone().unwrap(), t)),
+        );
+
+        self.begin_scope();
+        self.function_base = self.scopes.len() - 1;
+
+        for arg in node.function_definition_args.as_deref().unwrap_or(&[]) {
+            self.declare(arg.variable_definition_variable_name.as_ref().unwrap(), arg.variable_type.clone().unwrap());
+        }
+
+        self.block(node.function_definition_body.as_ref().unwrap());
+
+        self.end_scope();
+        self.function_base = saved_base;
+        self.returns = saved_returns;
+    }
+
+    // Fields are the constructor arguments plus every variable in the body, like the compiler
+    fn class_definition(&mut self, node: &AST) {
+        let name = node.class_definition_name.clone().unwrap();
+        let args = node.class_definition_args.as_deref().unwrap_or(&[]);
+        let body = node.class_definition_body.as_ref().and_then(|b| b.compound_value.as_deref()).unwrap_or(&[]);
+
+        let mut class = Class {
+            args: args.iter().map(|a| a.variable_type.clone().unwrap()).collect(),
+            fields: HashMap::new(),
+            methods: HashMap::new(),
+        };
+
+        for arg in args {
+            class.fields.insert(arg.variable_definition_variable_name.clone().unwrap(), arg.variable_type.clone().unwrap());
+        }
+
+        for stmt in body {
+            match stmt.ast_type {
+                Ast_Type::AST_VARIABLE_DEF => {
+                    class.fields.insert(stmt.variable_definition_variable_name.clone().unwrap(), stmt.variable_type.clone().unwrap_or(Data_Type::VOID));
+                }
+                Ast_Type::AST_ARRAY_DEF => {
+                    class.fields.insert(stmt.array_name.clone().unwrap(), Data_Type::ARRAY(Box::new(stmt.data_type.clone())));
+                }
+                Ast_Type::AST_FUNCTION_DEF => {
+                    class.methods.insert(stmt.function_definition_name.clone().unwrap(), signature(stmt));
+                }
+                _ => {
+                    if let (Some(field), Some(class_name)) = (stmt.variable_definition_variable_name.as_ref(), stmt.class_name.as_ref()) {
+                        class.fields.insert(field.clone(), Data_Type::CUSTOM(class_name.clone()));
+                    }
+                }
+            }
+        }
+
+        self.classes.insert(name.clone(), class);
+
+        let saved_class = self.class.replace(name);
+        let saved_base = self.function_base;
+
+        // Field initializers only see the constructor arguments
+        self.begin_scope();
+        self.function_base = self.scopes.len() - 1;
+
+        for arg in args {
+            self.declare(arg.variable_definition_variable_name.as_ref().unwrap(), arg.variable_type.clone().unwrap());
+        }
+
+        for stmt in body {
+            if stmt.ast_type != Ast_Type::AST_FUNCTION_DEF {
+                self.statement(stmt);
+            }
+        }
+
+        self.end_scope();
+        self.function_base = saved_base;
+
+        for stmt in body {
+            if stmt.ast_type == Ast_Type::AST_FUNCTION_DEF {
+                let saved = self.span.clone();
+                if stmt.span.is_some() {
+                    self.span = stmt.span.clone();
+                }
+
+                self.function(stmt);
+                self.span = saved;
+            }
+        }
+
+        self.class = saved_class;
+    }
+
+    // Library functions are checked too and can be called with or without the library name
+    fn import(&mut self, node: &AST) {
+        let lib = node.variable_name.clone().unwrap();
+
+        if node.is_builtin.unwrap_or(false) {
+            self.modules.insert(lib, None);
+            return;
+        }
+
+        let lib_ast = match crate::load_library(&lib, &node.span, Rc::new(RefCell::new(Scope::new()))) {
+            Ok(ast) => ast,
+            // A missing library is reported when the import runs
+            Err(IronError::ImportError(..)) => return,
+            Err(e) => {
+                self.errors.push(e);
+                return;
+            }
+        };
+
+        let statements = lib_ast.compound_value.as_deref().unwrap_or(&[]);
+        let mut exports = HashMap::new();
+
+        for stmt in statements {
+            if stmt.ast_type == Ast_Type::AST_FUNCTION_DEF {
+                let name = stmt.function_definition_name.clone().unwrap();
+                exports.insert(name.clone(), signature(stmt));
+                self.functions.entry(name).or_insert(signature(stmt));
+            }
+        }
+
+        for stmt in statements {
+            if stmt.ast_type == Ast_Type::AST_FUNCTION_DEF {
+                self.statement(stmt);
+            }
+        }
+
+        self.modules.insert(lib, Some(exports));
+    }
+
+    fn expression(&mut self, node: &AST) -> Option<Data_Type> {
+        let saved = self.span.clone();
+
+        if node.span.is_some() {
+            self.span = node.span.clone();
+        }
+
+        let t = self.check_expression(node);
+
+        self.span = saved;
+        t
+    }
+
+    fn check_expression(&mut self, node: &AST) -> Option<Data_Type> {
+        match node.ast_type {
+            Ast_Type::AST_INT => Some(Data_Type::INT),
+            Ast_Type::AST_FLOAT => Some(Data_Type::FLOAT),
+            Ast_Type::AST_STRING if node.data_type == Data_Type::CHAR => Some(Data_Type::CHAR),
+            Ast_Type::AST_STRING => Some(Data_Type::STR),
+            Ast_Type::AST_BOOL => Some(Data_Type::BOOL),
+            Ast_Type::AST_VARIABLE => self.lookup(node.variable_name.as_ref().unwrap()),
+            Ast_Type::AST_BINARY => self.binary(node),
+            Ast_Type::AST_UNARY => {
+                let t = self.expression(node.right.as_ref().unwrap())?;
+
+                match node.operator.as_ref().unwrap() {
+                    Types::TOKEN_SUBTRACT if !is_number(&t) => {
+                        self.type_error(format!("Cannot negate {}", t.name()));
+                        None
+                    }
+                    Types::TOKEN_NOT if !is_number(&t) && t != Data_Type::BOOL => {
+                        self.type_error(format!("Cannot apply ! to {}", t.name()));
+                        None
+                    }
+                    Types::TOKEN_TILDE if t != Data_Type::INT => {
+                        self.type_error(format!("Cannot apply ~ to {}", t.name()));
+                        None
+                    }
+                    _ => Some(t),
+                }
+            }
+            Ast_Type::AST_FUNCTION_CALL => self.call(node),
+            Ast_Type::AST_DOT | Ast_Type::AST_CLASS_ACCESS => self.dot(node),
+            Ast_Type::AST_ARRAY_ACCESS => self.array_access(node),
+            Ast_Type::AST_ARRAY_DEF if node.data_type == Data_Type::VOID => self.untyped_array(node),
+            Ast_Type::AST_ARRAY_DEF => {
+                for element in node.array_elements.as_deref().unwrap_or(&[]) {
+                    if let Some(t) = self.expression(element) {
+                        if !assignable(&node.data_type, &t) {
+                            self.type_error(format!("{}[] can't hold {}", node.data_type.name(), t.name()));
+                        }
+                    }
+                }
+
+                Some(Data_Type::ARRAY(Box::new(node.data_type.clone())))
+            }
+            Ast_Type::AST_ARRAY_FILL => self.array_fill(node),
+            Ast_Type::AST_TERNARY => {
+                self.condition(node.if_condition.as_ref().unwrap());
+
+                let then = self.expression(node.if_body.as_ref().unwrap());
+                let otherwise = self.expression(node.else_body.as_ref().unwrap());
+
+                match (then, otherwise) {
+                    (Some(a), Some(b)) if a == b => Some(a),
+                    (Some(a), Some(b)) => {
+                        self.type_error(format!("Both sides of ?: have to be the same type, got {} and {}", a.name(), b.name()));
+                        None
+                    }
+                    _ => None,
+                }
+            }
+            Ast_Type::AST_MAP_DEF => {
+                let Data_Type::MAP(key, value) = &node.data_type else {
+                    return None;
+                };
+
+                let keys = node.map_keys.as_deref().unwrap_or(&[]);
+                for (k, v) in keys.iter().zip(node.map_values.as_deref().unwrap_or(&[])) {
+                    if let Some(t) = self.expression(k) {
+                        if !assignable(key, &t) {
+                            self.type_error(format!("Map key must be {}, got {}", key.name(), t.name()));
+                        }
+                    }
+
+                    if let Some(t) = self.expression(v) {
+                        if !assignable(value, &t) {
+                            self.type_error(format!("Map value must be {}, got {}", value.name(), t.name()));
+                        }
+                    }
+                }
+
+                Some(node.data_type.clone())
+            }
+            Ast_Type::AST_REASSIGN => {
+                let name = node.reassign_name.clone().unwrap();
+                let value = self.expression(node.reassign_value.as_ref().unwrap());
+
+                match (self.lookup(&name), value) {
+                    (Some(declared), Some(t)) if !assignable(&declared, &t) => {
+                        self.type_error(format!("Cannot assign {} to {}, which is {}", t.name(), name, declared.name()));
+                        None
+                    }
+                    (declared, _) => declared,
+                }
+            }
+            Ast_Type::AST_COMPOUND_ASSIGN => self.compound_assign(node),
+            Ast_Type::AST_INCREMENT | Ast_Type::AST_DECREMENT => {
+                let name = node.reassign_name.clone().unwrap();
+                let t = self.lookup(&name)?;
+
+                if !is_number(&t) {
+                    let op = if node.ast_type == Ast_Type::AST_INCREMENT { "++" } else { "--" };
+                    self.type_error(format!("Cannot apply {} to {}, which is {}", op, name, t.name()));
+                    return None;
+                }
+
+                Some(t)
+            }
+            Ast_Type::AST_CLASS_INSTANCE => {
+                let name = node.class_name.clone().unwrap();
+                let args = node.class_args.as_deref().unwrap_or(&[]);
+
+                let Some(class) = self.classes.get(&name).cloned() else {
+                    self.name_error(format!("Class {} not defined", name));
+                    return None;
+                };
+
+                self.arguments(&name, &class.args, args);
+                Some(Data_Type::CUSTOM(name))
+            }
+            Ast_Type::AST_VARIABLE_DEF
+            | Ast_Type::AST_FUNCTION_DEF
+            | Ast_Type::AST_CLASS_DEF
+            | Ast_Type::AST_COMPOUND
+            | Ast_Type::AST_IF
+            | Ast_Type::AST_WHILE
+            | Ast_Type::AST_FOR
+            | Ast_Type::AST_FOR_EACH
+            | Ast_Type::AST_MATCH
+            | Ast_Type::AST_RETURN
+            | Ast_Type::AST_TRY
+            | Ast_Type::AST_THROW
+            | Ast_Type::AST_IMPORT => {
+                self.statement(node);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, node: &AST) -> Option<Data_Type> {
+        let l = self.expression(node.left.as_ref().unwrap());
+        let r = self.expression(node.right.as_ref().unwrap());
+        let op = node.operator.clone().unwrap();
+
+        match op {
+            Types::TOKEN_AND | Types::TOKEN_OR => {
+                for t in [&l, &r].into_iter().flatten() {
+                    if !is_number(t) && *t != Data_Type::BOOL {
+                        self.type_error(format!("Cannot apply {} to {}", symbol(&op), t.name()));
+                    }
+                }
+
+                Some(Data_Type::BOOL)
+            }
+            Types::TOKEN_ADD if l.as_ref().is_some_and(is_text) || r.as_ref().is_some_and(is_text) => {
+                match (&l, &r) {
+                    (Some(a), Some(b)) if is_text(a) && is_text(b) => Some(Data_Type::STR),
+                    (Some(a), Some(b)) => {
+                        self.type_error(format!("Cannot apply + to {} and {}", a.name(), b.name()));
+                        None
+                    }
+                    _ => None,
+                }
+            }
+            Types::TOKEN_EE | Types::TOKEN_NEQ if l.as_ref().is_some_and(is_text) || r.as_ref().is_some_and(is_text) => {
+                if let (Some(a), Some(b)) = (&l, &r) {
+                    if !is_text(a) || !is_text(b) {
+                        self.type_error(format!("Cannot compare {} and {}", a.name(), b.name()));
+                    }
+                }
+
+                Some(Data_Type::BOOL)
+            }
+            Types::TOKEN_EE | Types::TOKEN_NEQ if l == Some(Data_Type::BOOL) && r == Some(Data_Type::BOOL) => Some(Data_Type::BOOL),
+            Types::TOKEN_AMPERSAND | Types::TOKEN_PIPE | Types::TOKEN_XOR | Types::TOKEN_SHIFT_LEFT | Types::TOKEN_SHIFT_RIGHT => {
+                for t in [&l, &r].into_iter().flatten() {
+                    if *t != Data_Type::INT {
+                        self.type_error(format!("Cannot apply {} to {}", symbol(&op), t.name()));
+                        return None;
+                    }
+                }
+
+                Some(Data_Type::INT)
+            }
+            _ => {
+                for t in [&l, &r].into_iter().flatten() {
+                    if !is_number(t) {
+                        self.type_error(format!("Cannot apply {} to {}", symbol(&op), t.name()));
+                        return None;
+                    }
+                }
+
+                match op {
+                    Types::TOKEN_ADD
+                    | Types::TOKEN_SUBTRACT
+                    | Types::TOKEN_ASTERISK
+                    | Types::TOKEN_FSLASH
+                    | Types::TOKEN_PERCENT
+                    | Types::TOKEN_CARROT => match (l, r) {
+                        (Some(Data_Type::INT), Some(Data_Type::INT)) => Some(Data_Type::INT),
+                        (Some(Data_Type::FLOAT), _) | (_, Some(Data_Type::FLOAT)) => Some(Data_Type::FLOAT),
+                        _ => None,
+                    },
+                    _ => Some(Data_Type::BOOL),
+                }
+            }
+        }
+    }
+
+    // x += e is checked as x = x + e, an element or field gets its indices checked only once
+    fn compound_assign(&mut self, node: &AST) -> Option<Data_Type> {
+        let target = node.left.as_ref().unwrap();
+
+        let mut step = AST::new(Ast_Type::AST_BINARY);
+        step.operator = node.operator.clone();
+        step.right = node.right.clone();
+
+        if target.ast_type == Ast_Type::AST_VARIABLE {
+            step.left = Some(target.clone());
+
+            let mut store = AST::new(Ast_Type::AST_REASSIGN);
+            store.reassign_name = target.variable_name.clone();
+            store.reassign_value = Some(Box::new(step));
+            return self.expression(&store);
+        }
+
+        self.begin_scope();
+        let t = self.compound_element(target, step);
+        self.end_scope();
+        t
+    }
+
+    fn compound_element(&mut self, target: &AST, mut step: AST) -> Option<Data_Type> {
+        for (i, index) in target.indices().into_iter().enumerate() {
+            let t = self.expression(index)?;
+            self.declare(&stored_index(i), t);
+        }
+
+        let mut store = target.with_stored_indices();
+        let errors = self.errors.len();
+        let current = self.expression(&store)?;
+
+        // Checking the store would only say the same thing about the indices again
+        if self.errors.len() > errors {
+            return None;
+        }
+
+        self.declare("compound target", current);
+
+        let mut read = AST::new(Ast_Type::AST_VARIABLE);
+        read.variable_name = Some("compound target".to_string());
+        step.left = Some(Box::new(read));
+
+        if store.ast_type == Ast_Type::AST_ARRAY_ACCESS {
+            store.array_assign_value = Some(Box::new(step));
+        } else {
+            store.reassign_value = Some(Box::new(step));
+        }
+
+        self.expression(&store)
+    }
+
+    fn arguments(&mut self, name: &str, params: &[Data_Type], args: &[AST]) {
+        let types: Vec<Option<Data_Type>> = args.iter().map(|a| self.expression(a)).collect();
+
+        if params.len() != args.len() {
+            self.type_error(format!("Function {} expected {} arguments, got {}", name, params.len(), args.len()));
+            return;
+        }
+
+        for (i, (param, t)) in params.iter().zip(types).enumerate() {
+            if let Some(t) = t {
+                if !assignable(param, &t) {
+                    let saved = self.span.clone();
+                    if args[i].span.is_some() {
+                        self.span = args[i].span.clone();
+                    }
+
+                    self.type_error(format!("Function {} argument {} expects {}, got {}", name, i + 1, param.name(), t.name()));
+                    self.span = saved;
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, node: &AST) -> Option<Data_Type> {
+        let name = node.function_call_name.clone().unwrap();
+        let args = node.function_call_args.as_deref().unwrap_or(&[]);
+
+        if name == "print" || name == "println" {
+            for arg in args {
+                self.expression(arg);
+            }
+
+            return Some(Data_Type::VOID);
+        }
+
+        if matches!(name.as_str(), "int" | "float" | "str") {
+            return self.conversion(&name, args);
+        }
+
+        // Methods can call each other without going through the instance
+        let method = self.class.as_ref()
+            .and_then(|c| self.classes.get(c))
+            .and_then(|c| c.methods.get(&name))
+            .cloned();
+
+        let Some(signature) = method.or_else(|| self.functions.get(&name).cloned()) else {
+            for arg in args {
+                self.expression(arg);
+            }
+
+            self.name_error(format!("Function '{}' not defined", name));
+            return None;
+        };
+
+        self.arguments(&name, &signature.params, args);
+        signature.returns
+    }
+
+    // int(x) and float(x) take a number, a bool or text, str(x) anything that prints
+    fn conversion(&mut self, to: &str, args: &[AST]) -> Option<Data_Type> {
+        let types: Vec<Option<Data_Type>> = args.iter().map(|a| self.expression(a)).collect();
+
+        if let [Some(t)] = types.as_slice() {
+            let convertible = match to {
+                "str" => *t != Data_Type::VOID,
+                _ => is_number(t) || is_text(t) || *t == Data_Type::BOOL,
+            };
+
+            if !convertible {
+                self.type_error(format!("Can't convert {} to {}", t.name(), to));
+            }
+        } else if args.len() != 1 {
+            self.type_error(format!("Function {} expected 1 arguments, got {}", to, args.len()));
+        }
+
+        Some(match to {
+            "int" => Data_Type::INT,
+            "float" => Data_Type::FLOAT,
+            _ => Data_Type::STR,
+        })
+    }
+
+    fn dot(&mut self, node: &AST) -> Option<Data_Type> {
+        let left = node.dot_left.as_ref().unwrap();
+        let right = node.dot_right.as_ref().unwrap();
+
+        if left.ast_type == Ast_Type::AST_VARIABLE && self.is_module(left.variable_name.as_ref().unwrap()) {
+            return self.library_call(left.variable_name.as_ref().unwrap(), right);
+        }
+
+        let t = self.expression(left)?;
+
+        if t == Data_Type::CUSTOM("Error".to_string()) {
+            let field = right.variable_name.clone().unwrap_or_default();
+
+            if right.ast_type != Ast_Type::AST_VARIABLE || (field != "message" && field != "kind") {
+                self.name_error(format!("Error has no field {}", right.function_call_name.clone().unwrap_or(field)));
+                return None;
+            }
+
+            return Some(Data_Type::STR);
+        }
+
+        if is_text(&t) {
+            return self.string_method(right);
+        }
+
+        if let Data_Type::ARRAY(element) = &t {
+            return self.array_method(element, right);
+        }
+
+        if let Data_Type::MAP(key, value) = &t {
+            return self.map_method(key, value, right);
+        }
+
+        let Data_Type::CUSTOM(class_name) = &t else {
+            self.type_error(format!("Dot access not supported on {}", t.name()));
+            return None;
+        };
+
+        let class = self.classes.get(class_name).cloned()?;
+
+        if right.ast_type == Ast_Type::AST_FUNCTION_CALL {
+            let method = right.function_call_name.clone().unwrap();
+            let args = right.function_call_args.as_deref().unwrap_or(&[]);
+
+            let Some(signature) = class.methods.get(&method) else {
+                for arg in args {
+                    self.expression(arg);
+                }
+
+                self.name_error(format!("{} has no method {}", class_name, method));
+                return None;
+            };
+
+            self.arguments(&method, &signature.params, args);
+            return signature.returns.clone();
+        }
+
+        let field = right.variable_name.clone().unwrap();
+
+        let Some(field_type) = class.fields.get(&field).cloned() else {
+            self.name_error(format!("{} has no field {}", class_name, field));
+            return None;
+        };
+
+        if let Some(value) = node.reassign_value.as_ref() {
+            if let Some(value) = self.expression(value) {
+                if !assignable(&field_type, &value) {
+                    self.type_error(format!("Cannot assign {} to {}.{}, which is {}", value.name(), class_name, field, field_type.name()));
+                }
+            }
+        }
+
+        Some(field_type)
+    }
+
+    fn string_method(&mut self, right: &AST) -> Option<Data_Type> {
+        if right.ast_type != Ast_Type::AST_FUNCTION_CALL {
+            self.type_error("Dot access not supported on str".to_string());
+            return None;
+        }
+
+        let method = right.function_call_name.clone().unwrap();
+        let args = right.function_call_args.as_deref().unwrap_or(&[]);
+
+        let (arity, returns) = match method.as_str() {
+            "len" => (0, Data_Type::INT),
+            "upper" | "lower" | "trim" => (0, Data_Type::STR),
+            "split" => (1, Data_Type::ARRAY(Box::new(Data_Type::STR))),
+            "contains" | "starts_with" => (1, Data_Type::BOOL),
+            "replace" => (2, Data_Type::STR),
+            _ => {
+                for arg in args {
+                    self.expression(arg);
+                }
+
+                self.name_error(format!("str has no method {}", method));
+                return None;
+            }
+        };
+
+        if args.len() != arity {
+            self.type_error(format!("Function {} expected {} arguments, got {}", method, arity, args.len()));
+        }
+
+        // Chars are fine anywhere a method wants a string
+        for (i, arg) in args.iter().enumerate() {
+            if let Some(t) = self.expression(arg) {
+                if !is_text(&t) {
+                    self.type_error(format!("Function {} argument {} expects str, got {}", method, i + 1, t.name()));
+                }
+            }
+        }
+
+        Some(returns)
+    }
+
+    fn array_method(&mut self, element: &Data_Type, right: &AST) -> Option<Data_Type> {
+        if right.ast_type != Ast_Type::AST_FUNCTION_CALL {
+            self.type_error("Dot access not supported on array".to_string());
+            return None;
+        }
+
+        let method = right.function_call_name.clone().unwrap();
+        let args = right.function_call_args.as_deref().unwrap_or(&[]);
+        let array = Data_Type::ARRAY(Box::new(element.clone()));
+
+        // What each argument is: an index, a value to store, or a value to look for
+        let (params, returns): (&[&str], Data_Type) = match method.as_str() {
+            "len" => (&[], Data_Type::INT),
+            "pop" => (&[], element.clone()),
+            "sort" | "reverse" => (&[], Data_Type::VOID),
+            "push" => (&["value"], Data_Type::VOID),
+            "remove" => (&["index"], element.clone()),
+            "contains" => (&["search"], Data_Type::BOOL),
+            "index_of" => (&["search"], Data_Type::INT),
+            "insert" => (&["index", "value"], Data_Type::VOID),
+            "slice" => (&["index", "index"], array),
+            _ => {
+                for arg in args {
+                    self.expression(arg);
+                }
+
+                self.name_error(format!("array has no method {}", method));
+                return None;
+            }
+        };
+
+        if args.len() != params.len() {
+            self.type_error(format!("Function {} expected {} arguments, got {}", method, params.len(), args.len()));
+        }
+
+        for (i, arg) in args.iter().enumerate() {
+            let Some(t) = self.expression(arg) else {
+                continue;
+            };
+
+            match params.get(i).copied() {
+                Some("index") if t != Data_Type::INT => self.type_error("Array index must be an int".to_string()),
+                Some("value") if !assignable(element, &t) => self.type_error(format!("{}[] can't hold {}", element.name(), t.name())),
+                Some("search") if !(orderable(element, &t) || *element == t) => self.type_error(format!("Cannot compare {} and {}", element.name(), t.name())),
+                _ => {}
+            }
+        }
+
+        if method == "sort" && !orderable(element, element) {
+            self.type_error(format!("Can't sort {}[]", element.name()));
+        }
+
+        Some(returns)
+    }
+
+    fn map_method(&mut self, key: &Data_Type, value: &Data_Type, right: &AST) -> Option<Data_Type> {
+        if right.ast_type != Ast_Type::AST_FUNCTION_CALL {
+            self.type_error("Dot access not supported on map".to_string());
+            return None;
+        }
+
+        let method = right.function_call_name.clone().unwrap();
+        let args = right.function_call_args.as_deref().unwrap_or(&[]);
+
+        let (arity, returns) = match method.as_str() {
+            "len" => (0, Data_Type::INT),
+            "keys" => (0, Data_Type::ARRAY(Box::new(key.clone()))),
+            "values" => (0, Data_Type::ARRAY(Box::new(value.clone()))),
+            "has" => (1, Data_Type::BOOL),
+            "remove" => (1, value.clone()),
+            _ => {
+                for arg in args {
+                    self.expression(arg);
+                }
+
+                self.name_error(format!("map has no method {}", method));
+                return None;
+            }
+        };
+
+        if args.len() != arity {
+            self.type_error(format!("Function {} expected {} arguments, got {}", method, arity, args.len()));
+        }
+
+        for arg in args {
+            if let Some(t) = self.expression(arg) {
+                if !assignable(key, &t) {
+                    self.type_error(format!("Map key must be {}, got {}", key.name(), t.name()));
+                }
+            }
+        }
+
+        Some(returns)
+    }
+
+    fn library_call(&mut self, module: &str, right: &AST) -> Option<Data_Type> {
+        if right.ast_type != Ast_Type::AST_FUNCTION_CALL {
+            self.type_error(format!("Library `{}` can't be used as a value", module));
+            return None;
+        }
+
+        let function = right.function_call_name.clone().unwrap();
+        let args = right.function_call_args.as_deref().unwrap_or(&[]);
+
+        match self.modules.get(module).cloned().flatten() {
+            None => {
+                // Only math is built in so far
+                if !matches!(function.as_str(), "sqrt" | "abs" | "floor") {
+                    self.name_error(format!("Function `{}` not found in <{}>", function, module));
+                    return None;
+                }
+
+                self.arguments(&function, &[Data_Type::FLOAT], args);
+                Some(Data_Type::FLOAT)
+            }
+            Some(exports) => match exports.get(&function) {
+                Some(signature) => {
+                    self.arguments(&function, &signature.params, args);
+                    signature.returns.clone()
+                }
+                None => {
+                    self.name_error(format!("Function '{}' not found in {}", function, module));
+                    None
+                }
+            },
+        }
+    }
+
+    // [1, 2.5] outside a declaration holds whatever its items are, ints and floats together make floats.
+    // An empty one is a void[], which goes anywhere an array does
+    fn untyped_array(&mut self, node: &AST) -> Option<Data_Type> {
+        let mut element = Data_Type::VOID;
+
+        for item in node.array_elements.as_deref().unwrap_or(&[]) {
+            let t = self.expression(item)?;
+
+            element = match (&element, &t) {
+                (Data_Type::VOID, _) => t,
+                (a, b) if assignable(a, b) => element,
+                (a, b) if assignable(b, a) => t,
+                _ => {
+                    self.type_error(format!("Items of an array have to be the same type, got {} and {}", element.name(), t.name()));
+                    return None;
+                }
+            };
+        }
+
+        Some(Data_Type::ARRAY(Box::new(element)))
+    }
+
+    fn array_access(&mut self, node: &AST) -> Option<Data_Type> {
+        let name = node.array_name.clone().unwrap();
+        let path = node.array_path.as_deref().unwrap_or(&[]);
+        let target = match node.array_field.as_deref() {
+            Some(field) => self.expression(field),
+            None => self.lookup(&name),
+        };
+
+        // grid[i][j] goes one level in per index, a map wants its key type there instead of an int
+        let mut levels = vec![target.clone()];
+        let indices = node.array_index.as_deref().into_iter().chain(path);
+
+        for index in indices {
+            let holder = levels.last().cloned().flatten();
+            let t = self.expression(index);
+
+            let next = match holder {
+                Some(Data_Type::MAP(key, value)) => {
+                    if let Some(t) = t.filter(|t| !assignable(&key, t)) {
+                        self.type_error(format!("Map key must be {}, got {}", key.name(), t.name()));
+                    }
+
+                    Some(*value)
+                }
+                holder => {
+                    if let Some(t) = t.filter(|t| *t != Data_Type::INT) {
+                        self.type_error(format!("Array index must be an int, got {}", t.name()));
+                    }
+
+                    match holder {
+                        Some(Data_Type::ARRAY(element)) => Some(*element),
+                        Some(Data_Type::STR) => Some(Data_Type::CHAR),
+                        _ => None,
+                    }
+                }
+            };
+
+            levels.push(next);
+        }
+
+        if let Some(end) = node.array_slice_end.as_deref() {
+            if let Some(t) = self.expression(end).filter(|t| *t != Data_Type::INT) {
+                self.type_error(format!("Array index must be an int, got {}", t.name()));
+            }
+        }
+
+        let value = node.array_assign_value.as_ref().and_then(|v| self.expression(v));
+
+        target?;
+
+        // Every level but the last has to be something that can be indexed into
+        let holders = &levels[..levels.len() - 1];
+        for holder in &holders[..holders.len() - 1] {
+            match holder {
+                Some(Data_Type::ARRAY(_)) | Some(Data_Type::MAP(..)) | Some(Data_Type::STR) => {}
+                Some(t) => {
+                    self.type_error(format!("{} is not an array, it's {}", name, t.name()));
+                    return None;
+                }
+                None => return None,
+            }
+        }
+
+        let holder = holders.last().cloned().flatten()?;
+
+        let element = match holder {
+            Data_Type::STR if value.is_some() => {
+                self.type_error(format!("Strings can't be changed, {} is a str", name));
+                return None;
+            }
+            Data_Type::STR if node.array_slice_end.is_some() => return Some(Data_Type::STR),
+            Data_Type::STR => return Some(Data_Type::CHAR),
+            ref t if node.array_slice_end.is_some() => {
+                self.type_error(format!("{} is not a string, it's {}", name, t.name()));
+                return None;
+            }
+            Data_Type::ARRAY(ref element) => (**element).clone(),
+            Data_Type::MAP(_, ref value) => (**value).clone(),
+            ref t => {
+                self.type_error(format!("{} is not an array, it's {}", name, t.name()));
+                return None;
+            }
+        };
+
+        if let Some(value) = value {
+            if !assignable(&element, &value) {
+                self.type_error(format!("Cannot assign {} to an element of {}, which is {}", value.name(), name, holder.name()));
+            }
+        }
+
+        Some(element)
+    }
+
+    // array(int, 10, 0)
+    fn array_fill(&mut self, node: &AST) -> Option<Data_Type> {
+        let args = node.function_call_args.as_deref().unwrap();
+
+        if let Some(t) = self.expression(&args[0]) {
+            if t != Data_Type::INT {
+                self.type_error(format!("Array size must be an int, got {}", t.name()));
+            }
+        }
+
+        if let Some(t) = self.expression(&args[1]) {
+            if !assignable(&node.data_type, &t) {
+                self.type_error(format!("{}[] can't hold {}", node.data_type.name(), t.name()));
+            }
+        }
+
+        Some(Data_Type::ARRAY(Box::new(node.data_type.clone())))
+    }
+}
+
+fn signature(node: &AST) -> Signature {
+    Signature {
+        params: node.function_definition_args.as_deref().unwrap_or(&[])
+            .iter()
+            .map(|a| a.variable_type.clone().unwrap())
+            .collect(),
+        returns: node.function_return_type.clone(),
+    }
+}
+
+fn is_number(t: &Data_Type) -> bool {
+    matches!(t, Data_Type::INT | Data_Type::FLOAT)
+}
+
+// Strings and chars mix freely in +, == and string methods
+fn is_text(t: &Data_Type) -> bool {
+    matches!(t, Data_Type::STR | Data_Type::CHAR)
+}
+
+// Numbers go in order with numbers and text with text, the same as sort does at runtime
+fn orderable(a: &Data_Type, b: &Data_Type) -> bool {
+    (is_number(a) && is_number(b)) || (is_text(a) && is_text(b))
+}
+
+// Ints are let through where a float is wanted, same as the visitor does for arguments
+fn assignable(target: &Data_Type, value: &Data_Type) -> bool {
+    match (target, value) {
+        _ if target == value => true,
+        (Data_Type::FLOAT, Data_Type::INT) => true,
+        (Data_Type::ARRAY(_), Data_Type::ARRAY(element)) if **element == Data_Type::VOID => true, // an empty []
+        (Data_Type::ARRAY(target), Data_Type::ARRAY(element)) if matches!(**element, Data_Type::ARRAY(_)) => assignable(target, element),
+        _ => false,
+    }
+}
+
+fn symbol(op: &Types) -> &'static str {
+    match op {
+        Types::TOKEN_ADD => "+",
+        Types::TOKEN_SUBTRACT => "-",
+        Types::TOKEN_ASTERISK => "*",
+        Types::TOKEN_FSLASH => "/",
+        Types::TOKEN_PERCENT => "%",
+        Types::TOKEN_CARROT => "^",
+        Types::TOKEN_AMPERSAND => "&",
+        Types::TOKEN_PIPE => "|",
+        Types::TOKEN_XOR => "xor",
+        Types::TOKEN_SHIFT_LEFT => "<<",
+        Types::TOKEN_SHIFT_RIGHT => ">>",
+        Types::TOKEN_GREATER_THAN => ">",
+        Types::TOKEN_LESS_THAN => "<",
+        Types::TOKEN_GEQ => ">=",
+        Types::TOKEN_LEQ => "<=",
+        Types::TOKEN_EE => "==",
+        Types::TOKEN_NEQ => "!=",
+        Types::TOKEN_AND => "&&",
+        Types::TOKEN_OR => "||",
+        _ => "?",
+    }
+}
+
+// A class whose instances a map would hold, they can't be read back out of one yet
+fn mapped_class(t: &Data_Type, in_map: bool) -> Option<&str> {
+    match t {
+        Data_Type::CUSTOM(class) if in_map && class != "Error" => Some(class),
+        Data_Type::ARRAY(element) => mapped_class(element, in_map),
+        Data_Type::MAP(key, value) => mapped_class(key, true).or_else(|| mapped_class(value, true)),
+        _ => None,
+    }
+}
@@ -4,22 +4,33 @@ use std::collections::HashMap;
 use std::rc::Rc;
 use std::cell::RefCell;
 use crate::scope::{Scope, SharedScope};
-use crate::lexer::Lexer;
-use crate::parser::Parser; 
+use crate::error::{IronError, IronResult};
+use crate::span::Span;
+use crate::strings::{self, Text};
+use crate::arrays::{self, Element, Outcome};
+use crate::maps::{self, Key};
+use crate::numbers;
+use std::cmp::Ordering;
+
+pub type Builtin = Box<dyn Fn(&[AST]) -> IronResult<AST>>;
+
+// How many calls can be nested before it's a RuntimeError, the VM and native builds stop at the same depth
+pub const MAX_DEPTH: usize = 1000;
 
 pub struct Visitor {
-    pub builtins: HashMap<String, Box<dyn Fn(&[AST]) -> AST>>,
+    pub builtins: HashMap<String, Builtin>,
+    depth: usize, // the user function calls we're inside of
 }
 
 impl Visitor { 
     pub fn new() -> Self {
-        let mut b: HashMap<String, Box<dyn Fn(&[AST]) -> AST>> = HashMap::new();
+        let mut b: HashMap<String, Builtin> = HashMap::new();
 
         b.insert("print".to_string(), Box::new(|args: &[AST]| {
             for arg in args {
                 arg.print();
             }
-            AST::new(Ast_Type::AST_NOOP)
+            Ok(AST::new(Ast_Type::AST_NOOP))
         }));
 
         b.insert("println".to_string(), Box::new(|args: &[AST]| {
@@ -27,13 +38,17 @@ impl Visitor {
                 arg.print();
             }
             println!();
-            AST::new(Ast_Type::AST_NOOP)
+            Ok(AST::new(Ast_Type::AST_NOOP))
         }));
 
-        Visitor { builtins: b }
+        for to in ["int", "float", "str"] {
+            b.insert(to.to_string(), Box::new(move |args: &[AST]| convert(to, args)));
+        }
+
+        Visitor { builtins: b, depth: 0 }
     }
 
-    pub fn visit(&mut self, node: &mut AST) -> AST {
+    pub fn visit(&mut self, node: &mut AST) -> IronResult<AST> {
         // println!("Visiting {:#?}", node.ast_type); 
         match node.ast_type {
             Ast_Type::AST_VARIABLE => self.visit_variable(node),
@@ -42,22 +57,31 @@ impl Visitor {
             Ast_Type::AST_BINARY => self.visit_binary(node),
             Ast_Type::AST_RETURN => self.visit_return(node),
             Ast_Type::AST_IF => self.visit_if(node),
+            Ast_Type::AST_MATCH => self.visit_match(node),
+            Ast_Type::AST_TERNARY => self.visit_ternary(node),
             Ast_Type::AST_WHILE => self.visit_while(node), 
             Ast_Type::AST_REASSIGN => self.visit_reassign(node),
             Ast_Type::AST_INCREMENT => self.visit_increment(node),
             Ast_Type::AST_DECREMENT => self.visit_decrement(node), 
+            Ast_Type::AST_COMPOUND_ASSIGN => self.visit_compound_assign(node),
             Ast_Type::AST_FOR => self.visit_for(node),
+            Ast_Type::AST_FOR_EACH => self.visit_for_each(node),
             Ast_Type::AST_UNARY => self.visit_unary(node), 
             Ast_Type::AST_ARRAY_ACCESS => self.visit_array_access(node),
             Ast_Type::AST_DOT => self.visit_dot(node),
             Ast_Type::AST_IMPORT => self.visit_import(node), 
             Ast_Type::AST_CLASS_INSTANCE => self.visit_class_creation(node),
             Ast_Type::AST_CLASS_ACCESS => self.visit_class_access(node), 
-            _ => node.clone(),
+            Ast_Type::AST_TRY => self.visit_try(node),
+            Ast_Type::AST_THROW => self.visit_throw(node),
+            Ast_Type::AST_ARRAY_DEF => self.array_value(node),
+            Ast_Type::AST_ARRAY_FILL => self.visit_array_fill(node),
+            Ast_Type::AST_MAP_DEF => self.map_value(node),
+            _ => Ok(node.clone()),
         }
     }
 
-    pub fn visit_class_creation(&mut self, node: &mut AST) -> AST {
+    pub fn visit_class_creation(&mut self, node: &mut AST) -> IronResult<AST> {
         let name = node.class_name.as_ref().expect("Missing class name");
 
         let empty_vec = &vec![];
@@ -65,22 +89,22 @@ impl Visitor {
         let args = node.class_args.as_ref().unwrap_or(empty_vec).clone(); 
         let mut eval_args = args 
             .into_iter()
-            .map(|mut arg| { return self.visit(&mut arg);})
-            .collect::<Vec<_>>();
+            .map(|mut arg| self.visit(&mut arg))
+            .collect::<IronResult<Vec<_>>>()?;
 
         let scope = node.scope.as_ref().expect("Class instanitation missing scope");
 
-        let def = scope.borrow().get_class_definition(&node.class_name.as_ref().unwrap()).unwrap_or_else(|| panic!("Class {} not defined", name));
+        let def = scope.borrow().get_class_definition(node.class_name.as_ref().unwrap()).ok_or_else(|| IronError::NameError(format!("Class {} not defined", name), node.span.clone()))?;
 
         let params = def.class_definition_args.as_ref().unwrap_or(empty_vec); 
 
         if eval_args.len() != params.len() {
-            panic!(
+            return Err(IronError::TypeError(format!(
                 "Class instationation {} expected {} arguments, got {}",
                 name,
                 params.len(),
                 eval_args.len()
-            );
+            ), node.span.clone()));
         }
 
         let class_scope = def.scope.as_ref().expect("Class def missing scope");
@@ -89,48 +113,46 @@ impl Visitor {
         for (param, arg) in params.iter().zip(eval_args.iter()) {
             let expected = param.variable_type.as_ref().unwrap();
 
-            if param.variable_type.as_ref() != Some(&arg.data_type) && !(expected == &Data_Type::FLOAT && arg.data_type == Data_Type::INT){
-                panic!(
-                    "Function {} argument type mismatch: expected {:?}, got {:?}",
-                    name, param.variable_type, arg.data_type
-                );
-            }
-
-            let mut value = arg.clone();
+            let arg = arrays::stored(expected, arg);
+            let got = value_data_type(&arg);
 
-            value.scope = Some(new_scope.clone()); 
+            if param.variable_type.as_ref() != Some(&got) && !(expected == &Data_Type::FLOAT && got == Data_Type::INT){
+                return Err(IronError::TypeError(format!(
+                    "Function {} argument type mismatch: expected {}, got {}",
+                    name, expected.name(), got.name()
+                ), node.span.clone()));
+            }
 
-            let mut var_def = AST::new(Ast_Type::AST_VARIABLE_DEF);
-            var_def.variable_definition_variable_name = param.variable_definition_variable_name.clone(); 
-            var_def.variable_definition_value = Some(Box::new(value));
-            var_def.variable_type = param.variable_type.clone();
-            var_def.scope = Some(new_scope.clone());
+            let var_def = binding(param, arg, &new_scope);
 
-            new_scope.borrow_mut().update_variable_definition(param.variable_definition_variable_name.as_ref().unwrap().clone(), var_def);
+            new_scope.borrow_mut().update_variable_definition(param.variable_definition_variable_name.as_ref().unwrap().clone(), var_def).map_err(|e| e.with_span(&node.span))?;
         }
         let mut body = def.class_definition_body.as_ref().expect("Missing body").clone();
         self.set_scope_recursively(&mut body, new_scope.clone());
 
-        AST::new(Ast_Type::AST_NOOP)
+        // The fields live in the class's scope, what's handed back only says which class it is
+        let mut instance = AST::new(Ast_Type::AST_NOOP);
+        instance.class_name = Some(name.clone());
+        Ok(instance)
     }
 
-    pub fn visit_class_access(&mut self, node: &mut AST) -> AST {
+    pub fn visit_class_access(&mut self, node: &mut AST) -> IronResult<AST> {
         let instance = node.dot_left.as_mut().unwrap(); 
         let instance_name = instance.variable_name.as_ref().unwrap();
         
         let instance_def = node.scope.clone().unwrap().borrow()
             .get_variable_definition(instance_name)
-            .unwrap_or_else(|| panic!("No instance {} found", instance_name));
+            .ok_or_else(|| IronError::NameError(format!("No instance {} found", instance_name), node.span.clone()))?;
 
         let class_name = instance_def.class_name.as_ref()
-            .unwrap_or_else(|| panic!("Instance {} has no associated class", instance_name));
+            .ok_or_else(|| IronError::NameError(format!("Instance {} has no associated class", instance_name), node.span.clone()))?;
 
         let class_def = node.scope.clone().unwrap().borrow()
             .get_class_definition(class_name)
-            .unwrap_or_else(|| panic!("No class definition found for {}", class_name));
+            .ok_or_else(|| IronError::NameError(format!("No class definition found for {}", class_name), node.span.clone()))?;
 
         let class_scope = class_def.scope.as_ref()
-            .unwrap_or_else(|| panic!("Instance {} has no scope", instance_name));
+            .ok_or_else(|| IronError::RuntimeError(format!("Instance {} has no scope", instance_name), node.span.clone()))?;
 
         match node.dot_right.as_ref().unwrap().ast_type {
             Ast_Type::AST_VARIABLE => {
@@ -138,37 +160,57 @@ impl Visitor {
                     let name = node.dot_right.as_ref().unwrap().variable_name.as_ref().unwrap();
 
                     let mut var_def = class_scope.borrow().get_variable_definition(name)
-                        .unwrap_or_else(|| panic!("Undefined variable: {}", name));
+                        .ok_or_else(|| IronError::NameError(format!("Undefined variable: {}", name), node.span.clone()))?;
 
-                    if node.reassign_value.is_some() {
-                        var_def.variable_definition_value = Some(Box::new(*node.reassign_value.as_ref().unwrap().clone()));
+                    if let Some(value) = &node.reassign_value {
+                        var_def.variable_definition_value = Some(value.clone());
+
+                        let evaluated = self.visit(&mut value.clone())?;
+                        let evaluated = declared(&var_def, evaluated);
+
+                        if evaluated.ast_type == Ast_Type::AST_ARRAY_DEF {
+                            let mut field = evaluated;
+                            field.array_name = Some(name.clone());
+                            field.scope = var_def.scope.clone();
+                            var_def = field;
+                        }
                     }
 
-                    class_scope.borrow_mut().update_variable_definition(name.to_string(), var_def.clone()); 
+                    class_scope.borrow_mut().update_variable_definition(name.to_string(), var_def.clone()).map_err(|e| e.with_span(&node.span))?; 
 
                     let r = match var_def.ast_type {
                         Ast_Type::AST_VARIABLE_DEF => {
                             if let Some(val) = &var_def.variable_definition_value {
-                                return self.visit(&mut *val.clone());
+                                let value = self.visit(&mut val.clone())?;
+                                return Ok(declared(&var_def, value));
                             } else {
-                                panic!("Variable '{}' has no value", name);
+                                return Err(IronError::RuntimeError(format!("Variable '{}' has no value", name), node.span.clone()));
                             }
                         }
-                        Ast_Type::AST_ARRAY_DEF => var_def.clone(),
+                        Ast_Type::AST_ARRAY_DEF => self.array_value(&var_def)?,
                         Ast_Type::AST_INT | Ast_Type::AST_FLOAT | Ast_Type::AST_BOOL => var_def.clone(),
-                        _ => panic!("Unknown variable type '{}'", name),
+                        _ => return Err(IronError::RuntimeError(format!("Unknown variable type '{}'", name), node.span.clone())),
                     };
 
-                    return r;
+                    return Ok(r);
                 }
                 
                 let name = node.dot_right.as_ref().unwrap().variable_name.as_ref().unwrap();
 
-                if let Some(mut val) = class_scope.borrow().get_variable_definition(&name) {
-                    return self.visit(&mut val.variable_definition_value.unwrap().clone()); 
+                let field = class_scope.borrow().get_variable_definition(name);
+
+                if let Some(field) = field {
+                    if field.ast_type == Ast_Type::AST_ARRAY_DEF {
+                        return self.array_value(&field);
+                    }
+
+                    if let Some(mut value) = field.variable_definition_value.clone() {
+                        let value = self.visit(&mut value)?;
+                        return Ok(declared(&field, value));
+                    }
                 }
 
-                panic!("Value not found for node.dot_right");
+                Err(IronError::RuntimeError("Value not found for node.dot_right".to_string(), node.span.clone()))
             }
             Ast_Type::AST_FUNCTION_CALL => {
                 let a = node.dot_right.as_ref().unwrap(); 
@@ -180,7 +222,7 @@ impl Visitor {
                 let mut evaluated_args = args_vec
                     .into_iter()
                     .map(|mut arg| self.visit(&mut arg))
-                    .collect::<Vec<_>>();
+                    .collect::<IronResult<Vec<_>>>()?;
 
 
                 if let Some(f) = self.builtins.get(name) {
@@ -190,17 +232,17 @@ impl Visitor {
                 let def = class_scope
                     .borrow()
                     .get_function_definition(name)
-                    .unwrap_or_else(|| panic!("Function '{}' not defined", name));
+                    .ok_or_else(|| IronError::NameError(format!("Function '{}' not defined", name), node.span.clone()))?;
 
                 let params = def.function_definition_args.as_ref().unwrap_or(empty_vec);
 
                 if evaluated_args.len() != params.len() {
-                    panic!(
+                    return Err(IronError::TypeError(format!(
                         "Function {} expected {} arguments, got {}",
                         name,
                         params.len(),
                         evaluated_args.len()
-                    );
+                    ), node.span.clone()));
                 }
 
                 let func_scope = def.scope.as_ref().expect("Function def missing scope");
@@ -209,52 +251,43 @@ impl Visitor {
                 for (param, arg) in params.iter().zip(evaluated_args.iter()) {
                     let expected = param.variable_type.as_ref().unwrap();
 
-                    if param.variable_type.as_ref() != Some(&arg.data_type) && !(expected == &Data_Type::FLOAT && arg.data_type == Data_Type::INT){
-                        panic!(
-                            "Function {} argument type mismatch: expected {:?}, got {:?}",
-                            name, param.variable_type, arg.data_type
-                        );
-                    }
-
-                    let mut value = arg.clone();
+                    let arg = arrays::stored(expected, arg);
+                    let got = value_data_type(&arg);
 
-                    value.scope = Some(new_scope.clone()); 
+                    if param.variable_type.as_ref() != Some(&got) && !(expected == &Data_Type::FLOAT && got == Data_Type::INT){
+                        return Err(IronError::TypeError(format!(
+                            "Function {} argument type mismatch: expected {}, got {}",
+                            name, expected.name(), got.name()
+                        ), node.span.clone()));
+                    }
 
-                    let mut var_def = AST::new(Ast_Type::AST_VARIABLE_DEF);
-                    var_def.variable_definition_variable_name = param.variable_definition_variable_name.clone(); 
-                    var_def.variable_definition_value = Some(Box::new(value));
-                    var_def.variable_type = param.variable_type.clone();
-                    var_def.scope = Some(new_scope.clone());
+                    let var_def = binding(param, arg, &new_scope);
 
                     new_scope.borrow_mut().add_variable_definition(var_def);
                 }
 
-                let mut body = def.function_definition_body.as_ref().expect("Missing body").clone();
-                self.set_scope_recursively(&mut body, new_scope.clone());
-
-                let result = self.visit(&mut body);
-
-                if result.ast_type == Ast_Type::AST_RETURN {
-                    if let Some(value) = result.return_value {
-                        return *value;
-                    }
-                    else {
-                        return result; 
-                    }
-                }
-
-                AST::new(Ast_Type::AST_NOOP)
+                self.call_body(&def, new_scope, &node.span)
             }
 
-            _ => panic!("Invalid class access on dot_right"),
+            _ => Err(IronError::RuntimeError("Invalid class access on dot_right".to_string(), node.span.clone())),
         }
     }
 
-    pub fn visit_dot(&mut self, node: &mut AST) -> AST {
-        let left = self.visit(node.dot_left.as_mut().unwrap());
+    pub fn visit_dot(&mut self, node: &mut AST) -> IronResult<AST> {
+        if let Some(name) = self.map_variable(node) {
+            return self.visit_map_method_at(node, &name);
+        }
+
+        let left = self.visit(node.dot_left.as_mut().unwrap())?;
+
+        // A parameter holding an instance, its fields are found through its class like any instance's
+        let variable = node.dot_left.as_ref().is_some_and(|l| l.ast_type == Ast_Type::AST_VARIABLE);
+        if left.ast_type == Ast_Type::AST_NOOP && left.class_name.is_some() && variable {
+            return self.visit_class_access(node);
+        }
 
         if left.ast_type == Ast_Type::AST_NOOP {
-            panic!("Cannot access property on AST_NOOP (likely uninitialized)");
+            return Err(IronError::TypeError("Cannot access property on AST_NOOP (likely uninitialized)".to_string(), node.span.clone()));
         }
 
         match left.ast_type {
@@ -273,13 +306,13 @@ impl Visitor {
                         let args = f.function_call_args.clone().unwrap_or(vec![]);
 
                         if import.is_builtin.unwrap_or(false) {
-                            return self.call_library_function(name, f_name, args, &node.scope);
+                            return self.call_library_function(name, f_name, args, &node.scope, &node.span);
                         }
 
                         let import_scope = import.scope.clone().expect("Imported AST missing scope");
 
                         let def = import_scope.borrow().get_function_definition(f_name)
-                            .unwrap_or_else(|| panic!("Function '{}' not found in imported AST", f_name));
+                            .ok_or_else(|| IronError::NameError(format!("Function '{}' not found in imported AST", f_name), node.span.clone()))?;
 
                         let mut f_call = AST::new(Ast_Type::AST_FUNCTION_CALL);
                         f_call.function_call_name = Some(f_name.clone());
@@ -289,17 +322,212 @@ impl Visitor {
                         return self.visit_function_call(&mut f_call);
                     }
 
-                    panic!("Cannot call dot access on non-function node");
+                    return Err(IronError::RuntimeError("Cannot call dot access on non-function node".to_string(), node.span.clone()));
                 }
 
-                panic!("Library `{}` not found in imports", name);
+                Err(IronError::ImportError(format!("Library `{}` not found in imports", name), node.span.clone()))
+            }
+
+            Ast_Type::AST_ERROR => {
+                let field = node.dot_right.as_ref().unwrap().variable_name.clone().unwrap_or_default();
+
+                let mut value = AST::new(Ast_Type::AST_STRING);
+                value.data_type = Data_Type::STR;
+
+                value.string_value = match field.as_str() {
+                    "message" => left.string_value.clone(),
+                    "kind" => left.error_kind.clone(),
+                    _ => return Err(IronError::NameError(format!("Error has no field {}", field), node.span.clone())),
+                };
+
+                Ok(value)
+            }
+
+            Ast_Type::AST_STRING => {
+                let right = node.dot_right.as_ref().unwrap();
+
+                if right.ast_type != Ast_Type::AST_FUNCTION_CALL {
+                    return Err(IronError::TypeError("Dot access not supported on str".to_string(), node.span.clone()));
+                }
+
+                let args = right.function_call_args.clone().unwrap_or_default()
+                    .into_iter()
+                    .map(|mut arg| self.visit(&mut arg).map(|value| text(&value)))
+                    .collect::<IronResult<Vec<_>>>()?;
+
+                let s = left.string_value.as_ref().unwrap();
+
+                Ok(match strings::method(s, right.function_call_name.as_ref().unwrap(), &args, &node.span)? {
+                    Text::Int(i) => AST::from_int(i),
+                    Text::Bool(b) => AST::from_bool(b),
+                    Text::Str(s) => AST::from_string(&s),
+                    Text::List(parts) => {
+                        let mut array = AST::new(Ast_Type::AST_ARRAY_DEF);
+                        array.array_elements = Some(parts.iter().map(|p| AST::from_string(p)).collect());
+                        array.data_type = Data_Type::STR;
+                        array
+                    }
+                })
+            }
+
+            Ast_Type::AST_ARRAY_DEF => self.visit_array_method(node, left),
+            Ast_Type::AST_MAP_DEF => self.visit_map_method(node, left),
+
+            _ => Err(IronError::TypeError(format!("Dot access not supported on {:#?}", left.ast_type), node.span.clone())),
+        }
+    }
+
+    // Methods called on an array variable change it in place, on anything else they work on a copy
+    fn visit_array_method(&mut self, node: &mut AST, array: AST) -> IronResult<AST> {
+        let right = node.dot_right.as_ref().unwrap();
+
+        if right.ast_type != Ast_Type::AST_FUNCTION_CALL {
+            return Err(IronError::TypeError("Dot access not supported on array".to_string(), node.span.clone()));
+        }
+
+        let args = right.function_call_args.clone().unwrap_or_default()
+            .into_iter()
+            .map(|mut arg| self.visit(&mut arg))
+            .collect::<IronResult<Vec<_>>>()?;
+
+        let left = node.dot_left.as_ref().unwrap();
+        let name = if left.ast_type == Ast_Type::AST_VARIABLE { left.variable_name.clone() } else { None };
+        let field = field_of(left);
+        let scope = node.scope.clone().unwrap();
+
+        // The arguments might have changed the array, so read it again
+        let current = name.as_ref().and_then(|name| scope.borrow().get_variable_definition(name));
+        let array = match field.clone() {
+            Some(mut field) => self.visit(&mut field)?,
+            None => current.filter(|def| def.ast_type == Ast_Type::AST_ARRAY_DEF).unwrap_or(array),
+        };
+
+        let mut items = array.array_elements.clone().unwrap_or_default()
+            .into_iter()
+            .map(|mut item| self.visit(&mut item))
+            .collect::<IronResult<Vec<_>>>()?;
+
+        let element = array.data_type.clone();
+        let fits = |value: &AST| returns_type(&element, Some(value));
+        let outcome = arrays::method(&mut items, &element, fits, right.function_call_name.as_ref().unwrap(), &args, &node.span)?;
+
+        if let Some(mut field) = field {
+            let mut changed = AST::new(Ast_Type::AST_ARRAY_DEF);
+            changed.array_elements = Some(items.clone());
+            changed.data_type = element.clone();
+
+            field.reassign_value = Some(Box::new(changed));
+            self.visit(&mut field)?;
+        }
+
+        if let Some(name) = name {
+            let mut new_def = AST::new(Ast_Type::AST_ARRAY_DEF);
+            new_def.array_name = Some(name.clone());
+            new_def.array_elements = Some(items);
+            new_def.data_type = element.clone();
+            new_def.scope = array.scope.clone();
+
+            scope.borrow_mut().update_variable_definition(name, new_def).map_err(|e| e.with_span(&node.span))?;
+        }
+
+        Ok(match outcome {
+            Outcome::Nothing => AST::new(Ast_Type::AST_NOOP),
+            Outcome::Value(value) => value,
+            Outcome::Int(i) => AST::from_int(i),
+            Outcome::Bool(b) => AST::from_bool(b),
+            Outcome::Array(items) => {
+                let mut slice = AST::new(Ast_Type::AST_ARRAY_DEF);
+                slice.array_elements = Some(items);
+                slice.data_type = element;
+                slice
             }
+        })
+    }
+
+    // Like arrays, methods called on a map variable change it
+    fn visit_map_method(&mut self, node: &mut AST, map: AST) -> IronResult<AST> {
+        let right = node.dot_right.as_ref().unwrap();
 
-            _ => panic!("Dot access not supported on {:#?}", left.ast_type),
+        if right.ast_type != Ast_Type::AST_FUNCTION_CALL {
+            return Err(IronError::TypeError("Dot access not supported on map".to_string(), node.span.clone()));
         }
+
+        let args = right.function_call_args.clone().unwrap_or_default()
+            .into_iter()
+            .map(|mut arg| self.visit(&mut arg))
+            .collect::<IronResult<Vec<_>>>()?;
+
+        let left = node.dot_left.as_ref().unwrap();
+        let name = if left.ast_type == Ast_Type::AST_VARIABLE { left.variable_name.clone() } else { None };
+        let field = field_of(left);
+        let scope = node.scope.clone().unwrap();
+
+        // The arguments might have changed the map, so read it again
+        let def = name.as_ref().and_then(|name| scope.borrow().get_variable_definition(name));
+        let map = match def.as_ref().and_then(|def| def.variable_definition_value.as_deref().cloned()).or(field.clone()) {
+            Some(mut value) => self.visit(&mut value)?,
+            None => map,
+        };
+
+        let mut keys = map.map_keys.clone().unwrap_or_default();
+        let mut values = map.map_values.clone().unwrap_or_default();
+        let outcome = maps::method(&mut keys, &mut values, right.function_call_name.as_ref().unwrap(), &args, &node.span)?;
+
+
+        let map_type = map.data_type.clone();
+        let mut changed = map;
+        changed.map_keys = Some(keys);
+        changed.map_values = Some(values);
+
+        if let (Some(name), Some(mut def)) = (name, def) {
+            def.variable_definition_value = Some(Box::new(changed.clone()));
+
+            scope.borrow_mut().update_variable_definition(name, def).map_err(|e| e.with_span(&node.span))?;
+        }
+
+        if let Some(mut field) = field {
+            field.reassign_value = Some(Box::new(changed));
+            self.visit(&mut field)?;
+        }
+
+        Ok(map_outcome(outcome, map_type))
     }
 
-    pub fn visit_import(&mut self, node: &mut AST) -> AST {
+    // m.has(k) on a map variable, run on the map where it's kept instead of on a copy
+    fn visit_map_method_at(&mut self, node: &mut AST, name: &str) -> IronResult<AST> {
+        let right = node.dot_right.as_ref().unwrap();
+        let method = right.function_call_name.clone().unwrap();
+
+        let args = right.function_call_args.clone().unwrap_or_default()
+            .into_iter()
+            .map(|mut arg| self.visit(&mut arg))
+            .collect::<IronResult<Vec<_>>>()?;
+
+        let span = node.span.clone();
+        let call = |def: &mut AST| {
+            let AST { map_keys, map_values, data_type, .. } = &mut **def.variable_definition_value.as_mut().unwrap();
+            let outcome = maps::method(map_keys.get_or_insert_with(Vec::new), map_values.get_or_insert_with(Vec::new), &method, &args, &span);
+            (outcome, data_type.clone())
+        };
+
+        let (outcome, map_type) = node.scope.clone().unwrap().borrow_mut().with_variable_definition(name, call).unwrap();
+        Ok(map_outcome(outcome?, map_type))
+    }
+
+    // The map variable a method is called on, when it holds a map that's already worked out
+    fn map_variable(&self, node: &AST) -> Option<String> {
+        let left = node.dot_left.as_ref()?;
+
+        if left.ast_type != Ast_Type::AST_VARIABLE || node.dot_right.as_ref()?.ast_type != Ast_Type::AST_FUNCTION_CALL {
+            return None;
+        }
+
+        let name = left.variable_name.clone()?;
+        let found = node.scope.as_ref()?.borrow_mut().with_variable_definition(&name, |def| evaluated_map(def));
+        (found == Some(true)).then_some(name)
+    }
+
+    pub fn visit_import(&mut self, node: &mut AST) -> IronResult<AST> {
         let lib = node.variable_name.as_ref().unwrap().clone();
         let scope = node.scope.clone().expect("Import node missing scope"); 
 
@@ -311,17 +539,10 @@ impl Visitor {
 
             scope.borrow_mut().update_import(import_wrapper.clone());
 
-            return AST::new(Ast_Type::AST_NOOP);
+            return Ok(AST::new(Ast_Type::AST_NOOP));
         }
 
-        let path = format!("examples/lib/{}.steel", lib);
-        let contents = std::fs::read_to_string(&path)
-            .unwrap_or_else(|_| panic!("Library `{}` not found at path {}", lib, path));
-
-        let mut lexer = Lexer::new(&contents);
-        let mut parser = Parser::new(&mut lexer, scope.clone());
-
-        let mut lib_ast = parser.parse();
+        let mut lib_ast = crate::load_library(&lib, &node.span, scope.clone())?;
         lib_ast.scope = Some(scope.clone()); 
 
         let mut import_wrapper = AST::new(Ast_Type::AST_IMPORT);
@@ -333,10 +554,10 @@ impl Visitor {
 
         node.scope = Some(scope.clone()); 
 
-        AST::new(Ast_Type::AST_NOOP)
+        Ok(AST::new(Ast_Type::AST_NOOP))
     }
     
-    pub fn visit_variable(&mut self, node: &mut AST) -> AST {
+    pub fn visit_variable(&mut self, node: &mut AST) -> IronResult<AST> {
         let name = node.variable_name.as_ref().unwrap();
         let scope = node.scope.as_ref().unwrap();
 
@@ -347,27 +568,71 @@ impl Visitor {
             dummy.is_builtin = import.is_builtin;
             dummy.imported_ast = import.imported_ast.clone();
 
-            return dummy;
+            return Ok(dummy);
         }
 
         let var_def = scope.borrow().get_variable_definition(name)
-            .unwrap_or_else(|| panic!("Undefined variable: {}", name));
+            .ok_or_else(|| IronError::NameError(format!("Undefined variable: {}", name), node.span.clone()))?;
 
         match var_def.ast_type {
             Ast_Type::AST_VARIABLE_DEF => {
                 if let Some(val) = &var_def.variable_definition_value {
-                    return self.visit(&mut *val.clone());
+                    let value = self.visit(&mut val.clone())?;
+                    Ok(declared(&var_def, value))
                 } else {
-                    panic!("Variable '{}' has no value", name);
+                    Err(IronError::RuntimeError(format!("Variable '{}' has no value", name), node.span.clone()))
                 }
             }
-            Ast_Type::AST_ARRAY_DEF => var_def.clone(),
-            Ast_Type::AST_INT | Ast_Type::AST_FLOAT | Ast_Type::AST_BOOL => var_def.clone(),
-            _ => panic!("Unknown variable type '{}'", name),
+            Ast_Type::AST_ARRAY_DEF => self.array_value(&var_def),
+            Ast_Type::AST_CLASS_INSTANCE => Ok(var_def.clone()),
+            Ast_Type::AST_INT | Ast_Type::AST_FLOAT | Ast_Type::AST_BOOL => Ok(var_def.clone()),
+            // P b = a; and P c = make(); keep the term they were declared with, under the class they were declared as
+            _ if var_def.class_name.is_some() => Ok(var_def.clone()),
+            _ => Err(IronError::RuntimeError(format!("Unknown variable type '{}'", name), node.span.clone())),
         }
     }
+
+    // An array as a value, with its elements worked out so it no longer depends on where it came from
+    fn array_value(&mut self, def: &AST) -> IronResult<AST> {
+        let items = def.array_elements.clone().unwrap_or_default()
+            .into_iter()
+            .map(|mut item| self.visit(&mut item))
+            .collect::<IronResult<Vec<_>>>()?;
+
+        let mut value = AST::new(Ast_Type::AST_ARRAY_DEF);
+        value.array_elements = Some(items.iter().map(|item| arrays::stored(&def.data_type, item)).collect());
+        value.data_type = def.data_type.clone();
+        Ok(value)
+    }
     
-    pub fn visit_function_call(&mut self, node: &mut AST) -> AST {
+    // A map as a value, a key given twice keeps the last value
+    fn map_value(&mut self, def: &AST) -> IronResult<AST> {
+        if def.map_evaluated {
+            return Ok(def.clone());
+        }
+
+        let mut keys = vec![];
+        let mut values = vec![];
+
+        for (key, value) in def.map_keys.clone().unwrap_or_default().iter_mut().zip(def.map_values.clone().unwrap_or_default().iter_mut()) {
+            let key = self.visit(key)?;
+            let value = self.visit(value)?;
+            let value = match &def.data_type {
+                Data_Type::MAP(_, element) => arrays::stored(element, &value),
+                _ => value,
+            };
+            maps::set(&mut keys, &mut values, key, value);
+        }
+
+        let mut value = AST::new(Ast_Type::AST_MAP_DEF);
+        value.map_keys = Some(keys);
+        value.map_values = Some(values);
+        value.map_evaluated = true;
+        value.data_type = def.data_type.clone();
+        Ok(value)
+    }
+
+    pub fn visit_function_call(&mut self, node: &mut AST) -> IronResult<AST> {
         let name = node.function_call_name.as_ref().expect("Missing function name");
 
         let empty_vec = &vec![];
@@ -376,28 +641,28 @@ impl Visitor {
         let mut evaluated_args = args_vec
             .into_iter()
             .map(|mut arg| self.visit(&mut arg))
-            .collect::<Vec<_>>();
+            .collect::<IronResult<Vec<_>>>()?;
 
 
         if let Some(f) = self.builtins.get(name) {
-            return f(&evaluated_args);
+            return f(&evaluated_args).map_err(|e| e.with_span(&node.span));
         }
 
         let def_scope = node.scope.as_ref().expect("Function call missing scope");
         let def = def_scope
             .borrow()
             .get_function_definition(name)
-            .unwrap_or_else(|| panic!("Function '{}' not defined", name));
+            .ok_or_else(|| IronError::NameError(format!("Function '{}' not defined", name), node.span.clone()))?;
 
         let params = def.function_definition_args.as_ref().unwrap_or(empty_vec);
 
         if evaluated_args.len() != params.len() {
-            panic!(
+            return Err(IronError::TypeError(format!(
                 "Function {} expected {} arguments, got {}",
                 name,
                 params.len(),
                 evaluated_args.len()
-            );
+            ), node.span.clone()));
         }
 
         let func_scope = def.scope.as_ref().expect("Function def missing scope");
@@ -406,51 +671,89 @@ impl Visitor {
         for (param, arg) in params.iter().zip(evaluated_args.iter()) {
             let expected = param.variable_type.as_ref().unwrap();
 
-            if param.variable_type.as_ref() != Some(&arg.data_type) && !(expected == &Data_Type::FLOAT && arg.data_type == Data_Type::INT){
-                panic!(
-                    "Function {} argument type mismatch: expected {:?}, got {:?}",
-                    name, param.variable_type, arg.data_type
-                );
+            let arg = arrays::stored(expected, arg);
+            let got = value_data_type(&arg);
+
+            if param.variable_type.as_ref() != Some(&got) && !(expected == &Data_Type::FLOAT && got == Data_Type::INT){
+                return Err(IronError::TypeError(format!(
+                    "Function {} argument type mismatch: expected {}, got {}",
+                    name, expected.name(), got.name()
+                ), node.span.clone()));
             }
 
-            let mut value = arg.clone();
+            let var_def = binding(param, arg, &new_scope);
 
-            value.scope = Some(new_scope.clone()); 
+            new_scope.borrow_mut().add_variable_definition(var_def);
+        }
 
-            let mut var_def = AST::new(Ast_Type::AST_VARIABLE_DEF);
-            var_def.variable_definition_variable_name = param.variable_definition_variable_name.clone(); 
-            var_def.variable_definition_value = Some(Box::new(value));
-            var_def.variable_type = param.variable_type.clone();
-            var_def.scope = Some(new_scope.clone());
+        self.call_body(&def, new_scope, &node.span)
+    }
 
-            new_scope.borrow_mut().add_variable_definition(var_def);
+    // Runs a function's body in the scope its arguments were bound in
+    fn call_body(&mut self, def: &AST, scope: SharedScope, span: &Option<Span>) -> IronResult<AST> {
+        if self.depth == MAX_DEPTH {
+            return Err(IronError::RuntimeError(format!("Stack overflow, calls nested more than {} deep", MAX_DEPTH), span.clone()));
         }
 
         let mut body = def.function_definition_body.as_ref().expect("Missing body").clone();
-        self.set_scope_recursively(&mut body, new_scope.clone());
+        self.set_scope_recursively(&mut body, scope);
 
+        self.depth += 1;
         let result = self.visit(&mut body);
+        self.depth -= 1;
+
+        self.finish_call(def, result?)
+    }
+
+    // Checks what the body handed back against the declared return type, if there is one
+    fn finish_call(&self, def: &AST, result: AST) -> IronResult<AST> {
+        let returned = result.ast_type == Ast_Type::AST_RETURN;
+
+        let mut result = result;
+        if let (Some(expected), Some(value)) = (def.function_return_type.as_ref(), result.return_value.as_mut()) {
+            **value = arrays::stored(expected, value);
+        }
+
+        if let Some(expected) = def.function_return_type.as_ref() {
+            let name = def.function_definition_name.as_ref().unwrap();
 
-        if result.ast_type == Ast_Type::AST_RETURN {
+            if !returned && *expected != Data_Type::VOID {
+                return Err(IronError::TypeError(format!(
+                    "Function {} should return {} but ended without a return",
+                    name, expected.name()
+                ), def.span.clone()));
+            }
+
+            let value = result.return_value.as_deref();
+
+            if !returns_type(expected, value) {
+                return Err(IronError::TypeError(format!(
+                    "Function {} should return {}, got {}",
+                    name, expected.name(), value.map(value_type_name).unwrap_or("void")
+                ), result.span.clone()));
+            }
+        }
+
+        if returned {
             if let Some(value) = result.return_value {
-                return *value;
+                return Ok(*value);
             }
             else {
-                return result; 
+                return Ok(result); 
             }
         }
 
-        AST::new(Ast_Type::AST_NOOP)
+        Ok(AST::new(Ast_Type::AST_NOOP))
     }
 
-    pub fn visit_if(&mut self, node: &mut AST) -> AST {
-        let condition = self.visit(node.if_condition.as_mut().expect("No if condition"));
+    pub fn visit_if(&mut self, node: &mut AST) -> IronResult<AST> {
+        let condition = self.visit(node.if_condition.as_mut().expect("No if condition"))?;
 
         let is_true = match condition.ast_type {
             Ast_Type::AST_BOOL => condition.bool_value.unwrap_or(false),
             Ast_Type::AST_INT => condition.int_value.unwrap_or(0) != 0,
             Ast_Type::AST_FLOAT => condition.float_value.unwrap_or(0.0) != 0.0,
-            _ => panic!("Invalid type for if statement"),
+            _ => return Err(IronError::TypeError("Invalid type for if statement".to_string(), node.if_condition.as_ref().unwrap().span.clone())),
         };
 
         let result = if is_true {
@@ -460,17 +763,80 @@ impl Visitor {
             self.visit(e)
         }
         else {
-            AST::new(Ast_Type::AST_NOOP)
+            Ok(AST::new(Ast_Type::AST_NOOP))
         };
 
         result
     }
     
-    pub fn call_library_function(&mut self,module: &str,function: &str,args: Vec<AST>,scope: &Option<SharedScope>) -> AST {
+    // Only the side that's picked is worked out
+    pub fn visit_ternary(&mut self, node: &mut AST) -> IronResult<AST> {
+        let condition = self.visit(node.if_condition.as_mut().expect("No ternary condition"))?;
+
+        let is_true = match condition.ast_type {
+            Ast_Type::AST_BOOL => condition.bool_value.unwrap_or(false),
+            Ast_Type::AST_INT => condition.int_value.unwrap_or(0) != 0,
+            Ast_Type::AST_FLOAT => condition.float_value.unwrap_or(0.0) != 0.0,
+            _ => return Err(IronError::TypeError(format!("Condition must be a bool, int or float, got {}", value_type_name(&condition)), node.if_condition.as_ref().unwrap().span.clone())),
+        };
+
+        if is_true {
+            self.visit(node.if_body.as_mut().expect("Missing ternary value"))
+        }
+        else {
+            self.visit(node.else_body.as_mut().expect("Missing ternary value"))
+        }
+    }
+
+    pub fn visit_match(&mut self, node: &mut AST) -> IronResult<AST> {
+        let value = self.visit(node.match_value.as_mut().expect("Missing match value"))?;
+
+        for case in node.match_cases.iter_mut().flatten() {
+            let matched = match case.case_patterns.as_mut() {
+                Some(patterns) => {
+                    let mut matched = false;
+
+                    for pattern in patterns.iter_mut() {
+                        if self.matches(&value, pattern)? {
+                            matched = true;
+                            break;
+                        }
+                    }
+
+                    matched
+                }
+                None => true,
+            };
+
+            if matched {
+                return self.visit(case.case_body.as_mut().expect("Missing case body"));
+            }
+        }
+
+        Ok(AST::new(Ast_Type::AST_NOOP))
+    }
+
+    // Values are compared the way == compares them
+    fn matches(&mut self, value: &AST, pattern: &mut AST) -> IronResult<bool> {
+        if pattern.ast_type == Ast_Type::AST_CLASS_PATTERN {
+            return Ok(value.ast_type == Ast_Type::AST_CLASS_INSTANCE && value.class_name == pattern.class_name);
+        }
+
+        let mut test = AST::new(Ast_Type::AST_BINARY);
+        test.left = Some(Box::new(value.clone()));
+        test.right = Some(Box::new(pattern.clone()));
+        test.operator = Some(Types::TOKEN_EE);
+        test.scope = pattern.scope.clone();
+        test.span = pattern.span.clone();
+
+        Ok(self.visit(&mut test)?.bool_value == Some(true))
+    }
+
+    pub fn call_library_function(&mut self,module: &str,function: &str,args: Vec<AST>,scope: &Option<SharedScope>, span: &Option<Span>) -> IronResult<AST> {
         match module {
             "math" => match function {
                 "sqrt" => {
-                    let num = self.visit(&mut args[0].clone());
+                    let num = self.visit(&mut args[0].clone())?;
 
                     let mut result = AST::new(Ast_Type::AST_FLOAT);
                     result.float_init = Some(true);
@@ -479,13 +845,13 @@ impl Visitor {
                     result.float_value = Some(match num.ast_type {
                         Ast_Type::AST_FLOAT => num.float_value.unwrap().sqrt(),
                         Ast_Type::AST_INT => (num.int_value.unwrap() as f64).sqrt(),
-                        _ => panic!("sqrt() requires int or float"),
+                        _ => return Err(IronError::TypeError("sqrt() requires int or float".to_string(), span.clone())),
                     });
 
-                    return result;
+                    Ok(result)
                 }
                 "abs" => {
-                    let num = self.visit(&mut args[0].clone());
+                    let num = self.visit(&mut args[0].clone())?;
                     let mut result = AST::new(Ast_Type::AST_FLOAT);
                     result.float_init = Some(true);
                     result.data_type = Data_Type::FLOAT;
@@ -493,13 +859,13 @@ impl Visitor {
                     result.float_value = Some(match num.ast_type {
                         Ast_Type::AST_FLOAT => num.float_value.unwrap().abs(),
                         Ast_Type::AST_INT => (num.int_value.unwrap() as f64).abs(),
-                        _ => panic!("abs() requires int or float"),
+                        _ => return Err(IronError::TypeError("abs() requires int or float".to_string(), span.clone())),
                     });
 
-                    return result;
+                    Ok(result)
                 }
                 "floor" => {
-                    let num = self.visit(&mut args[0].clone());
+                    let num = self.visit(&mut args[0].clone())?;
                     let mut result = AST::new(Ast_Type::AST_FLOAT);
                     result.float_init = Some(true);
                     result.data_type = Data_Type::FLOAT;
@@ -507,18 +873,18 @@ impl Visitor {
                     result.float_value = Some(match num.ast_type {
                         Ast_Type::AST_FLOAT => num.float_value.unwrap().floor(),
                         Ast_Type::AST_INT => (num.int_value.unwrap() as f64).floor(),
-                        _ => panic!("Floor requires an integer or a float"),
+                        _ => return Err(IronError::TypeError("Floor requires an integer or a float".to_string(), span.clone())),
                     });
                     
-                    return result; 
+                    Ok(result)
                 }
-                _ => panic!("Function `{}` not found in <math>", function),
+                _ => Err(IronError::NameError(format!("Function `{}` not found in <math>", function), span.clone())),
             },
-            _ => panic!("Built-in library `{}` not implemented", module),
+            _ => Err(IronError::ImportError(format!("Built-in library `{}` not implemented", module), span.clone())),
         }
     }
 
-    pub fn visit_compound(&mut self, node: &mut AST) -> AST {
+    pub fn visit_compound(&mut self, node: &mut AST) -> IronResult<AST> {
         if let Some(compound) = node.compound_value.as_mut() {
             let scope = node.scope.as_mut().expect("Compound block missing scope");
 
@@ -533,29 +899,41 @@ impl Visitor {
                     self.set_scope_recursively(stmt, scope.clone());
 
                     if let Some(value_expr) = stmt.variable_definition_value.as_mut() {
-                        let evaluated = self.visit(value_expr);
-                        stmt.variable_definition_value = Some(Box::new(evaluated));
+                        let evaluated = self.visit(value_expr)?;
+
+                        // int[] xs = f(); holds an array, so it's stored like one
+                        if evaluated.ast_type == Ast_Type::AST_ARRAY_DEF {
+                            let def = binding(stmt, evaluated, scope);
+                            scope.borrow_mut().add_variable_definition(def);
+                            continue;
+                        }
+
+                        stmt.variable_definition_value = Some(Box::new(declared(stmt, evaluated)));
                     }
 
                     scope.borrow_mut().add_variable_definition(stmt.clone());
                 }
                 else if stmt.ast_type == Ast_Type::AST_ARRAY_DEF {                    
                     self.set_scope_recursively(stmt, scope.clone());
-                    scope.borrow_mut().add_variable_definition(stmt.clone()); 
+
+                    let mut def = self.array_value(stmt)?;
+                    def.array_name = stmt.array_name.clone();
+                    def.scope = Some(scope.clone());
+
+                    scope.borrow_mut().add_variable_definition(def); 
                 }
                 else {
-                    let result = self.visit(stmt);
-                    if result.ast_type == Ast_Type::AST_RETURN {
-                        return result;
-                    }
-                    else if result.ast_type == Ast_Type::AST_BREAK {
-                        return AST::new(Ast_Type::AST_BREAK); 
+                    let result = self.visit(stmt)?;
+
+                    // A return, break or continue stops the block here and goes up to whatever it's for
+                    if matches!(result.ast_type, Ast_Type::AST_RETURN | Ast_Type::AST_BREAK | Ast_Type::AST_CONTINUE) {
+                        return Ok(result);
                     }
                 }
             }
         }
 
-        AST::new(Ast_Type::AST_NOOP)
+        Ok(AST::new(Ast_Type::AST_NOOP))
     }
 
     pub fn set_scope_recursively(&self, node: &mut AST, scope: Rc<RefCell<Scope>>) {
@@ -614,6 +992,21 @@ impl Visitor {
         if let Some(body) = node.for_body.as_mut() {
             self.set_scope_recursively(body, scope.clone());
         }
+        if let Some(iterable) = node.for_iterable.as_mut() {
+            self.set_scope_recursively(iterable, scope.clone());
+        }
+        if let Some(value) = node.match_value.as_mut() {
+            self.set_scope_recursively(value, scope.clone());
+        }
+        for case in node.match_cases.iter_mut().flatten() {
+            self.set_scope_recursively(case, scope.clone());
+        }
+        for pattern in node.case_patterns.iter_mut().flatten() {
+            self.set_scope_recursively(pattern, scope.clone());
+        }
+        if let Some(body) = node.case_body.as_mut() {
+            self.set_scope_recursively(body, scope.clone());
+        }
 
         if let Some(cond) = node.if_condition.as_mut() {
             self.set_scope_recursively(cond, scope.clone());
@@ -639,9 +1032,27 @@ impl Visitor {
         if let Some(index) = node.array_index.as_mut() {
             self.set_scope_recursively(index, scope.clone());
         }
+        if let Some(keys) = node.map_keys.as_mut() {
+            for key in keys.iter_mut() {
+                self.set_scope_recursively(key, scope.clone());
+            }
+        }
+        if let Some(values) = node.map_values.as_mut() {
+            for value in values.iter_mut() {
+                self.set_scope_recursively(value, scope.clone());
+            }
+        }
         if let Some(value) = node.array_assign_value.as_mut() {
             self.set_scope_recursively(value, scope.clone());
         }
+        if let Some(end) = node.array_slice_end.as_mut() {
+            self.set_scope_recursively(end, scope.clone());
+        }
+        if let Some(path) = node.array_path.as_mut() {
+            for index in path.iter_mut() {
+                self.set_scope_recursively(index, scope.clone());
+            }
+        }
 
         if let Some(left) = node.dot_left.as_mut() {
             self.set_scope_recursively(left, scope.clone());
@@ -669,28 +1080,48 @@ impl Visitor {
         if let Some(imported) = node.imported_ast.as_mut() {
             self.set_scope_recursively(imported, scope.clone());
         }
+
+        if let Some(body) = node.try_body.as_mut() {
+            self.set_scope_recursively(body, scope.clone());
+        }
+        if let Some(body) = node.catch_body.as_mut() {
+            self.set_scope_recursively(body, scope.clone());
+        }
+        if let Some(body) = node.finally_body.as_mut() {
+            self.set_scope_recursively(body, scope.clone());
+        }
+        if let Some(value) = node.throw_value.as_mut() {
+            self.set_scope_recursively(value, scope.clone());
+        }
     }
 
-    pub fn visit_binary(&mut self, node: &mut AST) -> AST {
+    pub fn visit_binary(&mut self, node: &mut AST) -> IronResult<AST> {
         let op = node.operator.as_ref().expect("Missing operator");
 
-        let left_eval = self.visit(node.left.as_mut().expect("Missing left operand"));
-        let right_eval = self.visit(node.right.as_mut().expect("Missing right operand"));
+        let left_eval = self.visit(node.left.as_mut().expect("Missing left operand"))?;
 
-        // Handle boolean logic
+        // Handle boolean logic, the right side only runs when the left doesn't decide it
         if matches!(op, Types::TOKEN_OR | Types::TOKEN_AND) {
             let l_bool = match left_eval.ast_type {
                 Ast_Type::AST_BOOL => left_eval.bool_value.unwrap_or(false),
                 Ast_Type::AST_INT => left_eval.int_value.unwrap_or(0) != 0,
                 Ast_Type::AST_FLOAT => left_eval.float_value.unwrap_or(0.0) != 0.0,
-                _ => panic!("Invalid left operand type for boolean operation"),
+                _ => return Err(IronError::TypeError("Invalid left operand type for boolean operation".to_string(), node.left.as_ref().unwrap().span.clone())),
             };
 
+            if l_bool == (*op == Types::TOKEN_OR) {
+                let mut result_ast = AST::from_bool(l_bool);
+                result_ast.scope = Some(node.scope.clone().unwrap_or_else(|| Rc::new(RefCell::new(crate::scope::Scope::new()))));
+                return Ok(result_ast);
+            }
+
+            let right_eval = self.visit(node.right.as_mut().expect("Missing right operand"))?;
+
             let r_bool = match right_eval.ast_type {
                 Ast_Type::AST_BOOL => right_eval.bool_value.unwrap_or(false),
                 Ast_Type::AST_INT => right_eval.int_value.unwrap_or(0) != 0,
                 Ast_Type::AST_FLOAT => right_eval.float_value.unwrap_or(0.0) != 0.0,
-                _ => panic!("Invalid right operand type for boolean operation"),
+                _ => return Err(IronError::TypeError("Invalid right operand type for boolean operation".to_string(), node.right.as_ref().unwrap().span.clone())),
             };
 
             let result = match op {
@@ -701,43 +1132,90 @@ impl Visitor {
 
             let mut result_ast = AST::from_bool(result);
             result_ast.scope = Some(node.scope.clone().unwrap_or_else(|| Rc::new(RefCell::new(crate::scope::Scope::new()))));
-            return result_ast;
+            return Ok(result_ast);
         }
 
+        let right_eval = self.visit(node.right.as_mut().expect("Missing right operand"))?;
+
         if *op == Types::TOKEN_ADD {
             if let (Some(ls), Some(rs)) = (&left_eval.string_value, &right_eval.string_value) {
-                let mut n = AST::new(Ast_Type::AST_STRING);
-                n.string_value = Some(format!("{}{}", ls, rs));
+                let mut n = AST::from_string(&format!("{}{}", ls, rs));
                 n.scope = Some(node.scope.clone().unwrap_or_else(|| Rc::new(RefCell::new(crate::scope::Scope::new()))));
-                return n;
+                return Ok(n);
             }
         }
 
+        // Strings and chars are equal when their text is
+        if matches!(op, Types::TOKEN_EE | Types::TOKEN_NEQ) && left_eval.ast_type == Ast_Type::AST_STRING && right_eval.ast_type == Ast_Type::AST_STRING {
+            let same = left_eval.string_value == right_eval.string_value;
+
+            let mut b = AST::from_bool(if *op == Types::TOKEN_EE { same } else { !same });
+            b.scope = Some(node.scope.clone().unwrap_or_else(|| Rc::new(RefCell::new(crate::scope::Scope::new()))));
+            return Ok(b);
+        }
+
+        if matches!(op, Types::TOKEN_EE | Types::TOKEN_NEQ) && left_eval.ast_type == Ast_Type::AST_BOOL && right_eval.ast_type == Ast_Type::AST_BOOL {
+            let same = left_eval.bool_value == right_eval.bool_value;
+
+            let mut b = AST::from_bool(if *op == Types::TOKEN_EE { same } else { !same });
+            b.scope = Some(node.scope.clone().unwrap_or_else(|| Rc::new(RefCell::new(crate::scope::Scope::new()))));
+            return Ok(b);
+        }
+
+        // Two ints stay ints, compared exactly and with math that can't wrap around
+        if left_eval.ast_type == Ast_Type::AST_INT && right_eval.ast_type == Ast_Type::AST_INT {
+            let (l, r) = (left_eval.int_value.unwrap(), right_eval.int_value.unwrap());
+
+            let compared = match op {
+                Types::TOKEN_GREATER_THAN => Some(l > r),
+                Types::TOKEN_LESS_THAN => Some(l < r),
+                Types::TOKEN_LEQ => Some(l <= r),
+                Types::TOKEN_GEQ => Some(l >= r),
+                Types::TOKEN_EE => Some(l == r),
+                Types::TOKEN_NEQ => Some(l != r),
+                _ => None,
+            };
+
+            let mut result = match compared {
+                Some(b) => AST::from_bool(b),
+                None => AST::from_int(numbers::arithmetic(operator_symbol(op), l, r, &node.span)?),
+            };
+
+            result.scope = Some(node.scope.clone().unwrap_or_else(|| Rc::new(RefCell::new(crate::scope::Scope::new()))));
+            return Ok(result);
+        }
+
+        if numbers::is_bitwise(operator_symbol(op)) {
+            let other = if left_eval.ast_type == Ast_Type::AST_INT { &right_eval } else { &left_eval };
+            return Err(IronError::TypeError(format!("Cannot apply {} to {}", operator_symbol(op), value_type_name(other)), node.span.clone()));
+        }
+
         let l_val = match left_eval.ast_type {
             Ast_Type::AST_FLOAT => left_eval.float_value.unwrap(),
             Ast_Type::AST_INT => left_eval.int_value.unwrap() as f64,
-            _ => panic!("Invalid left operand type"),
+            _ => return Err(IronError::TypeError("Invalid left operand type".to_string(), node.left.as_ref().unwrap().span.clone())),
         };
 
         let r_val = match right_eval.ast_type {
             Ast_Type::AST_FLOAT => right_eval.float_value.unwrap(),
             Ast_Type::AST_INT => right_eval.int_value.unwrap() as f64,
-            _ => panic!("Invalid right operand type"),
+            _ => return Err(IronError::TypeError("Invalid right operand type".to_string(), node.right.as_ref().unwrap().span.clone())),
         };
 
         let cmp_result = match op {
             Types::TOKEN_GREATER_THAN => Some(l_val > r_val),
             Types::TOKEN_LESS_THAN => Some(l_val < r_val),
             Types::TOKEN_LEQ => Some(l_val <= r_val),
-            Types::TOKEN_GEQ => Some(l_val > r_val),
+            Types::TOKEN_GEQ => Some(l_val >= r_val),
             Types::TOKEN_EE => Some((l_val - r_val).abs() < 1e-8),
+            Types::TOKEN_NEQ => Some((l_val - r_val).abs() >= 1e-8),
             _ => None,
         };
 
         if let Some(bool_val) = cmp_result {
             let mut b = AST::from_bool(bool_val);
             b.scope = Some(node.scope.clone().unwrap_or_else(|| Rc::new(RefCell::new(crate::scope::Scope::new()))));
-            return b;
+            return Ok(b);
         }
 
         let result = match op {
@@ -747,193 +1225,238 @@ impl Visitor {
             Types::TOKEN_FSLASH => l_val / r_val,
             Types::TOKEN_PERCENT => l_val % r_val,
             Types::TOKEN_CARROT => l_val.powf(r_val), 
-            _ => panic!("Unknown operator"),
-        };
-
-        let result_type = if left_eval.float_init.unwrap_or(false)
-            || right_eval.float_init.unwrap_or(false)
-        {
-            Data_Type::FLOAT
-        } else {
-            Data_Type::INT
+            _ => return Err(IronError::RuntimeError("Unknown operator".to_string(), node.span.clone())),
         };
 
-        let mut result_node = match result_type {
-            Data_Type::FLOAT => {
-                let mut n = AST::new(Ast_Type::AST_FLOAT);
-                n.float_value = Some(result);
-                n.float_init = Some(true);
-                n.data_type = Data_Type::FLOAT;
-                n
-            }
-            Data_Type::INT => {
-                let mut n = AST::new(Ast_Type::AST_INT);
-                n.int_value = Some(result as i32);
-                n.int_init = Some(true);
-                n.data_type = Data_Type::INT;
-                n
-            }
-            _ => panic!("Unsupported result type"),
-        };
+        // One side is a float here, two ints were handled above
+        let mut result_node = AST::new(Ast_Type::AST_FLOAT);
+        result_node.float_value = Some(result);
+        result_node.float_init = Some(true);
+        result_node.data_type = Data_Type::FLOAT;
 
         result_node.scope = Some(node.scope.clone().unwrap_or_else(|| Rc::new(RefCell::new(crate::scope::Scope::new()))));
-        result_node
+        Ok(result_node)
     }
 
-    pub fn visit_return(&mut self, node: &mut AST) -> AST {
+    pub fn visit_return(&mut self, node: &mut AST) -> IronResult<AST> {
         if let Some(ret) = node.return_value.as_mut() {
-            let r = self.visit(ret);
+            let r = self.visit(ret)?;
             let mut return_node = AST::new(Ast_Type::AST_RETURN);
             return_node.return_value = Some(Box::new(r));
-            return return_node;
+            return_node.span = node.span.clone();
+            return Ok(return_node);
         }
 
-        AST::new(Ast_Type::AST_RETURN)
+        let mut return_node = AST::new(Ast_Type::AST_RETURN);
+        return_node.span = node.span.clone();
+        Ok(return_node)
     }
 
-    pub fn visit_reassign(&mut self, node: &mut AST) -> AST {
+    pub fn visit_reassign(&mut self, node: &mut AST) -> IronResult<AST> {
         let name = node.reassign_name.clone();
 
-        let new_value = self.visit(&mut *node.reassign_value.clone().as_mut().unwrap());
+        let new_value = self.visit(&mut *node.reassign_value.clone().as_mut().unwrap())?;
 
         let mut scope_ref = node.scope.clone().unwrap();
 
-        let original_value = scope_ref.borrow_mut().get_variable_definition(&name.clone().unwrap()).unwrap_or_else(|| panic!("Variable {} not defined", name.clone().unwrap()));
+        let original_value = scope_ref.borrow_mut().get_variable_definition(&name.clone().unwrap()).ok_or_else(|| IronError::NameError(format!("Variable {} not defined", name.clone().unwrap()), node.span.clone()))?;
+
+        let new_value = declared(&original_value, new_value);
 
         let mut updated_value = original_value.clone();
         updated_value.variable_definition_value = Some(Box::new(new_value.clone()));
 
-        scope_ref.borrow_mut().update_variable_definition(name.clone().unwrap(), updated_value);
+        // Arrays are values, the variable gets its own copy
+        if new_value.ast_type == Ast_Type::AST_ARRAY_DEF {
+            updated_value = AST::new(Ast_Type::AST_ARRAY_DEF);
+            updated_value.array_name = name.clone();
+            updated_value.array_elements = new_value.array_elements.clone();
+            updated_value.data_type = new_value.data_type.clone();
+            updated_value.scope = original_value.scope.clone();
+        }
+
+        scope_ref.borrow_mut().update_variable_definition(name.clone().unwrap(), updated_value).map_err(|e| e.with_span(&node.span))?;
 
-        new_value
+        Ok(new_value)
     }
 
-    pub fn visit_increment(&mut self, node: &mut AST) -> AST {
+    pub fn visit_increment(&mut self, node: &mut AST) -> IronResult<AST> {
         let name = node.reassign_name.clone().unwrap();
         let scope = node.scope.clone().unwrap();
 
-        let original_value = scope.borrow_mut().get_variable_definition(&name).unwrap_or_else(|| panic!("Variable {} not defined", name));
+        let original_value = scope.borrow_mut().get_variable_definition(&name).ok_or_else(|| IronError::NameError(format!("Variable {} not defined", name), node.span.clone()))?;
     
-        let mut val = original_value.variable_definition_value.clone().unwrap_or_else(|| panic!("Variable {} has no value", name));
+        let mut val = original_value.variable_definition_value.clone().ok_or_else(|| IronError::RuntimeError(format!("Variable {} has no value", name), node.span.clone()))?;
 
-        let mut eval = self.visit(&mut *val);
+        let mut eval = self.visit(&mut val)?;
+        let before = eval.clone();
 
         match eval.ast_type {
             Ast_Type::AST_INT => {
-                eval.int_value = Some(eval.int_value.unwrap() + 1);
+                eval.int_value = Some(numbers::arithmetic("+", eval.int_value.unwrap(), 1, &node.span)?);
                 eval.int_init = Some(true);
             }
             Ast_Type::AST_FLOAT => {
                 eval.float_value = Some(eval.float_value.unwrap() + 1.0);
                 eval.float_init = Some(true);
             }
-            _ => panic!("Cannot apply ++ to non-numeric type (only int/float supported)"),
+            _ => return Err(IronError::TypeError("Cannot apply ++ to non-numeric type (only int/float supported)".to_string(), node.span.clone())),
         };
 
         let mut updated = original_value.clone();
         updated.variable_definition_value = Some(Box::new(eval.clone()));
-        scope.borrow_mut().update_variable_definition(name.clone(), updated);
+        scope.borrow_mut().update_variable_definition(name.clone(), updated).map_err(|e| e.with_span(&node.span))?;
 
-        eval
+        Ok(if node.postfix { before } else { eval })
     }
 
-    pub fn visit_decrement(&mut self, node: &mut AST) -> AST {
+    pub fn visit_decrement(&mut self, node: &mut AST) -> IronResult<AST> {
         let name = node.reassign_name.clone().unwrap();
         let scope = node.scope.clone().unwrap();
 
-        let original_value = scope.borrow_mut().get_variable_definition(&name).unwrap_or_else(|| panic!("Variable {} not defined", name));
+        let original_value = scope.borrow_mut().get_variable_definition(&name).ok_or_else(|| IronError::NameError(format!("Variable {} not defined", name), node.span.clone()))?;
     
-        let mut val = original_value.variable_definition_value.clone().unwrap_or_else(|| panic!("Variable {} has no value", name));
+        let mut val = original_value.variable_definition_value.clone().ok_or_else(|| IronError::RuntimeError(format!("Variable {} has no value", name), node.span.clone()))?;
 
-        let mut eval = self.visit(&mut *val);
+        let mut eval = self.visit(&mut val)?;
+        let before = eval.clone();
 
         match eval.ast_type {
             Ast_Type::AST_INT => {
-                eval.int_value = Some(eval.int_value.unwrap() - 1);
+                eval.int_value = Some(numbers::arithmetic("-", eval.int_value.unwrap(), 1, &node.span)?);
                 eval.int_init = Some(true);
             }
             Ast_Type::AST_FLOAT => {
                 eval.float_value = Some(eval.float_value.unwrap() - 1.0);
                 eval.float_init = Some(true);
             }
-            _ => panic!("Cannot apply -- to non-numeric type (only int/float supported)"),
+            _ => return Err(IronError::TypeError("Cannot apply -- to non-numeric type (only int/float supported)".to_string(), node.span.clone())),
         };
 
         let mut updated = original_value.clone();
         updated.variable_definition_value = Some(Box::new(eval.clone()));
-        scope.borrow_mut().update_variable_definition(name.clone(), updated);
+        scope.borrow_mut().update_variable_definition(name.clone(), updated).map_err(|e| e.with_span(&node.span))?;
+
+        Ok(if node.postfix { before } else { eval })
+    }
+
+    // x += e, grid[i][j] *= 2 or p.x++, the indices are worked out once before the element is read
+    pub fn visit_compound_assign(&mut self, node: &mut AST) -> IronResult<AST> {
+        let mut target = *node.left.clone().unwrap();
+
+        // Once worked out an index is a literal, so reading and then storing doesn't run it again
+        let indices = target.array_index.iter_mut().map(|index| &mut **index).chain(target.array_path.iter_mut().flatten());
+        for index in indices {
+            let span = index.span.clone();
+            *index = AST { span, ..self.visit(index)? };
+        }
+
+        let before = self.visit(&mut target.clone())?;
+
+        let mut step = AST::new(Ast_Type::AST_BINARY);
+        step.left = Some(Box::new(before.clone()));
+        step.right = node.right.clone();
+        step.operator = node.operator.clone();
+        step.scope = node.scope.clone();
+        step.span = node.span.clone();
+
+        let after = self.visit(&mut step)?;
+
+        match target.ast_type {
+            Ast_Type::AST_VARIABLE => {
+                let mut store = AST::new(Ast_Type::AST_REASSIGN);
+                store.reassign_name = target.variable_name.clone();
+                store.reassign_value = Some(Box::new(after.clone()));
+                store.scope = node.scope.clone();
+                store.span = node.span.clone();
+                self.visit(&mut store)?;
+            }
+            Ast_Type::AST_ARRAY_ACCESS => {
+                target.array_assign_value = Some(Box::new(after.clone()));
+                self.visit(&mut target)?;
+            }
+            _ => {
+                target.reassign_value = Some(Box::new(after.clone()));
+                self.visit(&mut target)?;
+            }
+        }
 
-        eval
+        Ok(if node.postfix { before } else { after })
     }
 
-    pub fn visit_while(&mut self, node: &mut AST) -> AST {
+    pub fn visit_while(&mut self, node: &mut AST) -> IronResult<AST> {
         let cond_node = node.while_condition.as_mut().expect("Missing while condition");
         let body_node = node.while_body.as_mut().expect("Missing while body");
 
         loop {
-            let condition = self.visit(cond_node);
+            let condition = self.visit(cond_node)?;
 
             let is_true = match condition.ast_type {
                 Ast_Type::AST_BOOL => condition.bool_value.unwrap_or(false),
                 Ast_Type::AST_INT => condition.int_value.unwrap_or(0) != 0,
                 Ast_Type::AST_FLOAT => condition.float_value.unwrap_or(0.0) != 0.0,
-                _ => panic!("Invalid type for while condition"),
+                _ => return Err(IronError::TypeError("Invalid type for while condition".to_string(), cond_node.span.clone())),
             };
 
             if !is_true {
                 break;
             }
 
-            let result = self.visit(body_node);
+            let result = self.visit(body_node)?;
 
-            if result.ast_type == Ast_Type::AST_RETURN {
-                return result;  
-            }
-
-            if result.ast_type == Ast_Type::AST_BREAK {
-                return result; 
+            match flow(result, &node.loop_label) {
+                Flow::Next => {}
+                Flow::Stop => break,
+                Flow::Leave(result) => return Ok(*result),
             }
         }
 
-        AST::new(Ast_Type::AST_NOOP)
+        Ok(AST::new(Ast_Type::AST_NOOP))
     }
 
-    pub fn visit_unary(&mut self, node: &mut AST) -> AST {
+    pub fn visit_unary(&mut self, node: &mut AST) -> IronResult<AST> {
         let op = node.operator.as_ref().expect("unary operator missing");
-        let mut operand = self.visit(node.right.as_mut().expect("unary operand missing"));
+        let mut operand = self.visit(node.right.as_mut().expect("unary operand missing"))?;
 
         match op {
             Types::TOKEN_SUBTRACT => match operand.ast_type {
                 Ast_Type::AST_INT => {
                     operand.int_value = Some(-operand.int_value.unwrap());
-                    operand
+                    Ok(operand)
                 }
                 Ast_Type::AST_FLOAT => {
                     operand.float_value = Some(-operand.float_value.unwrap());
-                    operand
+                    Ok(operand)
                 }
-                _ => panic!("Unary minus only supports int and float"),
+                _ => Err(IronError::TypeError("Unary minus only supports int and float".to_string(), node.span.clone())),
             },
             Types::TOKEN_NOT => match operand.ast_type {
                 Ast_Type::AST_BOOL => {
                     operand.bool_value = Some(!operand.bool_value.unwrap());
-                    operand
+                    Ok(operand)
                 }
                 Ast_Type::AST_INT => {
                     operand.int_value = Some(if operand.int_value.unwrap() == 0 { 1 } else { 0 });
-                    operand
+                    Ok(operand)
                 }
                 Ast_Type::AST_FLOAT => {
                     operand.float_value = Some(if operand.float_value.unwrap() == 0.0 { 1.0 } else { 0.0 });
-                    operand
+                    Ok(operand)
+                }
+                _ => Err(IronError::TypeError("Unary not only supports bool, int, float".to_string(), node.span.clone())),
+            },
+            Types::TOKEN_TILDE => match operand.ast_type {
+                Ast_Type::AST_INT => {
+                    operand.int_value = Some(!operand.int_value.unwrap());
+                    Ok(operand)
                 }
-                _ => panic!("Unary not only supports bool, int, float"),
+                _ => Err(IronError::TypeError(format!("Cannot apply ~ to {}", value_type_name(&operand)), node.span.clone())),
             },
-            _ => panic!("Unknown unary operator {:?}", op),
+            _ => Err(IronError::RuntimeError(format!("Unknown unary operator {:?}", op), node.span.clone())),
         }
     }
 
-    pub fn visit_for(&mut self, node: &mut AST) -> AST {
+    pub fn visit_for(&mut self, node: &mut AST) -> IronResult<AST> {
         let loop_scope = Rc::new(RefCell::new(Scope::new_with_parent(node.scope.as_ref().unwrap().clone())));
         
         let mut init = node.for_init.as_ref().unwrap().clone(); 
@@ -944,7 +1467,7 @@ impl Visitor {
 
             let mut value = *init.variable_definition_value.clone().expect("Missing value");
             self.set_scope_recursively(&mut value, loop_scope.clone());
-            let mut eval = self.visit(&mut value);
+            let mut eval = self.visit(&mut value)?;
 
             init.variable_definition_value = Some(Box::new(eval.clone()));
 
@@ -958,59 +1481,686 @@ impl Visitor {
         while {
             let mut cond = *node.for_condition.clone().unwrap(); 
             self.set_scope_recursively(&mut cond, loop_scope.clone()); 
-            let condition = self.visit(&mut cond);
+            let condition = self.visit(&mut cond)?;
             
             match condition.ast_type {
                 Ast_Type::AST_BOOL => condition.bool_value.unwrap_or(false),
                 Ast_Type::AST_INT => condition.int_value.unwrap_or(0) != 0,
                 Ast_Type::AST_FLOAT => condition.float_value.unwrap_or(0.0) != 0.0,
-                _ => panic!("For condition not a boolean"), 
+                _ => return Err(IronError::TypeError("For condition not a boolean".to_string(), cond.span.clone())), 
             }
         } {
             let mut body = *node.for_body.clone().unwrap(); 
             self.set_scope_recursively(&mut body, loop_scope.clone()); 
             
-            let result = self.visit(&mut body);
-            
-            if result.ast_type == Ast_Type::AST_RETURN {
-                break; 
+            let result = self.visit(&mut body)?;
+
+            // continue still runs the increment
+            match flow(result, &node.loop_label) {
+                Flow::Next => {}
+                Flow::Stop => break,
+                Flow::Leave(result) => return Ok(*result),
             }
-            
+
             let mut increment = *node.for_increment.clone().unwrap();
             self.set_scope_recursively(&mut increment, loop_scope.clone()); 
             
-            self.visit(&mut increment);
+            self.visit(&mut increment)?;
         }
 
-        AST::new(Ast_Type::AST_NOOP)
+        Ok(AST::new(Ast_Type::AST_NOOP))
     }
 
-    pub fn visit_array_access(&mut self, node: &mut AST) -> AST {
+    pub fn visit_for_each(&mut self, node: &mut AST) -> IronResult<AST> {
+        let variable = node.for_init.as_ref().unwrap().clone();
+        let iterable = node.for_iterable.as_mut().unwrap();
+        let span = iterable.span.clone();
+
+        // Ranges count as they go, everything else is walked as it was when the loop started
+        let values: Box<dyn Iterator<Item = AST>> = if iterable.ast_type == Ast_Type::AST_RANGE {
+            let mut bounds = vec![];
+
+            for arg in iterable.function_call_args.as_mut().unwrap().iter_mut() {
+                match self.visit(arg)? {
+                    n if n.ast_type == Ast_Type::AST_INT => bounds.push(n.int_value.unwrap()),
+                    n => return Err(IronError::TypeError(format!("range takes ints, got {}", value_type_name(&n)), span)),
+                }
+            }
+
+            let (start, end, step) = (bounds[0], bounds[1], bounds[2]);
+
+            if step == 0 {
+                return Err(IronError::RuntimeError("range step can't be 0".to_string(), span));
+            }
+
+            Box::new(std::iter::successors(Some(start), move |i| i.checked_add(step))
+                .take_while(move |i| if step > 0 { *i < end } else { *i > end })
+                .map(AST::from_int))
+        }
+        else {
+            let value = self.visit(iterable)?;
+
+            match value.ast_type {
+                Ast_Type::AST_ARRAY_DEF => Box::new(value.array_elements.unwrap_or_default().into_iter()),
+                Ast_Type::AST_MAP_DEF => Box::new(value.map_keys.unwrap_or_default().into_iter()),
+                Ast_Type::AST_STRING => {
+                    let chars: Vec<char> = value.string_value.unwrap_or_default().chars().collect();
+                    Box::new(chars.into_iter().map(AST::from_char))
+                }
+                _ => return Err(IronError::TypeError(format!("Can't loop over {}", value_type_name(&value)), span)),
+            }
+        };
+
+        for value in values {
+            let loop_scope = Rc::new(RefCell::new(Scope::new_with_parent(node.scope.clone().unwrap())));
+            let def = binding(&variable, value, &loop_scope);
+            loop_scope.borrow_mut().add_variable_definition(def);
+
+            let mut body = *node.for_body.clone().unwrap();
+            self.set_scope_recursively(&mut body, loop_scope.clone());
+
+            let result = self.visit(&mut body)?;
+
+            match flow(result, &node.loop_label) {
+                Flow::Next => {}
+                Flow::Stop => break,
+                Flow::Leave(result) => return Ok(*result),
+            }
+        }
+
+        Ok(AST::new(Ast_Type::AST_NOOP))
+    }
+
+    pub fn visit_try(&mut self, node: &mut AST) -> IronResult<AST> {
+        let mut outcome = self.visit(node.try_body.as_mut().expect("Missing try body"));
+
+        if let Err(e) = &outcome {
+            let kind = node.catch_type.clone().unwrap_or_default();
+
+            if node.catch_body.is_some() && (kind == "Error" || kind == e.kind()) {
+                let catch_scope = Rc::new(RefCell::new(Scope::new_with_parent(node.scope.clone().unwrap())));
+
+                let mut def = AST::new(Ast_Type::AST_VARIABLE_DEF);
+                def.variable_definition_variable_name = node.catch_name.clone();
+                def.variable_definition_value = Some(Box::new(AST::from_error(e.kind(), e.message())));
+                def.variable_type = Some(Data_Type::CUSTOM("Error".to_string()));
+                def.scope = Some(catch_scope.clone());
+
+                catch_scope.borrow_mut().add_variable_definition(def);
+
+                let mut body = *node.catch_body.clone().unwrap();
+                self.set_scope_recursively(&mut body, catch_scope.clone());
+
+                outcome = self.visit(&mut body);
+            }
+        }
+
+        // finally runs no matter what, and a return, break or continue inside it wins
+        if let Some(finally) = node.finally_body.as_mut() {
+            let result = self.visit(finally)?;
+
+            if matches!(result.ast_type, Ast_Type::AST_RETURN | Ast_Type::AST_BREAK | Ast_Type::AST_CONTINUE) {
+                return Ok(result);
+            }
+        }
+
+        outcome
+    }
+
+    pub fn visit_throw(&mut self, node: &mut AST) -> IronResult<AST> {
+        let value = self.visit(node.throw_value.as_mut().expect("Missing throw value"))?;
+
+        match value.ast_type {
+            Ast_Type::AST_ERROR => Err(IronError::from_kind(
+                value.error_kind.as_deref().unwrap_or("Error"),
+                value.string_value.clone().unwrap_or_default(),
+                node.span.clone(),
+            )),
+            Ast_Type::AST_STRING => Err(IronError::UserError(value.string_value.clone().unwrap_or_default(), node.span.clone())),
+            _ => Err(IronError::TypeError("Can only throw a string or a caught error".to_string(), node.span.clone())),
+        }
+    }
+
+    pub fn visit_array_access(&mut self, node: &mut AST) -> IronResult<AST> {
+        if let Some(field) = node.array_field.clone() {
+            return self.visit_field_access(node, *field);
+        }
+
         let name = node.array_name.clone().unwrap();
-        let index = self.visit(&mut *node.array_index.as_mut().unwrap());
+        let index = self.visit(&mut *node.array_index.as_mut().unwrap())?;
         let scope = node.scope.clone().unwrap();
-        let def = scope.borrow().get_variable_definition(&name).unwrap_or_else(|| panic!("Array {} not defined", name.clone()));
-        let value = node.array_assign_value.as_ref().unwrap(); 
+
+        if scope.borrow_mut().with_variable_definition(&name, |def| evaluated_map(def)) == Some(true) {
+            return self.visit_map_access(node, &scope, index);
+        }
+
+        let mut def = scope.borrow().get_variable_definition(&name).ok_or_else(|| IronError::NameError(format!("Array {} not defined", name.clone()), node.span.clone()))?;
+        let span = node.array_index.as_ref().unwrap().span.clone();
+
+        // A field like int[] items = start; is a plain definition until something changes it, and maps always are
+        let value = match def.ast_type {
+            Ast_Type::AST_VARIABLE_DEF => match def.variable_definition_value.as_ref() {
+                Some(value) => Some(self.visit(&mut value.clone())?),
+                None => return Err(IronError::RuntimeError(format!("Variable '{}' has no value", name), node.span.clone())),
+            },
+            _ => None,
+        };
+
+        // A field's map is worked out when it's read, storing into it keeps what it worked out to
+        if let Some(map) = value.clone().filter(|v| v.ast_type == Ast_Type::AST_MAP_DEF) {
+            if node.array_assign_value.is_none() && node.array_slice_end.is_none() {
+                let value = maps::get(map.map_keys.as_deref().unwrap_or(&[]), map.map_values.as_deref().unwrap_or(&[]), &index, &name, &span)?;
+                return self.visit_path(node, value);
+            }
+
+            let stored = AST { variable_definition_value: Some(Box::new(map)), ..def };
+            scope.borrow_mut().update_variable_definition(name, stored).map_err(|e| e.with_span(&node.span))?;
+            return self.visit_map_access(node, &scope, index);
+        }
 
         let idx = match index.ast_type {
             Ast_Type::AST_INT => index.int_value.unwrap(),
-            _ => panic!("Array index must be an int"),
+            _ => return Err(IronError::TypeError("Array index must be an int".to_string(), span)),
         };
 
-        let mut elements = def.array_elements.clone().unwrap();
+        if let Some(value) = value {
+            if value.ast_type != Ast_Type::AST_ARRAY_DEF {
+                let value = self.visit_string_access(node, &def, idx)?;
+                return self.visit_path(node, value);
+            }
+
+            def = AST { array_name: Some(name.clone()), scope: def.scope.clone(), ..value };
+        }
 
-        if idx < 0 || (idx as usize) >= elements.len() {
-            panic!("index {} out of bounds for array {}", idx.clone(), name.clone());
+        if node.array_slice_end.is_some() {
+            return Err(IronError::TypeError(format!("{} is not a string", name), span));
         }
 
-        elements[idx as usize] = *value.clone(); 
+        // A read goes one index at a time, so grid[i] is checked before [j] is worked out
+        let Some(value) = node.array_assign_value.clone() else {
+            let elements = def.array_elements.clone().unwrap();
+
+            if idx < 0 || (idx as usize) >= elements.len() {
+                return Err(IronError::IndexError(format!("index {} out of bounds for array {}", idx.clone(), name.clone()), span));
+            }
+
+            let value = self.visit(&mut elements[idx as usize].clone())?;
+            return self.visit_path(node, value);
+        };
+
+        let mut indices = vec![index];
+
+        for index in node.array_path.iter_mut().flatten() {
+            indices.push(self.visit(index)?);
+        }
+
+        let value = self.visit(&mut value.clone())?;
+
+        let mut new_def = self.array_value(&def)?;
+        store_at(&mut new_def, &indices, value.clone(), &name, &span)?;
 
-        let mut new_def = AST::new(Ast_Type::AST_ARRAY_DEF);
         new_def.array_name = Some(name.clone());
-        new_def.array_elements = Some(elements.clone());
-        
-        scope.borrow_mut().update_variable_definition(name.clone(), new_def); 
+        new_def.scope = def.scope.clone();
+
+        scope.borrow_mut().update_variable_definition(name.clone(), new_def).map_err(|e| e.with_span(&node.span))?; 
+
+        Ok(value)
+    }
+
+    // m[key] reads or stores a key of the map m where it's kept, anything after that goes into the value
+    fn visit_map_access(&mut self, node: &mut AST, scope: &SharedScope, key: AST) -> IronResult<AST> {
+        let name = node.array_name.clone().unwrap();
+        let span = node.array_index.as_ref().unwrap().span.clone();
+
+        if node.array_slice_end.is_some() {
+            return Err(IronError::TypeError(format!("{} is not a string", name), span));
+        }
+
+        let Some(mut value) = node.array_assign_value.clone() else {
+            let read = |def: &mut AST| {
+                let map = def.variable_definition_value.as_ref().unwrap();
+                maps::get(map.map_keys.as_deref().unwrap_or(&[]), map.map_values.as_deref().unwrap_or(&[]), &key, &name, &span)
+            };
+
+            let value = scope.borrow_mut().with_variable_definition(&name, read).unwrap()?;
+            return self.visit_path(node, value);
+        };
+
+        let mut indices = vec![key];
+
+        for index in node.array_path.iter_mut().flatten() {
+            indices.push(self.visit(index)?);
+        }
+
+        let value = self.visit(&mut value)?;
+
+        let store = |def: &mut AST| store_at(def.variable_definition_value.as_mut().unwrap(), &indices, value.clone(), &name, &span);
+        scope.borrow_mut().with_variable_definition(&name, store).unwrap()?;
+
+        Ok(value)
+    }
+
+    // p.items[i] indexes what the field holds, a store changes a copy and writes it back to the field
+    fn visit_field_access(&mut self, node: &mut AST, field: AST) -> IronResult<AST> {
+        let name = node.array_name.clone().unwrap();
+        let span = node.array_index.as_ref().unwrap().span.clone();
+
+        let mut target = self.visit(&mut field.clone())?;
+        let index = self.visit(&mut *node.array_index.as_mut().unwrap())?;
+
+        let Some(mut value) = node.array_assign_value.clone() else {
+            let value = element(target, &index, &name, &span)?;
+            return self.visit_path(node, value);
+        };
+
+        let mut indices = vec![index];
+
+        for index in node.array_path.iter_mut().flatten() {
+            indices.push(self.visit(index)?);
+        }
+
+        let value = self.visit(&mut value)?;
+        store_at(&mut target, &indices, value.clone(), &name, &span)?;
+
+        let mut store = field;
+        store.reassign_value = Some(Box::new(target));
+        self.visit(&mut store)?;
+
+        Ok(value)
+    }
+
+    // The [j] in grid[i][j], value is what grid[i] gave
+    fn visit_path(&mut self, node: &mut AST, value: AST) -> IronResult<AST> {
+        let name = node.array_name.clone().unwrap();
+        let mut value = value;
+
+        for index in node.array_path.iter_mut().flatten() {
+            let span = index.span.clone();
+            let index = self.visit(index)?;
+            value = element(value, &index, &name, &span)?;
+        }
+
+        Ok(value)
+    }
+
+    pub fn visit_array_fill(&mut self, node: &mut AST) -> IronResult<AST> {
+        let mut args = node.function_call_args.clone().unwrap();
+        let size = self.visit(&mut args[0])?;
+        let value = self.visit(&mut args[1])?;
+
+        let element = node.data_type.clone();
+        let items = arrays::fill(&element, |v| returns_type(&element, Some(v)), &size, value, &node.span)?;
+
+        let mut array = AST::new(Ast_Type::AST_ARRAY_DEF);
+        array.array_elements = Some(items);
+        array.data_type = element;
+        Ok(array)
+    }
+
+    // s[i] gives a char and s[a:b] a new string, strings can't be changed in place
+    fn visit_string_access(&mut self, node: &mut AST, def: &AST, idx: i64) -> IronResult<AST> {
+        let name = node.array_name.clone().unwrap();
+        let span = node.array_index.as_ref().unwrap().span.clone();
+
+        let value = match def.variable_definition_value.as_ref() {
+            Some(value) => self.visit(&mut value.clone())?,
+            None => return Err(IronError::RuntimeError(format!("Variable '{}' has no value", name), node.span.clone())),
+        };
+
+        if value.ast_type != Ast_Type::AST_STRING || node.array_assign_value.is_some() {
+            return Err(IronError::TypeError(format!("{} is not an array", name), span));
+        }
+
+        let s = value.string_value.as_ref().unwrap();
+
+        let Some(end) = node.array_slice_end.as_mut() else {
+            return Ok(AST::from_char(strings::index(s, idx, &name, &span)?));
+        };
+
+        let end = match self.visit(end)? {
+            e if e.ast_type == Ast_Type::AST_INT => e.int_value.unwrap(),
+            _ => return Err(IronError::TypeError("Array index must be an int".to_string(), span)),
+        };
+
+        Ok(AST::from_string(&strings::slice(s, idx, end, &name, &span)?))
+    }
+}
+
+// Classes evaluate to a NOOP in the visitor, so any NOOP passes for a class type
+// What a loop does after its body ran
+enum Flow {
+    Next,            // on to the next time around
+    Stop,            // a break for this loop
+    Leave(Box<AST>), // a return, or a break or continue for a loop further out
+}
+
+// A break or continue without a label is for the innermost loop, one with a label is for the loop with that label
+fn flow(result: AST, label: &Option<String>) -> Flow {
+    let ours = result.loop_label.is_none() || result.loop_label == *label;
+
+    match result.ast_type {
+        Ast_Type::AST_BREAK if ours => Flow::Stop,
+        Ast_Type::AST_CONTINUE if ours => Flow::Next,
+        Ast_Type::AST_RETURN | Ast_Type::AST_BREAK | Ast_Type::AST_CONTINUE => Flow::Leave(Box::new(result)),
+        _ => Flow::Next,
+    }
+}
+
+fn returns_type(expected: &Data_Type, value: Option<&AST>) -> bool {
+    let Some(value) = value else {
+        return *expected == Data_Type::VOID;
+    };
+
+    match expected {
+        Data_Type::INT => value.ast_type == Ast_Type::AST_INT,
+        Data_Type::FLOAT => matches!(value.ast_type, Ast_Type::AST_FLOAT | Ast_Type::AST_INT),
+        Data_Type::STR => value.ast_type == Ast_Type::AST_STRING && value.data_type != Data_Type::CHAR,
+        Data_Type::CHAR => value.ast_type == Ast_Type::AST_STRING && value.data_type == Data_Type::CHAR,
+        Data_Type::BOOL => value.ast_type == Ast_Type::AST_BOOL,
+        Data_Type::ARRAY(element) => value.ast_type == Ast_Type::AST_ARRAY_DEF && value.data_type == **element,
+        Data_Type::MAP(..) => value.ast_type == Ast_Type::AST_MAP_DEF && value.data_type == *expected,
+        Data_Type::CUSTOM(name) if name == "Error" => value.ast_type == Ast_Type::AST_ERROR,
+        Data_Type::CUSTOM(_) => matches!(value.ast_type, Ast_Type::AST_CLASS_INSTANCE | Ast_Type::AST_NOOP),
+        _ => false,
+    }
+}
+
+// The p.items a method was called on, so what the method changes goes back into the field
+fn field_of(left: &AST) -> Option<AST> {
+    match left.dot_right.as_deref() {
+        Some(right) if right.ast_type == Ast_Type::AST_VARIABLE => Some(left.clone()),
+        _ => None,
+    }
+}
+
+// A definition holding a map that's already worked out, so it can be used where it's kept
+fn evaluated_map(def: &AST) -> bool {
+    def.variable_definition_value.as_ref().is_some_and(|v| v.ast_type == Ast_Type::AST_MAP_DEF && v.map_evaluated)
+}
+
+// What a map method hands back as a value
+fn map_outcome(outcome: maps::Outcome<AST>, map_type: Data_Type) -> AST {
+    let Data_Type::MAP(key_type, value_type) = map_type else {
+        unreachable!("Map without a map type");
+    };
+
+    let array = |items: Vec<AST>, element: Box<Data_Type>| {
+        let mut array = AST::new(Ast_Type::AST_ARRAY_DEF);
+        array.array_elements = Some(items);
+        array.data_type = *element;
+        array
+    };
+
+    match outcome {
+        maps::Outcome::Value(value) => value,
+        maps::Outcome::Int(i) => AST::from_int(i),
+        maps::Outcome::Bool(b) => AST::from_bool(b),
+        maps::Outcome::Keys(keys) => array(keys, key_type),
+        maps::Outcome::Values(values) => array(values, value_type),
+    }
+}
+
+// value[index] for a map, an array or a string, name is where value came from
+fn element(value: AST, index: &AST, name: &str, span: &Option<Span>) -> IronResult<AST> {
+    if value.ast_type == Ast_Type::AST_MAP_DEF {
+        let keys = value.map_keys.as_deref().unwrap_or(&[]);
+        return maps::get(keys, value.map_values.as_deref().unwrap_or(&[]), index, name, span);
+    }
+
+    let i = match index.ast_type {
+        Ast_Type::AST_INT => index.int_value.unwrap(),
+        _ => return Err(IronError::TypeError("Array index must be an int".to_string(), span.clone())),
+    };
+
+    match value.ast_type {
+        Ast_Type::AST_ARRAY_DEF => {
+            let elements = value.array_elements.unwrap_or_default();
+
+            match elements.get(i as usize) {
+                Some(element) if i >= 0 => Ok(element.clone()),
+                _ => Err(IronError::IndexError(format!("index {} out of bounds for array {}", i, name), span.clone())),
+            }
+        }
+        Ast_Type::AST_STRING if value.data_type != Data_Type::CHAR => {
+            Ok(AST::from_char(strings::index(value.string_value.as_ref().unwrap(), i, name, span)?))
+        }
+        _ => Err(IronError::TypeError(format!("{} is not an array", name), span.clone())),
+    }
+}
+
+// grid[i][j] = v, every index but the last picks the array to go into
+fn store_at(target: &mut AST, indices: &[AST], value: AST, name: &str, span: &Option<Span>) -> IronResult<()> {
+    // Storing under a new key adds it, going through one needs it to be there
+    if target.ast_type == Ast_Type::AST_MAP_DEF {
+        let keys = target.map_keys.get_or_insert_with(Vec::new);
+        let values = target.map_values.get_or_insert_with(Vec::new);
+
+        if indices.len() == 1 {
+            let value = match &target.data_type {
+                Data_Type::MAP(_, element) => arrays::stored(element, &value),
+                _ => value,
+            };
+            maps::set(keys, values, indices[0].clone(), value);
+            return Ok(());
+        }
+
+        let i = maps::find(keys, &indices[0]).ok_or_else(|| maps::missing(&indices[0], name, span))?;
+        return store_at(&mut values[i], &indices[1..], value, name, span);
+    }
+
+    if target.ast_type != Ast_Type::AST_ARRAY_DEF {
+        return Err(IronError::TypeError(format!("{} is not an array", name), span.clone()));
+    }
+
+    let i = match indices[0].ast_type {
+        Ast_Type::AST_INT => indices[0].int_value.unwrap(),
+        _ => return Err(IronError::TypeError("Array index must be an int".to_string(), span.clone())),
+    };
+
+    let items = target.array_elements.get_or_insert_with(Vec::new);
+
+    if i < 0 || i as usize >= items.len() {
+        return Err(IronError::IndexError(format!("index {} out of bounds for array {}", i, name), span.clone()));
+    }
+
+    if indices.len() == 1 {
+        items[i as usize] = arrays::stored(&target.data_type, &value);
+        return Ok(());
+    }
+
+    store_at(&mut items[i as usize], &indices[1..], value, name, span)
+}
 
-        self.visit(&mut elements[idx as usize].clone()) 
+// The type a value would be declared with, arrays carry their element type
+fn value_data_type(value: &AST) -> Data_Type {
+    match value.ast_type {
+        Ast_Type::AST_ARRAY_DEF => Data_Type::ARRAY(Box::new(value.data_type.clone())),
+        Ast_Type::AST_NOOP | Ast_Type::AST_CLASS_INSTANCE if value.class_name.is_some() => Data_Type::CUSTOM(value.class_name.clone().unwrap()),
+        _ => value.data_type.clone(),
     }
-}
\ No newline at end of file
+}
+
+// What a parameter or a definition holds once it has a value, arrays are stored under their own name
+fn binding(def: &AST, value: AST, scope: &SharedScope) -> AST {
+    let name = def.variable_definition_variable_name.clone();
+    let mut value = declared(def, value);
+
+    if value.ast_type == Ast_Type::AST_ARRAY_DEF {
+        value.array_name = name;
+        value.scope = Some(scope.clone());
+        return value;
+    }
+
+    value.scope = Some(scope.clone());
+
+    let mut var_def = AST::new(Ast_Type::AST_VARIABLE_DEF);
+    var_def.variable_definition_variable_name = name;
+    var_def.variable_definition_value = Some(Box::new(value));
+    var_def.variable_type = def.variable_type.clone();
+    var_def.class_name = match &def.variable_type {
+        Some(Data_Type::CUSTOM(class)) => Some(class.clone()),
+        _ => None,
+    };
+    var_def.scope = Some(scope.clone());
+    var_def
+}
+
+// What a variable declared by def holds, an int in a float variable is a float
+fn declared(def: &AST, value: AST) -> AST {
+    match (&def.ast_type, def.variable_type.as_ref()) {
+        (Ast_Type::AST_ARRAY_DEF, _) => arrays::stored(&Data_Type::ARRAY(Box::new(def.data_type.clone())), &value),
+        (_, Some(t)) => arrays::stored(t, &value),
+        _ => value,
+    }
+}
+
+// Named the way the VM names its values so errors read the same on both
+fn value_type_name(value: &AST) -> &'static str {
+    match value.ast_type {
+        Ast_Type::AST_INT => "int",
+        Ast_Type::AST_FLOAT => "float",
+        Ast_Type::AST_STRING if value.data_type == Data_Type::CHAR => "char",
+        Ast_Type::AST_STRING => "str",
+        Ast_Type::AST_BOOL => "bool",
+        Ast_Type::AST_ARRAY_DEF => "array",
+        Ast_Type::AST_MAP_DEF => "map",
+        Ast_Type::AST_ERROR => "Error",
+        Ast_Type::AST_CLASS_INSTANCE => "instance",
+        _ => "void",
+    }
+}
+
+// Numbers compare by value and text by text, the same as Value does in the vm
+impl Element for AST {
+    fn type_name(&self) -> &'static str {
+        value_type_name(self)
+    }
+
+    fn int(&self) -> Option<i64> {
+        if self.ast_type == Ast_Type::AST_INT { self.int_value } else { None }
+    }
+
+    fn equals(&self, other: &Self) -> bool {
+        if self.ast_type == Ast_Type::AST_BOOL && other.ast_type == Ast_Type::AST_BOOL {
+            return self.bool_value == other.bool_value;
+        }
+
+        if self.ast_type == Ast_Type::AST_INT && other.ast_type == Ast_Type::AST_INT {
+            return self.int_value == other.int_value;
+        }
+
+        match (number(self), number(other), text(self), text(other)) {
+            (Some(a), Some(b), _, _) => (a - b).abs() < 1e-8,
+            (_, _, Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    fn order(&self, other: &Self) -> Option<Ordering> {
+        if self.ast_type == Ast_Type::AST_INT && other.ast_type == Ast_Type::AST_INT {
+            return Some(self.int_value.cmp(&other.int_value));
+        }
+
+        match (number(self), number(other), text(self), text(other)) {
+            (Some(a), Some(b), _, _) => Some(a.partial_cmp(&b).unwrap_or(Ordering::Equal)),
+            (_, _, Ok(a), Ok(b)) => Some(a.cmp(&b)),
+            _ => None,
+        }
+    }
+
+    fn typed(&self, element: &Data_Type) -> Self {
+        if self.ast_type != Ast_Type::AST_ARRAY_DEF || self.data_type != Data_Type::VOID {
+            return self.clone();
+        }
+
+        let mut array = self.clone();
+        array.array_elements = Some(self.array_elements.iter().flatten().map(|item| arrays::stored(element, item)).collect());
+        array.data_type = element.clone();
+        array
+    }
+
+    fn widen(&self) -> Self {
+        match self.ast_type {
+            Ast_Type::AST_INT => {
+                // Shown like the literal it would have been written as, 3 becomes 3.0
+                let mut float = AST::from_float(self.int_value.unwrap() as f64);
+                float.past_decimal = Some(1);
+                float
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
+// Text is quoted so a missing "1" doesn't read like a missing 1
+impl Key for AST {
+    fn describe(&self) -> String {
+        match self.ast_type {
+            Ast_Type::AST_STRING if self.data_type == Data_Type::CHAR => format!("'{}'", self.string_value.clone().unwrap_or_default()),
+            Ast_Type::AST_STRING => format!("\"{}\"", self.string_value.clone().unwrap_or_default()),
+            Ast_Type::AST_INT => self.int_value.unwrap_or_default().to_string(),
+            Ast_Type::AST_BOOL => self.bool_value.unwrap_or_default().to_string(),
+            _ => value_type_name(self).to_string(),
+        }
+    }
+}
+
+fn number(value: &AST) -> Option<f64> {
+    match value.ast_type {
+        Ast_Type::AST_INT => value.int_value.map(|i| i as f64),
+        Ast_Type::AST_FLOAT => value.float_value,
+        _ => None,
+    }
+}
+
+// int(x), float(x) and str(x), the span is filled in by the call
+fn convert(to: &str, args: &[AST]) -> IronResult<AST> {
+    let [value] = args else {
+        return Err(IronError::TypeError(format!("Function {} expected 1 arguments, got {}", to, args.len()), None));
+    };
+
+    let number = match (to, &value.ast_type) {
+        ("str", _) => return Ok(AST::from_string(&value.to_string())),
+        (_, Ast_Type::AST_INT) => value.int_value.unwrap() as f64,
+        (_, Ast_Type::AST_FLOAT) => value.float_value.unwrap(),
+        (_, Ast_Type::AST_BOOL) => value.bool_value.unwrap() as i64 as f64,
+        ("int", Ast_Type::AST_STRING) => return Ok(AST::from_int(numbers::parse_int(value.string_value.as_ref().unwrap(), &None)?)),
+        ("float", Ast_Type::AST_STRING) => numbers::parse_float(value.string_value.as_ref().unwrap(), &None)?,
+        _ => return Err(IronError::TypeError(format!("Can't convert {} to {}", value_type_name(value), to), None)),
+    };
+
+    if to == "float" {
+        return Ok(AST::from_float(number));
+    }
+
+    match value.ast_type {
+        Ast_Type::AST_FLOAT => Ok(AST::from_int(numbers::float_to_int(number, &None)?)),
+        _ => Ok(AST::from_int(value.int_value.unwrap_or(number as i64))),
+    }
+}
+
+// How an arithmetic operator is written, for numbers::arithmetic
+fn operator_symbol(op: &Types) -> &'static str {
+    match op {
+        Types::TOKEN_ADD => "+",
+        Types::TOKEN_SUBTRACT => "-",
+        Types::TOKEN_ASTERISK => "*",
+        Types::TOKEN_FSLASH => "/",
+        Types::TOKEN_PERCENT => "%",
+        Types::TOKEN_CARROT => "^",
+        Types::TOKEN_AMPERSAND => "&",
+        Types::TOKEN_PIPE => "|",
+        Types::TOKEN_XOR => "xor",
+        Types::TOKEN_SHIFT_LEFT => "<<",
+        Types::TOKEN_SHIFT_RIGHT => ">>",
+        _ => "?",
+    }
+}
+
+// What a string method gets handed for an argument
+fn text(value: &AST) -> Result<String, &'static str> {
+    match value.ast_type {
+        Ast_Type::AST_STRING => Ok(value.string_value.clone().unwrap_or_default()),
+        _ => Err(value_type_name(value)),
+    }
+}
@@ -0,0 +1,1057 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use crate::ast::Data_Type;
+use crate::arrays::{self, Element, Outcome};
+use crate::bytecode::{Instance, Op, Program, Slot, Value};
+use crate::maps::{self, Key};
+use crate::numbers;
+use crate::error::{IronError, IronResult};
+use crate::span::Span;
+use crate::strings::{self, Text};
+use crate::visitor::MAX_DEPTH;
+
+struct Frame {
+    function: usize,
+    ip: usize,
+    base: usize, // where this call's locals start
+}
+
+// Where to pick back up when an error is raised inside a try
+struct Handler {
+    ip: usize,
+    frames: usize,
+    stack: usize,
+    locals: usize,
+}
+
+pub struct VM {
+    pub program: Program,
+    stack: Vec<Value>,
+    locals: Vec<Value>,
+    frames: Vec<Frame>,
+    globals: Vec<Option<Value>>, // None until the definition has run
+    handlers: Vec<Handler>,
+}
+
+impl VM {
+    pub fn new(program: Program) -> Self {
+        let globals = vec![None; program.globals.len()];
+
+        VM {
+            program,
+            stack: vec![],
+            locals: vec![],
+            frames: vec![],
+            globals,
+            handlers: vec![],
+        }
+    }
+
+    pub fn run(&mut self) -> IronResult<()> {
+        self.call(self.program.main, 0, None)?;
+
+        loop {
+            let error = match self.execute() {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+
+            // Unwind to the closest try, or give up if there isn't one
+            let handler = match self.handlers.pop() {
+                Some(h) => h,
+                None => return Err(error),
+            };
+
+            self.frames.truncate(handler.frames);
+            self.stack.truncate(handler.stack);
+            self.locals.truncate(handler.locals);
+
+            self.frame_mut().ip = handler.ip;
+            self.stack.push(Value::Error(Rc::new(error)));
+        }
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("No frame running")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("No frame running")
+    }
+
+    // The span of the op that is running right now
+    fn span(&self) -> Option<Span> {
+        let frame = self.frame();
+        self.program.functions[frame.function].chunk.spans[frame.ip - 1].clone()
+    }
+
+    fn constant(&self, index: usize) -> Value {
+        self.program.functions[self.frame().function].chunk.constants[index].clone()
+    }
+
+    fn name(&self, index: usize) -> String {
+        match self.constant(index) {
+            Value::Str(s) => s.to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("Stack underflow")
+    }
+
+    fn peek(&self) -> Value {
+        self.stack.last().expect("Stack underflow").clone()
+    }
+
+    fn receiver(&self) -> IronResult<Rc<RefCell<Instance>>> {
+        match &self.locals[self.frame().base] {
+            Value::Instance(instance) => Ok(instance.clone()),
+            _ => Err(IronError::RuntimeError("Field used outside of a method".to_string(), self.span())),
+        }
+    }
+
+    fn load(&self, slot: Slot) -> IronResult<Value> {
+        match slot {
+            Slot::Local(i) => Ok(self.locals[self.frame().base + i].clone()),
+            Slot::Global(i) => self.globals[i].clone()
+                .ok_or_else(|| IronError::NameError(format!("Undefined variable: {}", self.program.globals[i]), self.span())),
+            Slot::Field(i) => Ok(self.receiver()?.borrow().fields[i].clone()),
+        }
+    }
+
+    // Plain assignment needs the variable to exist already, a definition doesn't
+    fn store(&mut self, slot: Slot, value: Value, define: bool) -> IronResult<()> {
+        match slot {
+            Slot::Local(i) => {
+                let base = self.frame().base;
+                self.locals[base + i] = value;
+            }
+            Slot::Global(i) => {
+                if !define && self.globals[i].is_none() {
+                    return Err(IronError::NameError(format!("Variable {} not defined", self.program.globals[i]), self.span()));
+                }
+
+                self.globals[i] = Some(value);
+            }
+            Slot::Field(i) => self.receiver()?.borrow_mut().fields[i] = value,
+        }
+
+        Ok(())
+    }
+
+    // A variable defined as a float only ever holds floats, so an int assigned to it later is widened too
+    fn assigned(&self, slot: Slot, value: Value) -> Value {
+        match self.load(slot) {
+            Ok(old) => replacing(&old, value),
+            _ => value,
+        }
+    }
+
+    // Moves the value out so an array in it can be changed without copying
+    fn take(&mut self, slot: Slot) -> IronResult<Value> {
+        let value = self.load(slot)?;
+        self.store(slot, Value::Nil, true)?;
+        Ok(value)
+    }
+
+    fn call(&mut self, function: usize, argc: usize, receiver: Option<Value>) -> IronResult<()> {
+        let f = &self.program.functions[function];
+
+        if f.params.len() != argc {
+            return Err(IronError::TypeError(format!(
+                "Function {} expected {} arguments, got {}",
+                f.name,
+                f.params.len(),
+                argc
+            ), self.span()));
+        }
+
+        // The frame for the top of the program doesn't count
+        if self.frames.len() > MAX_DEPTH {
+            return Err(IronError::RuntimeError(format!("Stack overflow, calls nested more than {} deep", MAX_DEPTH), self.span()));
+        }
+
+        let start = self.stack.len() - argc;
+
+        for (param, arg) in f.params.iter().zip(&mut self.stack[start..]) {
+            *arg = arrays::stored(param, arg);
+        }
+
+        for (param, arg) in f.params.iter().zip(&self.stack[start..]) {
+            if !self.accepts(param, arg) {
+                return Err(IronError::TypeError(format!(
+                    "Function {} argument type mismatch: expected {:?}, got {}",
+                    f.name, param, arg.type_name()
+                ), self.span()));
+            }
+        }
+
+        let base = self.locals.len();
+        let size = f.locals;
+
+        self.locals.extend(receiver);
+        self.locals.extend(self.stack.drain(start..));
+        self.locals.resize(base + size.max(argc), Value::Nil);
+
+        self.frames.push(Frame { function, ip: 0, base });
+
+        Ok(())
+    }
+
+    // Ints are let through for float parameters just like the visitor does
+    fn accepts(&self, param: &Data_Type, arg: &Value) -> bool {
+        match (param, arg) {
+            (Data_Type::INT, Value::Int(_)) => true,
+            (Data_Type::FLOAT, Value::Float(..) | Value::Int(_)) => true,
+            (Data_Type::STR, Value::Str(_)) => true,
+            (Data_Type::CHAR, Value::Char(_)) => true,
+            (Data_Type::BOOL, Value::Bool(_)) => true,
+            (Data_Type::CUSTOM(name), Value::Instance(i)) => self.program.classes[i.borrow().class].name == *name,
+            (Data_Type::CUSTOM(name), Value::Error(_)) => name == "Error",
+            (Data_Type::ARRAY(element), Value::Array(array)) => array.element == **element,
+            (Data_Type::MAP(key, value), Value::Map(map)) => map.key == **key && map.value == **value,
+            (Data_Type::VOID, Value::Nil) => true,
+            _ => false,
+        }
+    }
+
+    // The arguments are on top of the stack
+    fn string_method(&mut self, receiver: &Value, name: &str, argc: usize) -> IronResult<Value> {
+        let args: Vec<Result<String, &'static str>> = self.stack.drain(self.stack.len() - argc..).map(|arg| text(&arg).ok_or(arg.type_name())).collect();
+
+        Ok(match strings::method(&text(receiver).unwrap(), name, &args, &self.span())? {
+            Text::Int(i) => Value::Int(i),
+            Text::Bool(b) => Value::Bool(b),
+            Text::Str(s) => Value::Str(s.into()),
+            Text::List(parts) => Value::array(Data_Type::STR, parts.into_iter().map(|p| Value::Str(p.into())).collect()),
+        })
+    }
+
+    // The arguments are on top of the stack, the array is changed in place
+    fn array_method(&mut self, target: &mut Value, name: &str, argc: usize) -> IronResult<Value> {
+        let args = self.stack.split_off(self.stack.len() - argc);
+
+        let Value::Array(array) = target else {
+            unreachable!("array_method called on {}", target.type_name());
+        };
+
+        let array = Rc::make_mut(array);
+        let element = array.element.clone();
+
+        Ok(match arrays::method(&mut array.items, &element, |v| self.accepts(&element, v), name, &args, &self.span())? {
+            Outcome::Nothing => Value::Nil,
+            Outcome::Value(value) => value,
+            Outcome::Int(i) => Value::Int(i),
+            Outcome::Bool(b) => Value::Bool(b),
+            Outcome::Array(items) => Value::array(element, items),
+        })
+    }
+
+    // The arguments are on top of the stack, the map is changed in place
+    fn map_method(&mut self, target: &mut Value, name: &str, argc: usize) -> IronResult<Value> {
+        let args = self.stack.split_off(self.stack.len() - argc);
+
+        let Value::Map(map) = target else {
+            unreachable!("map_method called on {}", target.type_name());
+        };
+
+        let map = Rc::make_mut(map);
+
+        Ok(match maps::method(&mut map.keys, &mut map.values, name, &args, &self.span())? {
+            maps::Outcome::Value(value) => value,
+            maps::Outcome::Int(i) => Value::Int(i),
+            maps::Outcome::Bool(b) => Value::Bool(b),
+            maps::Outcome::Keys(keys) => Value::array(map.key.clone(), keys),
+            maps::Outcome::Values(values) => Value::array(map.value.clone(), values),
+        })
+    }
+
+    // The arguments are on top of the stack
+    fn invoke(&mut self, receiver: Value, name: &str, argc: usize) -> IronResult<()> {
+        let method = match &receiver {
+            Value::Instance(instance) => self.program.classes[instance.borrow().class].methods.get(name).copied(),
+            Value::Str(_) | Value::Char(_) => {
+                let value = self.string_method(&receiver, name, argc)?;
+                self.stack.push(value);
+                return Ok(());
+            }
+            // Not a variable, so whatever the method changes is thrown away
+            Value::Array(_) => {
+                let value = self.array_method(&mut receiver.clone(), name, argc)?;
+                self.stack.push(value);
+                return Ok(());
+            }
+            Value::Map(_) => {
+                let value = self.map_method(&mut receiver.clone(), name, argc)?;
+                self.stack.push(value);
+                return Ok(());
+            }
+            _ => return Err(IronError::TypeError(format!("Cannot call {} on {}", name, receiver.type_name()), self.span())),
+        };
+
+        let method = method.ok_or_else(|| IronError::NameError(format!("Function '{}' not defined", name), self.span()))?;
+        self.call(method, argc, Some(receiver))
+    }
+
+    fn property(&self, receiver: Value, name: &str) -> IronResult<Value> {
+        Ok(match receiver {
+            Value::Instance(_) => {
+                let (instance, i) = self.field(receiver, name)?;
+                let value = instance.borrow().fields[i].clone();
+                value
+            }
+            Value::Error(e) => match name {
+                "message" => Value::Str(e.message().into()),
+                "kind" => Value::Str(e.kind().into()),
+                _ => return Err(IronError::NameError(format!("Error has no field {}", name), self.span())),
+            },
+            other => return Err(IronError::TypeError(format!("Dot access not supported on {}", other.type_name()), self.span())),
+        })
+    }
+
+    // The instance and where the field is in it
+    fn field(&self, receiver: Value, name: &str) -> IronResult<(Rc<RefCell<Instance>>, usize)> {
+        let Value::Instance(instance) = receiver else {
+            return Err(IronError::TypeError(format!("Dot access not supported on {}", receiver.type_name()), self.span()));
+        };
+
+        let class = &self.program.classes[instance.borrow().class];
+
+        match class.fields.iter().position(|f| f == name) {
+            Some(i) => Ok((instance, i)),
+            None => Err(IronError::NameError(format!("{} has no field {}", class.name, name), self.span())),
+        }
+    }
+
+    // target[index] for an array, a map or a string, name is the variable it came from
+    fn element(&self, target: &Value, index: &Value, name: usize) -> IronResult<Value> {
+        if let Value::Map(map) = target {
+            return maps::get(&map.keys, &map.values, index, &self.name(name), &self.span());
+        }
+
+        let i = self.index(index)?;
+
+        Ok(match target {
+            Value::Array(array) => match array.items.get(i as usize) {
+                Some(v) if i >= 0 => v.clone(),
+                _ => return Err(IronError::IndexError(format!("index {} out of bounds for array {}", i, self.name(name)), self.span())),
+            },
+            Value::Str(s) => Value::Char(strings::index(s, i, &self.name(name), &self.span())?),
+            _ => return Err(IronError::TypeError(format!("{} is not an array", self.name(name)), self.span())),
+        })
+    }
+
+    // grid[i][j] = v, every index but the last picks the array to go into
+    fn store_at(&self, target: &mut Value, indices: &[Value], value: Value, name: usize) -> IronResult<()> {
+        // Storing under a new key adds it, going through one needs it to be there
+        if let Value::Map(map) = target {
+            let map = Rc::make_mut(map);
+
+            if indices.len() == 1 {
+                let value = arrays::stored(&map.value, &value);
+                maps::set(&mut map.keys, &mut map.values, indices[0].clone(), value);
+                return Ok(());
+            }
+
+            let i = maps::find(&map.keys, &indices[0]).ok_or_else(|| maps::missing(&indices[0], &self.name(name), &self.span()))?;
+            return self.store_at(&mut map.values[i], &indices[1..], value, name);
+        }
+
+        let Value::Array(array) = target else {
+            return Err(IronError::TypeError(format!("{} is not an array", self.name(name)), self.span()));
+        };
+
+        let i = self.index(&indices[0])?;
+        let array = Rc::make_mut(array);
+        let items = &mut array.items;
+
+        if i < 0 || i as usize >= items.len() {
+            return Err(IronError::IndexError(format!("index {} out of bounds for array {}", i, self.name(name)), self.span()));
+        }
+
+        if indices.len() == 1 {
+            items[i as usize] = arrays::stored(&array.element, &value);
+            return Ok(());
+        }
+
+        self.store_at(&mut items[i as usize], &indices[1..], value, name)
+    }
+
+    fn index(&self, index: &Value) -> IronResult<i64> {
+        match index {
+            Value::Int(i) => Ok(*i),
+            _ => Err(IronError::TypeError("Array index must be an int".to_string(), self.span())),
+        }
+    }
+
+    fn execute(&mut self) -> IronResult<()> {
+        loop {
+            let frame = self.frames.last_mut().expect("No frame running");
+            let op = self.program.functions[frame.function].chunk.code[frame.ip];
+            frame.ip += 1;
+
+            match op {
+                Op::Constant(i) => {
+                    let value = self.constant(i);
+                    self.stack.push(value);
+                }
+                Op::Nil => self.stack.push(Value::Nil),
+                Op::Pop => {
+                    self.pop();
+                }
+
+                Op::Define(slot) => {
+                    let value = self.peek();
+                    self.store(slot, value, true)?;
+                }
+                Op::Get(slot) => {
+                    let value = self.load(slot)?;
+                    self.stack.push(value);
+                }
+                Op::Set(slot) => {
+                    let value = self.pop();
+                    let value = self.assigned(slot, value);
+                    self.store(slot, value.clone(), false)?;
+                    self.stack.push(value);
+                }
+                Op::Widen => {
+                    let value = self.pop();
+                    self.stack.push(value.widen());
+                }
+                Op::Increment(slot) | Op::Decrement(slot) => {
+                    let step = if op == Op::Increment(slot) { 1 } else { -1 };
+
+                    let value = match self.load(slot)? {
+                        Value::Int(i) => Value::Int(numbers::arithmetic(if step == 1 { "+" } else { "-" }, i, 1, &self.span())?),
+                        Value::Float(f, precision) => Value::Float(f + step as f64, precision),
+                        _ => return Err(IronError::TypeError(format!(
+                            "Cannot apply {} to non-numeric type (only int/float supported)",
+                            if step == 1 { "++" } else { "--" }
+                        ), self.span())),
+                    };
+
+                    self.store(slot, value.clone(), false)?;
+                    self.stack.push(value);
+                }
+
+                Op::Array(n, element) => {
+                    let Value::Array(empty) = self.constant(element) else {
+                        unreachable!("Array op without an element type");
+                    };
+
+                    let items = self.stack.split_off(self.stack.len() - n);
+                    let items = items.iter().map(|item| arrays::stored(&empty.element, item)).collect();
+                    self.stack.push(Value::array(empty.element.clone(), items));
+                }
+                Op::Map(n, map) => {
+                    let Value::Map(empty) = self.constant(map) else {
+                        unreachable!("Map op without a map type");
+                    };
+
+                    let pairs = self.stack.split_off(self.stack.len() - n * 2);
+                    let (mut keys, mut values) = (vec![], vec![]);
+
+                    for pair in pairs.chunks(2) {
+                        maps::set(&mut keys, &mut values, pair[0].clone(), arrays::stored(&empty.value, &pair[1]));
+                    }
+
+                    self.stack.push(Value::map(empty.key.clone(), empty.value.clone(), keys, values));
+                }
+                Op::Fill(element) => {
+                    let Value::Array(empty) = self.constant(element) else {
+                        unreachable!("Fill op without an element type");
+                    };
+
+                    let value = self.pop();
+                    let size = self.pop();
+
+                    let items = arrays::fill(&empty.element, |v| self.accepts(&empty.element, v), &size, value, &self.span())?;
+                    self.stack.push(Value::array(empty.element.clone(), items));
+                }
+                Op::IsInstance(class) => {
+                    let Value::Str(class) = self.constant(class) else {
+                        unreachable!("IsInstance op without a class name");
+                    };
+
+                    let is = match self.pop() {
+                        Value::Instance(i) => *self.program.classes[i.borrow().class].name == *class,
+                        _ => false,
+                    };
+
+                    self.stack.push(Value::Bool(is));
+                }
+                Op::Iter => {
+                    let items = match self.pop() {
+                        Value::Array(array) => Value::Array(array),
+                        Value::Str(s) => Value::array(Data_Type::CHAR, s.chars().map(Value::Char).collect()),
+                        Value::Map(map) => Value::array(map.key.clone(), map.keys.clone()),
+                        value => return Err(IronError::TypeError(format!("Can't loop over {}", value.type_name()), self.span())),
+                    };
+
+                    self.stack.push(items);
+                }
+                Op::Range => {
+                    let bounds = &self.stack[self.stack.len() - 3..];
+
+                    if let Some(value) = bounds.iter().find(|v| !matches!(v, Value::Int(_))) {
+                        return Err(IronError::TypeError(format!("range takes ints, got {}", value.type_name()), self.span()));
+                    }
+
+                    if matches!(bounds[2], Value::Int(0)) {
+                        return Err(IronError::RuntimeError("range step can't be 0".to_string(), self.span()));
+                    }
+                }
+                Op::GetIndex(slot, name) => {
+                    let index = self.pop();
+                    let target = self.load(slot)?;
+
+                    let value = self.element(&target, &index, name)?;
+                    self.stack.push(value);
+                }
+                Op::Index(name) => {
+                    let index = self.pop();
+                    let target = self.pop();
+
+                    let value = self.element(&target, &index, name)?;
+                    self.stack.push(value);
+                }
+                Op::Slice(slot, name) => {
+                    let end = self.pop();
+                    let start = self.pop();
+                    let (start, end) = (self.index(&start)?, self.index(&end)?);
+
+                    let value = match self.load(slot)? {
+                        Value::Str(s) => Value::Str(strings::slice(&s, start, end, &self.name(name), &self.span())?.into()),
+                        _ => return Err(IronError::TypeError(format!("{} is not a string", self.name(name)), self.span())),
+                    };
+
+                    self.stack.push(value);
+                }
+                Op::SetIndex(slot, name, depth) => {
+                    let value = self.pop();
+                    let indices = self.stack.split_off(self.stack.len() - depth);
+
+                    let mut target = self.take(slot)?;
+                    let result = self.store_at(&mut target, &indices, value.clone(), name);
+
+                    self.store(slot, target, true)?;
+                    result?;
+
+                    self.stack.push(value);
+                }
+
+                Op::SetPropertyIndex(field, name, depth) => {
+                    let value = self.pop();
+                    let indices = self.stack.split_off(self.stack.len() - depth);
+
+                    let receiver = self.pop();
+                    let (instance, i) = self.field(receiver, &self.name(field))?;
+                    let mut target = std::mem::replace(&mut instance.borrow_mut().fields[i], Value::Nil);
+                    let result = self.store_at(&mut target, &indices, value.clone(), name);
+
+                    instance.borrow_mut().fields[i] = target;
+                    result?;
+
+                    self.stack.push(value);
+                }
+
+                Op::Add
+                | Op::Subtract
+                | Op::Multiply
+                | Op::Divide
+                | Op::Modulo
+                | Op::Power
+                | Op::BitAnd
+                | Op::BitOr
+                | Op::BitXor
+                | Op::ShiftLeft
+                | Op::ShiftRight
+                | Op::Greater
+                | Op::Less
+                | Op::GreaterEqual
+                | Op::LessEqual
+                | Op::Equal
+                | Op::NotEqual
+                | Op::And
+                | Op::Or => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = binary(op, &left, &right, &self.span())?;
+                    self.stack.push(value);
+                }
+                Op::Negate => {
+                    let value = match self.pop() {
+                        Value::Int(i) => Value::Int(numbers::negate(i, &self.span())?),
+                        Value::Float(f, precision) => Value::Float(-f, precision),
+                        _ => return Err(IronError::TypeError("Unary minus only supports int and float".to_string(), self.span())),
+                    };
+
+                    self.stack.push(value);
+                }
+                Op::Not => {
+                    let value = match self.pop() {
+                        Value::Bool(b) => Value::Bool(!b),
+                        Value::Int(i) => Value::Int(if i == 0 { 1 } else { 0 }),
+                        Value::Float(f, precision) => Value::Float(if f == 0.0 { 1.0 } else { 0.0 }, precision),
+                        _ => return Err(IronError::TypeError("Unary not only supports bool, int, float".to_string(), self.span())),
+                    };
+
+                    self.stack.push(value);
+                }
+                Op::BitNot => {
+                    let value = match self.pop() {
+                        Value::Int(i) => Value::Int(!i),
+                        other => return Err(IronError::TypeError(format!("Cannot apply ~ to {}", other.type_name()), self.span())),
+                    };
+
+                    self.stack.push(value);
+                }
+
+                Op::Jump(target) => self.frame_mut().ip = target,
+                Op::JumpIfFalse(target) => {
+                    let condition = self.pop();
+                    let truthy = truthy(&condition)
+                        .ok_or_else(|| IronError::TypeError(format!("Condition must be a bool, int or float, got {}", condition.type_name()), self.span()))?;
+
+                    if !truthy {
+                        self.frame_mut().ip = target;
+                    }
+                }
+
+                Op::SkipAnd(target) | Op::SkipOr(target) => {
+                    let decided = matches!(op, Op::SkipOr(_));
+                    let left = truthy(&self.peek())
+                        .ok_or_else(|| IronError::TypeError("Invalid left operand type for boolean operation".to_string(), self.span()))?;
+
+                    if left == decided {
+                        *self.stack.last_mut().unwrap() = Value::Bool(left);
+                        self.frame_mut().ip = target;
+                    }
+                }
+
+                Op::Call(function, argc) => self.call(function, argc, None)?,
+                Op::CallBuiltin(name, argc) => {
+                    let args = self.stack.split_off(self.stack.len() - argc);
+                    let name = self.name(name);
+
+                    if name != "print" && name != "println" {
+                        let value = self.convert(&name, &args)?;
+                        self.stack.push(value);
+                        continue;
+                    }
+
+                    for arg in &args {
+                        arg.to_ast().print();
+                    }
+
+                    if name == "println" {
+                        println!();
+                    }
+
+                    self.stack.push(Value::Nil);
+                }
+                Op::CallLibrary(module, function, argc) => {
+                    let args = self.stack.split_off(self.stack.len() - argc);
+                    let value = self.call_library_function(&self.name(module), &self.name(function), &args)?;
+                    self.stack.push(value);
+                }
+                Op::Invoke(name, argc) => {
+                    let name = self.name(name);
+                    let receiver = self.stack.remove(self.stack.len() - argc - 1);
+                    self.invoke(receiver, &name, argc)?;
+                }
+                Op::InvokeSlot(slot, name, argc) => {
+                    let name = self.name(name);
+
+                    // Methods on an array or map variable change the variable itself
+                    if let Value::Array(_) | Value::Map(_) = self.load(slot)? {
+                        let mut target = self.take(slot)?;
+                        let result = match target {
+                            Value::Array(_) => self.array_method(&mut target, &name, argc),
+                            _ => self.map_method(&mut target, &name, argc),
+                        };
+                        self.store(slot, target, true)?;
+
+                        self.stack.push(result?);
+                        continue;
+                    }
+
+                    let receiver = self.load(slot)?;
+                    self.invoke(receiver, &name, argc)?;
+                }
+                Op::InvokeProperty(field, name, argc) => {
+                    let field = self.name(field);
+                    let name = self.name(name);
+                    let receiver = self.stack.remove(self.stack.len() - argc - 1);
+
+                    // An array or map field is changed where it is, like a variable's
+                    if let Value::Instance(_) = receiver {
+                        let (instance, i) = self.field(receiver.clone(), &field)?;
+                        let mut target = std::mem::replace(&mut instance.borrow_mut().fields[i], Value::Nil);
+
+                        let result = match target {
+                            Value::Array(_) => Some(self.array_method(&mut target, &name, argc)),
+                            Value::Map(_) => Some(self.map_method(&mut target, &name, argc)),
+                            _ => None,
+                        };
+                        instance.borrow_mut().fields[i] = target;
+
+                        if let Some(result) = result {
+                            self.stack.push(result?);
+                            continue;
+                        }
+                    }
+
+                    let value = self.property(receiver, &field)?;
+                    self.invoke(value, &name, argc)?;
+                }
+                Op::New(class, argc) => {
+                    let fields = vec![Value::Nil; self.program.classes[class].fields.len()];
+                    let instance = Value::Instance(Rc::new(RefCell::new(Instance { class, fields })));
+
+                    self.call(self.program.classes[class].constructor, argc, Some(instance))?;
+                }
+                Op::GetProperty(name) => {
+                    let receiver = self.pop();
+                    let value = self.property(receiver, &self.name(name))?;
+                    self.stack.push(value);
+                }
+                Op::SetProperty(name) => {
+                    let name = self.name(name);
+                    let value = self.pop();
+
+                    match self.pop() {
+                        Value::Instance(instance) => {
+                            let mut instance = instance.borrow_mut();
+                            let class = &self.program.classes[instance.class];
+
+                            match class.fields.iter().position(|f| *f == name) {
+                                Some(i) => instance.fields[i] = replacing(&instance.fields[i], value.clone()),
+                                None => return Err(IronError::NameError(format!("{} has no field {}", class.name, name), self.span())),
+                            }
+                        }
+                        other => return Err(IronError::TypeError(format!("Dot access not supported on {}", other.type_name()), self.span())),
+                    }
+
+                    self.stack.push(value);
+                }
+
+                Op::PushHandler(target) => {
+                    self.handlers.push(Handler {
+                        ip: target,
+                        frames: self.frames.len(),
+                        stack: self.stack.len(),
+                        locals: self.locals.len(),
+                    });
+                }
+                Op::PopHandler => {
+                    self.handlers.pop();
+                }
+                Op::MatchError(kind) => {
+                    let kind = self.name(kind);
+
+                    let matches = match self.stack.last() {
+                        Some(Value::Error(e)) => kind == "Error" || kind == e.kind(),
+                        _ => false,
+                    };
+
+                    self.stack.push(Value::Bool(matches));
+                }
+                Op::Throw => {
+                    return Err(match self.pop() {
+                        Value::Error(e) => IronError::from_kind(e.kind(), e.message().to_string(), self.span()),
+                        Value::Str(s) => IronError::UserError(s.to_string(), self.span()),
+                        _ => IronError::TypeError("Can only throw a string or a caught error".to_string(), self.span()),
+                    });
+                }
+                Op::Rethrow => {
+                    return Err(match self.pop() {
+                        Value::Error(e) => (*e).clone(),
+                        _ => IronError::RuntimeError("Can only rethrow an error".to_string(), self.span()),
+                    });
+                }
+
+                Op::Return => {
+                    let mut value = self.pop();
+
+                    if let Some(expected) = &self.program.functions[self.frame().function].returns {
+                        value = arrays::stored(expected, &value);
+
+                        if !self.accepts(expected, &value) {
+                            return Err(IronError::TypeError(format!(
+                                "Function {} should return {}, got {}",
+                                self.program.functions[self.frame().function].name, expected.name(), value.type_name()
+                            ), self.span()));
+                        }
+                    }
+
+                    let frame = self.frames.pop().expect("No frame to return from");
+
+                    self.locals.truncate(frame.base);
+
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+
+                    self.stack.push(value);
+                }
+                Op::MissingReturn => {
+                    let function = &self.program.functions[self.frame().function];
+
+                    return Err(IronError::TypeError(format!(
+                        "Function {} should return {} but ended without a return",
+                        function.name, function.returns.as_ref().unwrap().name()
+                    ), self.span()));
+                }
+            }
+        }
+    }
+
+    // int(x), float(x) and str(x), same as the visitor's convert
+    fn convert(&self, to: &str, args: &[Value]) -> IronResult<Value> {
+        let [value] = args else {
+            return Err(IronError::TypeError(format!("Function {} expected 1 arguments, got {}", to, args.len()), self.span()));
+        };
+
+        let span = self.span();
+
+        Ok(match (to, value) {
+            ("str", value) => Value::Str(value.to_ast().to_string().into()),
+            ("int", Value::Int(i)) => Value::Int(*i),
+            ("int", Value::Float(f, _)) => Value::Int(numbers::float_to_int(*f, &span)?),
+            ("int", Value::Bool(b)) => Value::Int(*b as i64),
+            ("int", Value::Str(s)) => Value::Int(numbers::parse_int(s, &span)?),
+            ("int", Value::Char(c)) => Value::Int(numbers::parse_int(&c.to_string(), &span)?),
+            ("float", Value::Int(i)) => Value::Float(*i as f64, None),
+            ("float", Value::Float(f, _)) => Value::Float(*f, None),
+            ("float", Value::Bool(b)) => Value::Float(*b as i64 as f64, None),
+            ("float", Value::Str(s)) => Value::Float(numbers::parse_float(s, &span)?, None),
+            ("float", Value::Char(c)) => Value::Float(numbers::parse_float(&c.to_string(), &span)?, None),
+            (to, value) => return Err(IronError::TypeError(format!("Can't convert {} to {}", value.type_name(), to), span)),
+        })
+    }
+
+    fn call_library_function(&self, module: &str, function: &str, args: &[Value]) -> IronResult<Value> {
+        match module {
+            "math" => {
+                let f: fn(f64) -> f64 = match function {
+                    "sqrt" => f64::sqrt,
+                    "abs" => f64::abs,
+                    "floor" => f64::floor,
+                    _ => return Err(IronError::NameError(format!("Function `{}` not found in <math>", function), self.span())),
+                };
+
+                match args {
+                    [Value::Int(i)] => Ok(Value::Float(f(*i as f64), None)),
+                    [Value::Float(x, _)] => Ok(Value::Float(f(*x), None)),
+                    _ => Err(IronError::TypeError(format!("{}() requires int or float", function), self.span())),
+                }
+            }
+            _ => Err(IronError::ImportError(format!("Built-in library `{}` not implemented", module), self.span())),
+        }
+    }
+}
+
+// What a variable or field that held old holds once value is assigned, a float stays a float
+// and an untyped [..] becomes the kind of array that was there
+fn replacing(old: &Value, value: Value) -> Value {
+    match old {
+        Value::Float(..) => value.widen(),
+        Value::Array(array) => value.typed(&array.element),
+        _ => value,
+    }
+}
+
+// Numbers compare by value and text by text, like == does
+impl Element for Value {
+    fn type_name(&self) -> &'static str {
+        Value::type_name(self)
+    }
+
+    fn int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn equals(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            _ => match (number(self), number(other), text(self), text(other)) {
+                (Some(a), Some(b), _, _) => (a - b).abs() < 1e-8,
+                (_, _, Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+
+    fn order(&self, other: &Self) -> Option<Ordering> {
+        if let (Value::Int(a), Value::Int(b)) = (self, other) {
+            return Some(a.cmp(b));
+        }
+
+        match (number(self), number(other), text(self), text(other)) {
+            (Some(a), Some(b), _, _) => Some(a.partial_cmp(&b).unwrap_or(Ordering::Equal)),
+            (_, _, Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => None,
+        }
+    }
+
+    fn widen(&self) -> Self {
+        match self {
+            Value::Int(i) => Value::Float(*i as f64, Some(1)),
+            other => other.clone(),
+        }
+    }
+
+    fn typed(&self, element: &Data_Type) -> Self {
+        match self {
+            Value::Array(array) if array.element == Data_Type::VOID => {
+                Value::array(element.clone(), array.items.iter().map(|item| arrays::stored(element, item)).collect())
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+// Text is quoted so a missing "1" doesn't read like a missing 1
+impl Key for Value {
+    fn describe(&self) -> String {
+        match self {
+            Value::Str(s) => format!("\"{}\"", s),
+            Value::Char(c) => format!("'{}'", c),
+            Value::Int(i) => i.to_string(),
+            Value::Bool(b) => b.to_string(),
+            other => other.type_name().to_string(),
+        }
+    }
+}
+
+// Strings and chars both count as text
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::Str(s) => Some(s.to_string()),
+        Value::Char(c) => Some(c.to_string()),
+        _ => None,
+    }
+}
+
+fn truthy(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::Int(i) => Some(*i != 0),
+        Value::Float(f, _) => Some(*f != 0.0),
+        _ => None,
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(i) => Some(*i as f64),
+        Value::Float(f, _) => Some(*f),
+        _ => None,
+    }
+}
+
+// Same rules as Visitor::visit_binary, two ints stay ints and anything with a float is done in f64
+fn binary(op: Op, left: &Value, right: &Value, span: &Option<Span>) -> IronResult<Value> {
+    let type_error = |message: &str| IronError::TypeError(message.to_string(), span.clone());
+
+    if matches!(op, Op::And | Op::Or) {
+        let l = truthy(left).ok_or_else(|| type_error("Invalid left operand type for boolean operation"))?;
+        let r = truthy(right).ok_or_else(|| type_error("Invalid right operand type for boolean operation"))?;
+
+        return Ok(Value::Bool(if op == Op::And { l && r } else { l || r }));
+    }
+
+    if let (Some(l), Some(r)) = (text(left), text(right)) {
+        match op {
+            Op::Add => return Ok(Value::Str(format!("{}{}", l, r).into())),
+            Op::Equal => return Ok(Value::Bool(l == r)),
+            Op::NotEqual => return Ok(Value::Bool(l != r)),
+            _ => {}
+        }
+    }
+
+    if let (Value::Bool(l), Value::Bool(r)) = (left, right) {
+        match op {
+            Op::Equal => return Ok(Value::Bool(l == r)),
+            Op::NotEqual => return Ok(Value::Bool(l != r)),
+            _ => {}
+        }
+    }
+
+    if let (Value::Int(l), Value::Int(r)) = (left, right) {
+        let compared = match op {
+            Op::Greater => Some(l > r),
+            Op::Less => Some(l < r),
+            Op::GreaterEqual => Some(l >= r),
+            Op::LessEqual => Some(l <= r),
+            Op::Equal => Some(l == r),
+            Op::NotEqual => Some(l != r),
+            _ => None,
+        };
+
+        return match compared {
+            Some(b) => Ok(Value::Bool(b)),
+            None => Ok(Value::Int(numbers::arithmetic(operator_symbol(op), *l, *r, span)?)),
+        };
+    }
+
+    if numbers::is_bitwise(operator_symbol(op)) {
+        let other = if matches!(left, Value::Int(_)) { right } else { left };
+        return Err(type_error(&format!("Cannot apply {} to {}", operator_symbol(op), other.type_name())));
+    }
+
+    let l = number(left).ok_or_else(|| type_error("Invalid left operand type"))?;
+    let r = number(right).ok_or_else(|| type_error("Invalid right operand type"))?;
+
+    let compared = match op {
+        Op::Greater => Some(l > r),
+        Op::Less => Some(l < r),
+        Op::GreaterEqual => Some(l >= r),
+        Op::LessEqual => Some(l <= r),
+        Op::Equal => Some((l - r).abs() < 1e-8),
+        Op::NotEqual => Some((l - r).abs() >= 1e-8),
+        _ => None,
+    };
+
+    if let Some(b) = compared {
+        return Ok(Value::Bool(b));
+    }
+
+    let result = match op {
+        Op::Add => l + r,
+        Op::Subtract => l - r,
+        Op::Multiply => l * r,
+        Op::Divide => l / r,
+        Op::Modulo => l % r,
+        Op::Power => l.powf(r),
+        _ => return Err(type_error(&format!("Unknown operator {:?}", op))),
+    };
+
+    Ok(Value::Float(result, None))
+}
+
+// How an arithmetic op is written, for numbers::arithmetic
+fn operator_symbol(op: Op) -> &'static str {
+    match op {
+        Op::Add => "+",
+        Op::Subtract => "-",
+        Op::Multiply => "*",
+        Op::Divide => "/",
+        Op::Modulo => "%",
+        Op::Power => "^",
+        Op::BitAnd => "&",
+        Op::BitOr => "|",
+        Op::BitXor => "xor",
+        Op::ShiftLeft => "<<",
+        Op::ShiftRight => ">>",
+        _ => "?",
+    }
+}
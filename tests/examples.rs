@@ -0,0 +1,80 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+const IRON: &str = env!("CARGO_BIN_EXE_iron");
+
+// What a run printed and how it ended, stderr included so errors have to match too
+fn outcome(output: Output) -> (String, String, Option<i32>) {
+    (
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+        output.status.code(),
+    )
+}
+
+fn run(command: &mut Command) -> (String, String, Option<i32>) {
+    outcome(command.stdin(Stdio::null()).output().expect("failed to start"))
+}
+
+fn examples() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("examples"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "iron"))
+        .collect();
+
+    files.sort();
+    files
+}
+
+// Every example prints what its .out file says in the interpreter, without an error
+#[test]
+fn examples_print_their_expected_output() {
+    let mut failures = vec![];
+
+    for file in examples() {
+        let expected = fs::read_to_string(file.with_extension("out")).unwrap_or_default();
+        let got = run(Command::new(IRON).arg(&file));
+
+        if got != (expected.clone(), String::new(), Some(0)) {
+            failures.push(format!(
+                "{}\n--- expected\n{}\n--- interpreter ({:?})\n{}{}",
+                file.display(), expected, got.2, got.0, got.1
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+// Every example has to print the same under the vm and as a native build as it does in the interpreter
+#[test]
+fn backends_match_the_interpreter() {
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let mut failures = vec![];
+
+    for file in examples() {
+        let name = file.file_stem().unwrap().to_string_lossy().into_owned();
+        let expected = run(Command::new(IRON).arg(&file));
+
+        let binary = out.join(&name);
+        let built = run(Command::new(IRON).arg("build").arg(&file).arg("-o").arg(&binary));
+
+        let native = match built.2 {
+            Some(0) => run(&mut Command::new(&binary)),
+            _ => built,
+        };
+
+        for (backend, got) in [("--vm", run(Command::new(IRON).arg("--vm").arg(&file))), ("build", native)] {
+            if got != expected {
+                failures.push(format!(
+                    "{} under {}\n--- interpreter ({:?})\n{}{}\n--- {} ({:?})\n{}{}",
+                    name, backend, expected.2, expected.0, expected.1, backend, got.2, got.0, got.1
+                ));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}